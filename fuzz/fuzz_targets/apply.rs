@@ -0,0 +1,63 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_lsm_db::mem_table::{MemTable, Operation};
+
+/// Decodes arbitrary bytes into Operations, each laid out as:
+///
+/// ```text
+/// +-----------+-------------+-----+---------------+-------+----------------+
+/// | Kind (1B) | KeyLen (1B) | Key | ValueLen (1B) | Value | Timestamp (1B) |
+/// +-----------+-------------+-----+---------------+-------+----------------+
+/// ```
+///
+/// The Value and its length are only present for sets. Keys are at most 7
+/// bytes long, so that keys are often written several times.
+fn decode(mut data: &[u8]) -> Vec<Operation> {
+    let mut operations = Vec::new();
+    let mut take = |len: usize| -> Option<Vec<u8>> {
+        let bytes = data.get(..len)?.to_vec();
+        data = &data[len..];
+        Some(bytes)
+    };
+
+    while let Some(kind) = take(1) {
+        let Some(key_len) = take(1) else { break };
+        let Some(key) = take(key_len[0] as usize % 8) else { break };
+
+        let operation = if kind[0] % 2 == 0 {
+            let Some(value_len) = take(1) else { break };
+            let Some(value) = take(value_len[0] as usize) else { break };
+            let Some(timestamp) = take(1) else { break };
+            Operation::Set {
+                key,
+                value,
+                timestamp_ms: timestamp[0] as u128,
+                expires_at_ms: None,
+            }
+        } else {
+            let Some(timestamp) = take(1) else { break };
+            Operation::Delete {
+                key,
+                timestamp_ms: timestamp[0] as u128,
+            }
+        };
+        operations.push(operation);
+    }
+
+    operations
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut table = MemTable::new();
+    for operation in decode(data) {
+        table.apply(operation).unwrap();
+    }
+
+    let entries = table.entries();
+    assert!(entries.windows(2).all(|w| w[0].key < w[1].key));
+
+    let rebuilt = MemTable::from_entries(entries.to_vec());
+    assert_eq!(table.size(), rebuilt.size());
+    assert_eq!(table.live_len(), rebuilt.live_len());
+});
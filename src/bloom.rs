@@ -0,0 +1,379 @@
+use std::io;
+
+/// Default false positive rate of the BloomFilter of a Table
+pub const DEFAULT_FP_RATE: f64 = 0.01;
+
+/// Hasher hashes the keys of a BloomFilter
+///
+/// The bit positions of a key are derived from its hash, so a BloomFilter
+/// must be decoded with the Hasher it was built with.
+pub trait Hasher {
+    fn hash(&self, key: &[u8]) -> u64;
+}
+
+/// Hashes keys with 64-bit xxHash, the default Hasher
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct XxHasher;
+
+impl Hasher for XxHasher {
+    fn hash(&self, key: &[u8]) -> u64 {
+        xxh64(key, 0)
+    }
+}
+
+/// Hashes keys with 64-bit FNV-1a, which is simpler than xxHash but slower
+/// on long keys
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Fnv1aHasher;
+
+impl Hasher for Fnv1aHasher {
+    fn hash(&self, key: &[u8]) -> u64 {
+        key.iter().fold(0xcbf29ce484222325, |hash, &b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+}
+
+/// BloomFilter is a probabilistic set of keys
+///
+/// It can tell with certainty that a key is not in the set, but may report a
+/// key that was never inserted as present. Tables store one to skip point
+/// lookups for keys they don't contain, hashed by the default Hasher.
+///
+/// A BloomFilter is serialized as:
+///
+/// ```text
+/// +------------------+-----------------+------+
+/// | Hash Count (4B)  | Bit Count (8B)  | Bits |
+/// +------------------+-----------------+------+
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter<H = XxHasher> {
+    bits: Vec<u8>,
+    num_bits: u64,
+    num_hashes: u32,
+    hasher: H,
+}
+
+impl BloomFilter {
+    /// Creates an empty BloomFilter sized to hold `num_entries` keys with the
+    /// given false positive rate
+    pub fn new(num_entries: usize, fp_rate: f64) -> BloomFilter {
+        BloomFilter::with_hasher(num_entries, fp_rate, XxHasher)
+    }
+
+    /// Decodes a BloomFilter serialized with [`BloomFilter::encode`]
+    pub fn decode(buf: &[u8]) -> io::Result<BloomFilter> {
+        BloomFilter::decode_with_hasher(buf, XxHasher)
+    }
+}
+
+impl<H: Hasher> BloomFilter<H> {
+    /// Creates an empty BloomFilter like `new`, hashing its keys with the
+    /// given Hasher
+    pub fn with_hasher(num_entries: usize, fp_rate: f64, hasher: H) -> BloomFilter<H> {
+        let fp_rate = fp_rate.clamp(f64::MIN_POSITIVE, 1.0);
+        let n = num_entries.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+
+        // Optimal number of bits and hashes for `n` entries at `fp_rate`.
+        let num_bits = ((-n * fp_rate.ln()) / (ln2 * ln2)).ceil().max(8.0) as u64;
+        let num_hashes = ((num_bits as f64 / n) * ln2).round().max(1.0) as u32;
+
+        BloomFilter {
+            bits: vec![0; num_bits.div_ceil(8) as usize],
+            num_bits,
+            num_hashes,
+            hasher,
+        }
+    }
+
+    /// Adds a key to the BloomFilter
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in self.probes(key) {
+            self.bits[(bit / 8) as usize] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Returns false if the key is definitely not in the BloomFilter
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.probes(key)
+            .all(|bit| self.bits[(bit / 8) as usize] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Appends the serialized BloomFilter to the buffer
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.num_hashes.to_le_bytes());
+        buf.extend_from_slice(&self.num_bits.to_le_bytes());
+        buf.extend_from_slice(&self.bits);
+    }
+
+    /// Decodes a BloomFilter serialized with [`BloomFilter::encode`], built
+    /// with the given Hasher
+    pub fn decode_with_hasher(buf: &[u8], hasher: H) -> io::Result<BloomFilter<H>> {
+        if buf.len() < 12 {
+            return Err(invalid_data("bloom filter header is truncated"));
+        }
+        let num_hashes = u32::from_le_bytes(buf[0..4].try_into().unwrap());
+        let num_bits = u64::from_le_bytes(buf[4..12].try_into().unwrap());
+        let bits = &buf[12..];
+
+        if num_hashes == 0 || num_bits == 0 || bits.len() as u64 != num_bits.div_ceil(8) {
+            return Err(invalid_data("bloom filter header is inconsistent"));
+        }
+
+        Ok(BloomFilter {
+            bits: bits.to_vec(),
+            num_bits,
+            num_hashes,
+            hasher,
+        })
+    }
+
+    /// Yields the bit positions of a key, derived from two base hashes by
+    /// double hashing
+    fn probes(&self, key: &[u8]) -> impl Iterator<Item = u64> {
+        let h1 = self.hasher.hash(key);
+        let h2 = mix(h1) | 1;
+        let num_bits = self.num_bits;
+
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+/// PrefixExtractor picks the prefix of a key that the prefix BloomFilter of
+/// a Table holds
+///
+/// Tables written with a PrefixExtractor also hold a BloomFilter of the
+/// prefixes of their keys, which lets a prefix scan skip the Tables holding
+/// no key with its prefix. Only the keys in the domain of the extractor have
+/// a prefix. Every key starting with a key in the domain must be in the
+/// domain too and have the same prefix, so that the prefix of a scan is the
+/// prefix of every key it may yield.
+pub trait PrefixExtractor: Send + Sync {
+    /// Returns the prefix of a key in the domain
+    fn extract<'a>(&self, key: &'a [u8]) -> &'a [u8];
+
+    /// Whether the key has a prefix, every key by default
+    fn in_domain(&self, _key: &[u8]) -> bool {
+        true
+    }
+}
+
+/// Extracts the first bytes of the keys, leaving shorter keys out of the
+/// domain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedPrefix(pub usize);
+
+impl PrefixExtractor for FixedPrefix {
+    fn extract<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        &key[..self.0]
+    }
+
+    fn in_domain(&self, key: &[u8]) -> bool {
+        key.len() >= self.0
+    }
+}
+
+/// Returns the length of the BloomFilter serialized at the start of the
+/// buffer, or None if its header is truncated
+pub(crate) fn encoded_len(buf: &[u8]) -> Option<usize> {
+    let num_bits = u64::from_le_bytes(buf.get(4..12)?.try_into().unwrap());
+    usize::try_from(num_bits.div_ceil(8)).ok()?.checked_add(12)
+}
+
+const PRIME_1: u64 = 0x9e3779b185ebca87;
+const PRIME_2: u64 = 0xc2b2ae3d27d4eb4f;
+const PRIME_3: u64 = 0x165667b19e3779f9;
+const PRIME_4: u64 = 0x85ebca77c2b2ae63;
+const PRIME_5: u64 = 0x27d4eb2f165667c5;
+
+/// 64-bit xxHash (XXH64), which is stable across platforms and releases
+fn xxh64(key: &[u8], seed: u64) -> u64 {
+    let round = |acc: u64, lane: u64| {
+        acc.wrapping_add(lane.wrapping_mul(PRIME_2))
+            .rotate_left(31)
+            .wrapping_mul(PRIME_1)
+    };
+    let read_u64 = |buf: &[u8]| u64::from_le_bytes(buf[..8].try_into().unwrap());
+
+    let mut rest = key;
+    let mut hash = if key.len() >= 32 {
+        let mut acc = [
+            seed.wrapping_add(PRIME_1).wrapping_add(PRIME_2),
+            seed.wrapping_add(PRIME_2),
+            seed,
+            seed.wrapping_sub(PRIME_1),
+        ];
+        while rest.len() >= 32 {
+            for (i, acc) in acc.iter_mut().enumerate() {
+                *acc = round(*acc, read_u64(&rest[i * 8..]));
+            }
+            rest = &rest[32..];
+        }
+        let hash = acc[0]
+            .rotate_left(1)
+            .wrapping_add(acc[1].rotate_left(7))
+            .wrapping_add(acc[2].rotate_left(12))
+            .wrapping_add(acc[3].rotate_left(18));
+        acc.iter().fold(hash, |hash, &acc| {
+            (hash ^ round(0, acc))
+                .wrapping_mul(PRIME_1)
+                .wrapping_add(PRIME_4)
+        })
+    } else {
+        seed.wrapping_add(PRIME_5)
+    };
+    hash = hash.wrapping_add(key.len() as u64);
+
+    while rest.len() >= 8 {
+        hash = (hash ^ round(0, read_u64(rest)))
+            .rotate_left(27)
+            .wrapping_mul(PRIME_1)
+            .wrapping_add(PRIME_4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        let lane = u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64;
+        hash = (hash ^ lane.wrapping_mul(PRIME_1))
+            .rotate_left(23)
+            .wrapping_mul(PRIME_2)
+            .wrapping_add(PRIME_3);
+        rest = &rest[4..];
+    }
+    for &b in rest {
+        hash = (hash ^ (b as u64).wrapping_mul(PRIME_5))
+            .rotate_left(11)
+            .wrapping_mul(PRIME_1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(PRIME_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(PRIME_3);
+    hash ^ (hash >> 32)
+}
+
+/// Finalizer of SplitMix64, used to derive a second independent hash
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bloom::{xxh64, BloomFilter, Fnv1aHasher, Hasher, XxHasher};
+
+    #[test]
+    fn test_bloom_contains_inserted() {
+        let mut bloom = BloomFilter::new(1000, 0.01);
+        for i in 0..1000u32 {
+            bloom.insert(&i.to_be_bytes());
+        }
+
+        for i in 0..1000u32 {
+            assert!(bloom.contains(&i.to_be_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_bloom_false_positive_rate() {
+        let mut bloom = BloomFilter::new(1000, 0.01);
+        for i in 0..1000u32 {
+            bloom.insert(&i.to_be_bytes());
+        }
+
+        let false_positives = (1000..11000u32)
+            .filter(|i| bloom.contains(&i.to_be_bytes()))
+            .count();
+        // Allow some slack over the 1% target, 100 false positives expected.
+        assert!(false_positives < 200, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_bloom_default_hasher_fp_rate() {
+        for fp_rate in [0.1, 0.01, 0.001] {
+            let mut bloom = BloomFilter::new(50_000, fp_rate);
+            for i in 0..50_000u64 {
+                bloom.insert(format!("user-{}", i).as_bytes());
+            }
+
+            let probes = 500_000u64;
+            let false_positives = (50_000..50_000 + probes)
+                .filter(|i| bloom.contains(format!("user-{}", i).as_bytes()))
+                .count();
+            let measured = false_positives as f64 / probes as f64;
+            // Allow a tenth over the target for the sampling noise.
+            assert!(
+                measured < fp_rate * 1.1,
+                "{} measured for a {} target",
+                measured,
+                fp_rate
+            );
+        }
+    }
+
+    #[test]
+    fn test_bloom_xxh64() {
+        assert_eq!(xxh64(b"", 0), 0xef46db3751d8e999);
+        assert_eq!(xxh64(b"a", 0), 0xd24ec4f1a98c6e5b);
+        assert_eq!(xxh64(b"abc", 0), 0x44bc2cf5ad770999);
+        assert_eq!(
+            xxh64(b"The quick brown fox jumps over the lazy dog", 0),
+            0x0b242d361fda71bc
+        );
+        assert_eq!(XxHasher.hash(b"abc"), xxh64(b"abc", 0));
+    }
+
+    /// Hashes every key to the same value
+    struct ConstantHasher;
+
+    impl Hasher for ConstantHasher {
+        fn hash(&self, _: &[u8]) -> u64 {
+            42
+        }
+    }
+
+    #[test]
+    fn test_bloom_custom_hasher() {
+        let mut bloom = BloomFilter::with_hasher(100, 0.01, ConstantHasher);
+        bloom.insert(b"Apple");
+        // Every key collides with Apple.
+        assert!(bloom.contains(b"Lime"));
+
+        let mut bloom = BloomFilter::with_hasher(100, 0.01, Fnv1aHasher);
+        bloom.insert(b"Apple");
+        let mut buf = Vec::new();
+        bloom.encode(&mut buf);
+        let decoded = BloomFilter::decode_with_hasher(&buf, Fnv1aHasher).unwrap();
+        assert_eq!(decoded, bloom);
+        assert!(decoded.contains(b"Apple"));
+        assert!(!decoded.contains(b"Lime"));
+    }
+
+    #[test]
+    fn test_bloom_encode_decode() {
+        let mut bloom = BloomFilter::new(10, 0.01);
+        bloom.insert(b"Apple");
+        bloom.insert(b"Lime");
+
+        let mut buf = Vec::new();
+        bloom.encode(&mut buf);
+        let decoded = BloomFilter::decode(&buf).unwrap();
+
+        assert_eq!(decoded, bloom);
+        assert!(decoded.contains(b"Apple"));
+        assert!(BloomFilter::decode(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_bloom_empty() {
+        let bloom = BloomFilter::new(0, 0.01);
+        assert!(!bloom.contains(b"Apple"));
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Default capacity of a BlockCache in bytes
+pub const DEFAULT_CAPACITY: usize = 8 * 1024 * 1024;
+
+/// Identifies a block by the id of its Table and its offset in the Table
+type BlockKey = (u64, u64);
+
+/// BlockCache keeps recently read blocks of Tables in memory
+///
+/// Blocks are keyed by the id of their Table and their offset, so one cache
+/// can be shared by every open Table through an `Arc`. Once the cached blocks
+/// take up more than the capacity, the least recently used ones are evicted.
+/// Blocks larger than the whole capacity are never cached.
+///
+/// Table ids are unique within the process, so the blocks of a deleted Table
+/// are never served for another one. They stay cached until they are
+/// evicted.
+pub struct BlockCache {
+    capacity: usize,
+    state: Mutex<State>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct State {
+    /// The cached blocks with the tick at which they were last used
+    blocks: HashMap<BlockKey, (Arc<[u8]>, u64)>,
+    /// The cached blocks by the tick at which they were last used, oldest
+    /// first
+    lru: BTreeMap<u64, BlockKey>,
+    size: usize,
+    tick: u64,
+}
+
+impl BlockCache {
+    /// Creates an empty BlockCache holding up to `capacity` bytes of blocks
+    pub fn new(capacity: usize) -> BlockCache {
+        BlockCache {
+            capacity,
+            state: Mutex::new(State::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Gets a block, marking it as the most recently used
+    ///
+    /// Every lookup counts as either a hit or a miss.
+    pub fn get(&self, table_id: u64, offset: u64) -> Option<Arc<[u8]>> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state.tick += 1;
+
+        match state.blocks.get_mut(&(table_id, offset)) {
+            Some((block, used)) => {
+                state.lru.remove(used);
+                state.lru.insert(state.tick, (table_id, offset));
+                *used = state.tick;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(Arc::clone(block))
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Adds a block as the most recently used, evicting the least recently
+    /// used blocks until the cache fits its capacity
+    pub fn insert(&self, table_id: u64, offset: u64, block: Arc<[u8]>) {
+        if block.len() > self.capacity {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        state.size += block.len();
+        if let Some((old, used)) = state.blocks.insert((table_id, offset), (block, tick)) {
+            state.size -= old.len();
+            state.lru.remove(&used);
+        }
+        state.lru.insert(tick, (table_id, offset));
+
+        while state.size > self.capacity {
+            let Some((_, key)) = state.lru.pop_first() else {
+                break;
+            };
+            if let Some((block, _)) = state.blocks.remove(&key) {
+                state.size -= block.len();
+            }
+        }
+    }
+
+    /// Returns the number of lookups that found their block
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of lookups that missed their block
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes of blocks in the cache
+    pub fn size(&self) -> usize {
+        self.state.lock().unwrap().size
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        BlockCache::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::cache::BlockCache;
+
+    fn block(len: usize) -> Arc<[u8]> {
+        vec![7; len].into()
+    }
+
+    #[test]
+    fn test_cache_get_insert() {
+        let cache = BlockCache::new(100);
+        assert!(cache.get(0, 0).is_none());
+
+        cache.insert(0, 0, block(10));
+        cache.insert(1, 0, block(20));
+        assert_eq!(cache.get(0, 0).as_deref(), Some(&[7; 10][..]));
+        assert_eq!(cache.get(1, 0).map(|b| b.len()), Some(20));
+        assert!(cache.get(0, 10).is_none());
+
+        assert_eq!(cache.hits(), 2);
+        assert_eq!(cache.misses(), 2);
+        assert_eq!(cache.size(), 30);
+
+        // Replacing a block only counts it once.
+        cache.insert(0, 0, block(5));
+        assert_eq!(cache.size(), 25);
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let cache = BlockCache::new(100);
+        cache.insert(0, 0, block(40));
+        cache.insert(0, 40, block(40));
+        // Using the first block makes the second one the least recently used.
+        assert!(cache.get(0, 0).is_some());
+
+        cache.insert(0, 80, block(40));
+        assert!(cache.get(0, 0).is_some());
+        assert!(cache.get(0, 40).is_none());
+        assert!(cache.get(0, 80).is_some());
+        assert_eq!(cache.size(), 80);
+    }
+
+    #[test]
+    fn test_cache_block_larger_than_capacity() {
+        let cache = BlockCache::new(100);
+        cache.insert(0, 0, block(50));
+        cache.insert(0, 50, block(101));
+
+        assert!(cache.get(0, 50).is_none());
+        assert!(cache.get(0, 0).is_some());
+        assert_eq!(cache.size(), 50);
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::mem_table::MemTableEntry;
+use crate::merge;
+
+/// Default number of events a subscription buffers
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// ChangeEvent describes a single write made to the Database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    pub key: Vec<u8>,
+    pub value: Option<Vec<u8>>,
+    pub timestamp_ms: u128,
+    pub is_deleted: bool,
+    /// Whether the write is a merge, whose operand is the value
+    pub is_merge: bool,
+}
+
+impl From<&MemTableEntry> for ChangeEvent {
+    fn from(entry: &MemTableEntry) -> Self {
+        let value = match (&entry.value, entry.is_merge) {
+            // The merge records of writes hold a single operand, as the
+            // merges of write hooks are split into one write per operand.
+            (Some(operands), true) => merge::decode_operands(operands)
+                .ok()
+                .and_then(|mut operands| operands.pop()),
+            (value, _) => value.clone(),
+        };
+        ChangeEvent {
+            key: entry.key.clone(),
+            value,
+            timestamp_ms: entry.timestamp_ms,
+            is_deleted: entry.is_deleted,
+            is_merge: entry.is_merge,
+        }
+    }
+}
+
+/// OverflowPolicy decides what happens to a write when a subscriber's buffer
+/// is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Drop the oldest buffered event to make room, so writes never wait on
+    /// a slow subscriber. Dropped events are counted by
+    /// [`Receiver::dropped`].
+    #[default]
+    DropOldest,
+    /// Block the write until the subscriber makes room. No event is lost,
+    /// but a slow subscriber slows down every write.
+    Backpressure,
+}
+
+struct State {
+    events: VecDeque<ChangeEvent>,
+    dropped: u64,
+    sender_alive: bool,
+    receiver_alive: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+/// Creates a bounded channel of ChangeEvents holding up to `capacity` events
+pub(crate) fn channel(capacity: usize, policy: OverflowPolicy) -> (Sender, Receiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            events: VecDeque::new(),
+            dropped: 0,
+            sender_alive: true,
+            receiver_alive: true,
+        }),
+        capacity: capacity.max(1),
+        policy,
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+/// The sending half of a subscription, held by the Database
+pub(crate) struct Sender {
+    shared: Arc<Shared>,
+}
+
+impl Sender {
+    /// Sends an event following the channel's OverflowPolicy
+    ///
+    /// Returns false once the Receiver has been dropped.
+    pub(crate) fn send(&self, event: ChangeEvent) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        if self.shared.policy == OverflowPolicy::Backpressure {
+            while state.receiver_alive && state.events.len() >= self.shared.capacity {
+                state = self.shared.not_full.wait(state).unwrap();
+            }
+        }
+        if !state.receiver_alive {
+            return false;
+        }
+
+        if state.events.len() >= self.shared.capacity {
+            state.events.pop_front();
+            state.dropped += 1;
+        }
+        state.events.push_back(event);
+        self.shared.not_empty.notify_one();
+        true
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().sender_alive = false;
+        self.shared.not_empty.notify_all();
+    }
+}
+
+/// Receiver yields the ChangeEvents of a subscription created by
+/// [`Database::subscribe`](crate::db::Database::subscribe), in the order of
+/// the writes
+///
+/// Once the Database is dropped, the buffered events can still be received,
+/// after which the Receiver is exhausted.
+pub struct Receiver {
+    shared: Arc<Shared>,
+}
+
+impl Receiver {
+    /// Waits for the next event
+    ///
+    /// Returns None once the Database has been dropped and every buffered
+    /// event has been received.
+    pub fn recv(&self) -> Option<ChangeEvent> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                self.shared.not_full.notify_one();
+                return Some(event);
+            }
+            if !state.sender_alive {
+                return None;
+            }
+            state = self.shared.not_empty.wait(state).unwrap();
+        }
+    }
+
+    /// Returns the next event if one is buffered, without waiting
+    pub fn try_recv(&self) -> Option<ChangeEvent> {
+        let event = self.shared.state.lock().unwrap().events.pop_front();
+        if event.is_some() {
+            self.shared.not_full.notify_one();
+        }
+        event
+    }
+
+    /// Returns the number of events dropped because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        self.shared.state.lock().unwrap().dropped
+    }
+}
+
+impl Iterator for Receiver {
+    type Item = ChangeEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv()
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().receiver_alive = false;
+        self.shared.not_full.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::cdc::{channel, ChangeEvent, OverflowPolicy};
+
+    fn event(timestamp_ms: u128) -> ChangeEvent {
+        ChangeEvent {
+            key: b"Apple".to_vec(),
+            value: Some(b"Apple Smoothie".to_vec()),
+            timestamp_ms,
+            is_deleted: false,
+            is_merge: false,
+        }
+    }
+
+    #[test]
+    fn test_cdc_drop_oldest() {
+        let (sender, receiver) = channel(2, OverflowPolicy::DropOldest);
+        for ts in 0..5 {
+            assert!(sender.send(event(ts)));
+        }
+
+        assert_eq!(receiver.dropped(), 3);
+        assert_eq!(receiver.try_recv(), Some(event(3)));
+        assert_eq!(receiver.try_recv(), Some(event(4)));
+        assert_eq!(receiver.try_recv(), None);
+    }
+
+    #[test]
+    fn test_cdc_backpressure() {
+        let (sender, receiver) = channel(1, OverflowPolicy::Backpressure);
+        let writer = thread::spawn(move || {
+            for ts in 0..100 {
+                assert!(sender.send(event(ts)));
+            }
+        });
+
+        let timestamps: Vec<u128> = receiver.map(|e| e.timestamp_ms).collect();
+        writer.join().unwrap();
+        assert_eq!(timestamps, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_cdc_receiver_dropped() {
+        let (sender, receiver) = channel(1, OverflowPolicy::Backpressure);
+        assert!(sender.send(event(0)));
+        drop(receiver);
+
+        // A full buffer must not block once nobody is listening.
+        assert!(!sender.send(event(1)));
+    }
+
+    #[test]
+    fn test_cdc_sender_dropped() {
+        let (sender, receiver) = channel(4, OverflowPolicy::DropOldest);
+        sender.send(event(0));
+        drop(sender);
+
+        assert_eq!(receiver.recv(), Some(event(0)));
+        assert_eq!(receiver.recv(), None);
+    }
+}
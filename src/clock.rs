@@ -0,0 +1,79 @@
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Clock provides the current time in milliseconds
+///
+/// It is injected into the structures that depend on the time, so tests can
+/// control it.
+pub trait Clock: Send + Sync {
+    fn now_ms(&self) -> u128;
+}
+
+/// Reads the time from the system clock, as milliseconds since the unix epoch
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system clock is before the unix epoch")
+            .as_millis()
+    }
+}
+
+/// A Clock that only moves when told to, for deterministic timestamps in tests
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now_ms: Mutex<u128>,
+}
+
+impl MockClock {
+    /// Creates a MockClock starting at the given time
+    pub fn new(now_ms: u128) -> MockClock {
+        MockClock {
+            now_ms: Mutex::new(now_ms),
+        }
+    }
+
+    /// Sets the current time
+    pub fn set(&self, now_ms: u128) {
+        *self.now_ms.lock().unwrap() = now_ms;
+    }
+
+    /// Moves the current time forward by `ms` milliseconds
+    pub fn advance(&self, ms: u128) {
+        *self.now_ms.lock().unwrap() += ms;
+    }
+}
+
+impl Clock for MockClock {
+    fn now_ms(&self) -> u128 {
+        *self.now_ms.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{Clock, MockClock, SystemClock};
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock::new(10);
+        assert_eq!(clock.now_ms(), 10);
+
+        clock.advance(5);
+        assert_eq!(clock.now_ms(), 15);
+
+        clock.set(3);
+        assert_eq!(clock.now_ms(), 3);
+    }
+
+    #[test]
+    fn test_system_clock() {
+        let first = SystemClock.now_ms();
+        let second = SystemClock.now_ms();
+        assert!(first > 0);
+        assert!(second >= first);
+    }
+}
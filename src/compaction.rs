@@ -0,0 +1,944 @@
+use std::borrow::Borrow;
+use std::ops::Bound;
+use std::path::Path;
+#[cfg(feature = "parallel-compaction")]
+use std::path::PathBuf;
+#[cfg(feature = "parallel-compaction")]
+use std::thread;
+
+use crate::db::{MergeIterator, MergeSource};
+use crate::error::Result;
+use crate::mem_table::{MemTableEntry, RangeTombstone};
+use crate::table::{TableBuilder, TableReader, TableWriter};
+
+/// Default number of Tables a level may hold before the SizeTieredPolicy
+/// compacts it
+pub const DEFAULT_MAX_TABLES: usize = 4;
+
+/// Summary of the Tables of one level, as seen by a CompactionPolicy
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LevelInfo {
+    /// Number of Tables in the level
+    pub table_count: usize,
+    /// Combined size of the Tables' files in bytes
+    pub total_bytes: u64,
+    /// Number of Tables whose key range overlaps the key range of another
+    /// Table of the level
+    pub overlapping_tables: usize,
+}
+
+impl LevelInfo {
+    /// Summarizes the Tables of a level
+    ///
+    /// This reads the last block of every Table to find its key range.
+    pub fn from_tables<T: Borrow<TableReader>>(tables: &[T]) -> Result<LevelInfo> {
+        let mut ranges = Vec::with_capacity(tables.len());
+        for table in tables {
+            ranges.extend(table.borrow().key_range()?);
+        }
+
+        let overlaps = |(a_start, a_end): &(Vec<u8>, Vec<u8>),
+                        (b_start, b_end): &(Vec<u8>, Vec<u8>)| {
+            a_start <= b_end && b_start <= a_end
+        };
+        let overlapping_tables = ranges
+            .iter()
+            .enumerate()
+            .filter(|(i, a)| {
+                ranges
+                    .iter()
+                    .enumerate()
+                    .any(|(j, b)| *i != j && overlaps(a, b))
+            })
+            .count();
+
+        Ok(LevelInfo {
+            table_count: tables.len(),
+            total_bytes: tables.iter().map(|t| t.borrow().file_size()).sum(),
+            overlapping_tables,
+        })
+    }
+}
+
+/// A compaction chosen by a CompactionPolicy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionJob {
+    /// Index of the level to compact
+    pub level: usize,
+    /// Indexes of the Tables of the level to compact, from the oldest to the
+    /// newest
+    pub tables: Vec<usize>,
+}
+
+/// Approximate cost and outcome of a CompactionJob, computed by `estimate`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionEstimate {
+    /// Number of records in the input Tables
+    pub input_entries: u64,
+    /// Combined size of the input Tables' files in bytes
+    pub input_bytes: u64,
+    /// Estimated number of records left once the shadowed ones are dropped
+    pub output_entries: u64,
+    /// Estimated size of the new Table's file in bytes
+    pub output_bytes: u64,
+}
+
+/// Estimates what a CompactionJob would reclaim without running it
+///
+/// `tables` are the Tables of the job's level, from the oldest to the
+/// newest, and indexes of the job past their end are ignored. The records a
+/// Table holds inside the key range of a newer Table of the job are assumed
+/// to be shadowed by it, up to the number of records the newer Table holds
+/// there. Record counts inside a key range are estimated from the block
+/// indexes, and only the last block of every Table is read. Tables don't
+/// count their tombstones, so the tombstones a compaction would drop are not
+/// accounted for.
+pub fn estimate(tables: &[TableReader], job: &CompactionJob) -> Result<CompactionEstimate> {
+    let mut inputs = Vec::with_capacity(job.tables.len());
+    for table in job.tables.iter().filter_map(|&idx| tables.get(idx)) {
+        if let Some(range) = table.key_range()? {
+            inputs.push((table, range));
+        }
+    }
+
+    let mut shadowed = 0.0;
+    for (i, (older, older_range)) in inputs.iter().enumerate() {
+        let overwritten: f64 = inputs[i + 1..]
+            .iter()
+            .map(|(newer, newer_range)| {
+                let older_len = older.len() as f64 * fraction_in(older, older_range, newer_range);
+                let newer_len = newer.len() as f64 * fraction_in(newer, newer_range, older_range);
+                older_len.min(newer_len)
+            })
+            .sum();
+        shadowed += overwritten.min(older.len() as f64);
+    }
+
+    let input_entries: u64 = inputs.iter().map(|(t, _)| t.len() as u64).sum();
+    let input_bytes = inputs.iter().map(|(t, _)| t.file_size()).sum();
+    let output_entries = input_entries - (shadowed.round() as u64).min(input_entries);
+    let output_bytes = match input_entries {
+        0 => 0,
+        _ => (input_bytes as f64 * output_entries as f64 / input_entries as f64) as u64,
+    };
+    Ok(CompactionEstimate {
+        input_entries,
+        input_bytes,
+        output_entries,
+        output_bytes,
+    })
+}
+
+/// Estimates the fraction of the records of a Table that fall inside a key
+/// range, given the smallest and the largest key of the Table
+fn fraction_in(
+    table: &TableReader,
+    (first, last): &(Vec<u8>, Vec<u8>),
+    (start, end): &(Vec<u8>, Vec<u8>),
+) -> f64 {
+    let (start, end) = (start.max(first), end.min(last));
+    if start > end {
+        return 0.0;
+    }
+    let whole = table.approximate_offset(last) - table.approximate_offset(first);
+    if whole == 0 {
+        return 1.0;
+    }
+    let part = table.approximate_offset(end) - table.approximate_offset(start);
+    part as f64 / whole as f64
+}
+
+/// CompactionPolicy decides when to compact and which Tables to merge
+///
+/// The merging itself is done by `compact` and `compact_above`, so a policy
+/// only has to look at the summaries of the levels.
+pub trait CompactionPolicy: Send + Sync {
+    /// Returns the compaction to run next, or None if no compaction is needed
+    fn should_compact(&self, levels: &[LevelInfo]) -> Option<CompactionJob>;
+}
+
+/// SizeTieredPolicy compacts every Table of a level once the level holds
+/// more than a maximum number of Tables
+///
+/// The lowest such level is compacted first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeTieredPolicy {
+    max_tables: usize,
+}
+
+impl Default for SizeTieredPolicy {
+    fn default() -> Self {
+        SizeTieredPolicy::new()
+    }
+}
+
+impl SizeTieredPolicy {
+    /// Creates a SizeTieredPolicy with the default maximum number of Tables
+    pub fn new() -> SizeTieredPolicy {
+        SizeTieredPolicy {
+            max_tables: DEFAULT_MAX_TABLES,
+        }
+    }
+
+    /// Sets the number of Tables a level may hold before it is compacted
+    pub fn with_max_tables(mut self, max_tables: usize) -> SizeTieredPolicy {
+        self.max_tables = max_tables;
+        self
+    }
+}
+
+impl CompactionPolicy for SizeTieredPolicy {
+    fn should_compact(&self, levels: &[LevelInfo]) -> Option<CompactionJob> {
+        let (level, info) = levels
+            .iter()
+            .enumerate()
+            .find(|(_, info)| info.table_count > self.max_tables)?;
+        Some(CompactionJob {
+            level,
+            tables: (0..info.table_count).collect(),
+        })
+    }
+}
+
+/// Compacts several Tables into a single new Table at `out`
+///
+/// The Tables must be ordered from the oldest to the newest. Only the newest
+/// record of each key is kept. The Tables are assumed to hold the oldest data
+/// of the Database, so tombstones have nothing left to shadow and are
+/// dropped as well.
+///
+/// Records are streamed through a merge of the Tables, so the inputs are
+/// never loaded into memory as a whole. Merge records are folded over the
+/// records they apply to by the MergeOperator of the TableWriter, see
+/// `compact_above_with`. The Tables may be given as anything borrowing a
+/// TableReader, such as an `Arc<TableReader>`.
+pub fn compact<T: Borrow<TableReader>>(tables: &[T], out: &Path) -> Result<TableReader> {
+    compact_above::<T>(tables, &[], out)
+}
+
+/// Compacts several Tables into a single new Table at `out`, on top of the
+/// `older` Tables that are not part of the compaction
+///
+/// Both lists must be ordered from the oldest to the newest. Only the newest
+/// record of each key is kept. Tombstones are dropped unless the key they
+/// delete still appears in one of the `older` Tables. Records covered by a
+/// newer Range Tombstone of the Tables are turned into tombstones, and the
+/// Range Tombstones themselves are only kept if there are `older` Tables
+/// they may still apply to.
+pub fn compact_above<T: Borrow<TableReader>>(
+    tables: &[T],
+    older: &[T],
+    out: &Path,
+) -> Result<TableReader> {
+    compact_above_with(&TableWriter::new(), tables, older, out)
+}
+
+/// Compacts like `compact_above`, writing the new Table with the block size,
+/// compression and BloomFilter false positive rate of `options`
+///
+/// Merge records are folded by the MergeOperator of `options`. The merge
+/// records of a key with no other record in the Tables are combined into one
+/// instead if there are `older` Tables, where the value they apply to may
+/// lie. Folding fails with [`Error::InvalidConfig`](crate::error::Error::InvalidConfig)
+/// without a MergeOperator.
+///
+/// Tombstones are also kept until the tombstone grace period of `options`
+/// has passed since their deletion, by the Clock of `options`. If `options`
+/// drops expired records, the records expired by that Clock are turned into
+/// tombstones, so they are dropped entirely unless they still have to hide
+/// the older records of their key.
+pub fn compact_above_with<T: Borrow<TableReader>>(
+    options: &TableWriter,
+    tables: &[T],
+    older: &[T],
+    out: &Path,
+) -> Result<TableReader> {
+    let expected_len = tables.iter().map(|t| t.borrow().len()).sum();
+    let range = (Bound::Unbounded, Bound::Unbounded);
+    match compact_range(options, tables, older, range, expected_len, out)? {
+        Some(table) => Ok(table),
+        // Nothing survived the compaction, the result is an empty Table.
+        None => {
+            TableBuilder::new(options, out, expected_len)?.finish()?;
+            TableReader::open(out)
+        }
+    }
+}
+
+/// Compacts like `compact_above_with`, splitting the key space into up to
+/// `outs.len()` disjoint ranges that are compacted in parallel, one thread
+/// each
+///
+/// The range boundaries are picked evenly among the first keys of the blocks
+/// of the Tables, so the ranges hold a similar number of blocks. The range
+/// written to `outs[i]` lies before the range written to `outs[i + 1]`, and
+/// ranges with no records left are not written at all, so the returned
+/// Tables are ordered by key and don't overlap. Every version of a key falls
+/// into the same range, and tombstones are still checked against every
+/// `older` Table, so the result holds the same records as `compact_above`.
+///
+/// # Panics
+///
+/// Panics if `outs` is empty.
+#[cfg(feature = "parallel-compaction")]
+pub fn compact_parallel(
+    options: &TableWriter,
+    tables: &[TableReader],
+    older: &[TableReader],
+    outs: &[PathBuf],
+) -> Result<Vec<TableReader>> {
+    assert!(
+        !outs.is_empty(),
+        "compact_parallel needs at least one output"
+    );
+
+    let mut keys: Vec<&[u8]> = tables.iter().flat_map(|t| t.block_keys()).collect();
+    keys.sort_unstable();
+    keys.dedup();
+    let mut boundaries: Vec<&[u8]> = (1..outs.len())
+        .map(|i| i * keys.len() / outs.len())
+        .filter(|&i| i > 0 && i < keys.len())
+        .map(|i| keys[i])
+        .collect();
+    boundaries.dedup();
+
+    compact_ranges(options, tables, older, &boundaries, outs)
+}
+
+/// Compacts the ranges split by the sorted `boundaries` in parallel, writing
+/// the range before `boundaries[i]` to `outs[i]`
+#[cfg(feature = "parallel-compaction")]
+fn compact_ranges<T: Borrow<TableReader> + Sync>(
+    options: &TableWriter,
+    tables: &[T],
+    older: &[T],
+    boundaries: &[&[u8]],
+    outs: &[PathBuf],
+) -> Result<Vec<TableReader>> {
+    let starts =
+        std::iter::once(Bound::Unbounded).chain(boundaries.iter().map(|&b| Bound::Included(b)));
+    let ends = boundaries
+        .iter()
+        .map(|&b| Bound::Excluded(b))
+        .chain(std::iter::once(Bound::Unbounded));
+    let expected_len =
+        tables.iter().map(|t| t.borrow().len()).sum::<usize>() / (boundaries.len() + 1) + 1;
+
+    let results: Vec<Result<Option<TableReader>>> = thread::scope(|scope| {
+        let handles: Vec<_> = starts
+            .zip(ends)
+            .zip(outs)
+            .map(|(range, out)| {
+                scope.spawn(move || compact_range(options, tables, older, range, expected_len, out))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("compaction thread panicked"))
+            .collect()
+    });
+
+    results.into_iter().filter_map(Result::transpose).collect()
+}
+
+/// Compacts the records of the Tables with keys between the given bounds
+/// into a new Table at `out`
+///
+/// The Table is only created once a record or Range Tombstone survives the
+/// compaction, so no file is written and None is returned if none does.
+fn compact_range<T: Borrow<TableReader>>(
+    options: &TableWriter,
+    tables: &[T],
+    older: &[T],
+    (start, end): (Bound<&[u8]>, Bound<&[u8]>),
+    expected_len: usize,
+    out: &Path,
+) -> Result<Option<TableReader>> {
+    let sources: Vec<MergeSource> = tables
+        .iter()
+        .rev()
+        .map(|t| Box::new(t.borrow().range(start, end)) as MergeSource)
+        .collect();
+    let range_tombstones: Vec<RangeTombstone> = tables
+        .iter()
+        .flat_map(|t| t.borrow().range_tombstones())
+        .filter(|t| overlaps(t, start, end))
+        .cloned()
+        .collect();
+    let mut merged = MergeIterator::new(sources)
+        .with_range_tombstones(range_tombstones.clone())
+        .with_merge_operator(options.merge_operator().cloned(), !older.is_empty());
+
+    let now_ms = options.clock().now_ms();
+    let in_grace_period = |entry: &MemTableEntry| {
+        now_ms.saturating_sub(entry.timestamp_ms) < options.tombstone_grace_ms()
+    };
+
+    let mut builder = None;
+    if !older.is_empty() && !range_tombstones.is_empty() {
+        let builder = builder.insert(TableBuilder::new(options, out, expected_len)?);
+        for tombstone in &range_tombstones {
+            builder.add_range_tombstone(tombstone);
+        }
+    }
+    while let Some(entry) = merged.next_entry() {
+        let mut entry = entry?;
+        if options.drop_expired() && entry.is_expired(now_ms) {
+            entry.value = None;
+            entry.is_deleted = true;
+            entry.expires_at_ms = None;
+        }
+        if entry.is_deleted && !in_grace_period(&entry) && !shadows_older(&entry.key, older)? {
+            continue;
+        }
+        let builder = match &mut builder {
+            Some(builder) => builder,
+            None => builder.insert(TableBuilder::new(options, out, expected_len)?),
+        };
+        builder.add(&entry)?;
+    }
+
+    match builder {
+        Some(builder) => {
+            builder.finish()?;
+            Ok(Some(TableReader::open(out)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Returns true if the Range Tombstone covers a key between the bounds
+fn overlaps(tombstone: &RangeTombstone, start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+    let after_start = match start {
+        Bound::Included(b) | Bound::Excluded(b) => tombstone.end.as_slice() > b,
+        Bound::Unbounded => true,
+    };
+    let before_end = match end {
+        Bound::Included(b) => tombstone.start.as_slice() <= b,
+        Bound::Excluded(b) => tombstone.start.as_slice() < b,
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+/// Returns true if any of the older Tables has a record of the key
+fn shadows_older<T: Borrow<TableReader>>(key: &[u8], older: &[T]) -> Result<bool> {
+    for table in older {
+        if table.borrow().get(key)?.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use std::sync::Arc;
+
+    use crate::clock::MockClock;
+    use crate::compaction::{
+        compact, compact_above, compact_above_with, estimate, CompactionJob, CompactionPolicy,
+        LevelInfo, SizeTieredPolicy,
+    };
+    #[cfg(feature = "parallel-compaction")]
+    use crate::compaction::{compact_parallel, compact_ranges};
+    use crate::mem_table::MemTable;
+    #[cfg(feature = "parallel-compaction")]
+    use crate::mem_table::MemTableEntry;
+    use crate::merge::{self, MergeOperator};
+    use crate::table::{TableReader, TableWriter};
+
+    fn write_table(dir: &std::path::Path, name: &str, table: &MemTable) -> TableReader {
+        let path = dir.join(name);
+        TableWriter::new().flush(table, &path).unwrap();
+        TableReader::open(&path).unwrap()
+    }
+
+    #[test]
+    fn test_compact_range_tombstones() {
+        let dir = tempdir().unwrap();
+
+        let mut first = MemTable::new();
+        first.set_at(b"Apple", b"Apple Smoothie", 0);
+        first.set_at(b"Lime", b"Lime Smoothie", 0);
+        let first = write_table(dir.path(), "0.sst", &first);
+
+        let mut second = MemTable::new();
+        second.set_at(b"Banana", b"Banana Smoothie", 10);
+        second.set_at(b"Orange", b"Orange Smoothie", 10);
+        let second = write_table(dir.path(), "1.sst", &second);
+
+        let mut third = MemTable::new();
+        third.delete_range_at(b"Apple", b"Orange", 20);
+        third.set_at(b"Banana", b"Banana Pie", 30);
+        let third = write_table(dir.path(), "2.sst", &third);
+
+        // Records covered by the Range Tombstone are dropped along with it.
+        let tables = [first, second, third];
+        let out = compact(&tables, &dir.path().join("3.sst")).unwrap();
+        assert!(out.range_tombstones().is_empty());
+        let keys: Vec<Vec<u8>> = out.iter().map(|e| e.unwrap().key).collect();
+        assert_eq!(keys, vec![b"Banana".to_vec(), b"Orange".to_vec()]);
+
+        // On top of older Tables, the Range Tombstone is kept.
+        let out = compact_above(&tables[1..], &tables[..1], &dir.path().join("4.sst")).unwrap();
+        assert_eq!(out.range_tombstones(), tables[2].range_tombstones());
+        assert_eq!(
+            out.get(b"Banana").unwrap().unwrap().value,
+            Some(b"Banana Pie".to_vec())
+        );
+        assert!(out.get(b"Orange").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_compact_keeps_newest() {
+        let dir = tempdir().unwrap();
+
+        let mut first = MemTable::new();
+        first.set_at(b"Apple", b"Apple Smoothie", 0);
+        first.set_at(b"Lime", b"Lime Smoothie", 0);
+        first.set_at(b"Orange", b"Orange Smoothie", 0);
+        let first = write_table(dir.path(), "0.sst", &first);
+
+        let mut second = MemTable::new();
+        second.set_at(b"Apple", b"Apple Pie", 10);
+        second.delete_at(b"Lime", 10);
+        second.set_at(b"Peach", b"Peach Smoothie", 10);
+        let second = write_table(dir.path(), "1.sst", &second);
+
+        let out = compact(&[first, second], &dir.path().join("2.sst")).unwrap();
+        let entries = out.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+
+        let keys: Vec<&[u8]> = entries.iter().map(|e| e.key.as_slice()).collect();
+        assert_eq!(keys, vec![&b"Apple"[..], b"Orange", b"Peach"]);
+        assert_eq!(entries[0].value.as_ref().unwrap(), b"Apple Pie");
+        assert_eq!(entries[0].timestamp_ms, 10);
+        assert_eq!(out.len(), 3);
+    }
+
+    #[test]
+    fn test_compact_value_runs() {
+        let dir = tempdir().unwrap();
+
+        // The second Table overwrites the first half of the keys, leaving two
+        // runs of equal Values.
+        let mut first = MemTable::new();
+        let mut second = MemTable::new();
+        for i in 0..500u32 {
+            let key = format!("key-{:04}", i);
+            first.set_at(key.as_bytes(), b"Apple Smoothie", 0);
+            if i < 250 {
+                second.set_at(key.as_bytes(), b"Lime Smoothie", 10);
+            }
+        }
+        second.delete_at(b"key-0300", 10);
+        let tables = [
+            write_table(dir.path(), "0.sst", &first),
+            write_table(dir.path(), "1.sst", &second),
+        ];
+
+        let options = TableWriter::new().with_value_runs(true);
+        let plain = compact(&tables, &dir.path().join("2.sst")).unwrap();
+        let runs = compact_above_with(&options, &tables, &[], &dir.path().join("3.sst")).unwrap();
+        assert!(runs.file_size() < plain.file_size());
+
+        let plain = plain.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+        let runs = runs.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+        assert_eq!(runs, plain);
+        assert_eq!(runs.len(), 499);
+        assert_eq!(runs[249].value.as_ref().unwrap(), b"Lime Smoothie");
+        assert_eq!(runs[250].value.as_ref().unwrap(), b"Apple Smoothie");
+    }
+
+    #[test]
+    fn test_compact_above_keeps_shadowing_tombstones() {
+        let dir = tempdir().unwrap();
+
+        let mut oldest = MemTable::new();
+        oldest.set_at(b"Lime", b"Lime Smoothie", 0);
+        let oldest = write_table(dir.path(), "0.sst", &oldest);
+
+        let mut first = MemTable::new();
+        first.set_at(b"Apple", b"Apple Smoothie", 10);
+        let first = write_table(dir.path(), "1.sst", &first);
+
+        let mut second = MemTable::new();
+        second.delete_at(b"Apple", 20);
+        second.delete_at(b"Lime", 20);
+        let second = write_table(dir.path(), "2.sst", &second);
+
+        let out = compact_above(&[first, second], &[oldest], &dir.path().join("3.sst")).unwrap();
+        let entries = out.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+
+        // The Apple tombstone only shadowed data inside the compaction, but
+        // the Lime tombstone still has to hide the oldest Table.
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, b"Lime");
+        assert!(entries[0].is_deleted);
+    }
+
+    #[test]
+    fn test_compact_tombstone_grace_period() {
+        let dir = tempdir().unwrap();
+
+        let mut oldest = MemTable::new();
+        oldest.set_at(b"Lime", b"Lime Smoothie", 0);
+        let oldest = write_table(dir.path(), "0.sst", &oldest);
+
+        let mut first = MemTable::new();
+        first.set_at(b"Apple", b"Apple Smoothie", 10);
+        let first = write_table(dir.path(), "1.sst", &first);
+
+        let mut second = MemTable::new();
+        second.delete_at(b"Apple", 20);
+        second.delete_at(b"Lime", 20);
+        let second = write_table(dir.path(), "2.sst", &second);
+
+        let clock = Arc::new(MockClock::new(50));
+        let options = TableWriter::new()
+            .with_tombstone_grace_period(100)
+            .with_clock(clock.clone());
+        let tables = [first, second];
+        let older = [oldest];
+        let keys = |out: &TableReader| -> Vec<Vec<u8>> {
+            out.iter()
+                .map(|e| {
+                    let e = e.unwrap();
+                    assert!(e.is_deleted);
+                    e.key
+                })
+                .collect()
+        };
+
+        // Both tombstones are within the grace period.
+        let out = compact_above_with(&options, &tables, &older, &dir.path().join("3.sst")).unwrap();
+        assert_eq!(keys(&out), [b"Apple".to_vec(), b"Lime".to_vec()]);
+
+        // Once it has passed, the Lime tombstone still has to hide the Table
+        // left out of the compaction, so Lime is not resurrected.
+        clock.set(120);
+        let out = compact_above_with(&options, &tables, &older, &dir.path().join("4.sst")).unwrap();
+        assert_eq!(keys(&out), [b"Lime".to_vec()]);
+    }
+
+    #[test]
+    fn test_compact_drop_expired() {
+        let dir = tempdir().unwrap();
+
+        let mut oldest = MemTable::new();
+        oldest.set_at(b"Lime", b"Lime Smoothie", 0);
+        let oldest = write_table(dir.path(), "0.sst", &oldest);
+
+        let mut first = MemTable::new().with_clock(Arc::new(MockClock::new(0)));
+        first.set_with_ttl(b"Apple", b"Apple Smoothie", 10, 50);
+        first.set_with_ttl(b"Banana", b"Banana Smoothie", 10, 500);
+        first.set_with_ttl(b"Lime", b"Lime Pie", 10, 50);
+        first.set_at(b"Orange", b"Orange Smoothie", 10);
+        let first = write_table(dir.path(), "1.sst", &first);
+
+        let mut second = MemTable::new().with_clock(Arc::new(MockClock::new(0)));
+        second.set_with_ttl(b"Cherry", b"Cherry Pie", 20, 50);
+        let second = write_table(dir.path(), "2.sst", &second);
+
+        let options = TableWriter::new().with_clock(Arc::new(MockClock::new(100)));
+        let tables = [first, second];
+        let older = [oldest];
+
+        // Expired records are carried forward by default.
+        let out = compact_above_with(&options, &tables, &older, &dir.path().join("3.sst")).unwrap();
+        assert_eq!(out.len(), 5);
+
+        let options = options.with_drop_expired(true);
+        let out = compact_above_with(&options, &tables, &older, &dir.path().join("4.sst")).unwrap();
+        let entries = out.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+        let keys: Vec<_> = entries.iter().map(|e| e.key.as_slice()).collect();
+        assert_eq!(keys, [&b"Banana"[..], b"Lime", b"Orange"]);
+        assert!(!entries[0].is_deleted);
+        // The expired Lime record still hides the Table left out of the
+        // compaction.
+        assert!(entries[1].is_deleted);
+        assert!(!entries[2].is_deleted);
+    }
+
+    struct AppendOperator;
+
+    impl MergeOperator for AppendOperator {
+        fn full_merge(&self, existing: Option<&[u8]>, operands: &[Vec<u8>]) -> Option<Vec<u8>> {
+            let mut value = existing.unwrap_or_default().to_vec();
+            operands.iter().for_each(|o| value.extend_from_slice(o));
+            Some(value)
+        }
+    }
+
+    #[test]
+    fn test_compact_merges() {
+        let dir = tempdir().unwrap();
+        let options = TableWriter::new().with_merge_operator(Arc::new(AppendOperator));
+
+        let mut oldest = MemTable::new();
+        oldest.set_at(b"Apple", b"Apple", 0);
+        let oldest = write_table(dir.path(), "0.sst", &oldest);
+
+        let mut first = MemTable::new();
+        first.merge_at(b"Apple", b" Smoothie", 10).unwrap();
+        first.merge_at(b"Lime", b"Lime", 10).unwrap();
+        let first = write_table(dir.path(), "1.sst", &first);
+
+        let mut second = MemTable::new();
+        second.merge_at(b"Apple", b" Bowl", 20).unwrap();
+        second.set_at(b"Lime", b"Lime Pie", 20);
+        let second = write_table(dir.path(), "2.sst", &second);
+
+        // The value of Apple may be in the older Table, so its operands are
+        // only combined, while Lime has a newer value.
+        let tables = [oldest, first, second];
+        let path = dir.path().join("3.sst");
+        let out = compact_above_with(&options, &tables[1..], &tables[..1], &path).unwrap();
+        let apple = out.get(b"Apple").unwrap().unwrap();
+        assert!(apple.is_merge);
+        assert_eq!(apple.timestamp_ms, 20);
+        assert_eq!(
+            merge::decode_operands(&apple.value.unwrap()).unwrap(),
+            vec![b" Smoothie".to_vec(), b" Bowl".to_vec()]
+        );
+        assert_eq!(
+            out.get(b"Lime").unwrap().unwrap().value.unwrap(),
+            b"Lime Pie"
+        );
+
+        let out = compact_above_with(&options, &tables, &[], &dir.path().join("4.sst")).unwrap();
+        let apple = out.get(b"Apple").unwrap().unwrap();
+        assert!(!apple.is_merge);
+        assert_eq!(apple.value.unwrap(), b"Apple Smoothie Bowl");
+
+        // Folding needs a MergeOperator.
+        assert!(compact(&tables, &dir.path().join("5.sst")).is_err());
+    }
+
+    #[test]
+    fn test_estimate() {
+        let dir = tempdir().unwrap();
+        let writer = TableWriter::new().with_block_size(256);
+        let write = |name: &str, keys: std::ops::Range<u64>, timestamp_ms| {
+            let mut table = MemTable::new();
+            for n in keys {
+                table.set_at(&n.to_be_bytes(), b"Smoothie", timestamp_ms);
+            }
+            let path = dir.path().join(name);
+            writer.flush(&table, &path).unwrap();
+            TableReader::open(&path).unwrap()
+        };
+        // The second Table overwrites half of the first one, the third one
+        // overlaps neither.
+        let tables = [
+            write("0.sst", 0..1000, 0),
+            write("1.sst", 500..1500, 10),
+            write("2.sst", 2000..2500, 20),
+        ];
+
+        let job = |tables: Vec<usize>| CompactionJob { level: 0, tables };
+        let overlapping = estimate(&tables, &job(vec![0, 1])).unwrap();
+        assert_eq!(overlapping.input_entries, 2000);
+        assert_eq!(
+            overlapping.input_bytes,
+            tables[0].file_size() + tables[1].file_size()
+        );
+        assert!(overlapping.output_entries.abs_diff(1500) <= 75);
+        assert!(overlapping.output_bytes < overlapping.input_bytes);
+
+        let disjoint = estimate(&tables, &job(vec![0, 2])).unwrap();
+        assert_eq!(disjoint.input_entries, 1500);
+        assert_eq!(disjoint.output_entries, 1500);
+        assert_eq!(disjoint.output_bytes, disjoint.input_bytes);
+
+        let all = estimate(&tables, &job(vec![0, 1, 2, 3])).unwrap();
+        assert_eq!(all.input_entries, 2500);
+        assert!(all.output_entries.abs_diff(2000) <= 75);
+    }
+
+    #[test]
+    fn test_level_info() {
+        let dir = tempdir().unwrap();
+
+        let mut first = MemTable::new();
+        first.set_at(b"Apple", b"Apple Smoothie", 0);
+        first.set_at(b"Lime", b"Lime Smoothie", 0);
+        let first = write_table(dir.path(), "0.sst", &first);
+
+        let mut second = MemTable::new();
+        second.set_at(b"Banana", b"Banana Smoothie", 10);
+        second.set_at(b"Orange", b"Orange Smoothie", 10);
+        let second = write_table(dir.path(), "1.sst", &second);
+
+        let mut third = MemTable::new();
+        third.set_at(b"Peach", b"Peach Smoothie", 20);
+        let third = write_table(dir.path(), "2.sst", &third);
+
+        let tables = [first, second, third];
+        let info = LevelInfo::from_tables(&tables).unwrap();
+        assert_eq!(info.table_count, 3);
+        assert_eq!(
+            info.total_bytes,
+            tables.iter().map(|t| t.file_size()).sum::<u64>()
+        );
+        // Apple..Lime and Banana..Orange overlap, Peach stands alone.
+        assert_eq!(info.overlapping_tables, 2);
+
+        assert_eq!(
+            LevelInfo::from_tables::<TableReader>(&[]).unwrap(),
+            LevelInfo::default()
+        );
+    }
+
+    #[test]
+    fn test_size_tiered_policy() {
+        let level = |table_count| LevelInfo {
+            table_count,
+            ..LevelInfo::default()
+        };
+        let policy = SizeTieredPolicy::new().with_max_tables(2);
+
+        assert_eq!(policy.should_compact(&[]), None);
+        assert_eq!(policy.should_compact(&[level(2), level(1)]), None);
+        assert_eq!(
+            policy.should_compact(&[level(1), level(3), level(4)]),
+            Some(CompactionJob {
+                level: 1,
+                tables: vec![0, 1, 2],
+            })
+        );
+        assert_eq!(
+            SizeTieredPolicy::default().should_compact(&[level(4)]),
+            None
+        );
+    }
+
+    #[cfg(feature = "parallel-compaction")]
+    fn records(tables: &[TableReader]) -> Vec<MemTableEntry> {
+        tables
+            .iter()
+            .flat_map(|t| t.iter().map(Result::unwrap))
+            .collect()
+    }
+
+    #[cfg(feature = "parallel-compaction")]
+    fn numbered_tables(dir: &std::path::Path, count: u32) -> (Vec<TableReader>, TableReader) {
+        let options = TableWriter::new().with_block_size(256);
+        let key = |i: u32| format!("key-{:06}", i).into_bytes();
+
+        let mut older = MemTable::new();
+        older.set_at(&key(count / 2), b"Smoothie", 0);
+        let older_path = dir.join("older.sst");
+        options.flush(&older, &older_path).unwrap();
+
+        let mut first = MemTable::new();
+        let mut second = MemTable::new();
+        for i in 0..count {
+            first.set_at(&key(i), b"Smoothie", 10);
+            match i % 7 {
+                0 => second.delete_at(&key(i), 20),
+                3 => second.set_at(&key(i), b"Pie", 20),
+                _ => None,
+            };
+        }
+        // Tombstones right at the boundaries, one of them shadowing `older`.
+        second.delete_at(&key(count / 4), 20);
+        second.delete_at(&key(count / 2), 20);
+
+        let mut tables = Vec::new();
+        for (name, table) in [("0.sst", &first), ("1.sst", &second)] {
+            options.flush(table, &dir.join(name)).unwrap();
+            tables.push(TableReader::open(&dir.join(name)).unwrap());
+        }
+        (tables, TableReader::open(&older_path).unwrap())
+    }
+
+    #[test]
+    #[cfg(feature = "parallel-compaction")]
+    fn test_compact_ranges_boundaries() {
+        let dir = tempdir().unwrap();
+        let (tables, older) = numbered_tables(dir.path(), 1000);
+        let older = [older];
+        let options = TableWriter::new();
+
+        let single = compact_above(&tables, &older, &dir.path().join("single.sst")).unwrap();
+
+        let outs: Vec<_> = (0..3)
+            .map(|i| dir.path().join(format!("out-{}.sst", i)))
+            .collect();
+        let boundaries: [&[u8]; 2] = [b"key-000250", b"key-000500"];
+        let outputs = compact_ranges(&options, &tables, &older, &boundaries, &outs).unwrap();
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(records(&outputs), records(&[single]));
+
+        let ranges: Vec<_> = outputs
+            .iter()
+            .map(|t| t.key_range().unwrap().unwrap())
+            .collect();
+        assert!(ranges[0].1.as_slice() < boundaries[0]);
+        assert!(ranges[1].0.as_slice() > boundaries[0]);
+        assert!(ranges[1].1.as_slice() < boundaries[1]);
+        // The tombstone shadowing `older` starts the last range.
+        assert_eq!(ranges[2].0, boundaries[1]);
+        assert!(outputs[2].get(boundaries[1]).unwrap().unwrap().is_deleted);
+
+        // Ranges with nothing left are not written.
+        let boundaries: [&[u8]; 2] = [b"a", b"b"];
+        let outputs = compact_ranges(&options, &tables, &older, &boundaries, &outs).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].path(), outs[2]);
+    }
+
+    #[test]
+    #[cfg(feature = "parallel-compaction")]
+    fn test_compact_parallel() {
+        let dir = tempdir().unwrap();
+        let (tables, older) = numbered_tables(dir.path(), 1000);
+        let older = [older];
+        let options = TableWriter::new();
+
+        let single = compact_above(&tables, &older, &dir.path().join("single.sst")).unwrap();
+        for partitions in [1, 4, 10_000] {
+            let outs: Vec<_> = (0..partitions)
+                .map(|i| dir.path().join(format!("{}-{}.sst", partitions, i)))
+                .collect();
+            let outputs = compact_parallel(&options, &tables, &older, &outs).unwrap();
+            assert!(outputs.len() <= partitions);
+            assert_eq!(records(&outputs), records(std::slice::from_ref(&single)));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "parallel-compaction")]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn bench_compact_parallel() {
+        use std::time::Instant;
+
+        let dir = tempdir().unwrap();
+        let (tables, older) = numbered_tables(dir.path(), 500_000);
+        let older = [older];
+        let options = TableWriter::new();
+
+        let start = Instant::now();
+        let single =
+            compact_above_with(&options, &tables, &older, &dir.path().join("single.sst")).unwrap();
+        let single_elapsed = start.elapsed();
+
+        let outs: Vec<_> = (0..4)
+            .map(|i| dir.path().join(format!("out-{}.sst", i)))
+            .collect();
+        let start = Instant::now();
+        let outputs = compact_parallel(&options, &tables, &older, &outs).unwrap();
+        let parallel_elapsed = start.elapsed();
+
+        assert_eq!(records(&outputs), records(&[single]));
+        println!(
+            "single: {:?}, parallel ({} threads): {:?}",
+            single_elapsed,
+            outs.len(),
+            parallel_elapsed
+        );
+    }
+}
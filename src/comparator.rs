@@ -0,0 +1,22 @@
+use std::cmp::Ordering;
+
+/// Comparator defines the order of keys in MemTables and Tables
+///
+/// The same Comparator must be used for every structure holding the same
+/// keys, as their sorted layout depends on it. A Comparator must be a total
+/// order, keys comparing as [`Ordering::Equal`] are treated as the same key.
+pub trait Comparator {
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering;
+}
+
+/// Orders keys lexicographically by their bytes
+///
+/// This is the default Comparator.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BytewiseComparator;
+
+impl Comparator for BytewiseComparator {
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        a.cmp(b)
+    }
+}
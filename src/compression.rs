@@ -0,0 +1,160 @@
+/// Compression is the codec used for the blocks of a Table
+///
+/// Each block stores the id of its codec, so a Table can mix compressed and
+/// uncompressed blocks and readers don't need to be told the codec.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Blocks are stored as is
+    #[default]
+    None,
+    /// Blocks are compressed with the LZ4 block format, prefixed with their
+    /// uncompressed length as a little endian u32
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// Blocks are compressed as Zstandard frames at the default level
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Gets the id stored in the header of a block written with the codec
+    pub fn id(&self) -> u8 {
+        match self {
+            Compression::None => 0,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => 1,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => 2,
+        }
+    }
+
+    /// Gets the codec of a block from the id in its header
+    ///
+    /// Returns None for unknown ids and for codecs whose feature is disabled.
+    pub fn from_id(id: u8) -> Option<Compression> {
+        match id {
+            0 => Some(Compression::None),
+            #[cfg(feature = "lz4")]
+            1 => Some(Compression::Lz4),
+            #[cfg(feature = "zstd")]
+            2 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Compresses the bytes of a block
+    pub fn compress(&self, input: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => input.to_vec(),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_flex::block::compress_prepend_size(input),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::bulk::compress(input, zstd::DEFAULT_COMPRESSION_LEVEL)
+                .expect("compressing into a growable buffer does not fail"),
+        }
+    }
+
+    /// Decompresses the bytes of a block, returning None if they are not
+    /// valid for the codec
+    pub fn decompress(&self, input: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Compression::None => Some(input.to_vec()),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_flex::block::decompress_size_prepended(input).ok(),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::stream::decode_all(input).ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compression::Compression;
+
+    /// Returns JSON documents, which compress well, along with edge cases
+    #[cfg(any(feature = "lz4", feature = "zstd"))]
+    fn inputs() -> (Vec<u8>, Vec<Vec<u8>>) {
+        let json: Vec<u8> = (0..200)
+            .flat_map(|i| {
+                format!(
+                    "{{\"id\":{},\"name\":\"Smoothie\",\"fruits\":[\"Apple\"]}}",
+                    i
+                )
+                .into_bytes()
+            })
+            .collect();
+        let inputs = vec![
+            Vec::new(),
+            b"Apple".to_vec(),
+            vec![b'a'; 1000],
+            (0..=255u8).cycle().take(5000).collect(),
+            json.clone(),
+        ];
+        (json, inputs)
+    }
+
+    /// Checks that the codec round-trips every input and shrinks JSON
+    #[cfg(any(feature = "lz4", feature = "zstd"))]
+    fn check_round_trip(compression: Compression) {
+        let (json, inputs) = inputs();
+        for input in &inputs {
+            let compressed = compression.compress(input);
+            assert_eq!(&compression.decompress(&compressed).unwrap(), input);
+        }
+
+        let compressed = compression.compress(&json);
+        assert!(compressed.len() * 4 < json.len());
+        assert_eq!(Compression::from_id(compression.id()), Some(compression));
+
+        // Cutting off the end of the compressed bytes is detected.
+        assert_eq!(
+            compression.decompress(&compressed[..compressed.len() - 1]),
+            None
+        );
+        assert_eq!(compression.decompress(&compressed[..1]), None);
+    }
+
+    #[test]
+    fn test_compression_none() {
+        let input = b"Apple Smoothie".to_vec();
+        assert_eq!(Compression::None.compress(&input), input);
+        assert_eq!(Compression::None.decompress(&input).unwrap(), input);
+        assert_eq!(
+            Compression::from_id(Compression::None.id()),
+            Some(Compression::None)
+        );
+        assert_eq!(Compression::from_id(200), None);
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn test_compression_lz4() {
+        check_round_trip(Compression::Lz4);
+
+        // The block format as written by the reference implementation: a
+        // token of 5 literals and no match, after the uncompressed length.
+        let reference = [5, 0, 0, 0, 0x50, b'A', b'p', b'p', b'l', b'e'];
+        assert_eq!(Compression::Lz4.decompress(&reference).unwrap(), b"Apple");
+        assert_eq!(Compression::Lz4.compress(b"Apple"), reference);
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn test_compression_lz4_invalid() {
+        // A match reaching before the start of the output.
+        assert_eq!(
+            Compression::Lz4.decompress(&[5, 0, 0, 0, 0x10, b'a', 0x05, 0x00]),
+            None
+        );
+        // Literals running past the end of the input.
+        assert_eq!(Compression::Lz4.decompress(&[5, 0, 0, 0, 0x50, b'a']), None);
+        assert_eq!(Compression::Lz4.decompress(&[]), None);
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_compression_zstd() {
+        check_round_trip(Compression::Zstd);
+        assert_eq!(Compression::Zstd.decompress(b"Apple Smoothie"), None);
+    }
+}
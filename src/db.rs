@@ -0,0 +1,4431 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::bloom::{PrefixExtractor, DEFAULT_FP_RATE};
+use crate::cache::BlockCache;
+use crate::cdc::{self, ChangeEvent, OverflowPolicy, Receiver, Sender};
+use crate::compaction::{self, CompactionPolicy, LevelInfo};
+use crate::comparator::{BytewiseComparator, Comparator};
+use crate::compression::Compression;
+use crate::error::{Error, Result};
+use crate::flush::{FlushWorker, WriteBufferManager};
+use crate::layout::{Layout, Lock, Paths};
+use crate::manifest::Manifest;
+use crate::mem_table::{
+    self, ImmutableMemTable, MemTable, MemTableEntry, Operation, RangeTombstone,
+    DEFAULT_MAX_KEY_LEN, DEFAULT_MAX_SIZE, DEFAULT_MAX_VALUE_LEN,
+};
+use crate::merge::{self, MergeOperator};
+use crate::metrics::{Counters, Metrics, Profile};
+use crate::table::{TableReader, TableWriter, DEFAULT_BLOCK_SIZE, DEFAULT_LARGE_VALUE_THRESHOLD};
+use crate::verify::{Problem, ProblemKind, VerifyReport};
+use crate::wal::{SyncPolicy, WriteAheadLog};
+
+/// Fraction of tombstones in a full MemTable above which dropping them is
+/// tried before flushing
+const TOMBSTONE_GC_RATIO: f64 = 0.5;
+
+/// Database ties together the MemTable, the WAL and the on-disk Tables
+///
+/// The live Tables are recorded in a [`Manifest`], which is replaced
+/// atomically whenever a flush or a compaction changes them.
+///
+/// Writes go to the WAL first and then to the MemTable. Once the MemTable
+/// is full it is frozen, and writes move on to a fresh MemTable and a new WAL
+/// segment. The frozen MemTable is then written to a new Table, on a
+/// background thread if [`DatabaseBuilder::with_background_flush`] is set,
+/// after which the segments that backed it are deleted.
+///
+/// Reads check the MemTable first, then the frozen MemTables still being
+/// flushed from the newest to the oldest, and then the Tables from the
+/// newest to the oldest. The first of them holding a record of the key
+/// decides, so a tombstone hides every older value of its key and the latest
+/// write of a key always wins.
+/// Merges are the exception, their operands are folded over the older
+/// records of the key by the [`MergeOperator`].
+///
+/// Every write is stamped with a strictly increasing timestamp, which is what
+/// [`Snapshot`]s are based on.
+pub struct Database {
+    paths: Paths,
+    /// Claim on the files of the Database, unless it was opened read-only
+    _lock: Option<Lock>,
+    /// The MemTable written to, shared with the Cursors pinning it
+    mem_table: Arc<MemTable>,
+    /// MemTables frozen by flushes that haven't written them to Tables yet,
+    /// from the oldest to the newest
+    frozen: Vec<Arc<ImmutableMemTable>>,
+    /// The WAL segment appended to, or None if the Database was opened
+    /// read-only or in memory
+    wal: Option<WriteAheadLog>,
+    /// Whether the Database keeps everything in memory, see
+    /// [`Database::in_memory`]
+    in_memory: bool,
+    /// Ids of the WAL segments backing the MemTable, from the oldest to the
+    /// newest. The newest one is the segment appended to.
+    wal_ids: Vec<u64>,
+    /// Ids of the WAL segments backing each frozen MemTable
+    frozen_wal_ids: Vec<Vec<u64>>,
+    /// Tables ordered from the oldest to the newest, shared with the Cursors
+    /// pinning them
+    tables: Vec<Arc<TableReader>>,
+    next_table_id: u64,
+    last_timestamp_ms: u128,
+    /// Timestamps of the Snapshots taken, from the oldest to the newest. The
+    /// MemTable keeps the versions that the live ones may read.
+    snapshots: Mutex<Vec<Weak<u128>>>,
+    subscribers: Vec<Sender>,
+    write_hooks: Vec<WriteHook>,
+    sync_policy: SyncPolicy,
+    table_writer: TableWriter,
+    compaction_policy: Option<Box<dyn CompactionPolicy>>,
+    block_cache: Option<Arc<BlockCache>>,
+    merge_operator: Option<Arc<dyn MergeOperator>>,
+    /// Worker writing full MemTables to Tables, if flushes run in the
+    /// background
+    flush_worker: Option<FlushWorker>,
+    /// Number of frozen MemTables, the oldest ones, handed to the flush
+    /// worker
+    background_flushes: usize,
+    write_buffer: WriteBufferManager,
+    /// Counters of the Metrics. The bytes appended to the current WAL
+    /// segment are only added once the segment is closed.
+    metrics: Counters,
+}
+
+/// A page of Key-Value pairs returned by [`Database::scan`], along with the
+/// key the next page starts from, if any
+pub type Page = (Vec<(Vec<u8>, Vec<u8>)>, Option<Vec<u8>>);
+
+/// A prefix and the Key-Value pairs with keys starting with it, yielded by
+/// [`Database::group_by_prefix`]
+pub type Group = (Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>);
+
+/// A callback registered with [`Database::on_write`], returning the
+/// Operations to write along with a write
+pub type WriteHook = Box<dyn FnMut(&ChangeEvent) -> Vec<Operation>>;
+
+/// Snapshot is a point in time view of the Database, created by
+/// [`Database::snapshot`]
+///
+/// Reads through a Snapshot ignore every write made after it was taken, so
+/// they are repeatable even while writes continue. Until the Snapshot and
+/// its clones are dropped, the MemTable keeps the versions they read of the
+/// keys written again, and flushes write them to the Tables.
+///
+/// Unlike Cursors, Snapshots don't pin the Tables, so a compaction running
+/// after the Snapshot was taken may drop the versions it reads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Shared with the Database, which keeps a weak reference to tell the
+    /// live Snapshots apart
+    timestamp_ms: Arc<u128>,
+}
+
+impl Snapshot {
+    /// Returns the timestamp of the newest write visible in the Snapshot
+    pub fn timestamp_ms(&self) -> u128 {
+        *self.timestamp_ms
+    }
+}
+
+/// Cursor moves back and forth over the live Key-Value pairs of the
+/// Database, created by [`Database::cursor`]
+///
+/// A Cursor is either positioned on a pair or unpositioned, such as before
+/// the first seek or after moving past either end. Every move merges the
+/// MemTables and the Tables from the current key, each of them finding the
+/// key by binary search, so shadowed records, tombstones and records covered
+/// by Range Tombstones are skipped like they are by `range`.
+///
+/// A Cursor doesn't borrow the Database. It pins the MemTables and the
+/// Tables as they were when it was created, so it keeps seeing the same
+/// pairs while the Database is written to, flushed and compacted. The first
+/// write to the pinned MemTable copies it instead, and the pinned Tables
+/// stay open, so they are still readable once a compaction deletes their
+/// files. Drop Cursors once done with them to release what they pin.
+pub struct Cursor {
+    mem_table: Arc<MemTable>,
+    frozen: Vec<Arc<ImmutableMemTable>>,
+    tables: Vec<Arc<TableReader>>,
+    merge_operator: Option<Arc<dyn MergeOperator>>,
+    current: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Cursor {
+    /// Positions the Cursor on the first pair with a key greater than or
+    /// equal to `key`, if any
+    pub fn seek(&mut self, key: &[u8]) -> Result<()> {
+        self.current = self.sources().first_after(Bound::Included(key))?;
+        Ok(())
+    }
+
+    /// Positions the Cursor on the first pair of the Database, if any
+    pub fn seek_to_first(&mut self) -> Result<()> {
+        self.current = self.sources().first_after(Bound::Unbounded)?;
+        Ok(())
+    }
+
+    /// Positions the Cursor on the last pair of the Database, if any
+    pub fn seek_to_last(&mut self) -> Result<()> {
+        self.current = self.sources().last_before(Bound::Unbounded)?;
+        Ok(())
+    }
+
+    /// Moves the Cursor to the next pair
+    ///
+    /// Moving past the last pair leaves the Cursor unpositioned, and an
+    /// unpositioned Cursor doesn't move.
+    // Unlike `Iterator::next`, this moves the Cursor rather than yielding.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<()> {
+        if let Some((key, _)) = &self.current {
+            self.current = self.sources().first_after(Bound::Excluded(key))?;
+        }
+        Ok(())
+    }
+
+    /// Moves the Cursor to the previous pair
+    ///
+    /// Moving past the first pair leaves the Cursor unpositioned, and an
+    /// unpositioned Cursor doesn't move.
+    pub fn prev(&mut self) -> Result<()> {
+        if let Some((key, _)) = &self.current {
+            self.current = self.sources().last_before(Bound::Excluded(key))?;
+        }
+        Ok(())
+    }
+
+    pub fn is_positioned(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Returns the key the Cursor is positioned on
+    pub fn key(&self) -> Option<&[u8]> {
+        self.current.as_ref().map(|(key, _)| key.as_slice())
+    }
+
+    /// Returns the value of the key the Cursor is positioned on
+    pub fn value(&self) -> Option<&[u8]> {
+        self.current.as_ref().map(|(_, value)| value.as_slice())
+    }
+
+    fn sources(&self) -> Sources<'_> {
+        Sources {
+            mem_table: &self.mem_table,
+            frozen: &self.frozen,
+            tables: &self.tables,
+            merge_operator: self.merge_operator.as_ref(),
+        }
+    }
+}
+
+/// The MemTables and the Tables read by a Database, or pinned by a Cursor
+#[derive(Clone, Copy)]
+struct Sources<'a> {
+    mem_table: &'a MemTable,
+    /// Frozen MemTables ordered from the oldest to the newest
+    frozen: &'a [Arc<ImmutableMemTable>],
+    /// Tables ordered from the oldest to the newest
+    tables: &'a [Arc<TableReader>],
+    merge_operator: Option<&'a Arc<dyn MergeOperator>>,
+}
+
+impl<'a> Sources<'a> {
+    /// Finds the first live pair with a key after the bound
+    fn first_after(self, start: Bound<&[u8]>) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        self.range_up_to(start, Bound::Unbounded, u128::MAX)
+            .next()
+            .transpose()
+    }
+
+    /// Finds the last live pair with a key before the bound, merging the
+    /// sources backwards
+    fn last_before(self, end: Bound<&[u8]>) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let mut sources: Vec<MergeSource> =
+            Vec::with_capacity(self.frozen.len() + self.tables.len() + 1);
+        sources.push(newest_versions_first(Box::new(
+            self.mem_table
+                .range(Bound::Unbounded, end)
+                .rev()
+                .cloned()
+                .map(Ok),
+        )));
+        for frozen in self.frozen.iter().rev() {
+            sources.push(newest_versions_first(Box::new(
+                frozen.range(Bound::Unbounded, end).rev().cloned().map(Ok),
+            )));
+        }
+        for table in self.tables.iter().rev() {
+            sources.push(newest_versions_first(Box::new(
+                table.range(Bound::Unbounded, end).rev(),
+            )));
+        }
+
+        // The Range Tombstones are applied here, as the MergeIterator would
+        // check them with the reversed Comparator.
+        let mut merge = MergeIterator::with_comparator(sources, ReverseComparator)
+            .with_merge_operator(self.merge_operator.cloned(), false);
+        while let Some(entry) = merge.next_entry() {
+            let entry = entry?;
+            if entry.is_deleted || self.is_range_deleted(&entry, u128::MAX) {
+                continue;
+            }
+            if let Some(value) = entry.value {
+                return Ok(Some((entry.key, value)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Iterates over the Range Tombstones of the MemTables and the Tables
+    /// written at or before `max_timestamp_ms`
+    fn range_tombstones_up_to(
+        self,
+        max_timestamp_ms: u128,
+    ) -> impl Iterator<Item = &'a RangeTombstone> {
+        self.mem_table
+            .range_tombstones()
+            .iter()
+            .chain(self.frozen.iter().flat_map(|f| f.range_tombstones()))
+            .chain(self.tables.iter().flat_map(|t| t.range_tombstones()))
+            .filter(move |t| t.timestamp_ms <= max_timestamp_ms)
+    }
+
+    /// Whether a Range Tombstone written at or before `max_timestamp_ms`
+    /// deletes the record
+    fn is_range_deleted(self, entry: &MemTableEntry, max_timestamp_ms: u128) -> bool {
+        self.range_tombstones_up_to(max_timestamp_ms)
+            .any(|t| t.deletes(&BytewiseComparator, &entry.key, entry.timestamp_ms))
+    }
+
+    /// Merges the MemTable, the frozen MemTables and the Tables between the
+    /// given bounds, ignoring versions written after `max_timestamp_ms`
+    ///
+    /// The sources are ordered from the newest to the oldest, so a record
+    /// found in a newer source wins over the records of the same key found in
+    /// the older ones.
+    fn range_up_to(
+        self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        max_timestamp_ms: u128,
+    ) -> MergeIterator<'a> {
+        self.range_up_to_skipping(start, end, max_timestamp_ms, |_| false)
+    }
+
+    /// Merges the sources like `range_up_to`, leaving out the records of the
+    /// Tables for which `skip` returns true
+    fn range_up_to_skipping(
+        self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        max_timestamp_ms: u128,
+        skip: impl Fn(&TableReader) -> bool,
+    ) -> MergeIterator<'a> {
+        let visible = move |e: &Result<MemTableEntry>| {
+            e.as_ref()
+                .map_or(true, |e| e.timestamp_ms <= max_timestamp_ms)
+        };
+
+        let mut sources: Vec<MergeSource> =
+            Vec::with_capacity(self.frozen.len() + self.tables.len() + 1);
+        sources.push(Box::new(
+            self.mem_table
+                .range(start, end)
+                .cloned()
+                .map(Ok)
+                .filter(visible),
+        ));
+        for frozen in self.frozen.iter().rev() {
+            sources.push(Box::new(
+                frozen.range(start, end).cloned().map(Ok).filter(visible),
+            ));
+        }
+        for table in self.tables.iter().rev() {
+            if !table.may_overlap(start, end) || skip(table) {
+                continue;
+            }
+            sources.push(Box::new(table.range(start, end).filter(visible)));
+        }
+
+        let range_tombstones = self
+            .range_tombstones_up_to(max_timestamp_ms)
+            .cloned()
+            .collect();
+        MergeIterator::new(sources)
+            .with_range_tombstones(range_tombstones)
+            .with_merge_operator(self.merge_operator.cloned(), false)
+    }
+}
+
+/// Reorders the versions of each key of a source iterated backwards from the
+/// newest to the oldest, as the MergeIterator only reads the first one
+fn newest_versions_first(source: MergeSource<'_>) -> MergeSource<'_> {
+    let mut source = source.peekable();
+    // The versions of the current key, from the oldest to the newest
+    let mut versions = Vec::new();
+    Box::new(std::iter::from_fn(move || {
+        if versions.is_empty() {
+            let first = source.next()?;
+            let Ok(entry) = &first else {
+                return Some(first);
+            };
+            let key = entry.key.clone();
+            versions.push(first);
+            while let Some(next) = source.next_if(|e| e.as_ref().is_ok_and(|e| e.key == key)) {
+                versions.push(next);
+            }
+        }
+        versions.pop()
+    }))
+}
+
+/// Orders keys in the reverse order of the BytewiseComparator, to merge
+/// sources iterated backwards
+struct ReverseComparator;
+
+impl Comparator for ReverseComparator {
+    fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        b.cmp(a)
+    }
+}
+
+/// DatabaseBuilder configures a Database before opening it
+///
+/// Every option has a default, so only the options that matter need to be
+/// set. The options are validated when the Database is opened.
+pub struct DatabaseBuilder {
+    flush_threshold: usize,
+    sync_policy: SyncPolicy,
+    compaction_policy: Option<Box<dyn CompactionPolicy>>,
+    compression: Compression,
+    block_size: usize,
+    bloom_fp_rate: f64,
+    large_value_threshold: usize,
+    block_cache: Option<Arc<BlockCache>>,
+    background_flush: bool,
+    write_buffer_limit: Option<usize>,
+    max_key_len: usize,
+    max_value_len: usize,
+    split_points: Vec<Vec<u8>>,
+    layout: Layout,
+    merge_operator: Option<Arc<dyn MergeOperator>>,
+    tombstone_grace_ms: u128,
+    drop_expired: bool,
+    prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+}
+
+impl Default for DatabaseBuilder {
+    fn default() -> Self {
+        DatabaseBuilder::new()
+    }
+}
+
+impl DatabaseBuilder {
+    /// Creates a DatabaseBuilder with the default options
+    pub fn new() -> DatabaseBuilder {
+        DatabaseBuilder {
+            flush_threshold: DEFAULT_MAX_SIZE,
+            sync_policy: SyncPolicy::default(),
+            compaction_policy: None,
+            compression: Compression::default(),
+            block_size: DEFAULT_BLOCK_SIZE,
+            bloom_fp_rate: DEFAULT_FP_RATE,
+            large_value_threshold: DEFAULT_LARGE_VALUE_THRESHOLD,
+            block_cache: None,
+            background_flush: false,
+            write_buffer_limit: None,
+            max_key_len: DEFAULT_MAX_KEY_LEN,
+            max_value_len: DEFAULT_MAX_VALUE_LEN,
+            split_points: Vec::new(),
+            layout: Layout::new(),
+            merge_operator: None,
+            tombstone_grace_ms: 0,
+            drop_expired: false,
+            prefix_extractor: None,
+        }
+    }
+
+    /// Sets the size in bytes at which the MemTable is flushed to a Table
+    pub fn with_flush_threshold(mut self, flush_threshold: usize) -> DatabaseBuilder {
+        self.flush_threshold = flush_threshold;
+        self
+    }
+
+    /// Sets how often the WAL is synced to the disk
+    pub fn with_sync_policy(mut self, sync_policy: SyncPolicy) -> DatabaseBuilder {
+        self.sync_policy = sync_policy;
+        self
+    }
+
+    /// Sets the policy deciding when the Tables are compacted after a flush
+    ///
+    /// Without a policy, the Tables are only compacted by
+    /// [`Database::compact`]. Compaction keeps only the newest record of each
+    /// key, so Snapshots taken before a compaction may no longer see the
+    /// values they were taken over.
+    pub fn with_compaction_policy(
+        mut self,
+        compaction_policy: impl CompactionPolicy + 'static,
+    ) -> DatabaseBuilder {
+        self.compaction_policy = Some(Box::new(compaction_policy));
+        self
+    }
+
+    /// Sets how long after its deletion a tombstone survives compactions
+    ///
+    /// A compaction always keeps the tombstones of keys that older Tables
+    /// left out of it still hold. The grace period keeps the others too
+    /// until it has passed, so [`Database::changes_since`] still reports
+    /// the deletions to a sync resuming within it.
+    pub fn with_tombstone_grace_period(mut self, tombstone_grace_ms: u128) -> DatabaseBuilder {
+        self.tombstone_grace_ms = tombstone_grace_ms;
+        self
+    }
+
+    /// Sets whether compactions drop the records whose TTL has run out
+    /// instead of carrying them forward
+    ///
+    /// Snapshots taken before such a record expired no longer see it once
+    /// it has been dropped.
+    pub fn with_drop_expired(mut self, drop_expired: bool) -> DatabaseBuilder {
+        self.drop_expired = drop_expired;
+        self
+    }
+
+    /// Sets the MergeOperator folding the operands of [`Database::merge`]
+    ///
+    /// A Database holding merges must always be opened with the same
+    /// MergeOperator, otherwise reading or compacting them fails.
+    pub fn with_merge_operator(
+        mut self,
+        merge_operator: impl MergeOperator + 'static,
+    ) -> DatabaseBuilder {
+        self.merge_operator = Some(Arc::new(merge_operator));
+        self
+    }
+
+    /// Sets the codec the blocks of new Tables are compressed with
+    pub fn with_compression(mut self, compression: Compression) -> DatabaseBuilder {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the size in bytes at which a data block of a new Table is closed
+    pub fn with_block_size(mut self, block_size: usize) -> DatabaseBuilder {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Sets the target false positive rate of the BloomFilters of new Tables
+    pub fn with_bloom_fp_rate(mut self, bloom_fp_rate: f64) -> DatabaseBuilder {
+        self.bloom_fp_rate = bloom_fp_rate;
+        self
+    }
+
+    /// Sets the PrefixExtractor whose prefixes of the keys new Tables hold in
+    /// a second BloomFilter, for [`Database::scan_prefix`] to skip Tables
+    ///
+    /// A Database must always be opened with the same PrefixExtractor,
+    /// otherwise prefix scans may skip Tables holding the prefix.
+    pub fn with_prefix_extractor(
+        mut self,
+        prefix_extractor: impl PrefixExtractor + 'static,
+    ) -> DatabaseBuilder {
+        self.prefix_extractor = Some(Arc::new(prefix_extractor));
+        self
+    }
+
+    /// Sets the size in bytes above which a Value is stored outside of the
+    /// blocks of new Tables
+    pub fn with_large_value_threshold(mut self, large_value_threshold: usize) -> DatabaseBuilder {
+        self.large_value_threshold = large_value_threshold;
+        self
+    }
+
+    /// Sets the BlockCache the blocks read from the Tables are cached in
+    ///
+    /// The cache may be shared with other Databases. Without one, every
+    /// block is read from the disk.
+    pub fn with_block_cache(mut self, block_cache: Arc<BlockCache>) -> DatabaseBuilder {
+        self.block_cache = Some(block_cache);
+        self
+    }
+
+    /// Sets whether full MemTables are flushed on a background thread
+    ///
+    /// A full MemTable is then handed to the thread, and writes go on in a
+    /// fresh MemTable while it is written. Several MemTables can be pending
+    /// within the limit set by [`DatabaseBuilder::with_write_buffer_limit`].
+    /// [`Database::flush`] stays synchronous.
+    pub fn with_background_flush(mut self, background_flush: bool) -> DatabaseBuilder {
+        self.background_flush = background_flush;
+        self
+    }
+
+    /// Sets the size in bytes the MemTable written to and the MemTables
+    /// pending a background flush may take up in total
+    ///
+    /// A write taking them above the limit blocks until enough pending
+    /// flushes are done, so writes slow down to the pace of the flushes
+    /// instead of piling up MemTables in memory. The limit must be at least
+    /// the flush threshold, and defaults to twice the flush threshold.
+    pub fn with_write_buffer_limit(mut self, write_buffer_limit: usize) -> DatabaseBuilder {
+        self.write_buffer_limit = Some(write_buffer_limit);
+        self
+    }
+
+    /// Sets the length in bytes of the longest key that can be written
+    ///
+    /// Writes of longer keys fail with [`Error::KeyTooLarge`].
+    pub fn with_max_key_len(mut self, max_key_len: usize) -> DatabaseBuilder {
+        self.max_key_len = max_key_len;
+        self
+    }
+
+    /// Sets the length in bytes of the longest value that can be written
+    ///
+    /// Writes of longer values fail with [`Error::ValueTooLarge`].
+    pub fn with_max_value_len(mut self, max_value_len: usize) -> DatabaseBuilder {
+        self.max_value_len = max_value_len;
+        self
+    }
+
+    /// Sets the keys at which a flush starts a new Table
+    ///
+    /// Every flush then writes one Table per range between the split points,
+    /// so the keys of a range always land in Tables of their own, and a
+    /// lookup only reads the Table covering its key. Compactions still merge
+    /// their Tables into one. The split points must be in strictly increasing
+    /// order.
+    pub fn with_split_points(mut self, split_points: Vec<Vec<u8>>) -> DatabaseBuilder {
+        self.split_points = split_points;
+        self
+    }
+
+    /// Sets where the files of the Database are placed within its directory
+    ///
+    /// Databases open for writing in the same process must not share files,
+    /// so opening one whose files overlap with another's fails with
+    /// [`Error::InvalidConfig`].
+    pub fn with_layout(mut self, layout: Layout) -> DatabaseBuilder {
+        self.layout = layout;
+        self
+    }
+
+    /// Opens the Database in the given directory with the configured options
+    ///
+    /// Fails with [`Error::InvalidConfig`] if an option is out of range.
+    pub fn open(self, dir: &Path) -> Result<Database> {
+        self.validate()?;
+        Database::open_with(dir, self, Mode::ReadWrite)
+    }
+
+    /// Opens the Database in the given directory read-only with the
+    /// configured options
+    ///
+    /// See [`Database::open_read_only`].
+    pub fn open_read_only(self, dir: &Path) -> Result<Database> {
+        self.validate()?;
+        Database::open_with(dir, self, Mode::ReadOnly)
+    }
+
+    /// Creates a Database kept in memory with the configured options
+    ///
+    /// See [`Database::in_memory`]. The options of the WAL and the Tables
+    /// have no effect.
+    pub fn open_in_memory(self) -> Result<Database> {
+        self.validate()?;
+        Database::open_with(Path::new(""), self, Mode::InMemory)
+    }
+
+    /// Checks the Database in the given directory, laid out with the
+    /// configured Layout, without repairing anything
+    ///
+    /// See [`Database::verify`]. Fails with [`Error::InvalidConfig`] if the
+    /// Layout is invalid.
+    pub fn verify(self, dir: &Path) -> Result<VerifyReport> {
+        self.layout.validate()?;
+        Database::verify_paths(&self.layout.resolve(dir))
+    }
+
+    /// Fails with [`Error::InvalidConfig`] if an option is out of range
+    fn validate(&self) -> Result<()> {
+        if self.flush_threshold == 0 {
+            return Err(Error::InvalidConfig(
+                "the flush threshold must be at least 1 byte".to_owned(),
+            ));
+        }
+        if self
+            .write_buffer_limit
+            .is_some_and(|limit| limit < self.flush_threshold)
+        {
+            return Err(Error::InvalidConfig(
+                "the write buffer limit must be at least the flush threshold".to_owned(),
+            ));
+        }
+        if self.block_size == 0 {
+            return Err(Error::InvalidConfig(
+                "the block size must be at least 1 byte".to_owned(),
+            ));
+        }
+        if !(self.bloom_fp_rate > 0.0 && self.bloom_fp_rate < 1.0) {
+            return Err(Error::InvalidConfig(format!(
+                "the bloom filter false positive rate must be between 0 and 1, got {}",
+                self.bloom_fp_rate
+            )));
+        }
+        if self.split_points.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(Error::InvalidConfig(
+                "the split points must be in strictly increasing order".to_owned(),
+            ));
+        }
+        self.layout.validate()
+    }
+}
+
+/// How `Database::open_with` opens a Database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    ReadWrite,
+    ReadOnly,
+    InMemory,
+}
+
+impl Database {
+    /// Opens the Database in the given directory with the default options,
+    /// creating it if needed
+    ///
+    /// Use a [`DatabaseBuilder`] to configure the Database instead.
+    pub fn open(dir: &Path) -> Result<Database> {
+        DatabaseBuilder::new().open(dir)
+    }
+
+    /// Opens the existing Database in the given directory without ever
+    /// modifying it
+    ///
+    /// The Tables and the WAL segments are read like [`Database::open`]
+    /// does, but nothing is written to the directory: Table files missing
+    /// from the Manifest are left alone, and no WAL segment is created.
+    /// Writes, flushes and compactions fail with [`Error::ReadOnly`], while
+    /// reads, snapshots and checkpoints work as usual. A Database shipped
+    /// without its WAL directory, such as on a read-only filesystem, is
+    /// opened from its Tables alone.
+    pub fn open_read_only(dir: &Path) -> Result<Database> {
+        DatabaseBuilder::new().open_read_only(dir)
+    }
+
+    /// Creates an empty Database that never touches the disk, with the
+    /// default options
+    ///
+    /// Writes only go to the MemTable, with no WAL behind it, so everything
+    /// is lost once the Database is dropped. Flushing seals the MemTable
+    /// into a frozen one that stays in memory instead of writing a Table,
+    /// and reads merge them like they merge the Tables. This suits caches
+    /// and tests that don't need durability.
+    pub fn in_memory() -> Database {
+        DatabaseBuilder::new()
+            .open_in_memory()
+            .expect("the default options are valid")
+    }
+
+    /// Checks the Database in the given directory and reports the problems
+    /// found in each file, without repairing anything
+    ///
+    /// Every Table listed in the Manifest is read in full with
+    /// [`TableReader::verify`], and the Manifest is cross-checked against the
+    /// Table files in the directory. Without a Manifest, every Table in the
+    /// directory is checked. Only failing to list the directory or to read
+    /// the Manifest is an error.
+    ///
+    /// This expects the default Layout, use [`DatabaseBuilder::verify`] for
+    /// a Database opened with another one.
+    pub fn verify(dir: &Path) -> Result<VerifyReport> {
+        DatabaseBuilder::new().verify(dir)
+    }
+
+    fn verify_paths(paths: &Paths) -> Result<VerifyReport> {
+        let present: Vec<String> = paths
+            .table_ids()?
+            .into_iter()
+            .map(|id| paths.table_file_name(id))
+            .collect();
+        let listed = match paths.load_manifest()? {
+            Some(manifest) => manifest.tables().to_vec(),
+            None => present.clone(),
+        };
+
+        let mut report = VerifyReport::default();
+        let problem = |file: &str, kind| Problem {
+            file: file.to_owned(),
+            kind,
+        };
+        for name in &listed {
+            let path = paths.table_dir().join(name);
+            if !path.exists() {
+                report.problems.push(problem(name, ProblemKind::Missing));
+                continue;
+            }
+
+            report.tables_checked += 1;
+            match TableReader::open(&path) {
+                Ok(table) => report
+                    .problems
+                    .extend(table.verify().into_iter().map(|kind| problem(name, kind))),
+                Err(e) => report
+                    .problems
+                    .push(problem(name, ProblemKind::Unreadable(e))),
+            }
+        }
+        for name in present.iter().filter(|name| !listed.contains(name)) {
+            report.problems.push(problem(name, ProblemKind::Orphan));
+        }
+        Ok(report)
+    }
+
+    /// Opens the Database with already validated options
+    ///
+    /// The Tables listed in the Manifest are loaded and the MemTable is
+    /// recovered by replaying every surviving WAL segment in order. Table
+    /// files missing from the Manifest were left behind by an interrupted
+    /// flush or compaction, and are deleted unless opening read-only. In
+    /// memory, the directory isn't read at all.
+    fn open_with(dir: &Path, options: DatabaseBuilder, mode: Mode) -> Result<Database> {
+        let paths = options.layout.resolve(dir);
+        let lock = match mode {
+            Mode::ReadWrite => {
+                paths.create_dirs()?;
+                Some(paths.lock()?)
+            }
+            Mode::ReadOnly | Mode::InMemory => None,
+        };
+        let (next_table_id, tables) = match mode {
+            Mode::InMemory => (0, Vec::new()),
+            _ => Database::open_tables(&paths, &options, mode == Mode::ReadOnly)?,
+        };
+
+        let mut wal_ids = match mode {
+            Mode::InMemory => Vec::new(),
+            _ => paths.wal_ids()?,
+        };
+        let mut mem_table = MemTable::with_capacity(options.flush_threshold)
+            .with_max_key_len(options.max_key_len)
+            .with_max_value_len(options.max_value_len);
+        if let Some(merge_operator) = &options.merge_operator {
+            mem_table = mem_table.with_merge_operator(Arc::clone(merge_operator));
+        }
+        let mut intact_len = 0;
+        for &id in &wal_ids {
+            (_, intact_len) = WriteAheadLog::recover_segment(&paths.wal_path(id), &mut mem_table)?;
+        }
+        let wal = match mode {
+            Mode::ReadWrite => {
+                if wal_ids.is_empty() {
+                    wal_ids.push(0);
+                }
+                let path = paths.wal_path(*wal_ids.last().unwrap());
+                WriteAheadLog::truncate_torn_tail(&path, intact_len)?;
+                let wal = WriteAheadLog::open(&path)?;
+                Some(wal.with_sync_policy(options.sync_policy))
+            }
+            Mode::ReadOnly | Mode::InMemory => None,
+        };
+        let last_timestamp_ms = mem_table
+            .entries()
+            .iter()
+            .map(|e| e.timestamp_ms)
+            .chain(mem_table.range_tombstones().iter().map(|t| t.timestamp_ms))
+            .max()
+            .unwrap_or(0);
+
+        let mut table_writer = TableWriter::new()
+            .with_block_size(options.block_size)
+            .with_compression(options.compression)
+            .with_bloom_fp_rate(options.bloom_fp_rate)
+            .with_large_value_threshold(options.large_value_threshold)
+            .with_split_points(options.split_points)
+            .with_tombstone_grace_period(options.tombstone_grace_ms)
+            .with_drop_expired(options.drop_expired);
+        if let Some(merge_operator) = &options.merge_operator {
+            table_writer = table_writer.with_merge_operator(Arc::clone(merge_operator));
+        }
+        if let Some(prefix_extractor) = options.prefix_extractor {
+            table_writer = table_writer.with_prefix_extractor(prefix_extractor);
+        }
+        let flush_worker = match options.background_flush && mode == Mode::ReadWrite {
+            true => Some(FlushWorker::spawn(table_writer.clone())?),
+            false => None,
+        };
+        let write_buffer_limit = options
+            .write_buffer_limit
+            .unwrap_or(options.flush_threshold.saturating_mul(2));
+
+        Ok(Database {
+            paths,
+            _lock: lock,
+            mem_table: Arc::new(mem_table),
+            frozen: Vec::new(),
+            wal,
+            in_memory: mode == Mode::InMemory,
+            wal_ids,
+            frozen_wal_ids: Vec::new(),
+            tables,
+            next_table_id,
+            last_timestamp_ms,
+            snapshots: Mutex::new(Vec::new()),
+            subscribers: Vec::new(),
+            write_hooks: Vec::new(),
+            sync_policy: options.sync_policy,
+            table_writer,
+            compaction_policy: options.compaction_policy,
+            block_cache: options.block_cache,
+            merge_operator: options.merge_operator,
+            flush_worker,
+            background_flushes: 0,
+            write_buffer: WriteBufferManager::new(write_buffer_limit),
+            metrics: Counters::new(1),
+        })
+    }
+
+    /// Loads the Tables listed in the Manifest and returns them along with
+    /// the id of the next Table
+    ///
+    /// Table files missing from the Manifest are deleted unless `read_only`
+    /// is set.
+    fn open_tables(
+        paths: &Paths,
+        options: &DatabaseBuilder,
+        read_only: bool,
+    ) -> Result<(u64, Vec<Arc<TableReader>>)> {
+        let table_ids = paths.table_ids()?;
+        let next_table_id = table_ids.last().map_or(0, |id| id + 1);
+        let table_names = match paths.load_manifest()? {
+            Some(manifest) => manifest.tables().to_vec(),
+            // Without a Manifest every Table in the directory is live.
+            None => table_ids
+                .iter()
+                .map(|&id| paths.table_file_name(id))
+                .collect(),
+        };
+        for &id in table_ids.iter().filter(|_| !read_only) {
+            if !table_names.contains(&paths.table_file_name(id)) {
+                fs::remove_file(paths.table_path(id))?;
+            }
+        }
+        let tables = table_names
+            .iter()
+            .map(|name| {
+                let table = TableReader::open(&paths.table_dir().join(name))?;
+                Ok(Arc::new(with_cache(table, &options.block_cache)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok((next_table_id, tables))
+    }
+
+    /// Gets the value of a key
+    ///
+    /// If the key doesn't exist or has been deleted, return None.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.get_up_to(key, u128::MAX)
+    }
+
+    /// Returns true if the key exists and hasn't been deleted
+    ///
+    /// The sources are checked in the same order as `get` and the first one
+    /// holding a record of the key decides. Tables are skipped when their
+    /// BloomFilter rules the key out, and no Value is ever copied.
+    pub fn contains_key(&self, key: &[u8]) -> Result<bool> {
+        let newest = self
+            .mem_table
+            .get(key)
+            .or_else(|| self.frozen.iter().rev().find_map(|f| f.get(key)));
+        if let Some(entry) = newest {
+            self.metrics.record_mem_table_hit();
+            if entry.is_merge {
+                return Ok(self.get(key)?.is_some());
+            }
+            return Ok(!entry.is_deleted && !self.sources().is_range_deleted(entry, u128::MAX));
+        }
+
+        for table in self.tables.iter().rev() {
+            if let Some(entry) = self.table_get(table, key, u128::MAX, false)? {
+                if entry.is_merge {
+                    return Ok(self.get(key)?.is_some());
+                }
+                return Ok(!entry.is_deleted && !self.sources().is_range_deleted(&entry, u128::MAX));
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Gets the value of a key as of the given Snapshot
+    pub fn get_at(&self, snapshot: &Snapshot, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.get_up_to(key, snapshot.timestamp_ms())
+    }
+
+    /// Scans the Database for live Key-Value pairs with keys between the given
+    /// bounds, in sorted key order
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> MergeIterator<'_> {
+        self.sources().range_up_to(start, end, u128::MAX)
+    }
+
+    /// Scans the Database as of the given Snapshot
+    pub fn range_at(
+        &self,
+        snapshot: &Snapshot,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> MergeIterator<'_> {
+        self.sources()
+            .range_up_to(start, end, snapshot.timestamp_ms())
+    }
+
+    /// Scans the Database for the live Key-Value pairs with keys starting
+    /// with the prefix, in sorted key order
+    ///
+    /// With [`DatabaseBuilder::with_prefix_extractor`], if the prefix is in
+    /// the domain of the PrefixExtractor, the Tables whose prefix
+    /// BloomFilter rules out its extracted prefix are skipped.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> MergeIterator<'_> {
+        let end = mem_table::prefix_upper_bound(prefix);
+        let end = end.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        let extractor = self
+            .table_writer
+            .prefix_extractor()
+            .filter(|e| e.in_domain(prefix));
+        let skip = |table: &TableReader| {
+            let skip = extractor.is_some_and(|e| !table.may_contain_prefix(e.extract(prefix)));
+            if skip {
+                self.metrics.record_prefix_bloom_negative();
+            }
+            skip
+        };
+        self.sources()
+            .range_up_to_skipping(Bound::Included(prefix), end, u128::MAX, skip)
+    }
+
+    /// Returns up to `limit` live Key-Value pairs with keys between the given
+    /// bounds, in sorted key order, and the key to start the next page from
+    /// if more pairs remain
+    ///
+    /// The next page is read by scanning again from the continuation key
+    /// included. Pages read with [`Database::scan_at`] over the same Snapshot
+    /// don't skip or repeat pairs whatever is written between them.
+    pub fn scan(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, limit: usize) -> Result<Page> {
+        take_page(self.range(start, end), limit)
+    }
+
+    /// Returns a page of the live Key-Value pairs as of the given Snapshot,
+    /// like [`Database::scan`]
+    pub fn scan_at(
+        &self,
+        snapshot: &Snapshot,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        limit: usize,
+    ) -> Result<Page> {
+        take_page(self.range_at(snapshot, start, end), limit)
+    }
+
+    /// Scans the Database for the live Key-Value pairs grouped by the first
+    /// `prefix_len` bytes of their keys, in sorted key order
+    ///
+    /// Every distinct prefix yields one Group holding its pairs. A key
+    /// shorter than `prefix_len` forms a Group of its own, with the whole
+    /// key as the prefix.
+    pub fn group_by_prefix(&self, prefix_len: usize) -> impl Iterator<Item = Result<Group>> + '_ {
+        let mut pairs = self.range(Bound::Unbounded, Bound::Unbounded).peekable();
+        std::iter::from_fn(move || {
+            let (key, value) = match pairs.next()? {
+                Ok(pair) => pair,
+                Err(e) => return Some(Err(e)),
+            };
+            let prefix = key[..key.len().min(prefix_len)].to_vec();
+            let mut group = vec![(key, value)];
+            if prefix.len() == prefix_len {
+                // An error ends the Group, and is yielded next.
+                while let Some(Ok((key, _))) = pairs.peek() {
+                    if !key.starts_with(&prefix) {
+                        break;
+                    }
+                    group.extend(pairs.next().and_then(Result::ok));
+                }
+            }
+            Some(Ok((prefix, group)))
+        })
+    }
+
+    /// Scans the Database for the keys changed after a resume point, in
+    /// sorted key order, for incremental replication
+    ///
+    /// Every key yields a ChangeEvent for its newest record if that record
+    /// was written after `last_ts`, and only keys after `last_key` are
+    /// scanned. Without a resume point every key is yielded. A sync keeps
+    /// `last_ts` across its pages and resumes after the key of the last
+    /// event it consumed, so stopping midway, even across a restart, leads
+    /// to no gap or duplicate.
+    ///
+    /// Deleted keys are yielded as tombstones, timestamped with their
+    /// deletion, until a compaction drops the tombstone. A merged key is
+    /// yielded with its folded value.
+    pub fn changes_since(
+        &self,
+        resume: Option<(Vec<u8>, u128)>,
+    ) -> impl Iterator<Item = Result<ChangeEvent>> + '_ {
+        let (start, last_ts) = match &resume {
+            Some((last_key, last_ts)) => (Bound::Excluded(last_key.as_slice()), Some(*last_ts)),
+            None => (Bound::Unbounded, None),
+        };
+        let sources = self.sources();
+        let mut records = sources.range_up_to(start, Bound::Unbounded, u128::MAX);
+        std::iter::from_fn(move || records.next_entry()).filter_map(move |entry| {
+            let mut event = match entry {
+                Ok(entry) => ChangeEvent::from(&entry),
+                Err(e) => return Some(Err(e)),
+            };
+            if event.is_deleted {
+                // A record deleted by a Range Tombstone changed when the
+                // newest Range Tombstone covering it was written.
+                let deleted_at = sources
+                    .range_tombstones_up_to(u128::MAX)
+                    .filter(|t| t.deletes(&BytewiseComparator, &event.key, event.timestamp_ms))
+                    .map(|t| t.timestamp_ms)
+                    .max();
+                event.timestamp_ms = deleted_at.unwrap_or(event.timestamp_ms);
+            }
+            let changed = last_ts.is_none_or(|last_ts| event.timestamp_ms > last_ts);
+            changed.then_some(Ok(event))
+        })
+    }
+
+    /// Iterates over the deleted keys of the MemTables and the Tables in
+    /// sorted key order
+    ///
+    /// Each key whose newest record is a tombstone, or is covered by a Range
+    /// Tombstone, is yielded once with that record, until a compaction drops
+    /// the tombstone.
+    pub fn tombstones(&self) -> impl Iterator<Item = Result<MemTableEntry>> + '_ {
+        let mut records = self
+            .sources()
+            .range_up_to(Bound::Unbounded, Bound::Unbounded, u128::MAX);
+        std::iter::from_fn(move || records.next_entry())
+            .filter(|entry| entry.as_ref().map_or(true, |e| e.is_deleted))
+    }
+
+    /// Creates a Cursor over the live Key-Value pairs of the Database
+    ///
+    /// The Cursor starts unpositioned, and sees the Database as it is now
+    /// until it is dropped, whatever is written after.
+    pub fn cursor(&self) -> Cursor {
+        Cursor {
+            mem_table: Arc::clone(&self.mem_table),
+            frozen: self.frozen.clone(),
+            tables: self.tables.clone(),
+            merge_operator: self.merge_operator.clone(),
+            current: None,
+        }
+    }
+
+    fn sources(&self) -> Sources<'_> {
+        Sources {
+            mem_table: &self.mem_table,
+            frozen: &self.frozen,
+            tables: &self.tables,
+            merge_operator: self.merge_operator.as_ref(),
+        }
+    }
+
+    /// Estimates the number of bytes taken up by the keys from `start`
+    /// included to `end` excluded
+    ///
+    /// The size in the MemTables is exact. Each Table contributes the distance
+    /// between the estimated offsets of both keys in its file, found from its
+    /// block index without reading from the disk. Each of these estimates is
+    /// off by at most one block, roughly the Table's block size, and counts
+    /// the compressed size of compressed blocks. Overwritten and deleted
+    /// records still take up space until they are compacted away, so they
+    /// are counted as well.
+    pub fn size_of_range(&self, start: &[u8], end: &[u8]) -> u64 {
+        if start >= end {
+            return 0;
+        }
+
+        let bounds = (Bound::Included(start), Bound::Excluded(end));
+        let mem_table_size: usize = self
+            .mem_table
+            .range(bounds.0, bounds.1)
+            .chain(self.frozen.iter().flat_map(|f| f.range(bounds.0, bounds.1)))
+            .map(MemTableEntry::encoded_len)
+            .sum();
+        let tables_size: u64 = self
+            .tables
+            .iter()
+            .map(|t| t.approximate_offset(end) - t.approximate_offset(start))
+            .sum();
+
+        mem_table_size as u64 + tables_size
+    }
+
+    /// Takes a Snapshot of the current state of the Database
+    pub fn snapshot(&self) -> Snapshot {
+        let timestamp_ms = Arc::new(self.last_timestamp_ms);
+        let mut snapshots = self.snapshots.lock().unwrap();
+        snapshots.retain(|s| s.strong_count() > 0);
+        snapshots.push(Arc::downgrade(&timestamp_ms));
+        Snapshot { timestamp_ms }
+    }
+
+    /// Returns the timestamp of the oldest Snapshot still alive, if any
+    fn oldest_snapshot_ms(&mut self) -> Option<u128> {
+        let snapshots = self.snapshots.get_mut().unwrap();
+        snapshots.retain(|s| s.strong_count() > 0);
+        snapshots.first().and_then(Weak::upgrade).map(|t| *t)
+    }
+
+    /// Gets the value of the newest version of a key that was written at or
+    /// before `max_timestamp_ms`
+    ///
+    /// The newest version is deleted if a Range Tombstone written after it,
+    /// and at or before `max_timestamp_ms`, covers the key. Merge records are
+    /// collected from the newest to the oldest until another record or a
+    /// Range Tombstone decides the value they are folded into.
+    fn get_up_to(&self, key: &[u8], max_timestamp_ms: u128) -> Result<Option<Vec<u8>>> {
+        let mut merges = Vec::new();
+        let frozen = self.frozen.iter().rev().map(|f| f.mem_table());
+        for mem_table in std::iter::once(&*self.mem_table).chain(frozen) {
+            let Some(entry) = mem_table.get_as_of(key, max_timestamp_ms) else {
+                continue;
+            };
+            if merges.is_empty() {
+                self.metrics.record_mem_table_hit();
+            }
+            if let Some(existing) = self.collect_merge(&mut merges, entry.clone(), max_timestamp_ms)
+            {
+                return self.fold_merges(&merges, existing);
+            }
+        }
+        for table in self.tables.iter().rev() {
+            let Some(entry) = self.table_get(table, key, max_timestamp_ms, true)? else {
+                continue;
+            };
+            if let Some(existing) = self.collect_merge(&mut merges, entry, max_timestamp_ms) {
+                return self.fold_merges(&merges, existing);
+            }
+        }
+
+        self.fold_merges(&merges, None)
+    }
+
+    /// Collects the Value of a merge record found by `get_up_to`, or returns
+    /// the value of the key decided by the record
+    fn collect_merge(
+        &self,
+        merges: &mut Vec<Vec<u8>>,
+        entry: MemTableEntry,
+        max_timestamp_ms: u128,
+    ) -> Option<Option<Vec<u8>>> {
+        if self.sources().is_range_deleted(&entry, max_timestamp_ms) {
+            return Some(None);
+        }
+        if !entry.is_merge {
+            return Some(entry.value);
+        }
+        merges.push(entry.value.unwrap_or_default());
+        None
+    }
+
+    /// Folds the Values of the merge records collected by `get_up_to`, if
+    /// any, over the existing value of the key
+    fn fold_merges(
+        &self,
+        merges: &[Vec<u8>],
+        existing: Option<Vec<u8>>,
+    ) -> Result<Option<Vec<u8>>> {
+        if merges.is_empty() {
+            return Ok(existing);
+        }
+        merge::fold(self.merge_operator.as_deref(), merges, existing.as_deref())
+    }
+
+    /// Looks a key up in a Table like `TableReader::get_as_of`, counting the
+    /// lookup in the Metrics
+    fn table_get(
+        &self,
+        table: &TableReader,
+        key: &[u8],
+        max_timestamp_ms: u128,
+        with_value: bool,
+    ) -> Result<Option<MemTableEntry>> {
+        if !table.bloom().contains(key) {
+            self.metrics.record_bloom_negative();
+            return Ok(None);
+        }
+        if !table.may_overlap(Bound::Included(key), Bound::Included(key)) {
+            return Ok(None);
+        }
+        // The Database has a single level.
+        self.metrics.record_table_read(0);
+        table.get_as_of_with(key, max_timestamp_ms, with_value)
+    }
+
+    /// Takes a snapshot of the counters of the Database
+    ///
+    /// Only point lookups through `get`, `get_at` and `contains_key` are
+    /// counted as reads.
+    pub fn metrics(&self) -> Metrics {
+        let mut metrics = self.metrics.snapshot();
+        metrics.wal_bytes_written += self.wal.as_ref().map_or(0, |w| w.bytes_written());
+        metrics
+    }
+
+    /// Profiles the sizes of the keys and Values held by the MemTables and
+    /// the Tables
+    ///
+    /// The Tables contribute the Profile written with them, so only the
+    /// records in the MemTables are visited.
+    pub fn profile(&self) -> Profile {
+        let mut profile = Profile::default();
+        let frozen = self.frozen.iter().map(|f| f.mem_table());
+        for mem_table in std::iter::once(&*self.mem_table).chain(frozen) {
+            for entry in mem_table.entries() {
+                let value_len = entry.value.as_ref().map_or(0, Vec::len);
+                profile.add(entry.key.len(), (!entry.is_deleted).then_some(value_len));
+            }
+        }
+        for table in &self.tables {
+            profile.merge(&table.profile());
+        }
+        profile
+    }
+
+    /// Returns a timestamp for the next write from the MemTable's Clock,
+    /// strictly greater than the timestamp of every previous write
+    fn next_timestamp(&mut self) -> u128 {
+        let now_ms = self.mem_table.clock().now_ms();
+        self.last_timestamp_ms = now_ms.max(self.last_timestamp_ms + 1);
+        self.last_timestamp_ms
+    }
+
+    /// Subscribes to the writes made to the Database
+    ///
+    /// Every write is sent to the Receiver once it is in the WAL. Up to
+    /// [`cdc::DEFAULT_CAPACITY`] events are buffered, after which the oldest
+    /// ones are dropped so a slow subscriber never blocks writes.
+    pub fn subscribe(&mut self) -> Receiver {
+        self.subscribe_with(cdc::DEFAULT_CAPACITY, OverflowPolicy::default())
+    }
+
+    /// Subscribes to the writes made to the Database, buffering up to
+    /// `capacity` events and handling a full buffer with the given policy
+    pub fn subscribe_with(&mut self, capacity: usize, policy: OverflowPolicy) -> Receiver {
+        let (sender, receiver) = cdc::channel(capacity, policy);
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Registers a callback deriving more writes from every write, such as to
+    /// maintain a secondary index
+    ///
+    /// The callback is called with the ChangeEvent of every key set, deleted
+    /// or merged, before the write reaches the WAL. The Operations it returns
+    /// are written in the same WAL batch as the write, so after a crash
+    /// either both or neither are recovered. They take the timestamp of the
+    /// write, and don't trigger the callbacks themselves. Range deletions
+    /// don't trigger the callbacks either.
+    pub fn on_write(&mut self, callback: WriteHook) {
+        self.write_hooks.push(callback);
+    }
+
+    /// Sends the written entries to every subscriber, forgetting the ones
+    /// whose Receiver was dropped
+    fn notify(&mut self, entries: &[MemTableEntry]) {
+        self.subscribers.retain(|subscriber| {
+            entries
+                .iter()
+                .all(|entry| subscriber.send(ChangeEvent::from(entry)))
+        });
+    }
+
+    /// Sets a Key-Value pair in the Database
+    pub fn set(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let timestamp_ms = self.next_timestamp();
+
+        let entry = MemTableEntry {
+            key: key.to_owned(),
+            value: Some(value.to_owned()),
+            timestamp_ms,
+            is_deleted: false,
+            is_merge: false,
+            expires_at_ms: None,
+        };
+        self.write_entries(vec![entry], timestamp_ms)
+    }
+
+    /// Deletes a Key-Value pair in the Database
+    pub fn delete(&mut self, key: &[u8]) -> Result<()> {
+        let timestamp_ms = self.next_timestamp();
+
+        let entry = MemTableEntry {
+            key: key.to_owned(),
+            value: None,
+            timestamp_ms,
+            is_deleted: true,
+            is_merge: false,
+            expires_at_ms: None,
+        };
+        self.write_entries(vec![entry], timestamp_ms)
+    }
+
+    /// Merges an operand into the value of a key with the MergeOperator set
+    /// by [`DatabaseBuilder::with_merge_operator`]
+    ///
+    /// The current value of the key isn't read. The operand is folded into
+    /// it by [`MergeOperator::full_merge`] right away if the MemTable holds
+    /// it, and otherwise once a read or a compaction finds it. Fails with
+    /// [`Error::InvalidConfig`] if the Database has no MergeOperator.
+    pub fn merge(&mut self, key: &[u8], operand: &[u8]) -> Result<()> {
+        if self.merge_operator.is_none() {
+            return Err(Error::InvalidConfig(
+                "merges need a MergeOperator".to_owned(),
+            ));
+        }
+        let timestamp_ms = self.next_timestamp();
+
+        let mut operands = Vec::new();
+        merge::push_operand(&mut operands, operand);
+        let entry = MemTableEntry {
+            key: key.to_owned(),
+            value: Some(operands),
+            timestamp_ms,
+            is_deleted: false,
+            is_merge: true,
+            expires_at_ms: None,
+        };
+        self.write_entries(vec![entry], timestamp_ms)
+    }
+
+    /// Deletes every key from `start` included to `end` excluded
+    ///
+    /// A single Range Tombstone is written instead of a tombstone per key,
+    /// so the cost doesn't depend on the number of keys in the range. It
+    /// only deletes the records written before it, and is applied by every
+    /// read until a compaction drops the records it covers. Subscribers are
+    /// not notified of range deletions. Does nothing if the range is empty.
+    pub fn delete_range(&mut self, start: &[u8], end: &[u8]) -> Result<()> {
+        if start >= end {
+            return Ok(());
+        }
+        self.mem_table.check_lengths(start, None)?;
+        self.mem_table.check_lengths(end, None)?;
+        self.check_writable()?;
+        let timestamp_ms = self.next_timestamp();
+
+        let tombstone = RangeTombstone {
+            start: start.to_owned(),
+            end: end.to_owned(),
+            timestamp_ms,
+        };
+        if let Some(wal) = &mut self.wal {
+            wal.append_range_tombstone(&tombstone)?;
+        }
+        Arc::make_mut(&mut self.mem_table).delete_range_at(start, end, timestamp_ms);
+
+        self.maybe_flush()
+    }
+
+    /// Fails with [`Error::ReadOnly`] if the Database was opened read-only
+    fn check_writable(&self) -> Result<()> {
+        match self.wal.is_some() || self.in_memory {
+            true => Ok(()),
+            false => Err(Error::ReadOnly),
+        }
+    }
+
+    /// Writes `new` to the key only if its current value is `expected`, and
+    /// returns whether it did
+    ///
+    /// The current value is resolved across the MemTable and the Tables, so
+    /// a deleted key is treated as absent. An `expected` of None only matches
+    /// an absent key, and a `new` of None deletes the key. The check and the
+    /// write happen while the Database is borrowed mutably, so no other write
+    /// can come in between.
+    pub fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool> {
+        if self.get(key)?.as_deref() != expected {
+            return Ok(false);
+        }
+
+        match new {
+            Some(value) => self.set(key, value)?,
+            None => self.delete(key)?,
+        }
+        Ok(true)
+    }
+
+    /// Writes every operation of the batch atomically
+    ///
+    /// The whole batch is written to the WAL as one unit, so after a crash
+    /// either all or none of its operations are recovered. All operations
+    /// share the same timestamp, and if a key appears several times in the
+    /// batch the last operation wins.
+    pub fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let timestamp_ms = self.next_timestamp();
+
+        let mut entries = batch.into_entries(timestamp_ms);
+        // Apply the batch in key order so insertions land at increasing
+        // positions of the MemTable. The sort is stable, so the last
+        // operation of a key is the last of its run.
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries.reverse();
+        entries.dedup_by(|a, b| a.key == b.key);
+        entries.reverse();
+
+        self.write_entries(entries, timestamp_ms)
+    }
+
+    /// Writes the entries to the WAL as one batch along with the Operations
+    /// the write hooks derive from them, then to the MemTable
+    ///
+    /// Nothing is written if any key or value is longer than allowed, or if a
+    /// write hook derives a merge while the Database has no MergeOperator.
+    fn write_entries(&mut self, mut entries: Vec<MemTableEntry>, timestamp_ms: u128) -> Result<()> {
+        self.check_writable()?;
+        let mut range_tombstones = Vec::new();
+        if !self.write_hooks.is_empty() {
+            let events: Vec<ChangeEvent> = entries.iter().map(ChangeEvent::from).collect();
+            for hook in &mut self.write_hooks {
+                for operation in events.iter().flat_map(&mut *hook) {
+                    match operation {
+                        Operation::Set {
+                            key,
+                            value,
+                            expires_at_ms,
+                            ..
+                        } => entries.push(MemTableEntry {
+                            key,
+                            value: Some(value),
+                            timestamp_ms,
+                            is_deleted: false,
+                            is_merge: false,
+                            expires_at_ms,
+                        }),
+                        Operation::Delete { key, .. } => entries.push(MemTableEntry {
+                            key,
+                            value: None,
+                            timestamp_ms,
+                            is_deleted: true,
+                            is_merge: false,
+                            expires_at_ms: None,
+                        }),
+                        Operation::DeleteRange { start, end, .. } if start < end => {
+                            range_tombstones.push(RangeTombstone {
+                                start,
+                                end,
+                                timestamp_ms,
+                            })
+                        }
+                        Operation::DeleteRange { .. } => {}
+                        Operation::Merge { .. } if self.merge_operator.is_none() => {
+                            return Err(Error::InvalidConfig(
+                                "merges need a MergeOperator".to_owned(),
+                            ));
+                        }
+                        // Each operand is a write of its own, so that
+                        // subscribers are notified of every one.
+                        Operation::Merge { key, operands, .. } => {
+                            for operand in &operands {
+                                let mut value = Vec::new();
+                                merge::push_operand(&mut value, operand);
+                                entries.push(MemTableEntry {
+                                    key: key.clone(),
+                                    value: Some(value),
+                                    timestamp_ms,
+                                    is_deleted: false,
+                                    is_merge: true,
+                                    expires_at_ms: None,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for entry in &entries {
+            self.mem_table
+                .check_lengths(&entry.key, entry.value.as_deref())?;
+        }
+        for tombstone in &range_tombstones {
+            self.mem_table.check_lengths(&tombstone.start, None)?;
+            self.mem_table.check_lengths(&tombstone.end, None)?;
+        }
+        if let Some(wal) = &mut self.wal {
+            wal.append_batch_with(&entries, &range_tombstones)?;
+        }
+        self.notify(&entries);
+        let oldest_snapshot_ms = self.oldest_snapshot_ms();
+        let mem_table = Arc::make_mut(&mut self.mem_table);
+        mem_table.set_oldest_snapshot(oldest_snapshot_ms);
+        for entry in entries {
+            mem_table.apply(Operation::from(entry))?;
+        }
+        for tombstone in range_tombstones {
+            mem_table.delete_range_at(&tombstone.start, &tombstone.end, timestamp_ms);
+        }
+
+        self.maybe_flush()
+    }
+
+    /// Flushes the MemTable to a new Table and returns the path of the Table
+    ///
+    /// With [`DatabaseBuilder::with_split_points`], the MemTable is written to
+    /// one Table per range between the split points instead, and the path of
+    /// the first of them is returned. The Table is synced to the disk before
+    /// the WAL segments backing the MemTable are deleted, so every write made
+    /// so far is durable once this returns. Flushing an empty MemTable does
+    /// nothing and returns None.
+    ///
+    /// The frozen MemTables still pending or left behind by a failed flush
+    /// are flushed first, so the Tables stay ordered from the oldest to the
+    /// newest. In memory, the MemTable is only frozen and None is returned.
+    pub fn flush(&mut self) -> Result<Option<PathBuf>> {
+        self.check_writable()?;
+        if self.in_memory {
+            self.freeze_mem_table()?;
+            return Ok(None);
+        }
+        while self.finish_background_flush(true)? {}
+        let mut pending = None;
+        while !self.frozen.is_empty() {
+            pending = self.flush_frozen()?.or(pending);
+        }
+        self.freeze_mem_table()?;
+        Ok(self.flush_frozen()?.or(pending))
+    }
+
+    /// Freezes the MemTable and moves writes on to a fresh MemTable and a new
+    /// WAL segment
+    ///
+    /// Does nothing if the MemTable is empty. In memory there is no WAL
+    /// segment to move on to.
+    fn freeze_mem_table(&mut self) -> Result<()> {
+        if self.mem_table.is_empty() {
+            return Ok(());
+        }
+        if self.in_memory {
+            let frozen = self.take_mem_table();
+            self.frozen.push(Arc::new(frozen));
+            return Ok(());
+        }
+
+        let id = self.wal_ids.last().map_or(0, |id| id + 1);
+        let wal = WriteAheadLog::open(&self.paths.wal_path(id))?.with_sync_policy(self.sync_policy);
+        if let Some(closed) = self.wal.replace(wal) {
+            self.metrics.record_wal_bytes(closed.bytes_written());
+        }
+        self.frozen_wal_ids
+            .push(std::mem::replace(&mut self.wal_ids, vec![id]));
+        let frozen = self.take_mem_table();
+        self.write_buffer.queue(frozen.size());
+        self.frozen.push(Arc::new(frozen));
+        Ok(())
+    }
+
+    /// Swaps in an empty MemTable and freezes the current one
+    ///
+    /// The current MemTable is only copied if a Cursor still pins it.
+    fn take_mem_table(&mut self) -> ImmutableMemTable {
+        let empty = Arc::new(self.mem_table.empty_like());
+        let mem_table = std::mem::replace(&mut self.mem_table, empty);
+        Arc::try_unwrap(mem_table)
+            .unwrap_or_else(|mem_table| (*mem_table).clone())
+            .freeze()
+    }
+
+    /// Writes the oldest frozen MemTable to new Tables split at the split
+    /// points, and returns the path of the first one
+    ///
+    /// The frozen MemTable stays readable until the Tables are recorded in
+    /// the Manifest, and the WAL segments backing it are only deleted
+    /// afterwards. Returns None if no MemTable is frozen.
+    fn flush_frozen(&mut self) -> Result<Option<PathBuf>> {
+        let Some(frozen) = self.frozen.first().cloned() else {
+            return Ok(None);
+        };
+
+        let paths = self.table_paths();
+        let written = self
+            .table_writer
+            .flush_split(frozen.mem_table(), |i| paths[i].clone())?;
+        self.next_table_id += paths.len() as u64;
+        self.install_flushed(&written)?;
+        Ok(written.into_iter().next())
+    }
+
+    /// Returns the paths of the Tables the next flush may write, one per
+    /// range between the split points
+    fn table_paths(&self) -> Vec<PathBuf> {
+        let count = self.table_writer.split_points().len() as u64 + 1;
+        (self.next_table_id..self.next_table_id + count)
+            .map(|id| self.paths.table_path(id))
+            .collect()
+    }
+
+    /// Freezes the MemTable and hands every frozen MemTable the flush worker
+    /// doesn't have yet to it, from the oldest to the newest
+    ///
+    /// The ids of the new Tables are taken right away, so compactions running
+    /// meanwhile don't reuse them.
+    fn start_background_flush(&mut self) -> Result<()> {
+        self.freeze_mem_table()?;
+        let Some(worker) = &self.flush_worker else {
+            return Ok(());
+        };
+
+        while self.background_flushes < self.frozen.len() {
+            let frozen = Arc::clone(&self.frozen[self.background_flushes]);
+            let paths = self.table_paths();
+            self.next_table_id += paths.len() as u64;
+            worker.submit(frozen, paths);
+            self.background_flushes += 1;
+        }
+        Ok(())
+    }
+
+    /// Records the Tables written by the flush worker for the oldest pending
+    /// MemTable once it is done, and returns whether it did
+    ///
+    /// Waits for the worker if `block` is set, and returns right away
+    /// otherwise. If the worker failed, the other pending flushes are
+    /// abandoned and the frozen MemTables kept, so the next flush writes them
+    /// again in order.
+    fn finish_background_flush(&mut self, block: bool) -> Result<bool> {
+        let Some(worker) = self
+            .flush_worker
+            .as_ref()
+            .filter(|_| self.background_flushes > 0)
+        else {
+            return Ok(false);
+        };
+        let result = match block {
+            true => worker.wait(),
+            false => match worker.try_finished() {
+                Some(result) => result,
+                None => return Ok(false),
+            },
+        };
+
+        self.background_flushes -= 1;
+        if let Err(e) = result.and_then(|paths| self.install_flushed(&paths)) {
+            self.abandon_background_flushes();
+            return Err(e);
+        }
+        self.maybe_compact()?;
+        Ok(true)
+    }
+
+    /// Waits for the pending background flushes and deletes the Tables they
+    /// wrote
+    ///
+    /// Their MemTables are newer than the one whose flush failed, so their
+    /// Tables can't be recorded before it is written.
+    fn abandon_background_flushes(&mut self) {
+        let Some(worker) = &self.flush_worker else {
+            return;
+        };
+        for _ in 0..std::mem::take(&mut self.background_flushes) {
+            for path in worker.wait().into_iter().flatten() {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    /// Adds the Tables the oldest frozen MemTable was written to, then drops
+    /// it and the WAL segments backing it
+    fn install_flushed(&mut self, paths: &[PathBuf]) -> Result<()> {
+        let tables = paths
+            .iter()
+            .map(|path| {
+                let table = TableReader::open(path)?;
+                Ok(Arc::new(with_cache(table, &self.block_cache)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.tables.extend(tables);
+        self.paths.save_manifest(&self.manifest())?;
+
+        let frozen = self.frozen.remove(0);
+        self.write_buffer.release(frozen.size());
+        for id in self.frozen_wal_ids.remove(0) {
+            fs::remove_file(self.paths.wal_path(id))?;
+        }
+        self.metrics.record_flush();
+        Ok(())
+    }
+
+    /// Compacts every Table into a single new one
+    ///
+    /// Does nothing if there are fewer than two Tables.
+    pub fn compact(&mut self) -> Result<()> {
+        self.check_writable()?;
+        if self.tables.len() < 2 {
+            return Ok(());
+        }
+        self.compact_tables(0..self.tables.len())
+    }
+
+    /// Runs the compaction chosen by the CompactionPolicy, if any
+    ///
+    /// The Database has a single level. A job is widened to every Table
+    /// between its oldest and newest Table, so the merged Table can take
+    /// their place without reordering the Tables.
+    fn maybe_compact(&mut self) -> Result<()> {
+        let Some(policy) = &self.compaction_policy else {
+            return Ok(());
+        };
+        let levels = [LevelInfo::from_tables(&self.tables)?];
+        let Some(job) = policy.should_compact(&levels) else {
+            return Ok(());
+        };
+        let (Some(&first), Some(&last)) = (job.tables.iter().min(), job.tables.iter().max()) else {
+            return Ok(());
+        };
+        if job.level != 0 || first == last || last >= self.tables.len() {
+            return Ok(());
+        }
+        self.compact_tables(first..last + 1)
+    }
+
+    /// Compacts the Tables in the range into a single new Table in their place
+    ///
+    /// The new Table is recorded in the Manifest before the old ones are
+    /// deleted, so a crash at any point leaves either the old or the new set
+    /// of Tables.
+    fn compact_tables(&mut self, range: std::ops::Range<usize>) -> Result<()> {
+        let path = self.paths.table_path(self.next_table_id);
+        let table = compaction::compact_above_with(
+            &self.table_writer,
+            &self.tables[range.clone()],
+            &self.tables[..range.start],
+            &path,
+        )?;
+        let table = with_cache(table, &self.block_cache);
+        self.next_table_id += 1;
+
+        let old_tables: Vec<_> = self.tables.splice(range, [Arc::new(table)]).collect();
+        self.paths.save_manifest(&self.manifest())?;
+        for table in old_tables {
+            fs::remove_file(table.path())?;
+        }
+        self.metrics.record_compaction();
+        Ok(())
+    }
+
+    /// Copies the current Tables into `dir` for a point in time backup
+    ///
+    /// The Tables are hard linked when possible and copied otherwise, since
+    /// they are never modified once written. A Manifest listing them is
+    /// written alongside. Writes still in the MemTables are not part of the
+    /// checkpoint, call [`Database::flush`] first to include them.
+    ///
+    /// The checkpoint can be opened as a Database of its own.
+    pub fn checkpoint(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+
+        for table in &self.tables {
+            let target = dir.join(table.path().file_name().expect("Table paths have a name"));
+            if fs::hard_link(table.path(), &target).is_err() {
+                fs::copy(table.path(), &target)?;
+            }
+        }
+
+        self.manifest().save(dir)
+    }
+
+    /// Returns a Manifest listing the current Tables
+    fn manifest(&self) -> Manifest {
+        Manifest::new(
+            self.tables
+                .iter()
+                .map(|t| {
+                    let name = t.path().file_name().expect("Table paths have a name");
+                    name.to_string_lossy().into_owned()
+                })
+                .collect(),
+        )
+    }
+
+    /// Flushes the MemTable to a new Table if it is full
+    ///
+    /// If the MemTable is mostly tombstones, the ones deleting keys that no
+    /// Table holds are dropped first, which may save the flush. With
+    /// background flushes, the write then blocks while the MemTables take up
+    /// more than the write buffer limit.
+    fn maybe_flush(&mut self) -> Result<()> {
+        if self.mem_table.is_full() && self.mem_table.tombstone_ratio() > TOMBSTONE_GC_RATIO {
+            let (frozen, tables) = (&self.frozen, &self.tables);
+            Arc::make_mut(&mut self.mem_table).compact_tombstones(|key| {
+                frozen.iter().all(|f| f.get(key).is_none())
+                    && tables.iter().all(|t| matches!(t.get(key), Ok(None)))
+            });
+        }
+        if self.flush_worker.is_none() {
+            if !self.mem_table.is_full() {
+                return Ok(());
+            }
+            self.flush()?;
+            return self.maybe_compact();
+        }
+
+        while self.finish_background_flush(false)? {}
+        if self.mem_table.is_full() {
+            self.start_background_flush()?;
+        }
+        self.stall_writes()
+    }
+
+    /// Waits for the oldest background flushes while the MemTables take up
+    /// more than the write buffer limit
+    fn stall_writes(&mut self) -> Result<()> {
+        let over_limit = |db: &Database| {
+            db.background_flushes > 0 && db.write_buffer.is_over_limit(db.mem_table.size())
+        };
+        if !over_limit(self) {
+            return Ok(());
+        }
+
+        self.metrics.record_write_stall();
+        while over_limit(self) {
+            self.finish_background_flush(true)?;
+        }
+        Ok(())
+    }
+}
+
+/// WriteBatch collects operations to be written to the Database atomically
+/// with [`Database::write_batch`]
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch {
+    operations: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteBatch {
+    /// Creates a new empty WriteBatch
+    pub fn new() -> WriteBatch {
+        WriteBatch::default()
+    }
+
+    /// Adds the setting of a Key-Value pair to the batch
+    pub fn set(&mut self, key: &[u8], value: &[u8]) -> &mut WriteBatch {
+        self.operations
+            .push((key.to_owned(), Some(value.to_owned())));
+        self
+    }
+
+    /// Adds the deletion of a key to the batch
+    pub fn delete(&mut self, key: &[u8]) -> &mut WriteBatch {
+        self.operations.push((key.to_owned(), None));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    fn into_entries(self, timestamp_ms: u128) -> Vec<MemTableEntry> {
+        self.operations
+            .into_iter()
+            .map(|(key, value)| MemTableEntry {
+                key,
+                is_deleted: value.is_none(),
+                is_merge: false,
+                value,
+                timestamp_ms,
+                expires_at_ms: None,
+            })
+            .collect()
+    }
+}
+
+/// A sorted source of records for the MergeIterator
+pub type MergeSource<'a> = Box<dyn Iterator<Item = Result<MemTableEntry>> + 'a>;
+
+/// MergeIterator performs a k-way merge of sorted sources, such as the
+/// MemTable and the Tables
+///
+/// When the same key appears in several sources, only the record with the
+/// highest timestamp is considered, with ties going to the source that comes
+/// first. A source holding several versions of a key must yield them from the
+/// newest to the oldest, and only its first one is considered. Keys whose
+/// newest record is a tombstone are skipped entirely. A newest record that
+/// is a merge is folded over the older records of its key by the
+/// MergeOperator.
+///
+/// Every source must be sorted by the MergeIterator's Comparator.
+pub struct MergeIterator<'a, C = BytewiseComparator> {
+    sources: Vec<MergeSource<'a>>,
+    /// The next record of every source, None once it needs to be refilled
+    heads: Vec<Option<MemTableEntry>>,
+    exhausted: Vec<bool>,
+    /// The last key returned, whose older versions are skipped
+    prev_key: Option<Vec<u8>>,
+    /// Range Tombstones deleting the records of the sources they cover
+    range_tombstones: Vec<RangeTombstone>,
+    merge_operator: Option<Arc<dyn MergeOperator>>,
+    /// Whether older records of the keys may lie outside the sources
+    has_older: bool,
+    comparator: C,
+}
+
+impl<'a> MergeIterator<'a> {
+    /// Creates a MergeIterator over the given sources, each sorted by key
+    ///
+    /// Sources should be ordered from the newest to the oldest.
+    pub fn new(sources: Vec<MergeSource<'a>>) -> MergeIterator<'a> {
+        MergeIterator::with_comparator(sources, BytewiseComparator)
+    }
+}
+
+impl<'a, C: Comparator> MergeIterator<'a, C> {
+    /// Creates a MergeIterator over the given sources, each sorted by the
+    /// given Comparator
+    ///
+    /// Sources should be ordered from the newest to the oldest.
+    pub fn with_comparator(sources: Vec<MergeSource<'a>>, comparator: C) -> MergeIterator<'a, C> {
+        let count = sources.len();
+        MergeIterator {
+            sources,
+            heads: (0..count).map(|_| None).collect(),
+            exhausted: vec![false; count],
+            prev_key: None,
+            range_tombstones: Vec::new(),
+            merge_operator: None,
+            has_older: false,
+            comparator,
+        }
+    }
+
+    /// Sets the Range Tombstones to apply to the records of the sources
+    ///
+    /// A record covered by a newer Range Tombstone is returned as a
+    /// tombstone, keeping its timestamp.
+    pub(crate) fn with_range_tombstones(
+        mut self,
+        range_tombstones: Vec<RangeTombstone>,
+    ) -> MergeIterator<'a, C> {
+        self.range_tombstones = range_tombstones;
+        self
+    }
+
+    /// Sets the MergeOperator folding the merge records of the sources
+    ///
+    /// With `has_older`, the merge records of a key found with no other record
+    /// of the key may still apply to a value outside the sources, so they are
+    /// combined into a single merge record instead of folded. Without a
+    /// MergeOperator, folding merge records fails with
+    /// [`Error::InvalidConfig`].
+    pub(crate) fn with_merge_operator(
+        mut self,
+        merge_operator: Option<Arc<dyn MergeOperator>>,
+        has_older: bool,
+    ) -> MergeIterator<'a, C> {
+        self.merge_operator = merge_operator;
+        self.has_older = has_older;
+        self
+    }
+
+    /// Returns the newest record of the next key, including tombstones
+    pub(crate) fn next_entry(&mut self) -> Option<Result<MemTableEntry>> {
+        for (i, source) in self.sources.iter_mut().enumerate() {
+            while self.heads[i].is_none() && !self.exhausted[i] {
+                match source.next() {
+                    Some(Ok(entry)) => {
+                        let is_older_version = self
+                            .prev_key
+                            .as_ref()
+                            .is_some_and(|k| self.comparator.compare(k, &entry.key).is_eq());
+                        if !is_older_version {
+                            self.heads[i] = Some(entry);
+                        }
+                    }
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => self.exhausted[i] = true,
+                }
+            }
+        }
+
+        let key = self
+            .heads
+            .iter()
+            .flatten()
+            .map(|e| &e.key)
+            .min_by(|a, b| self.comparator.compare(a, b))?
+            .clone();
+
+        let mut records = Vec::new();
+        for head in self.heads.iter_mut() {
+            if head
+                .as_ref()
+                .is_some_and(|e| self.comparator.compare(&e.key, &key).is_eq())
+            {
+                records.push(head.take().unwrap());
+            }
+        }
+        // The sort is stable, so ties go to the source that comes first.
+        records.sort_by_key(|e| Reverse(e.timestamp_ms));
+
+        self.prev_key = Some(key);
+        let mut records = records.into_iter();
+        let mut newest = records.next()?;
+        if self.is_range_deleted(&newest) {
+            newest.value = None;
+            newest.is_deleted = true;
+            newest.is_merge = false;
+            newest.expires_at_ms = None;
+        } else if newest.is_merge {
+            return Some(self.fold_merge(newest, records));
+        }
+        Some(Ok(newest))
+    }
+
+    fn is_range_deleted(&self, entry: &MemTableEntry) -> bool {
+        self.range_tombstones
+            .iter()
+            .any(|t| t.deletes(&self.comparator, &entry.key, entry.timestamp_ms))
+    }
+
+    /// Folds a merge record over the older records of its key, given from
+    /// the newest to the oldest
+    fn fold_merge(
+        &self,
+        mut newest: MemTableEntry,
+        older: impl Iterator<Item = MemTableEntry>,
+    ) -> Result<MemTableEntry> {
+        let mut merges = vec![newest.value.take().unwrap_or_default()];
+        let mut existing = None;
+        let mut resolved = false;
+        for record in older {
+            if self.is_range_deleted(&record) {
+                resolved = true;
+                break;
+            }
+            if !record.is_merge {
+                resolved = true;
+                existing = record.value;
+                break;
+            }
+            merges.push(record.value.unwrap_or_default());
+        }
+
+        if !resolved && self.has_older {
+            newest.value = Some(merges.iter().rev().flatten().copied().collect());
+            return Ok(newest);
+        }
+        let value = merge::fold(self.merge_operator.as_deref(), &merges, existing.as_deref())?;
+        newest.is_merge = false;
+        newest.is_deleted = value.is_none();
+        newest.value = value;
+        Ok(newest)
+    }
+}
+
+impl<C: Comparator> Iterator for MergeIterator<'_, C> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_entry()? {
+                Ok(MemTableEntry {
+                    key,
+                    value: Some(value),
+                    is_deleted: false,
+                    is_merge: false,
+                    ..
+                }) => return Some(Ok((key, value))),
+                Ok(_) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Takes up to `limit` pairs from the scan, and the key of the pair after them
+fn take_page(mut pairs: MergeIterator<'_>, limit: usize) -> Result<Page> {
+    let page = pairs.by_ref().take(limit).collect::<Result<Vec<_>>>()?;
+    let next = pairs.next().transpose()?.map(|(key, _)| key);
+    Ok((page, next))
+}
+
+/// Attaches the BlockCache of the Database, if any, to a Table
+fn with_cache(table: TableReader, block_cache: &Option<Arc<BlockCache>>) -> TableReader {
+    match block_cache {
+        Some(cache) => table.with_block_cache(Arc::clone(cache)),
+        None => table,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use std::cmp::Ordering;
+    use std::ops::Bound;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use crate::bloom::FixedPrefix;
+    use crate::cache::BlockCache;
+    use crate::cdc::{ChangeEvent, OverflowPolicy};
+    use crate::clock::MockClock;
+    use crate::compaction::{self, SizeTieredPolicy};
+    use crate::comparator::Comparator;
+    use crate::db::{
+        Cursor, Database, DatabaseBuilder, MergeIterator, MergeSource, ReverseComparator,
+        WriteBatch,
+    };
+    use crate::error::Error;
+    use crate::flush::FlushWorker;
+    use crate::key::encode_u64;
+    use crate::layout::Layout;
+    use crate::manifest::Manifest;
+    use crate::mem_table::{MemTable, MemTableEntry, Operation};
+    use crate::merge::MergeOperator;
+    use crate::metrics::{LengthStats, Metrics, Profile};
+    use crate::table::{TableReader, TableWriter};
+    use crate::verify::ProblemKind;
+    use crate::wal::{SyncPolicy, WriteAheadLog};
+
+    fn table_path(dir: &Path, id: u64) -> PathBuf {
+        Layout::new().resolve(dir).table_path(id)
+    }
+
+    fn wal_path(dir: &Path, id: u64) -> PathBuf {
+        Layout::new().resolve(dir).wal_path(id)
+    }
+
+    fn source(table: &MemTable) -> MergeSource<'_> {
+        Box::new(table.entries().iter().cloned().map(Ok))
+    }
+
+    #[test]
+    fn test_db_set_get_delete() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.delete(b"Lime").unwrap();
+
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+        assert!(db.get(b"Lime").unwrap().is_none());
+        assert!(db.get(b"Orange").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_db_contains_key() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.flush().unwrap();
+        db.delete(b"Lime").unwrap();
+        db.set(b"Orange", b"Orange Smoothie").unwrap();
+
+        let check = |db: &Database| {
+            assert!(db.contains_key(b"Apple").unwrap());
+            assert!(!db.contains_key(b"Lime").unwrap());
+            assert!(db.contains_key(b"Orange").unwrap());
+            assert!(!db.contains_key(b"Potato").unwrap());
+        };
+        check(&db);
+
+        // Tombstones in a Table shadow older Tables as well.
+        db.flush().unwrap();
+        check(&db);
+    }
+
+    #[test]
+    fn test_db_delete_range() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Banana", b"Banana Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.flush().unwrap();
+        db.set(b"Cherry", b"Cherry Smoothie").unwrap();
+        let snapshot = db.snapshot();
+
+        db.delete_range(b"Apple", b"Lime").unwrap();
+        db.set(b"Banana", b"Banana Pie").unwrap();
+
+        let check = |db: &Database| {
+            assert_eq!(db.get(b"Apple").unwrap(), None);
+            assert_eq!(db.get(b"Banana").unwrap(), Some(b"Banana Pie".to_vec()));
+            assert_eq!(db.get(b"Cherry").unwrap(), None);
+            assert_eq!(db.get(b"Lime").unwrap(), Some(b"Lime Smoothie".to_vec()));
+            assert!(!db.contains_key(b"Apple").unwrap());
+            assert!(db.contains_key(b"Banana").unwrap());
+
+            let keys: Vec<Vec<u8>> = db
+                .range(Bound::Unbounded, Bound::Unbounded)
+                .map(|pair| pair.unwrap().0)
+                .collect();
+            assert_eq!(keys, vec![b"Banana".to_vec(), b"Lime".to_vec()]);
+        };
+        check(&db);
+        assert_eq!(
+            db.get_at(&snapshot, b"Apple").unwrap(),
+            Some(b"Apple Smoothie".to_vec())
+        );
+        assert_eq!(
+            db.range_at(&snapshot, Bound::Unbounded, Bound::Unbounded)
+                .count(),
+            4
+        );
+
+        // The Range Tombstone is recovered from the WAL.
+        drop(db);
+        let mut db = Database::open(dir.path()).unwrap();
+        check(&db);
+
+        db.flush().unwrap();
+        check(&db);
+        assert_eq!(
+            db.get_at(&snapshot, b"Cherry").unwrap(),
+            Some(b"Cherry Smoothie".to_vec())
+        );
+
+        db.compact().unwrap();
+        check(&db);
+        assert!(db.tables[0].range_tombstones().is_empty());
+
+        // Writes after a reopen are newer than the Range Tombstone.
+        drop(db);
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Pie").unwrap();
+        assert_eq!(db.get(b"Apple").unwrap(), Some(b"Apple Pie".to_vec()));
+    }
+
+    #[test]
+    fn test_db_flush_and_reopen() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.mem_table = Arc::new(MemTable::with_capacity(128));
+
+        for i in 0..50u32 {
+            let key = format!("key-{:03}", i);
+            db.set(key.as_bytes(), &i.to_le_bytes()).unwrap();
+        }
+        db.delete(b"key-007").unwrap();
+        assert!(!db.tables.is_empty());
+        drop(db);
+
+        let db = Database::open(dir.path()).unwrap();
+        for i in 0..50u32 {
+            let key = format!("key-{:03}", i);
+            let value = db.get(key.as_bytes()).unwrap();
+            if i == 7 {
+                assert!(value.is_none());
+            } else {
+                assert_eq!(value.unwrap(), i.to_le_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn test_db_empty_key() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.mem_table = Arc::new(MemTable::with_capacity(64));
+
+        db.set(b"", b"Empty").unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        assert!(!db.tables.is_empty());
+
+        // The empty key sorts before every other key, in the Tables as well.
+        db.set(b"Orange", b"Orange Smoothie").unwrap();
+        let keys: Vec<Vec<u8>> = db
+            .range(Bound::Unbounded, Bound::Unbounded)
+            .map(|r| r.unwrap().0)
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                b"".to_vec(),
+                b"Apple".to_vec(),
+                b"Lime".to_vec(),
+                b"Orange".to_vec()
+            ]
+        );
+        drop(db);
+
+        // The flushed empty key survives a restart, as does its tombstone
+        // replayed from the WAL.
+        let mut db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.get(b"").unwrap().unwrap(), b"Empty");
+        db.delete(b"").unwrap();
+        drop(db);
+
+        let db = Database::open(dir.path()).unwrap();
+        assert!(db.get(b"").unwrap().is_none());
+        assert_eq!(db.get(b"Orange").unwrap().unwrap(), b"Orange Smoothie");
+    }
+
+    #[test]
+    fn test_db_tombstone_shadows_table() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.mem_table = Arc::new(MemTable::with_capacity(0));
+
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        assert_eq!(db.tables.len(), 1);
+        db.delete(b"Apple").unwrap();
+        assert_eq!(db.tables.len(), 2);
+
+        assert!(db.get(b"Apple").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_merge_iterator_newest_wins() {
+        let mut newer = MemTable::new();
+        newer.set_at(b"Apple", b"Apple Pie", 20);
+        newer.set_at(b"Lime", b"Lime Pie", 5);
+        newer.delete_at(b"Orange", 30);
+
+        let mut older = MemTable::new();
+        older.set_at(b"Apple", b"Apple Smoothie", 10);
+        older.set_at(b"Banana", b"Banana Smoothie", 10);
+        older.set_at(b"Lime", b"Lime Smoothie", 10);
+        older.set_at(b"Orange", b"Orange Smoothie", 10);
+
+        let pairs = MergeIterator::new(vec![source(&newer), source(&older)])
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(
+            pairs,
+            vec![
+                (b"Apple".to_vec(), b"Apple Pie".to_vec()),
+                (b"Banana".to_vec(), b"Banana Smoothie".to_vec()),
+                (b"Lime".to_vec(), b"Lime Smoothie".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_iterator_tie_goes_to_first_source() {
+        let mut newer = MemTable::new();
+        newer.set_at(b"Apple", b"Apple Pie", 10);
+        let mut older = MemTable::new();
+        older.set_at(b"Apple", b"Apple Smoothie", 10);
+
+        let pairs = MergeIterator::new(vec![source(&newer), source(&older)])
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(pairs, vec![(b"Apple".to_vec(), b"Apple Pie".to_vec())]);
+    }
+
+    #[test]
+    fn test_merge_iterator_over_frozen_mem_table() {
+        let dir = tempdir().unwrap();
+
+        let mut older = MemTable::new();
+        older.set_at(b"Apple", b"Apple Smoothie", 0);
+        older.set_at(b"Lime", b"Lime Smoothie", 0);
+        TableWriter::new()
+            .flush(&older, &dir.path().join("0.sst"))
+            .unwrap();
+
+        let mut newer = MemTable::new();
+        newer.delete_at(b"Apple", 10);
+        newer.set_at(b"Orange", b"Orange Smoothie", 10);
+
+        let older = TableReader::open(&dir.path().join("0.sst")).unwrap();
+        let sources: Vec<MergeSource> = vec![
+            Box::new(newer.freeze().into_iter().map(Ok)),
+            Box::new(older.iter()),
+        ];
+
+        let pairs = MergeIterator::new(sources)
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                (b"Lime".to_vec(), b"Lime Smoothie".to_vec()),
+                (b"Orange".to_vec(), b"Orange Smoothie".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_iterator_versions() {
+        let mut newer = MemTable::new().with_versions(3);
+        newer.set_at(b"Apple", b"Apple Juice", 10);
+        newer.set_at(b"Apple", b"Apple Pie", 30);
+        newer.set_at(b"Lime", b"Lime Smoothie", 10);
+
+        let mut older = MemTable::new().with_versions(3);
+        older.set_at(b"Apple", b"Apple Smoothie", 20);
+        older.set_at(b"Apple", b"Apple Tart", 5);
+
+        let sources: Vec<MergeSource> = vec![source(&newer), source(&older)];
+        let pairs = MergeIterator::new(sources)
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                (b"Apple".to_vec(), b"Apple Pie".to_vec()),
+                (b"Lime".to_vec(), b"Lime Smoothie".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_iterator_over_tables() {
+        let dir = tempdir().unwrap();
+
+        let mut first = MemTable::new();
+        first.set_at(b"Apple", b"Apple Smoothie", 0);
+        first.set_at(b"Lime", b"Lime Smoothie", 0);
+        TableWriter::new()
+            .flush(&first, &dir.path().join("0.sst"))
+            .unwrap();
+
+        let mut second = MemTable::new();
+        second.delete_at(b"Apple", 10);
+        second.set_at(b"Orange", b"Orange Smoothie", 10);
+        TableWriter::new()
+            .flush(&second, &dir.path().join("1.sst"))
+            .unwrap();
+
+        let mut active = MemTable::new();
+        active.set_at(b"Apple", b"Apple Pie", 20);
+        active.delete_at(b"Orange", 20);
+
+        let first = TableReader::open(&dir.path().join("0.sst")).unwrap();
+        let second = TableReader::open(&dir.path().join("1.sst")).unwrap();
+        let sources: Vec<MergeSource> = vec![
+            source(&active),
+            Box::new(second.iter()),
+            Box::new(first.iter()),
+        ];
+
+        let pairs = MergeIterator::new(sources)
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                (b"Apple".to_vec(), b"Apple Pie".to_vec()),
+                (b"Lime".to_vec(), b"Lime Smoothie".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_iterator_custom_comparator() {
+        struct ReverseComparator;
+
+        impl Comparator for ReverseComparator {
+            fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+                b.cmp(a)
+            }
+        }
+
+        let mut newer = MemTable::with_comparator(ReverseComparator);
+        newer.set_at(b"Apple", b"Apple Pie", 20);
+        newer.delete_at(b"Lime", 20);
+        let mut older = MemTable::with_comparator(ReverseComparator);
+        older.set_at(b"Apple", b"Apple Smoothie", 10);
+        older.set_at(b"Lime", b"Lime Smoothie", 10);
+        older.set_at(b"Orange", b"Orange Smoothie", 10);
+
+        let sources: Vec<MergeSource> = vec![
+            Box::new(newer.entries().iter().cloned().map(Ok)),
+            Box::new(older.entries().iter().cloned().map(Ok)),
+        ];
+        let pairs = MergeIterator::with_comparator(sources, ReverseComparator)
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(
+            pairs,
+            vec![
+                (b"Orange".to_vec(), b"Orange Smoothie".to_vec()),
+                (b"Apple".to_vec(), b"Apple Pie".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_iterator_empty() {
+        assert_eq!(MergeIterator::new(Vec::new()).count(), 0);
+
+        let table = MemTable::new();
+        assert_eq!(MergeIterator::new(vec![source(&table)]).count(), 0);
+    }
+
+    #[test]
+    fn test_db_write_batch() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+
+        let mut batch = WriteBatch::new();
+        batch
+            .set(b"Orange", b"Orange Smoothie")
+            .set(b"Lime", b"Lime Smoothie")
+            .delete(b"Apple")
+            .set(b"Lime", b"Lime Pie");
+        assert_eq!(batch.len(), 4);
+        db.write_batch(batch).unwrap();
+
+        assert!(db.get(b"Apple").unwrap().is_none());
+        assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Pie");
+        assert_eq!(db.get(b"Orange").unwrap().unwrap(), b"Orange Smoothie");
+        drop(db);
+
+        let db = Database::open(dir.path()).unwrap();
+        assert!(db.get(b"Apple").unwrap().is_none());
+        assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Pie");
+        assert_eq!(db.get(b"Orange").unwrap().unwrap(), b"Orange Smoothie");
+    }
+
+    #[test]
+    fn test_db_write_batch_torn() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+
+        let mut batch = WriteBatch::new();
+        batch
+            .set(b"Lime", b"Lime Smoothie")
+            .set(b"Orange", b"Orange Smoothie");
+        db.write_batch(batch).unwrap();
+        drop(db);
+
+        // Simulate a crash in the middle of writing the batch.
+        let wal_path = wal_path(dir.path(), 0);
+        let len = std::fs::metadata(&wal_path).unwrap().len();
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&wal_path)
+            .unwrap();
+        file.set_len(len - 1).unwrap();
+
+        let db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+        assert!(db.get(b"Lime").unwrap().is_none());
+        assert!(db.get(b"Orange").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_db_write_after_torn_wal() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        drop(db);
+
+        let wal_path = wal_path(dir.path(), 0);
+        let len = std::fs::metadata(&wal_path).unwrap().len();
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&wal_path)
+            .unwrap();
+        file.set_len(len - 1).unwrap();
+
+        // The writes made after the torn record must not be lost with it.
+        let mut db = Database::open(dir.path()).unwrap();
+        assert!(std::fs::metadata(&wal_path).unwrap().len() < len - 1);
+        db.set(b"Orange", b"Orange Smoothie").unwrap();
+        drop(db);
+
+        let db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+        assert!(db.get(b"Lime").unwrap().is_none());
+        assert_eq!(db.get(b"Orange").unwrap().unwrap(), b"Orange Smoothie");
+    }
+
+    #[test]
+    fn test_db_wal_rotation() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.mem_table = Arc::new(MemTable::with_capacity(64));
+
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        assert!(wal_path(dir.path(), 0).exists());
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        assert_eq!(db.tables.len(), 1);
+        assert!(!wal_path(dir.path(), 0).exists());
+        assert!(wal_path(dir.path(), 1).exists());
+
+        db.set(b"Orange", b"Orange Smoothie").unwrap();
+        drop(db);
+
+        let db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.mem_table.len(), 1);
+        assert_eq!(db.get(b"Orange").unwrap().unwrap(), b"Orange Smoothie");
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+        assert_eq!(db.wal_ids, vec![1]);
+    }
+
+    #[test]
+    fn test_db_recover_several_wal_segments() {
+        let dir = tempdir().unwrap();
+
+        // Simulate a crash after a new segment was started but before the
+        // older one was deleted.
+        let mut wal = WriteAheadLog::open(&wal_path(dir.path(), 3)).unwrap();
+        wal.append(&MemTableEntry {
+            key: b"Apple".to_vec(),
+            value: Some(b"Apple Smoothie".to_vec()),
+            timestamp_ms: 10,
+            is_deleted: false,
+            is_merge: false,
+            expires_at_ms: None,
+        })
+        .unwrap();
+        drop(wal);
+        let mut wal = WriteAheadLog::open(&wal_path(dir.path(), 4)).unwrap();
+        wal.append(&MemTableEntry {
+            key: b"Apple".to_vec(),
+            value: Some(b"Apple Pie".to_vec()),
+            timestamp_ms: 20,
+            is_deleted: false,
+            is_merge: false,
+            expires_at_ms: None,
+        })
+        .unwrap();
+        drop(wal);
+
+        let mut db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Pie");
+        assert_eq!(db.wal_ids, vec![3, 4]);
+
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        drop(db);
+        let db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Smoothie");
+    }
+
+    #[test]
+    fn test_db_flush_error() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+
+        // A directory in the way of the new Table makes the flush fail after
+        // the Table has been written.
+        let path = table_path(dir.path(), db.next_table_id);
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join("blocker"), b"").unwrap();
+
+        assert!(matches!(db.flush(), Err(Error::Io(_))));
+        assert!(db.tables.is_empty());
+        assert!(Manifest::load(dir.path()).unwrap().is_none());
+        assert!(std::fs::read_dir(dir.path()).unwrap().all(|e| e
+            .unwrap()
+            .path()
+            .extension()
+            .unwrap()
+            != "tmp"));
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+
+        // The MemTable is kept, so a later flush writes it once there's room.
+        std::fs::remove_dir_all(&path).unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.flush().unwrap();
+        assert_eq!(db.tables.len(), 2);
+        assert_eq!(db.tables[0].path(), path);
+        drop(db);
+
+        let db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+        assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Smoothie");
+    }
+
+    #[test]
+    fn test_db_flush() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        assert!(db.flush().unwrap().is_none());
+        assert!(db.tables.is_empty());
+
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.delete(b"Lime").unwrap();
+        let path = db.flush().unwrap().unwrap();
+        assert_eq!(path, dir.path().join("000000.sst"));
+        assert!(db.mem_table.is_empty());
+        assert!(db.flush().unwrap().is_none());
+        assert_eq!(db.tables.len(), 1);
+        drop(db);
+
+        let db = Database::open(dir.path()).unwrap();
+        assert!(db.mem_table.is_empty());
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+        assert!(db.get(b"Lime").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_db_checkpoint() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.flush().unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.flush().unwrap();
+        db.set(b"Orange", b"Orange Smoothie").unwrap();
+
+        let backup = dir.path().join("backup");
+        db.checkpoint(&backup).unwrap();
+        db.set(b"Apple", b"Apple Pie").unwrap();
+        db.flush().unwrap();
+
+        let manifest = std::fs::read_to_string(backup.join("MANIFEST")).unwrap();
+        assert_eq!(manifest, "000000.sst\n000001.sst\n");
+
+        let backup = Database::open(&backup).unwrap();
+        assert_eq!(backup.tables.len(), 2);
+        assert_eq!(backup.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+        assert_eq!(backup.get(b"Lime").unwrap().unwrap(), b"Lime Smoothie");
+        assert!(backup.get(b"Orange").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_db_compact() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.flush().unwrap();
+        db.delete(b"Lime").unwrap();
+        db.set(b"Orange", b"Orange Smoothie").unwrap();
+        db.flush().unwrap();
+
+        db.compact().unwrap();
+        assert_eq!(db.tables.len(), 1);
+        assert!(!table_path(dir.path(), 0).exists());
+        assert!(!table_path(dir.path(), 1).exists());
+        drop(db);
+
+        let db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.tables.len(), 1);
+        assert_eq!(db.tables[0].path(), table_path(dir.path(), 2));
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+        assert!(db.get(b"Lime").unwrap().is_none());
+        assert_eq!(db.get(b"Orange").unwrap().unwrap(), b"Orange Smoothie");
+    }
+
+    #[test]
+    fn test_db_recover_interrupted_compaction() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.flush().unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.flush().unwrap();
+
+        // Simulate a crash while the compacted Table was being written, before
+        // the Manifest was updated.
+        let orphan = table_path(dir.path(), db.next_table_id);
+        compaction::compact(&db.tables, &orphan).unwrap();
+        let len = std::fs::metadata(&orphan).unwrap().len();
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&orphan)
+            .unwrap();
+        file.set_len(len / 2).unwrap();
+        drop(db);
+
+        let mut db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.tables.len(), 2);
+        assert!(!orphan.exists());
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+        assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Smoothie");
+
+        db.compact().unwrap();
+        assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Smoothie");
+    }
+
+    #[test]
+    fn test_db_size_of_range() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        for i in 0..1000u32 {
+            db.set(&i.to_be_bytes(), &[0; 100]).unwrap();
+        }
+        db.flush().unwrap();
+        for i in 1000..1010u32 {
+            db.set(&i.to_be_bytes(), &[0; 100]).unwrap();
+        }
+
+        let key = |i: u32| i.to_be_bytes();
+        assert_eq!(db.size_of_range(&key(10), &key(10)), 0);
+        assert_eq!(db.size_of_range(&key(20), &key(10)), 0);
+        assert_eq!(db.size_of_range(&key(1000), &key(1005)), 5 * (4 + 100 + 37));
+
+        // Every record takes up about 130 bytes in the Table.
+        let half = db.size_of_range(&key(0), &key(500));
+        let block = 4 * 1024;
+        assert!(half.abs_diff(500 * 130) <= block);
+        assert!(db.size_of_range(&key(0), &key(2000)) > half);
+    }
+
+    #[test]
+    fn test_db_tombstone_gc() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.flush().unwrap();
+        db.mem_table = Arc::new(MemTable::with_capacity(100));
+
+        db.delete(b"Apple").unwrap();
+        db.delete(b"Lime").unwrap();
+        db.delete(b"Orange").unwrap();
+        db.delete(b"Peach").unwrap();
+        db.delete(b"Plum").unwrap();
+
+        // The tombstones of keys missing from the Table were dropped instead
+        // of flushing.
+        assert_eq!(db.tables.len(), 1);
+        assert_eq!(db.mem_table.len(), 1);
+        assert!(db.mem_table.get(b"Apple").unwrap().is_deleted);
+        assert!(db.get(b"Apple").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_db_cursor() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        for fruit in ["Apple", "Banana", "Cherry", "Lime", "Orange", "Peach"] {
+            db.set(fruit.as_bytes(), b"Smoothie").unwrap();
+        }
+        db.flush().unwrap();
+        db.set(b"Banana", b"Pie").unwrap();
+        db.delete(b"Cherry").unwrap();
+        db.flush().unwrap();
+        db.delete_range(b"Lime", b"Orange").unwrap();
+        db.set(b"Mango", b"Smoothie").unwrap();
+        db.delete(b"Peach").unwrap();
+
+        let forward = |cursor: &mut Cursor| {
+            let mut keys = Vec::new();
+            while let Some(key) = cursor.key() {
+                keys.push(String::from_utf8(key.to_vec()).unwrap());
+                cursor.next().unwrap();
+            }
+            keys
+        };
+        let backward = |cursor: &mut Cursor| {
+            let mut keys = Vec::new();
+            while let Some(key) = cursor.key() {
+                keys.push(String::from_utf8(key.to_vec()).unwrap());
+                cursor.prev().unwrap();
+            }
+            keys
+        };
+
+        let mut cursor = db.cursor();
+        assert!(!cursor.is_positioned());
+        cursor.seek(b"Blueberry").unwrap();
+        assert_eq!(cursor.key(), Some(&b"Mango"[..]));
+        assert_eq!(forward(&mut cursor), vec!["Mango", "Orange"]);
+        // Moving past either end leaves the Cursor there.
+        cursor.prev().unwrap();
+        assert!(!cursor.is_positioned());
+
+        cursor.seek(b"Banana").unwrap();
+        assert_eq!(cursor.value(), Some(&b"Pie"[..]));
+        cursor.next().unwrap();
+        assert_eq!(cursor.key(), Some(&b"Mango"[..]));
+        assert_eq!(backward(&mut cursor), vec!["Mango", "Banana", "Apple"]);
+
+        cursor.seek_to_last().unwrap();
+        assert_eq!(
+            backward(&mut cursor),
+            vec!["Orange", "Mango", "Banana", "Apple"]
+        );
+        cursor.seek_to_first().unwrap();
+        assert_eq!(
+            forward(&mut cursor),
+            vec!["Apple", "Banana", "Mango", "Orange"]
+        );
+        cursor.seek(b"Zucchini").unwrap();
+        assert!(!cursor.is_positioned());
+    }
+
+    #[test]
+    fn test_db_cursor_pins_view() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        for fruit in ["Apple", "Banana", "Cherry"] {
+            db.set(fruit.as_bytes(), b"Smoothie").unwrap();
+        }
+        db.flush().unwrap();
+        db.set(b"Lime", b"Pie").unwrap();
+
+        let pairs = |cursor: &mut Cursor| {
+            let mut pairs = Vec::new();
+            cursor.seek_to_first().unwrap();
+            while let (Some(key), Some(value)) = (cursor.key(), cursor.value()) {
+                pairs.push((key.to_vec(), value.to_vec()));
+                cursor.next().unwrap();
+            }
+            pairs
+        };
+
+        let mut cursor = db.cursor();
+        let before = pairs(&mut cursor);
+        assert_eq!(before.len(), 4);
+
+        db.set(b"Apple", b"Pie").unwrap();
+        db.delete(b"Lime").unwrap();
+        db.delete_range(b"Banana", b"Cherry\0").unwrap();
+        db.flush().unwrap();
+        db.set(b"Mango", b"Smoothie").unwrap();
+        db.compact().unwrap();
+
+        // The Cursor still sees the pairs as they were when it was created,
+        // in both directions, while the Database sees the writes.
+        assert_eq!(pairs(&mut cursor), before);
+        cursor.seek_to_last().unwrap();
+        assert_eq!(cursor.key(), Some(&b"Lime"[..]));
+        cursor.prev().unwrap();
+        assert_eq!(cursor.key(), Some(&b"Cherry"[..]));
+        assert_eq!(
+            pairs(&mut db.cursor()),
+            vec![
+                (b"Apple".to_vec(), b"Pie".to_vec()),
+                (b"Mango".to_vec(), b"Smoothie".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_db_changes_since() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        for i in 0..20 {
+            db.set(format!("key-{:02}", i).as_bytes(), b"Smoothie")
+                .unwrap();
+        }
+        db.flush().unwrap();
+        let since = db.snapshot().timestamp_ms();
+        for i in (0..20).step_by(4) {
+            db.set(format!("key-{:02}", i).as_bytes(), b"Pie").unwrap();
+        }
+        db.delete(b"key-05").unwrap();
+        db.flush().unwrap();
+        db.delete_range(b"key-10", b"key-13").unwrap();
+        db.set(b"key-20", b"Pie").unwrap();
+
+        let keys = |events: &[ChangeEvent]| -> Vec<String> {
+            events
+                .iter()
+                .map(|e| String::from_utf8(e.key.clone()).unwrap())
+                .collect()
+        };
+        let resume = Some((Vec::new(), since));
+        let changes: Vec<_> = db
+            .changes_since(resume.clone())
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(
+            keys(&changes),
+            [
+                "key-00", "key-04", "key-05", "key-08", "key-10", "key-11", "key-12", "key-16",
+                "key-20"
+            ]
+        );
+        assert!(changes.iter().all(|e| e.timestamp_ms > since));
+        // Keys deleted by the Range Tombstone changed when it was written.
+        let deleted: Vec<_> = changes.iter().filter(|e| e.is_deleted).collect();
+        assert_eq!(deleted.len(), 4);
+        assert_eq!(changes[0].value.as_deref(), Some(&b"Pie"[..]));
+        assert_eq!(db.changes_since(None).count(), 21);
+
+        // Consume half of the changes, then resume after a restart.
+        let consumed: Vec<_> = db
+            .changes_since(resume)
+            .take(4)
+            .map(|e| e.unwrap())
+            .collect();
+        let resume = Some((consumed.last().unwrap().key.clone(), since));
+        drop(db);
+        let db = Database::open(dir.path()).unwrap();
+        let rest: Vec<_> = db.changes_since(resume).map(|e| e.unwrap()).collect();
+        assert_eq!([consumed, rest].concat(), changes);
+    }
+
+    #[test]
+    fn test_db_subscribe() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Juice").unwrap();
+
+        let receiver = db.subscribe();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.delete(b"Lime").unwrap();
+        let mut batch = WriteBatch::new();
+        batch
+            .set(b"Orange", b"Orange Smoothie")
+            .set(b"Lime", b"Lime Smoothie");
+        db.write_batch(batch).unwrap();
+        drop(db);
+
+        let events: Vec<ChangeEvent> = receiver.collect();
+        let keys: Vec<&[u8]> = events.iter().map(|e| e.key.as_slice()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                b"Apple".as_slice(),
+                b"Lime".as_slice(),
+                b"Lime".as_slice(),
+                b"Orange".as_slice()
+            ]
+        );
+        let values: Vec<Option<&[u8]>> = events.iter().map(|e| e.value.as_deref()).collect();
+        assert_eq!(
+            values,
+            vec![
+                Some(b"Apple Smoothie".as_slice()),
+                None,
+                Some(b"Lime Smoothie".as_slice()),
+                Some(b"Orange Smoothie".as_slice())
+            ]
+        );
+        assert!(events[1].is_deleted);
+        assert!(events
+            .windows(2)
+            .all(|w| w[0].timestamp_ms <= w[1].timestamp_ms));
+        assert_eq!(events[2].timestamp_ms, events[3].timestamp_ms);
+    }
+
+    #[test]
+    fn test_db_on_write_reverse_index() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        // Index the fruits by color under color/<color>/<fruit>.
+        db.on_write(Box::new(|event: &ChangeEvent| {
+            let (Some(fruit), Some(color)) = (event.key.strip_prefix(b"fruit/"), &event.value)
+            else {
+                return Vec::new();
+            };
+            let key = [b"color/", color.as_slice(), b"/", fruit].concat();
+            vec![Operation::Set {
+                key,
+                value: Vec::new(),
+                timestamp_ms: 0,
+                expires_at_ms: None,
+            }]
+        }));
+
+        db.set(b"fruit/Lime", b"Green").unwrap();
+        let mut batch = WriteBatch::new();
+        batch
+            .set(b"fruit/Apple", b"Green")
+            .set(b"fruit/Cherry", b"Red");
+        db.write_batch(batch).unwrap();
+        db.delete(b"fruit/Banana").unwrap();
+
+        let check = |db: &Database| {
+            let green = db
+                .range(
+                    Bound::Included(b"color/Green/"),
+                    Bound::Excluded(b"color/Green0"),
+                )
+                .map(|pair| pair.unwrap().0)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                green,
+                vec![b"color/Green/Apple".to_vec(), b"color/Green/Lime".to_vec()]
+            );
+            assert!(db.contains_key(b"color/Red/Cherry").unwrap());
+        };
+        check(&db);
+
+        // The index entries were written in the same WAL batches.
+        drop(db);
+        let db = Database::open(dir.path()).unwrap();
+        check(&db);
+    }
+
+    #[test]
+    fn test_db_subscribe_dropped_receiver() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        let receiver = db.subscribe_with(1, OverflowPolicy::Backpressure);
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        drop(receiver);
+
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        assert!(db.subscribers.is_empty());
+    }
+
+    #[test]
+    fn test_db_compare_and_swap() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Juice").unwrap();
+        db.flush().unwrap();
+
+        // The current value is read from the Table.
+        assert!(db
+            .compare_and_swap(b"Apple", Some(b"Apple Juice"), Some(b"Apple Smoothie"))
+            .unwrap());
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+
+        // A mismatch doesn't write anything.
+        assert!(!db
+            .compare_and_swap(b"Apple", Some(b"Apple Juice"), Some(b"Apple Pie"))
+            .unwrap());
+        assert!(!db
+            .compare_and_swap(b"Apple", None, Some(b"Apple Pie"))
+            .unwrap());
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+
+        assert!(db
+            .compare_and_swap(b"Apple", Some(b"Apple Smoothie"), None)
+            .unwrap());
+        assert!(db.get(b"Apple").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_db_compare_and_swap_absent() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        assert!(!db
+            .compare_and_swap(b"Lime", Some(b"Lime Smoothie"), None)
+            .unwrap());
+        assert!(db
+            .compare_and_swap(b"Lime", None, Some(b"Lime Juice"))
+            .unwrap());
+        assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Juice");
+
+        // A deleted key counts as absent, even with older versions in Tables.
+        db.flush().unwrap();
+        db.delete(b"Lime").unwrap();
+        assert!(db
+            .compare_and_swap(b"Lime", None, Some(b"Lime Smoothie"))
+            .unwrap());
+        assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Smoothie");
+    }
+
+    #[test]
+    fn test_db_builder() {
+        let dir = tempdir().unwrap();
+        let builder = || {
+            DatabaseBuilder::new()
+                .with_flush_threshold(100)
+                .with_sync_policy(SyncPolicy::Never)
+                .with_block_size(64)
+                .with_bloom_fp_rate(0.1)
+        };
+
+        let mut db = builder().open(dir.path()).unwrap();
+        assert_eq!(db.mem_table.max_size(), 100);
+        assert_eq!(db.wal.as_ref().unwrap().sync_policy(), SyncPolicy::Never);
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        drop(db);
+
+        // The recovered MemTable keeps the configured threshold.
+        let mut db = builder().open(dir.path()).unwrap();
+        assert_eq!(db.mem_table.max_size(), 100);
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.set(b"Orange", b"Orange Smoothie").unwrap();
+        assert_eq!(db.tables.len(), 1);
+        assert_eq!(db.wal.as_ref().unwrap().sync_policy(), SyncPolicy::Never);
+    }
+
+    #[test]
+    fn test_db_block_cache() {
+        let dir = tempdir().unwrap();
+        let cache = Arc::new(BlockCache::new(1024 * 1024));
+        let mut db = DatabaseBuilder::new()
+            .with_block_cache(Arc::clone(&cache))
+            .open(dir.path())
+            .unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.flush().unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.flush().unwrap();
+        db.compact().unwrap();
+
+        // Tables written by a compaction use the cache as well.
+        let (misses, hits) = (cache.misses(), cache.hits());
+        assert_eq!(db.get(b"Apple").unwrap(), Some(b"Apple Smoothie".to_vec()));
+        assert_eq!(db.get(b"Apple").unwrap(), Some(b"Apple Smoothie".to_vec()));
+        assert_eq!((cache.misses(), cache.hits()), (misses + 1, hits + 1));
+        drop(db);
+
+        let db = DatabaseBuilder::new()
+            .with_block_cache(Arc::clone(&cache))
+            .open(dir.path())
+            .unwrap();
+        assert_eq!(db.get(b"Lime").unwrap(), Some(b"Lime Smoothie".to_vec()));
+        assert_eq!((cache.misses(), cache.hits()), (misses + 2, hits + 1));
+    }
+
+    #[test]
+    fn test_db_profile() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.profile(), Profile::default());
+
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.flush().unwrap();
+        db.delete(b"Apple").unwrap();
+        db.set(b"Orange", b"Orange Juice").unwrap();
+
+        // The shadowed Apple still counts until it is compacted away.
+        let profile = db.profile();
+        assert_eq!(
+            profile,
+            Profile {
+                live_count: 3,
+                tombstone_count: 1,
+                key_lens: LengthStats {
+                    count: 4,
+                    min: 4,
+                    max: 6,
+                    total: 20,
+                },
+                value_lens: LengthStats {
+                    count: 3,
+                    min: 12,
+                    max: 14,
+                    total: 39,
+                },
+            }
+        );
+        assert_eq!(profile.key_lens.mean(), 5.0);
+        assert_eq!(profile.value_lens.mean(), 13.0);
+
+        drop(db);
+        let mut db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.profile(), profile);
+        db.flush().unwrap();
+        assert_eq!(db.profile(), profile);
+        db.compact().unwrap();
+        let profile = db.profile();
+        assert_eq!((profile.live_count, profile.tombstone_count), (2, 0));
+        assert_eq!((profile.key_lens.min, profile.key_lens.max), (4, 6));
+    }
+
+    #[test]
+    fn test_db_metrics() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        assert_eq!(
+            db.metrics(),
+            Metrics {
+                table_reads: vec![0],
+                ..Metrics::default()
+            }
+        );
+
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        let wal_bytes_written = db.metrics().wal_bytes_written;
+        assert!(wal_bytes_written > 0);
+        db.flush().unwrap();
+        // Bytes of closed WAL segments are still counted.
+        assert_eq!(db.metrics().wal_bytes_written, wal_bytes_written);
+
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        assert!(db.get(b"Lime").unwrap().is_some());
+        db.flush().unwrap();
+        // The newest Table rules Apple out, the oldest one is read.
+        assert!(db.get(b"Apple").unwrap().is_some());
+        db.compact().unwrap();
+
+        let metrics = db.metrics();
+        assert_eq!(metrics.mem_table_hits, 1);
+        assert_eq!(metrics.table_reads, vec![1]);
+        assert_eq!(metrics.bloom_negatives, 1);
+        assert!(metrics.wal_bytes_written > wal_bytes_written);
+        assert_eq!(metrics.flushes, 2);
+        assert_eq!(metrics.compactions, 1);
+    }
+
+    struct CounterOperator;
+
+    impl MergeOperator for CounterOperator {
+        fn full_merge(&self, existing: Option<&[u8]>, operands: &[Vec<u8>]) -> Option<Vec<u8>> {
+            let count = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().unwrap());
+            let total = existing.map_or(0, count) + operands.iter().map(|o| count(o)).sum::<u64>();
+            Some(total.to_le_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn test_db_merge_counter() {
+        let dir = tempdir().unwrap();
+        let open = || {
+            DatabaseBuilder::new()
+                .with_merge_operator(CounterOperator)
+                .open(dir.path())
+                .unwrap()
+        };
+        let add = |db: &mut Database, n: u64| db.merge(b"hits", &n.to_le_bytes()).unwrap();
+        let count = |db: &Database| {
+            let value = db.get(b"hits").unwrap()?;
+            Some(u64::from_le_bytes(value.try_into().unwrap()))
+        };
+
+        let mut db = open();
+        assert_eq!(count(&db), None);
+        add(&mut db, 1);
+        add(&mut db, 2);
+        assert_eq!(count(&db), Some(3));
+
+        // The operands in the MemTable are folded over the ones in the
+        // Tables, and compaction folds the Tables into a single value.
+        db.flush().unwrap();
+        add(&mut db, 3);
+        assert_eq!(count(&db), Some(6));
+        db.flush().unwrap();
+        db.compact().unwrap();
+        assert!(!db.tables[0].get(b"hits").unwrap().unwrap().is_merge);
+        assert_eq!(count(&db), Some(6));
+
+        // Operands are recovered from the WAL.
+        add(&mut db, 4);
+        drop(db);
+        let mut db = open();
+        assert_eq!(count(&db), Some(10));
+        assert!(db.contains_key(b"hits").unwrap());
+        let pairs: Vec<_> = db.range(Bound::Unbounded, Bound::Unbounded).collect();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].as_ref().unwrap().1, 10u64.to_le_bytes());
+
+        // Deletions reset the counter, a value in the MemTable is folded into
+        // right away.
+        db.flush().unwrap();
+        db.delete_range(b"a", b"z").unwrap();
+        add(&mut db, 5);
+        assert_eq!(count(&db), Some(5));
+        db.set(b"hits", &100u64.to_le_bytes()).unwrap();
+        add(&mut db, 1);
+        assert!(!db.mem_table.get(b"hits").unwrap().is_merge);
+        assert_eq!(count(&db), Some(101));
+        db.delete(b"hits").unwrap();
+        add(&mut db, 7);
+        assert_eq!(count(&db), Some(7));
+
+        // Merging and reading merges need the MergeOperator.
+        db.merge(b"misses", &1u64.to_le_bytes()).unwrap();
+        db.flush().unwrap();
+        drop(db);
+        let mut db = Database::open(dir.path()).unwrap();
+        assert!(db.get(b"hits").unwrap().is_some());
+        assert!(matches!(db.get(b"misses"), Err(Error::InvalidConfig(_))));
+        assert!(matches!(
+            db.merge(b"hits", &1u64.to_le_bytes()),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_db_merge_without_operator() {
+        // A write hook can't sneak a merge past the missing MergeOperator.
+        let mut db = Database::in_memory();
+        db.on_write(Box::new(|event: &ChangeEvent| {
+            vec![Operation::Merge {
+                key: [b"count/", event.key.as_slice()].concat(),
+                operands: vec![1u64.to_le_bytes().to_vec()],
+                timestamp_ms: 0,
+            }]
+        }));
+        assert!(matches!(
+            db.set(b"Apple", b"Apple Smoothie"),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(db.get(b"Apple").unwrap().is_none());
+
+        // Nor can a WAL holding merges be replayed without it.
+        let dir = tempdir().unwrap();
+        let open = |builder: DatabaseBuilder| builder.open(dir.path());
+        let mut db = open(DatabaseBuilder::new().with_merge_operator(CounterOperator)).unwrap();
+        db.set(b"hits", &1u64.to_le_bytes()).unwrap();
+        db.merge(b"hits", &2u64.to_le_bytes()).unwrap();
+        drop(db);
+        assert!(matches!(
+            open(DatabaseBuilder::new()),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            WriteAheadLog::recover(&wal_path(dir.path(), 0)),
+            Err(Error::InvalidConfig(_))
+        ));
+        let db = open(DatabaseBuilder::new().with_merge_operator(CounterOperator)).unwrap();
+        assert_eq!(db.get(b"hits").unwrap().unwrap(), 3u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_db_on_write_merge_operands() {
+        let mut db = DatabaseBuilder::new()
+            .with_merge_operator(CounterOperator)
+            .open_in_memory()
+            .unwrap();
+        db.on_write(Box::new(|event: &ChangeEvent| match event.key.as_slice() {
+            b"visit" => vec![Operation::Merge {
+                key: b"hits".to_vec(),
+                operands: (1..=3u64).map(|n| n.to_le_bytes().to_vec()).collect(),
+                timestamp_ms: 0,
+            }],
+            _ => Vec::new(),
+        }));
+        let receiver = db.subscribe();
+        db.set(b"visit", b"").unwrap();
+        drop(db);
+
+        // Subscribers get every operand of the merge.
+        let operands: Vec<Vec<u8>> = receiver
+            .filter(|e| e.key == b"hits")
+            .map(|e| e.value.unwrap())
+            .collect();
+        assert_eq!(
+            operands,
+            (1..=3u64)
+                .map(|n| n.to_le_bytes().to_vec())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_db_merge_range_deleted() {
+        let dir = tempdir().unwrap();
+        let open = || {
+            DatabaseBuilder::new()
+                .with_merge_operator(CounterOperator)
+                .open(dir.path())
+                .unwrap()
+        };
+        let count = |db: &Database| {
+            let value = db.get(b"hits").unwrap()?;
+            Some(u64::from_le_bytes(value.try_into().unwrap()))
+        };
+
+        // The value and the Range Tombstone deleting it are both in the
+        // MemTable.
+        let mut db = open();
+        db.set(b"hits", &100u64.to_le_bytes()).unwrap();
+        db.delete_range(b"a", b"z").unwrap();
+        db.merge(b"hits", &5u64.to_le_bytes()).unwrap();
+        assert_eq!(count(&db), Some(5));
+
+        drop(db);
+        let db = open();
+        assert_eq!(count(&db), Some(5));
+    }
+
+    #[test]
+    fn test_db_background_flush() {
+        let dir = tempdir().unwrap();
+        let mut db = DatabaseBuilder::new()
+            .with_flush_threshold(64 * 1024)
+            .with_background_flush(true)
+            // Syncing every write would measure the disk instead of the flush.
+            .with_sync_policy(SyncPolicy::Never)
+            .open(dir.path())
+            .unwrap();
+
+        let value = [7; 100];
+        let mut slowest = Duration::ZERO;
+        for n in 0..5000u64 {
+            let start = Instant::now();
+            db.set(&encode_u64(n), &value).unwrap();
+            slowest = slowest.max(start.elapsed());
+        }
+        assert!(
+            slowest < Duration::from_millis(100),
+            "a write took {slowest:?}"
+        );
+        assert!(db.metrics().flushes > 0);
+
+        let check = |db: &Database| {
+            for n in 0..5000u64 {
+                assert_eq!(db.get(&encode_u64(n)).unwrap().unwrap(), value);
+            }
+        };
+        check(&db);
+
+        // A MemTable still pending in the worker is recovered from the WAL.
+        drop(db);
+        let mut db = Database::open(dir.path()).unwrap();
+        check(&db);
+        db.flush().unwrap();
+        assert!(db.frozen.is_empty() && db.mem_table.is_empty());
+        check(&db);
+    }
+
+    #[test]
+    fn test_db_write_buffer_limit() {
+        let dir = tempdir().unwrap();
+        let limit = 12 * 1024;
+        let mut db = DatabaseBuilder::new()
+            .with_flush_threshold(4 * 1024)
+            .with_write_buffer_limit(limit)
+            .with_background_flush(true)
+            .open(dir.path())
+            .unwrap();
+        let delay = Duration::from_millis(50);
+        db.flush_worker = Some(FlushWorker::spawn_slow(db.table_writer.clone(), delay).unwrap());
+
+        let value = [7; 200];
+        let (mut slowest, mut most_frozen) = (Duration::ZERO, 0);
+        for n in 0..300u64 {
+            let start = Instant::now();
+            db.set(&encode_u64(n), &value).unwrap();
+            slowest = slowest.max(start.elapsed());
+            most_frozen = most_frozen.max(db.frozen.len());
+            // Writes never leave the MemTables above the limit.
+            assert!(db.write_buffer.usage(db.mem_table.size()) <= limit);
+        }
+        // Several MemTables were queued before the writes blocked, waiting
+        // for the slow worker.
+        assert!(most_frozen >= 2);
+        assert!(db.metrics().write_stalls > 0);
+        assert!(slowest >= delay / 2, "the slowest write took {slowest:?}");
+
+        db.flush().unwrap();
+        assert!(db.frozen.is_empty());
+        assert_eq!(db.write_buffer.usage(0), 0);
+        for n in 0..300u64 {
+            assert_eq!(db.get(&encode_u64(n)).unwrap().unwrap(), value);
+        }
+
+        let result = DatabaseBuilder::new()
+            .with_flush_threshold(4 * 1024)
+            .with_write_buffer_limit(1024)
+            .open(dir.path());
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_db_max_lengths() {
+        let dir = tempdir().unwrap();
+        let mut db = DatabaseBuilder::new()
+            .with_max_key_len(5)
+            .with_max_value_len(8)
+            .open(dir.path())
+            .unwrap();
+
+        db.set(b"Apple", b"Smoothie").unwrap();
+        assert!(matches!(
+            db.set(b"Banana", b"Smoothie"),
+            Err(Error::KeyTooLarge { len: 6, max: 5 })
+        ));
+        assert!(matches!(
+            db.set(b"Lime", b"Smoothies"),
+            Err(Error::ValueTooLarge { len: 9, max: 8 })
+        ));
+        assert!(matches!(
+            db.delete(b"Banana"),
+            Err(Error::KeyTooLarge { .. })
+        ));
+        assert!(matches!(
+            db.delete_range(b"Apple", b"Banana"),
+            Err(Error::KeyTooLarge { .. })
+        ));
+
+        // A batch is rejected as a whole.
+        let mut batch = WriteBatch::new();
+        batch.set(b"Lime", b"Pie").set(b"Orange", b"Pie");
+        assert!(matches!(
+            db.write_batch(batch),
+            Err(Error::KeyTooLarge { .. })
+        ));
+        assert!(db.get(b"Lime").unwrap().is_none());
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Smoothie");
+
+        drop(db);
+        let db = Database::open(dir.path()).unwrap();
+        assert_eq!(db.range(Bound::Unbounded, Bound::Unbounded).count(), 1);
+    }
+
+    #[test]
+    fn test_db_open_read_only() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.flush().unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        drop(db);
+        // A Table left behind by an interrupted flush.
+        std::fs::write(table_path(dir.path(), 7), b"orphan").unwrap();
+
+        let files = |dir: &Path| {
+            let mut files: Vec<_> = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|e| e.unwrap().file_name())
+                .collect();
+            files.sort();
+            files
+        };
+        let before = files(dir.path());
+
+        let mut db = Database::open_read_only(dir.path()).unwrap();
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+        assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Smoothie");
+        assert_eq!(db.range(Bound::Unbounded, Bound::Unbounded).count(), 2);
+
+        assert!(matches!(db.set(b"Orange", b"Pie"), Err(Error::ReadOnly)));
+        assert!(matches!(db.delete(b"Apple"), Err(Error::ReadOnly)));
+        assert!(matches!(
+            db.delete_range(b"Apple", b"Orange"),
+            Err(Error::ReadOnly)
+        ));
+        let mut batch = WriteBatch::new();
+        batch.set(b"Orange", b"Pie");
+        assert!(matches!(db.write_batch(batch), Err(Error::ReadOnly)));
+        assert!(matches!(db.flush(), Err(Error::ReadOnly)));
+        assert!(matches!(db.compact(), Err(Error::ReadOnly)));
+        assert!(db.get(b"Orange").unwrap().is_none());
+        drop(db);
+        assert_eq!(files(dir.path()), before);
+
+        assert!(Database::open_read_only(&dir.path().join("missing")).is_err());
+        assert!(!dir.path().join("missing").exists());
+    }
+
+    #[test]
+    fn test_db_open_read_only_without_wal() {
+        let dir = tempdir().unwrap();
+        let layout = Layout::new().with_wal_dir("wal");
+        let builder = || DatabaseBuilder::new().with_layout(layout.clone());
+        let mut db = builder().open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.flush().unwrap();
+        drop(db);
+
+        // Shipping only the Tables and the Manifest leaves nothing to recover.
+        std::fs::remove_dir_all(dir.path().join("wal")).unwrap();
+        let db = builder().open_read_only(dir.path()).unwrap();
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Smoothie");
+        assert!(db.mem_table.is_empty());
+        assert!(!dir.path().join("wal").exists());
+    }
+
+    #[test]
+    fn test_db_in_memory() {
+        let files = || {
+            let mut files: Vec<_> = std::fs::read_dir(".")
+                .unwrap()
+                .map(|e| e.unwrap().file_name())
+                .collect();
+            files.sort();
+            files
+        };
+        let before = files();
+
+        let mut db = DatabaseBuilder::new()
+            .with_flush_threshold(256)
+            .open_in_memory()
+            .unwrap();
+        for i in 0..50u32 {
+            let key = format!("key-{:03}", i);
+            db.set(key.as_bytes(), &i.to_le_bytes()).unwrap();
+        }
+        db.delete(b"key-007").unwrap();
+        db.delete_range(b"key-020", b"key-030").unwrap();
+        assert_eq!(db.flush().unwrap(), None);
+        db.set(b"key-021", b"back").unwrap();
+        db.compact().unwrap();
+
+        // Full MemTables and flushes seal frozen MemTables, read like Tables.
+        assert!(db.frozen.len() > 1);
+        assert!(db.tables.is_empty());
+        assert!(db.wal.is_none());
+        assert_eq!(
+            db.get(b"key-003").unwrap(),
+            Some(3u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(db.get(b"key-007").unwrap(), None);
+        assert_eq!(db.get(b"key-025").unwrap(), None);
+        assert_eq!(db.get(b"key-021").unwrap(), Some(b"back".to_vec()));
+        let pairs: Vec<_> = db.range(Bound::Unbounded, Bound::Unbounded).collect();
+        assert_eq!(pairs.len(), 50 - 1 - 10 + 1);
+        assert_eq!(db.metrics().wal_bytes_written, 0);
+        assert_eq!(files(), before);
+
+        // Nothing outlives the Database.
+        drop(db);
+        let db = Database::in_memory();
+        assert_eq!(db.get(b"key-003").unwrap(), None);
+        assert!(db
+            .range(Bound::Unbounded, Bound::Unbounded)
+            .next()
+            .is_none());
+        assert_eq!(files(), before);
+    }
+
+    #[test]
+    fn test_db_verify() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        for fruit in [&b"Apple"[..], b"Banana", b"Cherry"] {
+            db.set(fruit, &[fruit, &b" Smoothie"[..]].concat()).unwrap();
+            db.flush().unwrap();
+        }
+        drop(db);
+
+        let report = Database::verify(dir.path()).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!(report.tables_checked, 3);
+
+        // Corrupt the record of the first Table.
+        let first = table_path(dir.path(), 0);
+        let mut bytes = std::fs::read(&first).unwrap();
+        bytes[20] ^= 0xFF;
+        std::fs::write(&first, bytes).unwrap();
+
+        // Write a Table whose keys are in the reverse order.
+        let mut reversed = MemTable::with_comparator(ReverseComparator);
+        reversed.set(b"Apple", b"Apple Pie");
+        reversed.set(b"Lime", b"Lime Pie");
+        TableWriter::new()
+            .flush(&reversed, &table_path(dir.path(), 3))
+            .unwrap();
+
+        // Drop the second Table from the Manifest, and list a missing one.
+        let names = ["000000.sst", "000002.sst", "000003.sst", "000004.sst"];
+        Manifest::new(names.iter().map(|n| n.to_string()).collect())
+            .save(dir.path())
+            .unwrap();
+
+        let report = Database::verify(dir.path()).unwrap();
+        assert_eq!(report.tables_checked, 3);
+        assert!(report
+            .problems_of("000000.sst")
+            .any(|p| matches!(p, ProblemKind::Corruption { .. })));
+        assert!(matches!(
+            report.problems_of("000001.sst").collect::<Vec<_>>()[..],
+            [ProblemKind::Orphan]
+        ));
+        assert_eq!(report.problems_of("000002.sst").count(), 0);
+        assert!(report
+            .problems_of("000003.sst")
+            .any(|p| matches!(p, ProblemKind::Misordered { key, .. } if key == b"Apple")));
+        assert!(matches!(
+            report.problems_of("000004.sst").collect::<Vec<_>>()[..],
+            [ProblemKind::Missing]
+        ));
+
+        // Verifying leaves the directory as it is.
+        assert!(table_path(dir.path(), 1).exists());
+    }
+
+    #[test]
+    fn test_db_verify_layout() {
+        let dir = tempdir().unwrap();
+        let builder = || {
+            DatabaseBuilder::new()
+                .with_layout(Layout::new().with_table_dir("tables").with_prefix("fruit-"))
+        };
+        let mut db = builder().open(dir.path()).unwrap();
+        for fruit in [&b"Apple"[..], b"Banana"] {
+            db.set(fruit, &[fruit, &b" Smoothie"[..]].concat()).unwrap();
+            db.flush().unwrap();
+        }
+        drop(db);
+
+        let report = builder().verify(dir.path()).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!(report.tables_checked, 2);
+
+        let first = dir.path().join("tables").join("fruit-000000.sst");
+        let mut bytes = std::fs::read(&first).unwrap();
+        bytes[20] ^= 0xFF;
+        std::fs::write(&first, bytes).unwrap();
+
+        let report = builder().verify(dir.path()).unwrap();
+        assert_eq!(report.tables_checked, 2);
+        assert!(report
+            .problems_of("fruit-000000.sst")
+            .any(|p| matches!(p, ProblemKind::Corruption { .. })));
+        assert_eq!(report.problems_of("fruit-000001.sst").count(), 0);
+
+        let invalid = DatabaseBuilder::new().with_layout(Layout::new().with_prefix("a/b"));
+        assert!(matches!(
+            invalid.verify(dir.path()),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_db_split_points() {
+        let dir = tempdir().unwrap();
+        let builder = || {
+            DatabaseBuilder::new()
+                .with_split_points(vec![b"fruit:".to_vec(), b"vegetable:".to_vec()])
+        };
+        let mut db = builder().open(dir.path()).unwrap();
+        for key in [
+            &b"berry"[..],
+            b"fruit:apple",
+            b"fruit:lime",
+            b"vegetable:potato",
+        ] {
+            db.set(key, b"Smoothie").unwrap();
+        }
+        db.delete_range(b"berry", b"fruit:banana").unwrap();
+        db.flush().unwrap();
+
+        assert_eq!(db.tables.len(), 3);
+        let key_ranges: Vec<(Vec<u8>, Vec<u8>)> = db
+            .tables
+            .iter()
+            .map(|t| t.key_range().unwrap().unwrap())
+            .collect();
+        assert!(key_ranges.windows(2).all(|w| w[0].1 < w[1].0));
+        assert_eq!(
+            Manifest::load(dir.path()).unwrap().unwrap().tables().len(),
+            3
+        );
+
+        let check = |db: &Database| {
+            assert_eq!(db.get(b"berry").unwrap(), None);
+            assert_eq!(db.get(b"fruit:apple").unwrap(), None);
+            assert_eq!(db.get(b"fruit:lime").unwrap().unwrap(), b"Smoothie");
+            assert_eq!(db.get(b"vegetable:potato").unwrap().unwrap(), b"Smoothie");
+            let keys: Vec<Vec<u8>> = db
+                .range(Bound::Included(b"fruit:"), Bound::Unbounded)
+                .map(|pair| pair.unwrap().0)
+                .collect();
+            assert_eq!(
+                keys,
+                vec![b"fruit:lime".to_vec(), b"vegetable:potato".to_vec()]
+            );
+        };
+        check(&db);
+
+        // A lookup only reads the Table covering its key.
+        let reads = db.metrics().table_reads[0];
+        db.get(b"fruit:lime").unwrap();
+        assert_eq!(db.metrics().table_reads[0], reads + 1);
+
+        drop(db);
+        let db = builder().open(dir.path()).unwrap();
+        check(&db);
+
+        let unordered =
+            DatabaseBuilder::new().with_split_points(vec![b"b".to_vec(), b"a".to_vec()]);
+        assert!(matches!(
+            unordered.open(dir.path()),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_db_layout() {
+        let dir = tempdir().unwrap();
+        let layout = |prefix: &str| {
+            Layout::new()
+                .with_wal_dir("wal")
+                .with_table_dir("tables")
+                .with_prefix(prefix)
+        };
+        let open = |prefix: &str| {
+            DatabaseBuilder::new()
+                .with_layout(layout(prefix))
+                .open(dir.path())
+        };
+
+        let mut apple = open("apple-").unwrap();
+        let mut lime = open("lime-").unwrap();
+        apple.set(b"Fruit", b"Apple Smoothie").unwrap();
+        apple.flush().unwrap();
+        apple.set(b"Count", b"1").unwrap();
+        lime.set(b"Fruit", b"Lime Smoothie").unwrap();
+        lime.flush().unwrap();
+
+        let mut tables: Vec<_> = std::fs::read_dir(dir.path().join("tables"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        tables.sort();
+        assert_eq!(tables, ["apple-000000.sst", "lime-000000.sst"]);
+        assert!(dir.path().join("apple-MANIFEST").exists());
+        assert!(dir.path().join("wal").join("apple-wal-000001.log").exists());
+
+        // Databases with overlapping files can't be open at the same time.
+        assert!(matches!(open("apple-"), Err(Error::InvalidConfig(_))));
+        assert!(matches!(open("apple"), Err(Error::InvalidConfig(_))));
+
+        drop(apple);
+        drop(lime);
+        let apple = open("apple-").unwrap();
+        let lime = open("lime-").unwrap();
+        assert_eq!(apple.get(b"Fruit").unwrap().unwrap(), b"Apple Smoothie");
+        assert_eq!(apple.get(b"Count").unwrap().unwrap(), b"1");
+        assert_eq!(lime.get(b"Fruit").unwrap().unwrap(), b"Lime Smoothie");
+        assert_eq!(lime.get(b"Count").unwrap(), None);
+
+        let escaping =
+            DatabaseBuilder::new().with_layout(Layout::new().with_table_dir("../tables"));
+        assert!(matches!(
+            escaping.open(dir.path()),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_db_builder_invalid() {
+        let dir = tempdir().unwrap();
+        let invalid = [
+            DatabaseBuilder::new().with_flush_threshold(0),
+            DatabaseBuilder::new().with_block_size(0),
+            DatabaseBuilder::new().with_bloom_fp_rate(0.0),
+            DatabaseBuilder::new().with_bloom_fp_rate(1.0),
+            DatabaseBuilder::new().with_bloom_fp_rate(f64::NAN),
+        ];
+        for builder in invalid {
+            assert!(matches!(
+                builder.open(dir.path()),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_db_compaction_policy() {
+        let dir = tempdir().unwrap();
+        let mut db = DatabaseBuilder::new()
+            .with_flush_threshold(1)
+            .with_compaction_policy(SizeTieredPolicy::new().with_max_tables(2))
+            .open(dir.path())
+            .unwrap();
+
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        assert_eq!(db.tables.len(), 2);
+        db.delete(b"Apple").unwrap();
+        assert_eq!(db.tables.len(), 1);
+        db.set(b"Orange", b"Orange Smoothie").unwrap();
+        assert_eq!(db.tables.len(), 2);
+
+        assert!(db.get(b"Apple").unwrap().is_none());
+        assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Smoothie");
+        assert_eq!(db.get(b"Orange").unwrap().unwrap(), b"Orange Smoothie");
+    }
+
+    #[test]
+    fn test_db_read_during_flush() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.flush().unwrap();
+        db.set(b"Apple", b"Apple Pie").unwrap();
+        db.set(b"Orange", b"Orange Smoothie").unwrap();
+        db.set(b"Peach", b"Peach Smoothie").unwrap();
+
+        // Pin the MemTable as frozen, as if its flush were still running.
+        db.freeze_mem_table().unwrap();
+        assert!(db.mem_table.is_empty());
+        db.delete(b"Orange").unwrap();
+        db.set(b"Lime", b"Lime Pie").unwrap();
+
+        let check = |db: &Database| {
+            assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Pie");
+            assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Pie");
+            assert!(db.get(b"Orange").unwrap().is_none());
+            assert_eq!(db.get(b"Peach").unwrap().unwrap(), b"Peach Smoothie");
+
+            let pairs = db
+                .range(Bound::Unbounded, Bound::Unbounded)
+                .collect::<crate::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(
+                pairs,
+                vec![
+                    (b"Apple".to_vec(), b"Apple Pie".to_vec()),
+                    (b"Lime".to_vec(), b"Lime Pie".to_vec()),
+                    (b"Peach".to_vec(), b"Peach Smoothie".to_vec()),
+                ]
+            );
+        };
+        check(&db);
+
+        // Writes made while frozen survive a crash before the flush ends.
+        drop(db);
+        let mut db = Database::open(dir.path()).unwrap();
+        check(&db);
+
+        db.freeze_mem_table().unwrap();
+        db.delete(b"Peach").unwrap();
+        db.flush_frozen().unwrap();
+        assert!(db.frozen.is_empty());
+        assert_eq!(db.tables.len(), 2);
+        assert!(db.get(b"Peach").unwrap().is_none());
+        assert_eq!(db.get(b"Lime").unwrap().unwrap(), b"Lime Pie");
+        assert_eq!(db.wal_ids.len(), 1);
+    }
+
+    #[test]
+    fn test_db_range() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.mem_table = Arc::new(MemTable::with_capacity(64));
+
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        db.set(b"Orange", b"Orange Smoothie").unwrap();
+        db.delete(b"Lime").unwrap();
+        db.set(b"Peach", b"Peach Smoothie").unwrap();
+        assert!(!db.tables.is_empty());
+
+        let keys: Vec<Vec<u8>> = db
+            .range(Bound::Unbounded, Bound::Unbounded)
+            .map(|r| r.unwrap().0)
+            .collect();
+        assert_eq!(
+            keys,
+            vec![b"Apple".to_vec(), b"Orange".to_vec(), b"Peach".to_vec()]
+        );
+
+        let keys: Vec<Vec<u8>> = db
+            .range(Bound::Excluded(b"Apple"), Bound::Excluded(b"Peach"))
+            .map(|r| r.unwrap().0)
+            .collect();
+        assert_eq!(keys, vec![b"Orange".to_vec()]);
+    }
+
+    #[test]
+    fn test_db_scan_prefix() {
+        let dir = tempdir().unwrap();
+        let mut db = DatabaseBuilder::new()
+            .with_prefix_extractor(FixedPrefix(4))
+            .open(dir.path())
+            .unwrap();
+        for user in [b"usr1", b"usr2", b"usr3"] {
+            for item in [&b":a"[..], b":b", b":c"] {
+                db.set(&[user, item].concat(), item).unwrap();
+            }
+            // Every Table spans the keys of every user.
+            db.set(&[&b"usr0:"[..], user].concat(), b"").unwrap();
+            db.set(&[&b"usr9:"[..], user].concat(), b"").unwrap();
+            db.flush().unwrap();
+        }
+        db.set(b"usr2:d", b":d").unwrap();
+        db.delete(b"usr2:b").unwrap();
+
+        let keys = |db: &Database, prefix: &[u8]| -> Vec<Vec<u8>> {
+            db.scan_prefix(prefix).map(|r| r.unwrap().0).collect()
+        };
+        // The Tables of the other users are skipped, the MemTable never is.
+        assert_eq!(
+            keys(&db, b"usr2"),
+            vec![b"usr2:a".to_vec(), b"usr2:c".to_vec(), b"usr2:d".to_vec()]
+        );
+        assert_eq!(db.metrics().prefix_bloom_negatives, 2);
+        assert_eq!(keys(&db, b"usr3:c"), vec![b"usr3:c".to_vec()]);
+        assert_eq!(db.metrics().prefix_bloom_negatives, 4);
+        assert!(keys(&db, b"usr4").is_empty());
+        assert_eq!(db.metrics().prefix_bloom_negatives, 7);
+
+        // A prefix out of the domain of the extractor reads every Table.
+        assert_eq!(keys(&db, b"usr").len(), 15);
+        assert_eq!(db.metrics().prefix_bloom_negatives, 7);
+    }
+
+    #[test]
+    fn test_db_group_by_prefix() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        assert!(db.group_by_prefix(3).next().is_none());
+
+        for key in ["app:1", "app:2", "app:3", "bo", "box:1", "box:2", "cat:1"] {
+            db.set(key.as_bytes(), key.to_uppercase().as_bytes())
+                .unwrap();
+        }
+        db.flush().unwrap();
+        db.set(b"box:3", b"BOX:3").unwrap();
+        db.delete(b"cat:1").unwrap();
+        db.set(b"cow:1", b"COW:1").unwrap();
+
+        let groups = db
+            .group_by_prefix(3)
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+        let keys: Vec<(&[u8], Vec<&[u8]>)> = groups
+            .iter()
+            .map(|(prefix, pairs)| {
+                let keys = pairs.iter().map(|(k, _)| k.as_slice()).collect();
+                (prefix.as_slice(), keys)
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (&b"app"[..], vec![&b"app:1"[..], b"app:2", b"app:3"]),
+                // The short key is a Group of its own, even though the next
+                // keys start with it.
+                (b"bo", vec![b"bo"]),
+                (b"box", vec![b"box:1", b"box:2", b"box:3"]),
+                (b"cow", vec![b"cow:1"]),
+            ]
+        );
+        assert_eq!(groups[2].1[2].1, b"BOX:3");
+    }
+
+    #[test]
+    fn test_db_tombstones() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        for key in ["a", "b", "c", "d", "e", "f"] {
+            db.set(key.as_bytes(), b"value").unwrap();
+        }
+        db.delete(b"b").unwrap();
+        db.delete(b"d").unwrap();
+        db.flush().unwrap();
+        // The newest record wins over the tombstone of the Table.
+        db.set(b"d", b"value").unwrap();
+        db.delete(b"a").unwrap();
+        db.delete_range(b"e", b"f").unwrap();
+        db.delete(b"z").unwrap();
+
+        let keys =
+            |db: &Database| -> Vec<Vec<u8>> { db.tombstones().map(|e| e.unwrap().key).collect() };
+        assert_eq!(keys(&db), [&b"a"[..], b"b", b"e", b"z"].map(|k| k.to_vec()));
+        assert!(db.tombstones().all(|e| e.unwrap().is_deleted));
+
+        db.flush().unwrap();
+        assert_eq!(keys(&db), [&b"a"[..], b"b", b"e", b"z"].map(|k| k.to_vec()));
+    }
+
+    #[test]
+    fn test_db_scan() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        for n in 0..10u64 {
+            db.set(&encode_u64(n), &n.to_le_bytes()).unwrap();
+        }
+        db.delete(&encode_u64(4)).unwrap();
+        let keys = |pairs: &[(Vec<u8>, Vec<u8>)]| -> Vec<Vec<u8>> {
+            pairs.iter().map(|(key, _)| key.clone()).collect()
+        };
+        let all: Vec<Vec<u8>> = [0, 1, 2, 3, 5, 6, 7, 8, 9]
+            .into_iter()
+            .map(|n| encode_u64(n).to_vec())
+            .collect();
+
+        // A limit smaller than the range pages through it.
+        let (mut pages, mut next_key) = (Vec::new(), None);
+        loop {
+            let start = next_key
+                .as_deref()
+                .map_or(Bound::Unbounded, Bound::Included);
+            let (pairs, next) = db.scan(start, Bound::Unbounded, 4).unwrap();
+            assert!(pairs.len() <= 4);
+            pages.extend(keys(&pairs));
+            if next.is_none() {
+                break;
+            }
+            next_key = next;
+        }
+        assert_eq!(pages, all);
+
+        // A limit equal to or larger than the range returns all of it.
+        for limit in [all.len(), all.len() + 5] {
+            let (pairs, next) = db.scan(Bound::Unbounded, Bound::Unbounded, limit).unwrap();
+            assert_eq!((keys(&pairs), next), (all.clone(), None));
+        }
+        let (pairs, next) = db
+            .scan(
+                Bound::Included(&encode_u64(2)),
+                Bound::Excluded(&encode_u64(6)),
+                3,
+            )
+            .unwrap();
+        assert_eq!(keys(&pairs), [2, 3, 5].map(|n| encode_u64(n).to_vec()));
+        assert_eq!(next, None);
+        let (pairs, next) = db.scan(Bound::Unbounded, Bound::Unbounded, 0).unwrap();
+        assert_eq!((pairs.len(), next), (0, Some(encode_u64(0).to_vec())));
+    }
+
+    #[test]
+    fn test_db_scan_at_is_stable() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        for n in 0..10u64 {
+            db.set(&encode_u64(n * 2), b"old").unwrap();
+        }
+        db.flush().unwrap();
+        let snapshot = db.snapshot();
+
+        let (first, next) = db
+            .scan_at(&snapshot, Bound::Unbounded, Bound::Unbounded, 5)
+            .unwrap();
+        let next = next.unwrap();
+        assert_eq!(next, encode_u64(10));
+
+        // Writes between the pages don't show up in the next one.
+        db.set(&encode_u64(9), b"new").unwrap();
+        db.set(&encode_u64(12), b"new").unwrap();
+        db.delete(&encode_u64(14)).unwrap();
+        db.delete(&encode_u64(2)).unwrap();
+        let (second, next) = db
+            .scan_at(&snapshot, Bound::Included(&next), Bound::Unbounded, 5)
+            .unwrap();
+        assert_eq!(next, None);
+
+        let pairs: Vec<_> = first.into_iter().chain(second).collect();
+        let expected: Vec<_> = (0..10u64)
+            .map(|n| (encode_u64(n * 2).to_vec(), b"old".to_vec()))
+            .collect();
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn test_db_snapshot() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        db.mem_table = Arc::new(MemTable::with_capacity(0));
+
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        let snapshot = db.snapshot();
+
+        db.set(b"Apple", b"Apple Pie").unwrap();
+        db.delete(b"Lime").unwrap();
+        db.set(b"Orange", b"Orange Smoothie").unwrap();
+
+        assert_eq!(
+            db.get_at(&snapshot, b"Apple").unwrap().unwrap(),
+            b"Apple Smoothie"
+        );
+        assert_eq!(
+            db.get_at(&snapshot, b"Lime").unwrap().unwrap(),
+            b"Lime Smoothie"
+        );
+        assert!(db.get_at(&snapshot, b"Orange").unwrap().is_none());
+
+        let pairs = db
+            .range_at(&snapshot, Bound::Unbounded, Bound::Unbounded)
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                (b"Apple".to_vec(), b"Apple Smoothie".to_vec()),
+                (b"Lime".to_vec(), b"Lime Smoothie".to_vec()),
+            ]
+        );
+
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Pie");
+        assert!(db.get(b"Lime").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_db_snapshot_overwritten_in_mem_table() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        let snapshot = db.snapshot();
+
+        db.set(b"Apple", b"Apple Pie").unwrap();
+        db.set(b"Apple", b"Apple Juice").unwrap();
+        db.delete(b"Lime").unwrap();
+
+        assert_eq!(
+            db.get_at(&snapshot, b"Apple").unwrap().unwrap(),
+            b"Apple Smoothie"
+        );
+        assert_eq!(
+            db.get_at(&snapshot, b"Lime").unwrap().unwrap(),
+            b"Lime Smoothie"
+        );
+        let pairs = db
+            .range_at(&snapshot, Bound::Unbounded, Bound::Unbounded)
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                (b"Apple".to_vec(), b"Apple Smoothie".to_vec()),
+                (b"Lime".to_vec(), b"Lime Smoothie".to_vec()),
+            ]
+        );
+
+        // Reads without the Snapshot still see the newest versions only.
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Juice");
+        let mut cursor = db.cursor();
+        cursor.seek_to_last().unwrap();
+        assert_eq!(cursor.key().unwrap(), b"Apple");
+        assert_eq!(cursor.value().unwrap(), b"Apple Juice");
+
+        // The versions outlive a flush, and are dropped once the Snapshot is.
+        let clone = snapshot.clone();
+        drop(snapshot);
+        db.flush().unwrap();
+        assert_eq!(
+            db.get_at(&clone, b"Apple").unwrap().unwrap(),
+            b"Apple Smoothie"
+        );
+        let mut cursor = db.cursor();
+        cursor.seek_to_last().unwrap();
+        assert_eq!(cursor.value().unwrap(), b"Apple Juice");
+        db.set(b"Apple", b"Apple Tart").unwrap();
+        drop(clone);
+        db.set(b"Apple", b"Apple Crumble").unwrap();
+        assert_eq!(db.mem_table.len(), 1);
+        assert_eq!(db.get(b"Apple").unwrap().unwrap(), b"Apple Crumble");
+    }
+
+    #[test]
+    fn test_db_timestamps_increase() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+
+        let before = db.snapshot();
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        db.set(b"Apple", b"Apple Pie").unwrap();
+        let after = db.snapshot();
+
+        assert!(after.timestamp_ms() >= before.timestamp_ms() + 2);
+        assert!(db.get_at(&before, b"Apple").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_db_timestamps_from_clock() {
+        let dir = tempdir().unwrap();
+        let mut db = Database::open(dir.path()).unwrap();
+        let clock = Arc::new(MockClock::new(100));
+        db.mem_table = Arc::new(MemTable::new().with_clock(clock.clone()));
+
+        db.set(b"Apple", b"Apple Smoothie").unwrap();
+        assert_eq!(db.mem_table.get(b"Apple").unwrap().timestamp_ms, 100);
+
+        // A clock going backwards still yields increasing timestamps.
+        clock.set(50);
+        db.set(b"Lime", b"Lime Smoothie").unwrap();
+        assert_eq!(db.mem_table.get(b"Lime").unwrap().timestamp_ms, 101);
+    }
+}
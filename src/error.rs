@@ -0,0 +1,62 @@
+use std::fmt;
+use std::io;
+
+/// Errors returned by the Database and its on-disk structures
+#[derive(Debug)]
+pub enum Error {
+    /// An IO operation failed
+    Io(io::Error),
+    /// The data at the given offset of a file failed its checksum or could
+    /// not be decoded
+    Corruption { offset: u64 },
+    /// The Database was configured with invalid options
+    InvalidConfig(String),
+    /// A key is longer than the maximum key length
+    KeyTooLarge { len: usize, max: usize },
+    /// A Value is longer than the maximum Value length
+    ValueTooLarge { len: usize, max: usize },
+    /// A write was made to a Database opened read-only
+    ReadOnly,
+    /// A typed key or Value could not be encoded to or decoded from bytes
+    Serialization(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Corruption { offset } => write!(f, "corrupted data at offset {}", offset),
+            Error::InvalidConfig(reason) => write!(f, "invalid config: {}", reason),
+            Error::KeyTooLarge { len, max } => {
+                write!(f, "key of {} bytes is longer than {} bytes", len, max)
+            }
+            Error::ValueTooLarge { len, max } => {
+                write!(f, "value of {} bytes is longer than {} bytes", len, max)
+            }
+            Error::ReadOnly => write!(f, "the database is read-only"),
+            Error::Serialization(reason) => write!(f, "serialization error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Corruption { .. }
+            | Error::InvalidConfig(_)
+            | Error::KeyTooLarge { .. }
+            | Error::ValueTooLarge { .. }
+            | Error::ReadOnly
+            | Error::Serialization(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
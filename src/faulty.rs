@@ -0,0 +1,114 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::wal::WalFile;
+
+/// FaultyFile is an in-memory file that loses the writes not yet synced when
+/// it crashes, for testing what survives a crash
+///
+/// Clones share the same contents, so a test can keep one to inspect the
+/// file while the WAL or a TableBuilder writes to another. Every write and
+/// sync is an operation, and once the injected crash point is reached that
+/// operation and every later one fails.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FaultyFile {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Contents known to be on the disk
+    synced: Vec<u8>,
+    /// Writes since the last sync, as their offset and bytes
+    pending: Vec<(usize, Vec<u8>)>,
+    len: usize,
+    ops: usize,
+    crash_at: Option<usize>,
+}
+
+impl State {
+    fn crashed(&mut self) -> io::Result<()> {
+        self.ops += 1;
+        match self.crash_at {
+            Some(crash_at) if self.ops > crash_at => Err(io::Error::other("crashed")),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FaultyFile {
+    pub(crate) fn new() -> FaultyFile {
+        FaultyFile::default()
+    }
+
+    /// Crashes on the operation with the given index, counting from 0
+    pub(crate) fn with_crash_at(self, op: usize) -> FaultyFile {
+        self.state.lock().unwrap().crash_at = Some(op);
+        self
+    }
+
+    /// Returns the number of writes since the last sync
+    pub(crate) fn pending_writes(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    /// Returns the contents the file would have after a crash now
+    ///
+    /// The synced contents always survive. Of the writes since the last
+    /// sync, only the `i`th ones for which `persisted(i)` holds reach the
+    /// disk, in any order, so a later write may survive an earlier one. The
+    /// gaps they leave are zeros.
+    pub(crate) fn recovered(&self, persisted: impl Fn(usize) -> bool) -> Vec<u8> {
+        let state = self.state.lock().unwrap();
+        let mut contents = state.synced.clone();
+        for (i, (offset, bytes)) in state.pending.iter().enumerate() {
+            if !persisted(i) {
+                continue;
+            }
+            let end = offset + bytes.len();
+            if contents.len() < end {
+                contents.resize(end, 0);
+            }
+            contents[*offset..end].copy_from_slice(bytes);
+        }
+        contents
+    }
+}
+
+impl Write for FaultyFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        state.crashed()?;
+        let offset = state.len;
+        state.pending.push((offset, buf.to_vec()));
+        state.len += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WalFile for FaultyFile {
+    fn sync_data(&mut self) -> io::Result<()> {
+        let contents = self.recovered(|_| true);
+        let mut state = self.state.lock().unwrap();
+        state.crashed()?;
+        state.synced = contents;
+        state.pending.clear();
+        Ok(())
+    }
+
+    // Truncating is treated as synced right away, as the WAL syncs just after.
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        let contents = self.recovered(|_| true);
+        let mut state = self.state.lock().unwrap();
+        state.crashed()?;
+        state.synced = contents;
+        state.synced.resize(len as usize, 0);
+        state.pending.clear();
+        state.len = len as usize;
+        Ok(())
+    }
+}
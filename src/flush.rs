@@ -0,0 +1,160 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::mem_table::ImmutableMemTable;
+use crate::table::TableWriter;
+
+/// A frozen MemTable to be written to new Tables, split like
+/// `TableWriter::flush_split` with the `i`th Table at the `i`th path
+struct Job {
+    mem_table: Arc<ImmutableMemTable>,
+    paths: Vec<PathBuf>,
+}
+
+/// FlushWorker writes frozen MemTables to Tables on a background thread
+///
+/// Jobs are written one at a time in the order they were submitted, and the
+/// paths of the Tables written by every job are sent back. The worker only
+/// writes the Table files, recording them in the Manifest is left to the
+/// Database.
+pub(crate) struct FlushWorker {
+    jobs: Option<Sender<Job>>,
+    results: Receiver<Result<Vec<PathBuf>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl FlushWorker {
+    /// Spawns the worker thread, writing Tables with the TableWriter
+    pub(crate) fn spawn(table_writer: TableWriter) -> Result<FlushWorker> {
+        FlushWorker::spawn_with_delay(table_writer, Duration::ZERO)
+    }
+
+    /// Spawns a worker sleeping for `delay` before every job, as if the
+    /// disk were slow
+    #[cfg(test)]
+    pub(crate) fn spawn_slow(table_writer: TableWriter, delay: Duration) -> Result<FlushWorker> {
+        FlushWorker::spawn_with_delay(table_writer, delay)
+    }
+
+    fn spawn_with_delay(table_writer: TableWriter, delay: Duration) -> Result<FlushWorker> {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, results) = mpsc::channel();
+        let handle = thread::Builder::new()
+            .name("flush".to_owned())
+            .spawn(move || {
+                for job in job_receiver {
+                    if !delay.is_zero() {
+                        thread::sleep(delay);
+                    }
+                    let result = table_writer
+                        .flush_split(job.mem_table.mem_table(), |i| job.paths[i].clone());
+                    if result_sender.send(result).is_err() {
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(FlushWorker {
+            jobs: Some(jobs),
+            results,
+            handle: Some(handle),
+        })
+    }
+
+    /// Hands a frozen MemTable to the worker without waiting for it
+    pub(crate) fn submit(&self, mem_table: Arc<ImmutableMemTable>, paths: Vec<PathBuf>) {
+        let jobs = self.jobs.as_ref().expect("the worker is running");
+        // The worker only stops once the jobs are closed, a panic aside, which
+        // `wait` reports.
+        let _ = jobs.send(Job { mem_table, paths });
+    }
+
+    /// Returns the result of the oldest finished job, if any
+    pub(crate) fn try_finished(&self) -> Option<Result<Vec<PathBuf>>> {
+        match self.results.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => panic!("the flush worker panicked"),
+        }
+    }
+
+    /// Waits for the oldest submitted job to finish and returns its result
+    pub(crate) fn wait(&self) -> Result<Vec<PathBuf>> {
+        self.results.recv().expect("the flush worker panicked")
+    }
+}
+
+impl Drop for FlushWorker {
+    /// Stops the worker once it has written every submitted job
+    fn drop(&mut self) {
+        self.jobs = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// WriteBufferManager bounds the memory taken by the MemTables of a Database
+///
+/// It tracks the summed size of the MemTable written to and of the frozen
+/// MemTables queued for flushing. Once the sum exceeds the limit, writes wait
+/// for the oldest flushes until it is back under.
+#[derive(Debug, Clone)]
+pub(crate) struct WriteBufferManager {
+    limit: usize,
+    /// Summed size of the frozen MemTables queued for flushing
+    queued: usize,
+}
+
+impl WriteBufferManager {
+    pub(crate) fn new(limit: usize) -> WriteBufferManager {
+        WriteBufferManager { limit, queued: 0 }
+    }
+
+    /// Records a MemTable of the given size frozen for flushing
+    pub(crate) fn queue(&mut self, size: usize) {
+        self.queued += size;
+    }
+
+    /// Records the flush of a frozen MemTable of the given size
+    pub(crate) fn release(&mut self, size: usize) {
+        self.queued -= size;
+    }
+
+    /// Returns the summed size of the MemTables, the one written to being of
+    /// size `active`
+    pub(crate) fn usage(&self, active: usize) -> usize {
+        self.queued + active
+    }
+
+    /// Whether writes must wait for flushes, the MemTable written to being of
+    /// size `active`
+    pub(crate) fn is_over_limit(&self, active: usize) -> bool {
+        self.usage(active) > self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flush::WriteBufferManager;
+
+    #[test]
+    fn test_write_buffer_manager() {
+        let mut manager = WriteBufferManager::new(100);
+        assert!(!manager.is_over_limit(100));
+        assert!(manager.is_over_limit(101));
+
+        manager.queue(60);
+        manager.queue(30);
+        assert_eq!(manager.usage(10), 100);
+        assert!(manager.is_over_limit(11));
+
+        manager.release(60);
+        assert_eq!(manager.usage(11), 41);
+        assert!(!manager.is_over_limit(70));
+    }
+}
@@ -0,0 +1,215 @@
+use std::borrow::Cow;
+
+/// AsBytes encodes a typed key into the bytes stored in the Database
+///
+/// The encoding must preserve the order: keys are compared byte by byte, so
+/// comparing the bytes of two keys must give the same result as comparing
+/// the keys themselves.
+pub trait AsBytes {
+    fn as_bytes(&self) -> Cow<'_, [u8]>;
+}
+
+/// FromBytes decodes a typed key from the bytes encoded by [`AsBytes`]
+pub trait FromBytes: Sized {
+    /// Returns None if the bytes don't encode a key of this type
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl AsBytes for u64 {
+    fn as_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_u64(*self).to_vec())
+    }
+}
+
+impl FromBytes for u64 {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        decode_u64(bytes)
+    }
+}
+
+impl AsBytes for i64 {
+    fn as_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_i64(*self).to_vec())
+    }
+}
+
+impl FromBytes for i64 {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        decode_i64(bytes)
+    }
+}
+
+impl AsBytes for u32 {
+    fn as_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.to_be_bytes().to_vec())
+    }
+}
+
+impl FromBytes for u32 {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(u32::from_be_bytes(bytes.try_into().ok()?))
+    }
+}
+
+impl AsBytes for i32 {
+    fn as_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned((*self as u32 ^ (1 << 31)).to_be_bytes().to_vec())
+    }
+}
+
+impl FromBytes for i32 {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some((u32::from_bytes(bytes)? ^ (1 << 31)) as i32)
+    }
+}
+
+/// Strings are stored as their UTF-8 bytes, which compare like the code
+/// points they encode
+impl AsBytes for String {
+    fn as_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(str::as_bytes(self))
+    }
+}
+
+impl FromBytes for String {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl AsBytes for Vec<u8> {
+    fn as_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl FromBytes for Vec<u8> {
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+/// Encodes a u64 as 8 big-endian bytes
+///
+/// Keys are compared byte by byte, and big-endian encodings compare the same
+/// way as the integers they encode.
+pub fn encode_u64(n: u64) -> [u8; 8] {
+    n.to_be_bytes()
+}
+
+/// Decodes a u64 encoded by `encode_u64`
+///
+/// Returns None if the key isn't 8 bytes long.
+pub fn decode_u64(key: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(key.try_into().ok()?))
+}
+
+/// Encodes an i64 as 8 big-endian bytes with the sign bit flipped
+///
+/// Flipping the sign bit makes negative integers sort before positive ones.
+pub fn encode_i64(n: i64) -> [u8; 8] {
+    encode_u64(n as u64 ^ (1 << 63))
+}
+
+/// Decodes an i64 encoded by `encode_i64`
+///
+/// Returns None if the key isn't 8 bytes long.
+pub fn decode_i64(key: &[u8]) -> Option<i64> {
+    Some((decode_u64(key)? ^ (1 << 63)) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::key::{decode_i64, decode_u64, encode_i64, encode_u64, AsBytes, FromBytes};
+    use crate::mem_table::MemTable;
+
+    #[test]
+    fn test_key_u64_order() {
+        let values = [
+            0,
+            1,
+            255,
+            256,
+            u32::MAX as u64,
+            u32::MAX as u64 + 1,
+            (1 << 63) - 1,
+            1 << 63,
+            u64::MAX - 1,
+            u64::MAX,
+        ];
+
+        for w in values.windows(2) {
+            assert!(encode_u64(w[0]) < encode_u64(w[1]));
+        }
+        for n in values {
+            assert_eq!(decode_u64(&encode_u64(n)), Some(n));
+        }
+    }
+
+    #[test]
+    fn test_key_i64_order() {
+        let values = [
+            i64::MIN,
+            i64::MIN + 1,
+            -256,
+            -255,
+            -1,
+            0,
+            1,
+            255,
+            256,
+            i64::MAX - 1,
+            i64::MAX,
+        ];
+
+        for w in values.windows(2) {
+            assert!(encode_i64(w[0]) < encode_i64(w[1]));
+        }
+        for n in values {
+            assert_eq!(decode_i64(&encode_i64(n)), Some(n));
+        }
+    }
+
+    #[test]
+    fn test_key_as_bytes_order() {
+        let values = [i32::MIN, -256, -1, 0, 1, 255, i32::MAX];
+        for w in values.windows(2) {
+            assert!(w[0].as_bytes() < w[1].as_bytes());
+        }
+        for n in values {
+            assert_eq!(i32::from_bytes(&n.as_bytes()), Some(n));
+        }
+        assert_eq!(u32::from_bytes(&7u32.as_bytes()), Some(7));
+        assert_eq!(u32::from_bytes(&[0; 3]), None);
+
+        let words = ["", "Apple", "Apples", "Lime", "Éclair"].map(String::from);
+        for w in words.windows(2) {
+            assert!(AsBytes::as_bytes(&w[0]) < AsBytes::as_bytes(&w[1]));
+        }
+        let bytes = AsBytes::as_bytes(&words[4]);
+        assert_eq!(String::from_bytes(&bytes), Some(words[4].clone()));
+        assert_eq!(String::from_bytes(&[0xFF]), None);
+    }
+
+    #[test]
+    fn test_key_decode_wrong_length() {
+        assert_eq!(decode_u64(&[0; 7]), None);
+        assert_eq!(decode_i64(&[0; 9]), None);
+        assert_eq!(decode_u64(&[]), None);
+    }
+
+    #[test]
+    fn test_key_mem_table_order() {
+        let mut table = MemTable::new();
+        for n in [42, -7, 0, i64::MIN, 3, i64::MAX, -1] {
+            table.set_at(&encode_i64(n), b"Smoothie", 0);
+        }
+
+        let keys: Vec<i64> = table
+            .entries()
+            .iter()
+            .map(|e| decode_i64(&e.key).unwrap())
+            .collect();
+        assert_eq!(keys, vec![i64::MIN, -7, -1, 0, 3, 42, i64::MAX]);
+    }
+}
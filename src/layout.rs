@@ -0,0 +1,230 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::error::{Error, Result};
+use crate::manifest::Manifest;
+
+const WAL_PREFIX: &str = "wal-";
+const WAL_EXTENSION: &str = "log";
+const TABLE_EXTENSION: &str = "sst";
+
+/// Directories and file name prefixes of the Databases open for writing in
+/// the process
+static OPEN_LOCATIONS: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+/// Layout places the files of a Database within its directory
+///
+/// The WAL segments, the Tables and the Manifest each go to a subdirectory
+/// of their own, and every file name starts with the prefix. By default they
+/// all go to the Database's directory itself with no prefix. Giving each
+/// Database its own subdirectories or prefix lets several of them share one
+/// directory.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Layout {
+    wal_dir: PathBuf,
+    table_dir: PathBuf,
+    manifest_dir: PathBuf,
+    prefix: String,
+}
+
+impl Layout {
+    /// Creates a Layout putting every file in the Database's directory
+    pub fn new() -> Layout {
+        Layout::default()
+    }
+
+    /// Sets the subdirectory of the WAL segments, relative to the Database's
+    /// directory
+    pub fn with_wal_dir(mut self, wal_dir: impl Into<PathBuf>) -> Layout {
+        self.wal_dir = wal_dir.into();
+        self
+    }
+
+    /// Sets the subdirectory of the Tables, relative to the Database's
+    /// directory
+    pub fn with_table_dir(mut self, table_dir: impl Into<PathBuf>) -> Layout {
+        self.table_dir = table_dir.into();
+        self
+    }
+
+    /// Sets the subdirectory of the Manifest, relative to the Database's
+    /// directory
+    pub fn with_manifest_dir(mut self, manifest_dir: impl Into<PathBuf>) -> Layout {
+        self.manifest_dir = manifest_dir.into();
+        self
+    }
+
+    /// Sets the prefix of the name of every file of the Database
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Layout {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Fails with [`Error::InvalidConfig`] if a subdirectory leaves the
+    /// Database's directory or the prefix holds a path separator
+    pub(crate) fn validate(&self) -> Result<()> {
+        for dir in [&self.wal_dir, &self.table_dir, &self.manifest_dir] {
+            if !dir.components().all(|c| matches!(c, Component::Normal(_))) {
+                return Err(Error::InvalidConfig(format!(
+                    "the subdirectory {} must be relative and stay within the database directory",
+                    dir.display()
+                )));
+            }
+        }
+        if self.prefix.contains(std::path::is_separator) {
+            return Err(Error::InvalidConfig(format!(
+                "the file name prefix {:?} must not hold a path separator",
+                self.prefix
+            )));
+        }
+        Ok(())
+    }
+
+    /// Resolves the subdirectories against the Database's directory
+    pub(crate) fn resolve(&self, dir: &Path) -> Paths {
+        Paths {
+            wal_dir: dir.join(&self.wal_dir),
+            table_dir: dir.join(&self.table_dir),
+            manifest_dir: dir.join(&self.manifest_dir),
+            prefix: self.prefix.clone(),
+        }
+    }
+}
+
+/// Paths of the files of a Database, following its Layout
+#[derive(Debug, Clone)]
+pub(crate) struct Paths {
+    wal_dir: PathBuf,
+    table_dir: PathBuf,
+    manifest_dir: PathBuf,
+    prefix: String,
+}
+
+impl Paths {
+    /// Creates the directories of the files
+    pub(crate) fn create_dirs(&self) -> Result<()> {
+        for dir in [&self.wal_dir, &self.table_dir, &self.manifest_dir] {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn table_file_name(&self, id: u64) -> String {
+        format!("{}{:06}.{}", self.prefix, id, TABLE_EXTENSION)
+    }
+
+    pub(crate) fn table_path(&self, id: u64) -> PathBuf {
+        self.table_dir.join(self.table_file_name(id))
+    }
+
+    pub(crate) fn wal_path(&self, id: u64) -> PathBuf {
+        self.wal_dir.join(format!(
+            "{}{}{:06}.{}",
+            self.prefix, WAL_PREFIX, id, WAL_EXTENSION
+        ))
+    }
+
+    /// Returns the sorted ids of the Tables in their directory
+    pub(crate) fn table_ids(&self) -> Result<Vec<u64>> {
+        file_ids(&self.table_dir, &self.prefix, TABLE_EXTENSION)
+    }
+
+    /// Returns the sorted ids of the WAL segments in their directory
+    ///
+    /// A missing directory holds no segment, as for a Database shipped
+    /// without its WAL to be opened read-only.
+    pub(crate) fn wal_ids(&self) -> Result<Vec<u64>> {
+        if !self.wal_dir.exists() {
+            return Ok(Vec::new());
+        }
+        file_ids(
+            &self.wal_dir,
+            &format!("{}{}", self.prefix, WAL_PREFIX),
+            WAL_EXTENSION,
+        )
+    }
+
+    pub(crate) fn table_dir(&self) -> &Path {
+        &self.table_dir
+    }
+
+    pub(crate) fn load_manifest(&self) -> Result<Option<Manifest>> {
+        Manifest::load_with_prefix(&self.manifest_dir, &self.prefix)
+    }
+
+    pub(crate) fn save_manifest(&self, manifest: &Manifest) -> Result<()> {
+        manifest.save_with_prefix(&self.manifest_dir, &self.prefix)
+    }
+
+    /// Claims the files for a Database open for writing until the returned
+    /// Lock is dropped
+    ///
+    /// Fails with [`Error::InvalidConfig`] if another Database of the process
+    /// holds files that could be mistaken for these, that is if both use a
+    /// directory with one prefix starting with the other. The directories
+    /// must exist.
+    pub(crate) fn lock(&self) -> Result<Lock> {
+        let mut locations = Vec::new();
+        for dir in [&self.wal_dir, &self.table_dir, &self.manifest_dir] {
+            let location = (fs::canonicalize(dir)?, self.prefix.clone());
+            if !locations.contains(&location) {
+                locations.push(location);
+            }
+        }
+
+        let mut open = OPEN_LOCATIONS.lock().unwrap();
+        for (dir, prefix) in &locations {
+            let overlaps = open.iter().any(|(open_dir, open_prefix)| {
+                open_dir == dir
+                    && (open_prefix.starts_with(prefix) || prefix.starts_with(open_prefix))
+            });
+            if overlaps {
+                return Err(Error::InvalidConfig(format!(
+                    "the files in {} with the prefix {:?} overlap with another open database",
+                    dir.display(),
+                    prefix
+                )));
+            }
+        }
+        open.extend(locations.iter().cloned());
+        Ok(Lock { locations })
+    }
+}
+
+/// Lock releases the files claimed by `Paths::lock` once dropped
+pub(crate) struct Lock {
+    locations: Vec<(PathBuf, String)>,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let mut open = OPEN_LOCATIONS.lock().unwrap();
+        for location in &self.locations {
+            if let Some(idx) = open.iter().position(|l| l == location) {
+                open.swap_remove(idx);
+            }
+        }
+    }
+}
+
+/// Returns the sorted ids of the files in `dir` named `<prefix><id>.<extension>`
+fn file_ids(dir: &Path, prefix: &str, extension: &str) -> Result<Vec<u64>> {
+    let mut ids = Vec::new();
+    for dir_entry in fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(extension) {
+            continue;
+        }
+        if let Some(id) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.strip_prefix(prefix))
+            .and_then(|s| s.parse::<u64>().ok())
+        {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
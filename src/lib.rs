@@ -1 +1,25 @@
-mod mem_table;
+pub mod bloom;
+pub mod cache;
+pub mod cdc;
+pub mod clock;
+pub mod compaction;
+pub mod comparator;
+pub mod compression;
+pub mod db;
+pub mod error;
+#[cfg(test)]
+mod faulty;
+mod flush;
+pub mod key;
+pub mod layout;
+pub mod manifest;
+pub mod mem_table;
+pub mod merge;
+pub mod metrics;
+pub mod table;
+#[cfg(feature = "serde")]
+pub mod typed;
+pub mod verify;
+pub mod wal;
+
+pub use error::{Error, Result};
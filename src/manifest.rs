@@ -0,0 +1,121 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::error::Result;
+
+const MANIFEST_FILE_NAME: &str = "MANIFEST";
+const MANIFEST_TMP_FILE_NAME: &str = "MANIFEST.tmp";
+
+/// Manifest records the live Tables of a Database
+///
+/// It is stored in the Database's directory as a text file holding the file
+/// name of one Table per line, from the oldest to the newest Table. A
+/// Manifest is never modified in place: a new one is written next to it and
+/// then renamed over it, so after a crash the directory holds either the old
+/// or the new Manifest as a whole.
+///
+/// Table files that are not listed in the Manifest, such as the output of an
+/// interrupted compaction, are not part of the Database.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    tables: Vec<String>,
+}
+
+impl Manifest {
+    /// Creates a Manifest from the file names of the Tables, ordered from the
+    /// oldest to the newest
+    pub fn new(tables: Vec<String>) -> Manifest {
+        Manifest { tables }
+    }
+
+    /// Loads the Manifest stored in the given directory
+    ///
+    /// Returns None if the directory has no Manifest.
+    pub fn load(dir: &Path) -> Result<Option<Manifest>> {
+        Manifest::load_with_prefix(dir, "")
+    }
+
+    /// Loads the Manifest stored in the given directory under a file name
+    /// starting with the prefix
+    pub fn load_with_prefix(dir: &Path, prefix: &str) -> Result<Option<Manifest>> {
+        let path = dir.join(format!("{}{}", prefix, MANIFEST_FILE_NAME));
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let tables = contents
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect();
+        Ok(Some(Manifest { tables }))
+    }
+
+    /// Atomically replaces the Manifest stored in the given directory
+    ///
+    /// The new Manifest is synced to the disk before it is renamed into
+    /// place, and the directory is synced after the rename.
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        self.save_with_prefix(dir, "")
+    }
+
+    /// Atomically replaces the Manifest stored in the given directory under a
+    /// file name starting with the prefix
+    pub fn save_with_prefix(&self, dir: &Path, prefix: &str) -> Result<()> {
+        let mut contents = String::new();
+        for table in &self.tables {
+            contents.push_str(table);
+            contents.push('\n');
+        }
+
+        let tmp_path = dir.join(format!("{}{}", prefix, MANIFEST_TMP_FILE_NAME));
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(
+            &tmp_path,
+            dir.join(format!("{}{}", prefix, MANIFEST_FILE_NAME)),
+        )?;
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Returns the file names of the Tables, from the oldest to the newest
+    pub fn tables(&self) -> &[String] {
+        &self.tables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::manifest::Manifest;
+
+    #[test]
+    fn test_manifest_save_load() {
+        let dir = tempdir().unwrap();
+        assert!(Manifest::load(dir.path()).unwrap().is_none());
+
+        let manifest = Manifest::new(vec!["000000.sst".to_owned(), "000002.sst".to_owned()]);
+        manifest.save(dir.path()).unwrap();
+        assert_eq!(Manifest::load(dir.path()).unwrap().unwrap(), manifest);
+        assert!(!dir.path().join("MANIFEST.tmp").exists());
+
+        let manifest = Manifest::new(vec!["000003.sst".to_owned()]);
+        manifest.save(dir.path()).unwrap();
+        assert_eq!(Manifest::load(dir.path()).unwrap().unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_manifest_empty() {
+        let dir = tempdir().unwrap();
+        Manifest::default().save(dir.path()).unwrap();
+
+        let manifest = Manifest::load(dir.path()).unwrap().unwrap();
+        assert!(manifest.tables().is_empty());
+    }
+}
@@ -1,3 +1,22 @@
+use std::cmp::Ordering;
+use std::io;
+use std::ops::Bound;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+use crate::clock::{Clock, SystemClock};
+use crate::comparator::{BytewiseComparator, Comparator};
+use crate::error::{self, Error};
+use crate::merge::{self, MergeOperator};
+
+/// Default size at which a MemTable is considered full
+pub const DEFAULT_MAX_SIZE: usize = 4 * 1024 * 1024;
+
+/// Default maximum length of a key in bytes
+pub const DEFAULT_MAX_KEY_LEN: usize = 64 * 1024;
+
+/// Default maximum length of a Value in bytes
+pub const DEFAULT_MAX_VALUE_LEN: usize = 1024 * 1024 * 1024;
+
 /// MemTable holds a sorted list of the latest written records
 ///
 /// Writes are dublicated to the WAL(Write Ahead Log) for the
@@ -7,181 +26,1780 @@
 /// flush the MemTable to the disk as a Table(SSTable).
 ///
 /// Entries are stored in a Vector instead of a HashMap to
-/// support scans.
-pub struct MemTable {
-    entries: Vec<MemTableEntry>,
+/// support scans. They are ordered by the MemTable's Comparator,
+/// which defaults to ordering keys by their bytes.
+///
+/// Keys may be empty. With the default Comparator the empty key sorts before
+/// every other key, and it round-trips through the WAL and the Tables like
+/// any other key.
+///
+/// Entries written with a TTL are hidden from reads once the MemTable's
+/// Clock passes their expiry time.
+///
+/// By default a write replaces the previous version of its key. A MemTable
+/// created `with_versions` keeps several versions of each key instead.
+///
+/// Values are stored as `V`, which defaults to `Vec<u8>`. Any type that can
+/// be viewed as bytes works, so callers that already hold their values in
+/// their own buffers or fixed size structs don't need to copy them into a
+/// `Vec<u8>` on every write. Keys are always bytes, since they are ordered by
+/// the Comparator. Sizes are accounted from the bytes of the Values.
+#[derive(Clone)]
+pub struct MemTable<C = BytewiseComparator, V = Vec<u8>> {
+    entries: Vec<MemTableEntry<V>>,
+    /// Range Tombstones in the order they were written
+    range_tombstones: Vec<RangeTombstone>,
     size: usize,
+    live_len: usize,
+    max_size: usize,
+    /// Number of versions kept for each key
+    max_versions: usize,
+    /// Timestamp of the oldest Snapshot of the Database, whose versions are
+    /// kept on top of `max_versions`
+    oldest_snapshot_ms: Option<u128>,
+    /// Folds merges into the records of their keys
+    merge_operator: Option<Arc<dyn MergeOperator>>,
+    /// Longest key accepted by `try_set` and `check_lengths`
+    max_key_len: usize,
+    /// Longest Value accepted by `try_set` and `check_lengths`
+    max_value_len: usize,
+    comparator: C,
+    clock: Arc<dyn Clock>,
 }
 
 /// A MemTable Entry
-pub struct MemTableEntry {
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemTableEntry<V = Vec<u8>> {
     pub key: Vec<u8>,
-    pub value: Option<Vec<u8>>,
+    pub value: Option<V>,
     pub timestamp_ms: u128,
     pub is_deleted: bool,
+    /// Whether the record is a merge, whose Value holds the operands still to
+    /// be folded by a [`MergeOperator`] rather than a value
+    pub is_merge: bool,
+    pub expires_at_ms: Option<u128>,
+}
+
+/// A single mutation of a MemTable, applied with [`MemTable::apply`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// Sets a Key-Value pair, optionally expiring at `expires_at_ms`
+    Set {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        timestamp_ms: u128,
+        expires_at_ms: Option<u128>,
+    },
+    /// Deletes a key with a tombstone
+    Delete { key: Vec<u8>, timestamp_ms: u128 },
+    /// Deletes every key from `start` included to `end` excluded with a
+    /// Range Tombstone
+    DeleteRange {
+        start: Vec<u8>,
+        end: Vec<u8>,
+        timestamp_ms: u128,
+    },
+    /// Merges operands into the value of a key with the MemTable's
+    /// MergeOperator, from the oldest to the newest
+    Merge {
+        key: Vec<u8>,
+        operands: Vec<Vec<u8>>,
+        timestamp_ms: u128,
+    },
+}
+
+impl Operation {
+    /// Decodes the Operation of a WAL record from the start of the buffer
+    ///
+    /// Records are decoded like `MemTableEntry::decode`, except that
+    /// records of Range Tombstones turn into `DeleteRange` and merge records
+    /// into `Merge`.
+    pub(crate) fn decode(buf: &[u8]) -> io::Result<(Operation, usize)> {
+        let mut pos = 0;
+        let key_len = u64::from_le_bytes(take(buf, &mut pos, 8)?.try_into().unwrap());
+        let key = take(buf, &mut pos, key_len as usize)?.to_vec();
+        let is_range = buf.get(pos).is_some_and(|&flags| flags & FLAG_RANGE != 0);
+        let entry = MemTableEntry::decode_body(key, buf, &mut pos, true)?;
+        check_crc(buf, &mut pos)?;
+
+        let operation = if is_range {
+            Operation::DeleteRange {
+                start: entry.key,
+                end: entry.value.unwrap_or_default(),
+                timestamp_ms: entry.timestamp_ms,
+            }
+        } else if entry.is_merge {
+            Operation::Merge {
+                operands: merge::decode_operands(entry.value.as_deref().unwrap_or_default())?,
+                key: entry.key,
+                timestamp_ms: entry.timestamp_ms,
+            }
+        } else {
+            Operation::from(entry)
+        };
+        Ok((operation, pos))
+    }
+}
+
+impl From<MemTableEntry> for Operation {
+    /// Turns a record back into the Operation that wrote it, such as when
+    /// replaying the WAL
+    fn from(entry: MemTableEntry) -> Self {
+        match entry {
+            MemTableEntry {
+                key,
+                value,
+                timestamp_ms,
+                is_deleted: false,
+                is_merge: false,
+                expires_at_ms,
+            } => Operation::Set {
+                key,
+                value: value.unwrap_or_default(),
+                timestamp_ms,
+                expires_at_ms,
+            },
+            MemTableEntry {
+                key,
+                value,
+                timestamp_ms,
+                is_merge: true,
+                ..
+            } => Operation::Merge {
+                key,
+                operands: merge::decode_operands(&value.unwrap_or_default()).unwrap_or_default(),
+                timestamp_ms,
+            },
+            MemTableEntry {
+                key, timestamp_ms, ..
+            } => Operation::Delete { key, timestamp_ms },
+        }
+    }
+}
+
+/// A Range Tombstone deletes every key from `start` included to `end`
+/// excluded that was written before it
+///
+/// Unlike a tombstone, it doesn't replace the records it deletes. They are
+/// hidden when the sources of the Database are merged, and dropped once they
+/// are compacted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeTombstone {
+    pub start: Vec<u8>,
+    pub end: Vec<u8>,
+    pub timestamp_ms: u128,
+}
+
+impl RangeTombstone {
+    /// Whether the Range Tombstone deletes the record of the key written at
+    /// `timestamp_ms`
+    pub fn deletes<C: Comparator>(&self, comparator: &C, key: &[u8], timestamp_ms: u128) -> bool {
+        timestamp_ms < self.timestamp_ms
+            && comparator.compare(&self.start, key).is_le()
+            && comparator.compare(key, &self.end).is_lt()
+    }
+
+    /// Encodes the Range Tombstone into the record layout of the WAL
+    ///
+    /// The record of a Range Tombstone sets bit 3 of the Flags, and holds the
+    /// start of the range as its Key and the end as its Value.
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend_from_slice(&(self.start.len() as u64).to_le_bytes());
+        out.extend_from_slice(&self.start);
+        out.push(FLAG_RANGE);
+        out.extend_from_slice(&(self.end.len() as u64).to_le_bytes());
+        out.extend_from_slice(&self.end);
+        out.extend_from_slice(&self.timestamp_ms.to_le_bytes());
+
+        let crc = crc32c::crc32c(&out[start..]);
+        out.extend_from_slice(&crc.to_le_bytes());
+    }
+
+    /// Returns the size the Range Tombstone adds to a MemTable
+    fn size(&self) -> usize {
+        self.start.len() + self.end.len() + 16
+    }
+}
+
+/// Statistics about the records of a MemTable, for monitoring
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemTableStats {
+    /// Number of entries that are not tombstones
+    pub live_count: usize,
+    /// Number of tombstones
+    pub tombstone_count: usize,
+    /// Logical size of the MemTable, as returned by `MemTable::size`
+    pub size: usize,
+    /// Length of the longest Key
+    pub max_key_len: usize,
+    /// Length of the longest Value
+    pub max_value_len: usize,
+}
+
+/// Record flag marking a tombstone
+const FLAG_DELETED: u8 = 1 << 0;
+
+/// Record flag marking a record with an expiry time
+const FLAG_EXPIRES: u8 = 1 << 1;
+
+/// Record flag marking a Table record whose Value is stored in the blob area
+/// of the Table. WAL records never carry it.
+pub(crate) const FLAG_BLOB: u8 = 1 << 2;
+
+/// Record flag marking a WAL record of a Range Tombstone
+const FLAG_RANGE: u8 = 1 << 3;
+
+/// Record flag marking a merge record
+const FLAG_MERGE: u8 = 1 << 4;
+
+/// Record flag marking a Table record with the Value of the record before it
+/// in its block, which it leaves out. WAL records never carry it.
+pub(crate) const FLAG_SHARED_VALUE: u8 = 1 << 5;
+
+impl<V: AsRef<[u8]>> MemTableEntry<V> {
+    /// Whether the entry has a TTL that has run out at the given time
+    ///
+    /// An expired entry is logically deleted.
+    pub fn is_expired(&self, now_ms: u128) -> bool {
+        self.expires_at_ms
+            .is_some_and(|expires_at| now_ms >= expires_at)
+    }
+
+    /// Returns the number of bytes `encode` writes for the entry
+    pub fn encoded_len(&self) -> usize {
+        let value_len = match self.is_deleted {
+            true => 0,
+            false => 8 + self.value.as_ref().map_or(0, |v| v.as_ref().len()),
+        };
+        let expires_len = self.expires_at_ms.map_or(0, |_| 16);
+        8 + self.key.len() + 1 + value_len + 16 + expires_len + 4
+    }
+
+    /// Encodes the entry into the record layout of the WAL
+    ///
+    /// ```text
+    /// +--------------+-----+------------+----------------+-------+----------------+------------------+----------+
+    /// | Key Len (8B) | Key | Flags (1B) | Value Len (8B) | Value | Timestamp(16B) | Expires At (16B) | CRC (4B) |
+    /// +--------------+-----+------------+----------------+-------+----------------+------------------+----------+
+    /// ```
+    ///
+    /// Bit 0 of the Flags marks a tombstone, bit 1 marks a record with an
+    /// expiry time and bit 4 marks a merge record, whose Value holds its
+    /// operands. The Value Len and Value are omitted for tombstones and the
+    /// Expires At is omitted for records without an expiry. The CRC is the
+    /// CRC32C of the preceding bytes of the record. All integers are
+    /// little-endian. Tables prefix compress the key but share the rest of
+    /// the layout.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend_from_slice(&(self.key.len() as u64).to_le_bytes());
+        out.extend_from_slice(&self.key);
+        self.encode_body(out);
+
+        let crc = crc32c::crc32c(&out[start..]);
+        out.extend_from_slice(&crc.to_le_bytes());
+    }
+
+    /// Encodes the part of the record following the key, from the Flags up to
+    /// the Expires At
+    pub(crate) fn encode_body(&self, out: &mut Vec<u8>) {
+        self.encode_body_with(false, out);
+    }
+
+    /// Encodes the part of the record following the key like `encode_body`
+    ///
+    /// With `shares_value`, the Shared Value Flag is set and the Value Len
+    /// and Value are left out, the entry must not be a tombstone.
+    pub(crate) fn encode_body_with(&self, shares_value: bool, out: &mut Vec<u8>) {
+        let mut flags = 0;
+        if self.is_deleted {
+            flags |= FLAG_DELETED;
+        }
+        if self.expires_at_ms.is_some() {
+            flags |= FLAG_EXPIRES;
+        }
+        if self.is_merge {
+            flags |= FLAG_MERGE;
+        }
+        if shares_value {
+            flags |= FLAG_SHARED_VALUE;
+        }
+        out.push(flags);
+
+        if !self.is_deleted && !shares_value {
+            let value = self.value.as_ref().map_or(&[][..], AsRef::as_ref);
+            out.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            out.extend_from_slice(value);
+        }
+        out.extend_from_slice(&self.timestamp_ms.to_le_bytes());
+        if let Some(expires_at_ms) = self.expires_at_ms {
+            out.extend_from_slice(&expires_at_ms.to_le_bytes());
+        }
+    }
+}
+
+impl MemTableEntry {
+    /// Decodes an entry from the start of the buffer
+    ///
+    /// Returns the entry and the number of bytes it took up. Fails with
+    /// `UnexpectedEof` if the buffer ends before the record does, and with
+    /// `InvalidData` if the record fails its checksum.
+    pub fn decode(buf: &[u8]) -> io::Result<(MemTableEntry, usize)> {
+        let mut pos = 0;
+        let key_len = u64::from_le_bytes(take(buf, &mut pos, 8)?.try_into().unwrap());
+        let key = take(buf, &mut pos, key_len as usize)?.to_vec();
+        let entry = MemTableEntry::decode_body(key, buf, &mut pos, true)?;
+        check_crc(buf, &mut pos)?;
+        Ok((entry, pos))
+    }
+
+    /// Decodes the part of the record following the key, starting at `pos`
+    ///
+    /// Without `with_value` the Value is skipped over instead of copied, and
+    /// left as None. The Value of a record with the Shared Value Flag is left
+    /// as None as well, for the caller to fill in.
+    pub(crate) fn decode_body(
+        key: Vec<u8>,
+        buf: &[u8],
+        pos: &mut usize,
+        with_value: bool,
+    ) -> io::Result<MemTableEntry> {
+        let u64_from = |b: &[u8]| u64::from_le_bytes(b.try_into().unwrap());
+        let u128_from = |b: &[u8]| u128::from_le_bytes(b.try_into().unwrap());
+
+        let flags = take(buf, pos, 1)?[0];
+        let is_deleted = flags & FLAG_DELETED != 0;
+        let value = if is_deleted || flags & FLAG_SHARED_VALUE != 0 {
+            None
+        } else {
+            let value_len = u64_from(take(buf, pos, 8)?) as usize;
+            let value = take(buf, pos, value_len)?;
+            with_value.then(|| value.to_vec())
+        };
+        let timestamp_ms = u128_from(take(buf, pos, 16)?);
+        let expires_at_ms = if flags & FLAG_EXPIRES != 0 {
+            Some(u128_from(take(buf, pos, 16)?))
+        } else {
+            None
+        };
+
+        Ok(MemTableEntry {
+            key,
+            value,
+            timestamp_ms,
+            is_deleted,
+            is_merge: flags & FLAG_MERGE != 0,
+            expires_at_ms,
+        })
+    }
+}
+
+/// Takes `len` bytes of the buffer at `pos`, moving `pos` past them
+pub(crate) fn take<'a>(buf: &'a [u8], pos: &mut usize, len: usize) -> io::Result<&'a [u8]> {
+    let bytes = pos
+        .checked_add(len)
+        .and_then(|end| buf.get(*pos..end))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated record"))?;
+    *pos += len;
+    Ok(bytes)
+}
+
+/// Reads the CRC at `pos` and checks it against the bytes of the buffer
+/// before it
+pub(crate) fn check_crc(buf: &[u8], pos: &mut usize) -> io::Result<()> {
+    let expected = crc32c::crc32c(&buf[..*pos]);
+    let crc = u32::from_le_bytes(take(buf, pos, 4)?.try_into().unwrap());
+    if crc != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "record checksum mismatch",
+        ));
+    }
+    Ok(())
+}
+
+impl<C: Comparator + Default, V: AsRef<[u8]> + Clone> Default for MemTable<C, V> {
+    fn default() -> Self {
+        MemTable::with_capacity_and_comparator(DEFAULT_MAX_SIZE, C::default())
+    }
 }
 
 impl MemTable {
     /// Creates a new empty MemTable
     pub fn new() -> MemTable {
+        MemTable::with_capacity(DEFAULT_MAX_SIZE)
+    }
+
+    /// Creates a new empty MemTable that is full once its size reaches
+    /// `max_size`
+    pub fn with_capacity(max_size: usize) -> MemTable {
+        MemTable::with_capacity_and_comparator(max_size, BytewiseComparator)
+    }
+
+    /// Creates a MemTable from a list of entries already sorted by key
+    ///
+    /// The size of the MemTable is recomputed from the entries. Unsorted
+    /// input is caught by an assertion in debug builds.
+    pub fn from_entries(entries: Vec<MemTableEntry>) -> MemTable {
+        let size = entries.iter().map(MemTableEntry::encoded_len).sum();
+        let live_len = entries.iter().filter(|e| !e.is_deleted).count();
+
+        let table = MemTable {
+            entries,
+            range_tombstones: Vec::new(),
+            size,
+            live_len,
+            max_size: DEFAULT_MAX_SIZE,
+            max_versions: 1,
+            oldest_snapshot_ms: None,
+            merge_operator: None,
+            max_key_len: DEFAULT_MAX_KEY_LEN,
+            max_value_len: DEFAULT_MAX_VALUE_LEN,
+            comparator: BytewiseComparator,
+            clock: Arc::new(SystemClock),
+        };
+        #[cfg(debug_assertions)]
+        table.assert_sorted();
+        table
+    }
+}
+
+impl<C: Comparator> MemTable<C> {
+    /// Creates a new empty MemTable ordering its keys with the given
+    /// Comparator
+    pub fn with_comparator(comparator: C) -> MemTable<C> {
+        MemTable::with_capacity_and_comparator(DEFAULT_MAX_SIZE, comparator)
+    }
+
+    /// Estimates the heap memory used by the MemTable
+    ///
+    /// Unlike `size`, which counts the records as they are encoded in the
+    /// WAL, this includes the spare capacity of the entries Vec and of every Key
+    /// and Value buffer.
+    pub fn heap_size(&self) -> usize {
+        let buffers: usize = self
+            .entries
+            .iter()
+            .map(|e| e.key.capacity() + e.value.as_ref().map_or(0, |v| v.capacity()))
+            .sum();
+        self.entries.capacity() * std::mem::size_of::<MemTableEntry>() + buffers
+    }
+}
+
+impl<C: Comparator, V: AsRef<[u8]> + Clone> MemTable<C, V> {
+    /// Creates a new empty MemTable ordering its keys with the given
+    /// Comparator, that is full once its size reaches `max_size`
+    ///
+    /// Unlike the other constructors, this works for any type of Value.
+    pub fn with_capacity_and_comparator(max_size: usize, comparator: C) -> MemTable<C, V> {
         MemTable {
             entries: Vec::new(),
+            range_tombstones: Vec::new(),
             size: 0,
+            live_len: 0,
+            max_size,
+            max_versions: 1,
+            oldest_snapshot_ms: None,
+            merge_operator: None,
+            max_key_len: DEFAULT_MAX_KEY_LEN,
+            max_value_len: DEFAULT_MAX_VALUE_LEN,
+            comparator,
+            clock: Arc::new(SystemClock),
         }
     }
 
-    /// Sets a Key-Value pair in the MemTable.
-    pub fn set(&mut self, key: &[u8], value: &[u8], timestamp_ms: u128) {
-        let entry = MemTableEntry {
+    /// Sets the Clock used to stamp writes and to decide whether entries have
+    /// expired
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> MemTable<C, V> {
+        self.clock = clock;
+        self
+    }
+
+    /// Keeps up to `max_versions` versions of each key, for reads of older
+    /// versions with `get_as_of`
+    ///
+    /// This must be set before anything is written. Versions of a key are
+    /// stored next to each other from the newest to the oldest, and once a
+    /// key has more versions the oldest one is dropped. Each version counts
+    /// towards the size of the MemTable. Lookups and `iter_live` only see the
+    /// newest version of a key, while scans yield every version and flushing
+    /// writes them all to the Table. Merging MemTables and compacting Tables
+    /// may drop all but the newest version.
+    pub fn with_versions(mut self, max_versions: usize) -> MemTable<C, V> {
+        self.max_versions = max_versions.max(1);
+        self
+    }
+
+    /// Sets the MergeOperator folding the operands of `merge_at` into the
+    /// records of their keys
+    pub fn with_merge_operator(mut self, merge_operator: Arc<dyn MergeOperator>) -> MemTable<C, V> {
+        self.merge_operator = Some(merge_operator);
+        self
+    }
+
+    /// Sets the length in bytes of the longest key accepted by `try_set`
+    pub fn with_max_key_len(mut self, max_key_len: usize) -> MemTable<C, V> {
+        self.max_key_len = max_key_len;
+        self
+    }
+
+    /// Sets the length in bytes of the longest Value accepted by `try_set`
+    pub fn with_max_value_len(mut self, max_value_len: usize) -> MemTable<C, V> {
+        self.max_value_len = max_value_len;
+        self
+    }
+
+    /// Checks a key and its Value, if any, against the maximum lengths of
+    /// the MemTable
+    ///
+    /// Fails with [`Error::KeyTooLarge`] or [`Error::ValueTooLarge`] if either
+    /// is longer than allowed.
+    pub fn check_lengths(&self, key: &[u8], value: Option<&[u8]>) -> error::Result<()> {
+        if key.len() > self.max_key_len {
+            return Err(Error::KeyTooLarge {
+                len: key.len(),
+                max: self.max_key_len,
+            });
+        }
+        match value {
+            Some(value) if value.len() > self.max_value_len => Err(Error::ValueTooLarge {
+                len: value.len(),
+                max: self.max_value_len,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets a Key-Value pair like `set`, unless the key or the Value is
+    /// longer than allowed
+    ///
+    /// The other ways of writing to the MemTable don't check the lengths.
+    /// Returns the previous Value of the key, or None if the key was absent
+    /// or deleted.
+    pub fn try_set(&mut self, key: &[u8], value: impl Into<V>) -> error::Result<Option<V>> {
+        let value = value.into();
+        self.check_lengths(key, Some(value.as_ref()))?;
+        Ok(self.set(key, value))
+    }
+
+    /// Sets a Key-Value pair in the MemTable, stamped with the current time of
+    /// the MemTable's Clock
+    ///
+    /// Returns the previous Value of the key, or None if the key was absent
+    /// or deleted.
+    pub fn set(&mut self, key: &[u8], value: impl Into<V>) -> Option<V> {
+        let timestamp_ms = self.clock.now_ms();
+        self.set_at(key, value, timestamp_ms)
+    }
+
+    /// Sets a Key-Value pair in the MemTable with an explicit timestamp
+    ///
+    /// This is meant for replaying records that already carry a timestamp,
+    /// such as during recovery. Returns the previous Value of the key, or None
+    /// if the key was absent or deleted.
+    pub fn set_at(&mut self, key: &[u8], value: impl Into<V>, timestamp_ms: u128) -> Option<V> {
+        self.set_owned(key.to_owned(), value, timestamp_ms)
+    }
+
+    /// Sets a Key-Value pair like `set_at`, taking ownership of the key
+    ///
+    /// The key is stored as is rather than copied, and so is a Value that is
+    /// already a `V`, so ingesting keys and Values built by the caller
+    /// allocates nothing besides the room for the record.
+    pub fn set_owned(
+        &mut self,
+        key: Vec<u8>,
+        value: impl Into<V>,
+        timestamp_ms: u128,
+    ) -> Option<V> {
+        self.insert(key, value.into(), timestamp_ms, None)
+    }
+
+    /// Sets a Key-Value pair in the MemTable that expires `ttl_ms`
+    /// milliseconds after `timestamp_ms`
+    ///
+    /// Once expired, the record is treated as deleted. Returns the previous
+    /// Value of the key, or None if the key was absent or deleted.
+    pub fn set_with_ttl(
+        &mut self,
+        key: &[u8],
+        value: impl Into<V>,
+        timestamp_ms: u128,
+        ttl_ms: u128,
+    ) -> Option<V> {
+        self.insert(
+            key.to_owned(),
+            value.into(),
+            timestamp_ms,
+            Some(timestamp_ms + ttl_ms),
+        )
+    }
+
+    /// Applies an Operation to the MemTable
+    ///
+    /// Every mutation goes through the same paths as `set_at`,
+    /// `set_with_ttl`, `delete_at` and `merge_at`. Returns the previous Value
+    /// of the key, or None if the key was absent or deleted.
+    ///
+    /// Fails like `merge_at` for a merge, leaving the MemTable as it was.
+    pub fn apply(&mut self, operation: Operation) -> error::Result<Option<V>>
+    where
+        V: From<Vec<u8>>,
+    {
+        let previous = match operation {
+            Operation::Set {
+                key,
+                value,
+                timestamp_ms,
+                expires_at_ms,
+            } => self.insert(key, value.into(), timestamp_ms, expires_at_ms),
+            Operation::Delete { key, timestamp_ms } => self.delete_at(&key, timestamp_ms),
+            Operation::DeleteRange {
+                start,
+                end,
+                timestamp_ms,
+            } => {
+                self.delete_range_at(&start, &end, timestamp_ms);
+                None
+            }
+            Operation::Merge {
+                key,
+                operands,
+                timestamp_ms,
+            } => {
+                let previous = self.get(&key).and_then(|e| e.value.clone());
+                // Only the first operand may have to be folded, the next
+                // ones then fold into its result.
+                for operand in &operands {
+                    self.merge_at(&key, operand, timestamp_ms)?;
+                }
+                previous
+            }
+        };
+        Ok(previous)
+    }
+
+    /// Merges an operand into the value of a key with an explicit timestamp
+    ///
+    /// If the MemTable holds a record of the key that isn't a merge, the
+    /// operand is folded into it by the MergeOperator right away, which sets
+    /// or deletes the key. Otherwise the key may still have a value in an
+    /// older source, so the operand is added after the others to the merge
+    /// record of the key, for the Database to fold once it finds the value.
+    ///
+    /// Fails with [`Error::InvalidConfig`] if the operand has to be folded
+    /// and the MemTable has no MergeOperator, leaving the MemTable as it was.
+    pub fn merge_at(&mut self, key: &[u8], operand: &[u8], timestamp_ms: u128) -> error::Result<()>
+    where
+        V: From<Vec<u8>>,
+    {
+        let now_ms = self.clock.now_ms();
+        let newest = self.entries[self.lower_bound(key)..self.upper_bound(key)].first();
+        // A record deleted by a newer Range Tombstone is folded over as if
+        // the key were deleted, and so are the operands of older sources.
+        let range_deleted = newest.is_some_and(|e| {
+            self.range_tombstones
+                .iter()
+                .any(|t| t.deletes(&self.comparator, &e.key, e.timestamp_ms))
+        });
+        let mut operands = Vec::new();
+        match newest {
+            Some(entry) if entry.is_merge && !range_deleted => {
+                operands.extend_from_slice(entry.value.as_ref().map_or(&[][..], AsRef::as_ref));
+            }
+            Some(entry) => {
+                let existing = entry
+                    .value
+                    .as_ref()
+                    .filter(|_| !entry.is_deleted && !entry.is_expired(now_ms) && !range_deleted);
+                let mut merge = Vec::new();
+                merge::push_operand(&mut merge, operand);
+                let folded = merge::fold(
+                    self.merge_operator.as_deref(),
+                    &[merge],
+                    existing.map(AsRef::as_ref),
+                )?;
+                match folded {
+                    Some(value) => self.insert(key.to_owned(), value.into(), timestamp_ms, None),
+                    None => self.delete_at(key, timestamp_ms),
+                };
+                return Ok(());
+            }
+            None => {}
+        }
+
+        merge::push_operand(&mut operands, operand);
+        self.insert_entry(MemTableEntry {
             key: key.to_owned(),
-            value: Some(value.to_owned()),
+            value: Some(operands.into()),
             timestamp_ms,
             is_deleted: false,
+            is_merge: true,
+            expires_at_ms: None,
+        });
+        Ok(())
+    }
+
+    /// Deletes every key from `start` included to `end` excluded that was
+    /// written before `timestamp_ms`, with a single Range Tombstone
+    ///
+    /// The records of the range are kept, and the lookups and scans of the
+    /// MemTable itself still return them: a Range Tombstone deletes records
+    /// of older sources too, so it is applied by the Database when merging
+    /// them. An empty range is ignored.
+    pub fn delete_range_at(&mut self, start: &[u8], end: &[u8], timestamp_ms: u128) {
+        if self.comparator.compare(start, end).is_ge() {
+            return;
+        }
+
+        let tombstone = RangeTombstone {
+            start: start.to_vec(),
+            end: end.to_vec(),
+            timestamp_ms,
         };
+        self.size += tombstone.size();
+        self.range_tombstones.push(tombstone);
+    }
+
+    /// Returns the Range Tombstones in the order they were written
+    pub fn range_tombstones(&self) -> &[RangeTombstone] {
+        &self.range_tombstones
+    }
+
+    fn insert(
+        &mut self,
+        key: Vec<u8>,
+        value: V,
+        timestamp_ms: u128,
+        expires_at_ms: Option<u128>,
+    ) -> Option<V> {
+        self.insert_entry(MemTableEntry {
+            key,
+            value: Some(value),
+            timestamp_ms,
+            is_deleted: false,
+            is_merge: false,
+            expires_at_ms,
+        })
+    }
+
+    /// Inserts a record holding a Value, replacing the previous record of its
+    /// key
+    fn insert_entry(&mut self, entry: MemTableEntry<V>) -> Option<V> {
+        if self.keeps_versions() {
+            return self.insert_version(entry);
+        }
 
-        match self.get_index(key) {
+        self.size += entry.encoded_len();
+        match self.insert_index(&entry.key) {
             Ok(idx) => {
-                // If a value existed on the is_deleted record,
-                // then add the difference of the new and old Value
-                // to the MemTable's size.
-                if let Some(v) = self.entries[idx].value.as_ref() {
-                    if value.len() < v.len() {
-                        self.size -= v.len() - value.len();
-                    } else {
-                        self.size += value.len() - v.len();
-                    }
+                if self.entries[idx].is_deleted {
+                    self.live_len += 1;
                 }
-                self.entries[idx] = entry;
+                let old = std::mem::replace(&mut self.entries[idx], entry);
+                self.shrink_size(old.encoded_len());
+                old.value
             }
             Err(idx) => {
-                // Increase the size of the MemTable by the size of the Key, Value, Timestamp(16
-                // bytes) and Tombstone(1 byte).
-                self.size += key.len() + value.len() + 16 + 1;
-                self.entries.insert(idx, entry)
+                self.live_len += 1;
+                self.entries.insert(idx, entry);
+                #[cfg(debug_assertions)]
+                self.assert_sorted_around(idx);
+                None
             }
         }
     }
 
-    /// Deletes a Key-Value pair in the MemTable
+    /// Deletes a Key-Value pair in the MemTable, stamped with the current time
+    /// of the MemTable's Clock
     ///
-    /// This is achieved using tombstones
-    pub fn delete(&mut self, key: &[u8], timestamp_ms: u128) {
+    /// This is achieved using tombstones. Returns the previous Value of the
+    /// key, or None if the key was absent or already deleted.
+    pub fn delete(&mut self, key: &[u8]) -> Option<V> {
+        let timestamp_ms = self.clock.now_ms();
+        self.delete_at(key, timestamp_ms)
+    }
+
+    /// Deletes a Key-Value pair in the MemTable with an explicit timestamp
+    ///
+    /// Like `set_at`, this is meant for replaying records. Returns the
+    /// previous Value of the key, or None if the key was absent or already
+    /// deleted.
+    pub fn delete_at(&mut self, key: &[u8], timestamp_ms: u128) -> Option<V> {
         let entry = MemTableEntry {
             key: key.to_owned(),
             value: None,
             timestamp_ms,
             is_deleted: true,
+            is_merge: false,
+            expires_at_ms: None,
         };
+        if self.keeps_versions() {
+            return self.insert_version(entry);
+        }
 
-        match self.get_index(key) {
+        self.size += entry.encoded_len();
+        match self.insert_index(key) {
             Ok(idx) => {
-                if let Some(value) = self.entries[idx].value.as_ref() {
-                    self.size -= value.len();
+                if !self.entries[idx].is_deleted {
+                    self.live_len -= 1;
                 }
-                self.entries[idx] = entry;
+                let old = std::mem::replace(&mut self.entries[idx], entry);
+                self.shrink_size(old.encoded_len());
+                old.value
             }
             Err(idx) => {
-                // Increase the size of the MemTable by the size of the Key, Timestamp(16 bytes)
-                // and Tombstone(1 byte).
-                self.size += key.len() + 16 + 1;
-                self.entries.insert(idx, entry)
+                self.entries.insert(idx, entry);
+                #[cfg(debug_assertions)]
+                self.assert_sorted_around(idx);
+                None
             }
         }
     }
 
-    /// Get a Key-Value pair from the MemTable
+    /// Inserts a new version of a key, dropping the oldest versions once the
+    /// key has more than `max_versions`, unless a Snapshot may read them
     ///
-    /// If no record with the same key exists in the MemTable, return None
-    pub fn get(&self, key: &[u8]) -> Option<&MemTableEntry> {
-        if let Ok(idx) = self.get_index(key) {
-            return Some(&self.entries[idx]);
+    /// A version with the same timestamp as an existing one replaces it.
+    /// Returns the previous Value of the key, or None if the key was absent
+    /// or deleted.
+    fn insert_version(&mut self, entry: MemTableEntry<V>) -> Option<V> {
+        let start = self.lower_bound(&entry.key);
+        let end = self.upper_bound(&entry.key);
+        let newest = self.entries[start..end].first();
+        let was_live = newest.is_some_and(|e| !e.is_deleted);
+        let previous = newest.and_then(|e| e.value.clone());
+
+        // Versions are ordered by descending timestamp.
+        let pos = start
+            + self.entries[start..end].partition_point(|e| e.timestamp_ms > entry.timestamp_ms);
+        self.size += entry.encoded_len();
+        if pos < end && self.entries[pos].timestamp_ms == entry.timestamp_ms {
+            let old = std::mem::replace(&mut self.entries[pos], entry);
+            self.shrink_size(old.encoded_len());
+        } else {
+            self.entries.insert(pos, entry);
+            self.drop_old_versions(start, end + 1);
+        }
+
+        match (was_live, !self.entries[start].is_deleted) {
+            (false, true) => self.live_len += 1,
+            (true, false) => self.live_len -= 1,
+            _ => {}
+        }
+        #[cfg(debug_assertions)]
+        self.assert_sorted_around(pos.min(self.entries.len() - 1));
+
+        if was_live {
+            previous
+        } else {
+            None
         }
-        None
     }
 
-    /// Performs Binary Search to find a record in the MemTable
-    ///
-    /// If the record is found `[Result::Ok]` is returned, with
-    /// the index of record. If the record is not found then
-    /// `[Result::Err]` is returned, with the index to insert
-    /// the record at
-    fn get_index(&self, key: &[u8]) -> Result<usize, usize> {
-        self.entries
-            .binary_search_by_key(&key, |e| e.key.as_slice())
+    /// Drops the versions of a key, stored from `start` to `end`, that are
+    /// past `max_versions` and that no Snapshot can read
+    fn drop_old_versions(&mut self, start: usize, end: usize) {
+        let oldest_snapshot_ms = self.oldest_snapshot_ms.unwrap_or(u128::MAX);
+        // A version is only read by the Snapshots taken before the next newer
+        // version was written, so once that one is older than every Snapshot
+        // so are the remaining versions.
+        let keep = (start + self.max_versions..end)
+            .find(|&i| self.entries[i - 1].timestamp_ms <= oldest_snapshot_ms)
+            .unwrap_or(end);
+        let dropped_size = self.entries.drain(keep..end).map(|e| e.encoded_len()).sum();
+        self.shrink_size(dropped_size);
     }
 
-    pub fn len(&self) -> usize {
-        self.entries.len()
+    /// Keeps the versions of each key that a Snapshot taken at
+    /// `timestamp_ms`, or later, may read
+    ///
+    /// None means no Snapshot is left. The versions kept until then are
+    /// dropped by the next writes of their keys.
+    pub(crate) fn set_oldest_snapshot(&mut self, timestamp_ms: Option<u128>) {
+        // Once versions were kept, writes still go through `insert_version`
+        // so that they drop them, as no version is read at u128::MAX.
+        if timestamp_ms.is_some() || self.oldest_snapshot_ms.is_some() {
+            self.oldest_snapshot_ms = Some(timestamp_ms.unwrap_or(u128::MAX));
+        }
     }
 
-    pub fn entries(&self) -> &[MemTableEntry] {
-        &self.entries
+    /// Whether a key may have several versions
+    fn keeps_versions(&self) -> bool {
+        self.max_versions > 1 || self.oldest_snapshot_ms.is_some()
     }
 
-    pub fn size(&self) -> usize {
-        self.size
+    /// Sets many Key-Value pairs that arrive in sorted key order
+    ///
+    /// Each pair in order is appended to the end of the MemTable without a
+    /// search. A key that is not greater than the last key falls back to
+    /// `set_at`, so out of order input is still handled correctly, only more
+    /// slowly.
+    pub fn extend_sorted(&mut self, entries: impl Iterator<Item = (Vec<u8>, V, u128)>) {
+        for (key, value, timestamp_ms) in entries {
+            let in_order = self
+                .entries
+                .last()
+                .is_none_or(|last| self.comparator.compare(&last.key, &key) == Ordering::Less);
+            if !in_order {
+                self.set_at(&key, value, timestamp_ms);
+                continue;
+            }
+
+            let entry = MemTableEntry {
+                key,
+                value: Some(value),
+                timestamp_ms,
+                is_deleted: false,
+                is_merge: false,
+                expires_at_ms: None,
+            };
+            self.size += entry.encoded_len();
+            self.live_len += 1;
+            self.entries.push(entry);
+        }
+
+        #[cfg(debug_assertions)]
+        self.assert_sorted();
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::mem_table::MemTable;
+    /// Freezes the MemTable, so it can only be read from
+    pub fn freeze(self) -> ImmutableMemTable<C, V> {
+        ImmutableMemTable { inner: self }
+    }
 
-    #[test]
-    fn test_mem_table_put_start() {
-        let mut table = MemTable::new();
-        table.set(b"Lime", b"Lime Smoothie", 0); // 17 + 16 + 1
-        table.set(b"Orange", b"Orange Smoothie", 10); // 21 + 16 + 1
+    /// Merges two MemTables into one
+    ///
+    /// For keys present in both MemTables the entry with the larger timestamp
+    /// is kept. On equal timestamps the entry of `other` wins, as it is
+    /// expected to be the newer MemTable. The merged MemTable keeps the
+    /// Comparator, capacity and Clock of `self`.
+    pub fn merge(self, other: MemTable<C, V>) -> MemTable<C, V> {
+        let mut entries = Vec::with_capacity(self.entries.len() + other.entries.len());
+        let mut ours = self.entries.into_iter().peekable();
+        let mut theirs = other.entries.into_iter().peekable();
 
-        table.set(b"Apple", b"Apple Smoothie", 20); // 19 + 16 + 1
+        loop {
+            let next = match (ours.peek(), theirs.peek()) {
+                (Some(a), Some(b)) => match self.comparator.compare(&a.key, &b.key) {
+                    Ordering::Less => ours.next(),
+                    Ordering::Greater => theirs.next(),
+                    Ordering::Equal => {
+                        let (a, b) = (ours.next().unwrap(), theirs.next().unwrap());
+                        Some(if a.timestamp_ms > b.timestamp_ms {
+                            a
+                        } else {
+                            b
+                        })
+                    }
+                },
+                (Some(_), None) => ours.next(),
+                (None, Some(_)) => theirs.next(),
+                (None, None) => break,
+            };
+            entries.extend(next);
+        }
 
-        assert_eq!(table.entries[0].key, b"Apple");
-        assert_eq!(table.entries[0].value.as_ref().unwrap(), b"Apple Smoothie");
-        assert_eq!(table.entries[0].timestamp_ms, 20);
-        assert_eq!(table.entries[0].is_deleted, false);
-        assert_eq!(table.entries[1].key, b"Lime");
-        assert_eq!(table.entries[1].value.as_ref().unwrap(), b"Lime Smoothie");
-        assert_eq!(table.entries[1].timestamp_ms, 0);
-        assert_eq!(table.entries[1].is_deleted, false);
-        assert_eq!(table.entries[2].key, b"Orange");
-        assert_eq!(table.entries[2].value.as_ref().unwrap(), b"Orange Smoothie");
-        assert_eq!(table.entries[2].timestamp_ms, 10);
-        assert_eq!(table.entries[2].is_deleted, false);
+        let mut range_tombstones = self.range_tombstones;
+        range_tombstones.extend(other.range_tombstones);
 
-        assert_eq!(table.size, 108);
+        let mut merged = MemTable {
+            size: entries
+                .iter()
+                .map(MemTableEntry::encoded_len)
+                .sum::<usize>()
+                + range_tombstones
+                    .iter()
+                    .map(RangeTombstone::size)
+                    .sum::<usize>(),
+            live_len: 0,
+            entries,
+            range_tombstones,
+            max_size: self.max_size,
+            max_versions: self.max_versions,
+            oldest_snapshot_ms: self.oldest_snapshot_ms.or(other.oldest_snapshot_ms),
+            merge_operator: self.merge_operator.clone(),
+            max_key_len: self.max_key_len,
+            max_value_len: self.max_value_len,
+            comparator: self.comparator,
+            clock: self.clock,
+        };
+        merged.live_len = merged.newest_versions().filter(|e| !e.is_deleted).count();
+        #[cfg(debug_assertions)]
+        merged.assert_sorted();
+        merged
     }
 
-    #[test]
-    fn test_mem_table_put_middle() {
-        let mut table = MemTable::new();
-        table.set(b"Apple", b"Apple Smoothie", 0);
-        table.set(b"Orange", b"Orange Smoothie", 10);
+    /// Get a Key-Value pair from the MemTable
+    ///
+    /// If no record with the same key exists in the MemTable, or the record
+    /// has expired, return None
+    pub fn get(&self, key: &[u8]) -> Option<&MemTableEntry<V>> {
+        if let Ok(idx) = self.get_index(key) {
+            let entry = &self.entries[idx];
+            if !entry.is_expired(self.clock.now_ms()) {
+                return Some(entry);
+            }
+        }
+        None
+    }
 
-        table.set(b"Lime", b"Lime Smoothie", 20);
+    /// Get an owned copy of the record of a key, as its Value, timestamp and
+    /// whether it is a tombstone
+    ///
+    /// This finds the record `get` does, but the result doesn't borrow the
+    /// MemTable, so a lock around it can be released right away. The Value
+    /// of a tombstone is empty.
+    pub fn get_owned(&self, key: &[u8]) -> Option<(Vec<u8>, u128, bool)> {
+        self.get(key).map(|e| {
+            let value = e
+                .value
+                .as_ref()
+                .map_or_else(Vec::new, |v| v.as_ref().to_vec());
+            (value, e.timestamp_ms, e.is_deleted)
+        })
+    }
 
-        assert_eq!(table.entries[0].key, b"Apple");
-        assert_eq!(table.entries[0].value.as_ref().unwrap(), b"Apple Smoothie");
-        assert_eq!(table.entries[0].timestamp_ms, 0);
-        assert_eq!(table.entries[0].is_deleted, false);
-        assert_eq!(table.entries[1].key, b"Lime");
-        assert_eq!(table.entries[1].value.as_ref().unwrap(), b"Lime Smoothie");
-        assert_eq!(table.entries[1].timestamp_ms, 20);
-        assert_eq!(table.entries[1].is_deleted, false);
-        assert_eq!(table.entries[2].key, b"Orange");
-        assert_eq!(table.entries[2].value.as_ref().unwrap(), b"Orange Smoothie");
-        assert_eq!(table.entries[2].timestamp_ms, 10);
-        assert_eq!(table.entries[2].is_deleted, false);
+    /// Returns true if the MemTable holds a live record of the key
+    ///
+    /// Tombstones and expired records don't count. Note that false doesn't
+    /// mean the key is absent from the Database, an older Table may still
+    /// hold it.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some_and(|e| !e.is_deleted)
+    }
 
-        assert_eq!(table.size, 108);
+    /// Get the newest version of a key written at or before `timestamp_ms`
+    ///
+    /// Older versions are only kept by a MemTable created `with_versions`,
+    /// otherwise this finds the record of the key only if it was written at
+    /// or before `timestamp_ms`. Like `get`, a version that has expired by
+    /// now is not returned.
+    pub fn get_as_of(&self, key: &[u8], timestamp_ms: u128) -> Option<&MemTableEntry<V>> {
+        self.entries[self.lower_bound(key)..self.upper_bound(key)]
+            .iter()
+            .find(|e| e.timestamp_ms <= timestamp_ms)
+            .filter(|e| !e.is_expired(self.clock.now_ms()))
     }
 
-    #[test]
-    fn test_mem_table_put_end() {
-        let mut table = MemTable::new();
-        table.set(b"Apple", b"Apple Smoothie", 0);
-        table.set(b"Lime", b"Lime Smoothie", 10);
+    /// Get the records of many keys from the MemTable
+    ///
+    /// The result holds one lookup for each key, in the order of `keys`, with
+    /// the same semantics as `get`. Sorted keys are resolved with a single
+    /// walk over the entries, other keys with a search each.
+    pub fn multi_get(&self, keys: &[&[u8]]) -> Vec<Option<&MemTableEntry<V>>> {
+        let sorted = keys
+            .windows(2)
+            .all(|w| self.comparator.compare(w[0], w[1]) != Ordering::Greater);
+        if !sorted {
+            return keys.iter().map(|key| self.get(key)).collect();
+        }
 
-        table.set(b"Orange", b"Orange Smoothie", 20);
+        let now_ms = self.clock.now_ms();
+        let mut idx = 0;
+        keys.iter()
+            .map(|key| {
+                while idx < self.entries.len()
+                    && self.comparator.compare(&self.entries[idx].key, key) == Ordering::Less
+                {
+                    idx += 1;
+                }
+                self.entries
+                    .get(idx)
+                    .filter(|e| self.comparator.compare(&e.key, key) == Ordering::Equal)
+                    .filter(|e| !e.is_expired(now_ms))
+            })
+            .collect()
+    }
+
+    /// Get the Value of a key from the MemTable
+    ///
+    /// Returns None if the key is absent, deleted or expired.
+    pub fn get_value(&self, key: &[u8]) -> Option<&[u8]> {
+        self.get(key)
+            .filter(|e| !e.is_deleted)
+            .and_then(|e| e.value.as_ref())
+            .map(AsRef::as_ref)
+    }
+
+    /// Scans the MemTable for records with keys between the given bounds
+    ///
+    /// Records are yielded in sorted key order, or in descending order with
+    /// `.rev()`. Tombstones are yielded as well, it is up to the caller to
+    /// decide how to interpret them.
+    pub fn range(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> impl DoubleEndedIterator<Item = &MemTableEntry<V>> {
+        self.entries[self.range_indices(start, end)].iter()
+    }
+
+    /// Scans the MemTable for the keys of live records between the given
+    /// bounds, in sorted key order
+    ///
+    /// Tombstones and expired records are skipped, and the Values are never
+    /// touched, which makes counting or checking the existence of keys cheap.
+    pub fn range_keys(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> impl Iterator<Item = &[u8]> {
+        let now_ms = self.clock.now_ms();
+        let range = self.range_indices(start, end);
+        let first = range.start;
+        self.entries[range]
+            .iter()
+            .enumerate()
+            .filter(move |(i, e)| {
+                // Only the newest version of a key decides whether it is live.
+                let idx = first + i;
+                idx == first
+                    || self
+                        .comparator
+                        .compare(&self.entries[idx - 1].key, &e.key)
+                        .is_ne()
+            })
+            .filter(move |(_, e)| !e.is_deleted && !e.is_expired(now_ms))
+            .map(|(_, e)| e.key.as_slice())
+    }
+
+    /// Collects owned copies of the live Key-Value pairs with keys between
+    /// the given bounds, in sorted key order
+    ///
+    /// The pairs are the ones of the keys `range_keys` yields, and like
+    /// `get_owned` they don't borrow the MemTable.
+    pub fn range_owned(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let now_ms = self.clock.now_ms();
+        let mut pairs = Vec::new();
+        let mut prev_key: Option<&[u8]> = None;
+        for entry in self.range(start, end) {
+            // Only the newest version of a key decides whether it is live.
+            let is_newest =
+                prev_key.is_none_or(|prev| self.comparator.compare(prev, &entry.key).is_ne());
+            prev_key = Some(&entry.key);
+            if !is_newest || entry.is_deleted || entry.is_expired(now_ms) {
+                continue;
+            }
+            if let Some(value) = &entry.value {
+                pairs.push((entry.key.clone(), value.as_ref().to_vec()));
+            }
+        }
+        pairs
+    }
+
+    /// Returns the range of the entries with keys between the given bounds
+    fn range_indices(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> std::ops::Range<usize> {
+        let start_idx = match start {
+            Bound::Included(key) => self.lower_bound(key),
+            Bound::Excluded(key) => self.upper_bound(key),
+            Bound::Unbounded => 0,
+        };
+        let end_idx = match end {
+            Bound::Included(key) => self.upper_bound(key),
+            Bound::Excluded(key) => self.lower_bound(key),
+            Bound::Unbounded => self.entries.len(),
+        };
+
+        // An end bound before the start bound results in an empty scan.
+        start_idx..end_idx.max(start_idx)
+    }
+
+    /// Iterates over every record of the MemTable in descending key order
+    ///
+    /// Tombstones are yielded as well.
+    pub fn iter_rev(&self) -> impl DoubleEndedIterator<Item = &MemTableEntry<V>> {
+        self.entries.iter().rev()
+    }
+
+    /// Scans the MemTable for records with keys starting with the given prefix
+    ///
+    /// Records are yielded in sorted key order, tombstones included. The end
+    /// of the scan is found by incrementing the prefix, so the MemTable's
+    /// Comparator must keep keys sharing a prefix next to each other, as the
+    /// bytewise ordering does.
+    pub fn prefix(&self, prefix: &[u8]) -> impl DoubleEndedIterator<Item = &MemTableEntry<V>> {
+        let end = prefix_upper_bound(prefix);
+        let end = match &end {
+            Some(end) => Bound::Excluded(end.as_slice()),
+            None => Bound::Unbounded,
+        };
+        self.range(Bound::Included(prefix), end)
+    }
+
+    /// Iterates over the live Key-Value pairs in the MemTable
+    ///
+    /// Tombstones and expired records are skipped, so every yielded pair has
+    /// a value.
+    pub fn iter_live(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        let now_ms = self.clock.now_ms();
+        self.newest_versions()
+            .filter(move |e| !e.is_expired(now_ms))
+            .filter_map(|e| match (&e.value, e.is_deleted) {
+                (Some(value), false) => Some((e.key.as_slice(), value.as_ref())),
+                _ => None,
+            })
+    }
+
+    /// Iterates over the tombstones of the MemTable in sorted key order
+    ///
+    /// Every deleted record is yielded, including the older versions kept by
+    /// `with_versions`. This is meant for finding out how much of the size
+    /// compactions could reclaim.
+    pub fn tombstones(&self) -> impl Iterator<Item = &MemTableEntry<V>> {
+        self.entries.iter().filter(|e| e.is_deleted)
+    }
+
+    /// Iterates over the records written at or after `timestamp_ms`, in
+    /// sorted key order
+    ///
+    /// This is meant for shipping every write made since a sync point.
+    /// Entries are sorted by key and not by time, so this scans the whole
+    /// MemTable and takes O(n) time however few records match. Tombstones are
+    /// yielded as well.
+    pub fn iter_since(&self, timestamp_ms: u128) -> impl Iterator<Item = &MemTableEntry<V>> {
+        self.entries
+            .iter()
+            .filter(move |e| e.timestamp_ms >= timestamp_ms)
+    }
+
+    /// Iterates over the newest version of every key
+    fn newest_versions(&self) -> impl Iterator<Item = &MemTableEntry<V>> {
+        self.entries.iter().enumerate().filter_map(|(i, e)| {
+            let is_newest = i == 0
+                || self.comparator.compare(&self.entries[i - 1].key, &e.key) != Ordering::Equal;
+            is_newest.then_some(e)
+        })
+    }
+
+    /// Removes every entry from the MemTable, keeping the allocated capacity
+    ///
+    /// This lets a MemTable be reused after it has been flushed to a Table.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.range_tombstones.clear();
+        self.size = 0;
+        self.live_len = 0;
+    }
+
+    /// Moves every entry into a new MemTable with the same settings, leaving
+    /// this one empty
+    ///
+    /// This lets writes carry on in this MemTable while the taken one is
+    /// frozen and flushed.
+    pub fn take(&mut self) -> MemTable<C, V>
+    where
+        C: Clone,
+    {
+        let empty = self.empty_like();
+        std::mem::replace(self, empty)
+    }
+
+    /// Creates an empty MemTable with the same settings as this one
+    pub(crate) fn empty_like(&self) -> MemTable<C, V>
+    where
+        C: Clone,
+    {
+        MemTable {
+            entries: Vec::new(),
+            range_tombstones: Vec::new(),
+            size: 0,
+            live_len: 0,
+            max_size: self.max_size,
+            max_versions: self.max_versions,
+            oldest_snapshot_ms: self.oldest_snapshot_ms,
+            merge_operator: self.merge_operator.clone(),
+            max_key_len: self.max_key_len,
+            max_value_len: self.max_value_len,
+            comparator: self.comparator.clone(),
+            clock: Arc::clone(&self.clock),
+        }
+    }
+
+    /// Drops the tombstones of the keys for which `can_drop` returns true
+    ///
+    /// A tombstone can only be dropped if no older source, such as a Table,
+    /// holds a record of its key, otherwise dropping it would bring the older
+    /// record back. The MemTable can't know about these sources, so the
+    /// caller must decide through `can_drop`. With versions, tombstones are
+    /// only dropped if they are the only version of their key.
+    pub fn compact_tombstones(&mut self, can_drop: impl Fn(&[u8]) -> bool) {
+        let n = self.entries.len();
+        let ne = |i: usize, j: usize| {
+            self.comparator
+                .compare(&self.entries[i].key, &self.entries[j].key)
+                .is_ne()
+        };
+        let only_version: Vec<bool> = (0..n)
+            .map(|i| (i == 0 || ne(i - 1, i)) && (i + 1 == n || ne(i, i + 1)))
+            .collect();
+
+        let mut dropped_size = 0;
+        let mut i = 0;
+        self.entries.retain(|e| {
+            let drop = e.is_deleted && only_version[i] && can_drop(&e.key);
+            i += 1;
+            if drop {
+                dropped_size += e.encoded_len();
+            }
+            !drop
+        });
+        self.shrink_size(dropped_size);
+    }
+
+    /// Releases the spare capacity of the entries, such as after
+    /// `compact_tombstones` dropped most of them
+    pub fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit();
+        self.range_tombstones.shrink_to_fit();
+    }
+
+    /// Returns the index of the first entry with a key greater than or equal
+    /// to the given key
+    ///
+    /// This is the position the key would be inserted at, or `len()` if every
+    /// key is smaller. Combined with `entries()` it allows seeking to a key.
+    pub fn lower_bound(&self, key: &[u8]) -> usize {
+        self.get_index(key).unwrap_or_else(|idx| idx)
+    }
+
+    /// Returns the smallest key of the MemTable, or None if it has no entries
+    ///
+    /// Tombstones count as keys, but Range Tombstones don't.
+    pub fn first_key(&self) -> Option<&[u8]> {
+        self.entries.first().map(|e| e.key.as_slice())
+    }
+
+    /// Returns the largest key of the MemTable, or None if it has no entries
+    ///
+    /// Tombstones count as keys, but Range Tombstones don't.
+    pub fn last_key(&self) -> Option<&[u8]> {
+        self.entries.last().map(|e| e.key.as_slice())
+    }
+
+    /// Returns the index of the first entry with a key greater than the given
+    /// key
+    fn upper_bound(&self, key: &[u8]) -> usize {
+        self.entries
+            .partition_point(|e| self.comparator.compare(&e.key, key) != Ordering::Greater)
+    }
+
+    /// Panics if any two adjacent entries are out of order or share a key
+    ///
+    /// Every lookup relies on the entries being sorted and unique under the
+    /// Comparator, so this is checked after mutations in debug builds. With
+    /// versions, entries may share a key if they go from the newest to the
+    /// oldest.
+    #[cfg(debug_assertions)]
+    pub(crate) fn assert_sorted(&self) {
+        for (i, w) in self.entries.windows(2).enumerate() {
+            self.assert_ordered(i, &w[0], &w[1]);
+        }
+    }
+
+    /// Checks only the neighbours of the entry at `idx`, which is enough
+    /// after inserting into entries that were already sorted
+    #[cfg(debug_assertions)]
+    fn assert_sorted_around(&self, idx: usize) {
+        let start = idx.saturating_sub(1);
+        let end = (idx + 2).min(self.entries.len());
+        for (i, w) in self.entries[start..end].windows(2).enumerate() {
+            self.assert_ordered(start + i, &w[0], &w[1]);
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn assert_ordered(&self, idx: usize, a: &MemTableEntry<V>, b: &MemTableEntry<V>) {
+        // Versions of a key go from the newest to the oldest.
+        let ordered = match self.comparator.compare(&a.key, &b.key) {
+            Ordering::Less => true,
+            Ordering::Equal => self.keeps_versions() && a.timestamp_ms > b.timestamp_ms,
+            Ordering::Greater => false,
+        };
+        assert!(
+            ordered,
+            "MemTable entries must be sorted by key and unique, but entries {} and {} are not",
+            idx,
+            idx + 1
+        );
+    }
+
+    /// Subtracts from the size of the MemTable
+    ///
+    /// The size can only drop below zero through a bug in its accounting.
+    /// That fails an assertion in debug builds, while release builds saturate
+    /// at zero instead of wrapping around.
+    fn shrink_size(&mut self, by: usize) {
+        debug_assert!(
+            by <= self.size,
+            "MemTable size accounting dropped below zero"
+        );
+        self.size = self.size.saturating_sub(by);
+    }
+
+    /// Performs Binary Search to find a record in the MemTable
+    ///
+    /// If the record is found `[Result::Ok]` is returned, with
+    /// the index of record. If the record is not found then
+    /// `[Result::Err]` is returned, with the index to insert
+    /// the record at. With versions, the index of the newest
+    /// version is returned.
+    fn get_index(&self, key: &[u8]) -> Result<usize, usize> {
+        let idx = self
+            .entries
+            .partition_point(|e| self.comparator.compare(&e.key, key) == Ordering::Less);
+        match self.entries.get(idx) {
+            Some(e) if self.comparator.compare(&e.key, key) == Ordering::Equal => Ok(idx),
+            _ => Err(idx),
+        }
+    }
+
+    /// Finds the position of a key like `get_index`, for a write
+    ///
+    /// Keys written in increasing order always land past the last key, which
+    /// is checked first so that appending them skips the Binary Search.
+    fn insert_index(&self, key: &[u8]) -> Result<usize, usize> {
+        match self.entries.last() {
+            Some(last) if self.comparator.compare(&last.key, key) == Ordering::Less => {
+                Err(self.entries.len())
+            }
+            None => Err(0),
+            _ => self.get_index(key),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the number of entries that are not tombstones
+    ///
+    /// Unlike `len`, tombstones are not counted. Entries that have expired but
+    /// were not flushed yet are still counted.
+    pub fn live_len(&self) -> usize {
+        self.live_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.range_tombstones.is_empty()
+    }
+
+    /// Returns the fraction of the records that are tombstones, or 0 for an
+    /// empty MemTable
+    ///
+    /// Older versions kept by `with_versions` count as records, but only the
+    /// deleted ones count as tombstones.
+    pub fn tombstone_ratio(&self) -> f64 {
+        if self.entries.is_empty() {
+            return 0.0;
+        }
+        let tombstones = self.entries.iter().filter(|e| e.is_deleted).count();
+        tombstones as f64 / self.entries.len() as f64
+    }
+
+    pub fn entries(&self) -> &[MemTableEntry<V>] {
+        &self.entries
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Computes statistics about the records of the MemTable
+    ///
+    /// This takes a single pass over the entries.
+    pub fn stats(&self) -> MemTableStats {
+        let mut stats = MemTableStats {
+            size: self.size,
+            ..MemTableStats::default()
+        };
+        for entry in &self.entries {
+            if entry.is_deleted {
+                stats.tombstone_count += 1;
+            } else {
+                stats.live_count += 1;
+            }
+            stats.max_key_len = stats.max_key_len.max(entry.key.len());
+            if let Some(value) = &entry.value {
+                stats.max_value_len = stats.max_value_len.max(value.as_ref().len());
+            }
+        }
+        stats
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Returns the number of entries the MemTable can hold without
+    /// reallocating
+    ///
+    /// This is unrelated to the max size passed to `with_capacity`, which
+    /// is counted in bytes.
+    pub fn capacity(&self) -> usize {
+        self.entries.capacity()
+    }
+
+    /// Returns true once the MemTable has reached its max size and should be
+    /// flushed to the disk
+    pub fn is_full(&self) -> bool {
+        self.size >= self.max_size
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.comparator
+    }
+
+    /// Gets the Clock used to stamp writes and to decide whether entries have
+    /// expired
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+}
+
+/// ImmutableMemTable is a MemTable that can no longer be written to
+///
+/// MemTables are frozen while they are being flushed to a Table, so that a
+/// write can not slip into a MemTable that is being serialized. It is created
+/// with `MemTable::freeze`.
+pub struct ImmutableMemTable<C = BytewiseComparator, V = Vec<u8>> {
+    inner: MemTable<C, V>,
+}
+
+impl<C: Comparator, V: AsRef<[u8]> + Clone> ImmutableMemTable<C, V> {
+    /// Get a Key-Value pair from the MemTable
+    ///
+    /// If no record with the same key exists in the MemTable, or the record
+    /// has expired, return None
+    pub fn get(&self, key: &[u8]) -> Option<&MemTableEntry<V>> {
+        self.inner.get(key)
+    }
+
+    /// Get an owned copy of the record of a key, like
+    /// [`MemTable::get_owned`]
+    pub fn get_owned(&self, key: &[u8]) -> Option<(Vec<u8>, u128, bool)> {
+        self.inner.get_owned(key)
+    }
+
+    /// Scans the MemTable for records with keys between the given bounds
+    ///
+    /// Tombstones are yielded as well.
+    pub fn range(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> impl DoubleEndedIterator<Item = &MemTableEntry<V>> {
+        self.inner.range(start, end)
+    }
+
+    /// Collects owned copies of the live Key-Value pairs with keys between
+    /// the given bounds, like [`MemTable::range_owned`]
+    pub fn range_owned(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.inner.range_owned(start, end)
+    }
+
+    /// Iterates over every record of the MemTable in sorted key order
+    ///
+    /// Tombstones are yielded as well.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &MemTableEntry<V>> {
+        self.inner.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    /// Returns the Range Tombstones in the order they were written
+    pub fn range_tombstones(&self) -> &[RangeTombstone] {
+        self.inner.range_tombstones()
+    }
+
+    pub(crate) fn mem_table(&self) -> &MemTable<C, V> {
+        &self.inner
+    }
+}
+
+/// Moves the records out of the frozen MemTable in sorted key order
+///
+/// Tombstones are yielded as well, so the MemTable can be merged with other
+/// sorted sources without cloning its records.
+impl<C, V> IntoIterator for ImmutableMemTable<C, V> {
+    type Item = MemTableEntry<V>;
+    type IntoIter = std::vec::IntoIter<MemTableEntry<V>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.entries.into_iter()
+    }
+}
+
+/// SharedMemTable shares a MemTable between threads
+///
+/// Reads take a shared lock, so they run concurrently, while writes take an
+/// exclusive lock. Lookups and scans return owned copies of the records so no
+/// reference outlives the lock; `read` hands out the guard itself for callers
+/// that want to borrow instead.
+pub struct SharedMemTable<C = BytewiseComparator, V = Vec<u8>> {
+    inner: Arc<RwLock<MemTable<C, V>>>,
+}
+
+impl<C, V> Clone for SharedMemTable<C, V> {
+    fn clone(&self) -> Self {
+        SharedMemTable {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<C: Comparator, V: AsRef<[u8]> + Clone> From<MemTable<C, V>> for SharedMemTable<C, V> {
+    fn from(mem_table: MemTable<C, V>) -> Self {
+        SharedMemTable::new(mem_table)
+    }
+}
+
+impl<C: Comparator, V: AsRef<[u8]> + Clone> SharedMemTable<C, V> {
+    /// Wraps the MemTable so it can be shared between threads
+    pub fn new(mem_table: MemTable<C, V>) -> SharedMemTable<C, V> {
+        SharedMemTable {
+            inner: Arc::new(RwLock::new(mem_table)),
+        }
+    }
+
+    /// Sets a Key-Value pair, stamped with the current time of the MemTable's
+    /// Clock
+    pub fn set(&self, key: &[u8], value: impl Into<V>) -> Option<V> {
+        self.inner.write().unwrap().set(key, value)
+    }
+
+    /// Sets a Key-Value pair with an explicit timestamp
+    pub fn set_at(&self, key: &[u8], value: impl Into<V>, timestamp_ms: u128) -> Option<V> {
+        self.inner.write().unwrap().set_at(key, value, timestamp_ms)
+    }
+
+    /// Deletes a Key-Value pair, stamped with the current time of the
+    /// MemTable's Clock
+    pub fn delete(&self, key: &[u8]) -> Option<V> {
+        self.inner.write().unwrap().delete(key)
+    }
+
+    /// Deletes a Key-Value pair with an explicit timestamp
+    pub fn delete_at(&self, key: &[u8], timestamp_ms: u128) -> Option<V> {
+        self.inner.write().unwrap().delete_at(key, timestamp_ms)
+    }
+
+    /// Get a copy of the record of a key
+    pub fn get(&self, key: &[u8]) -> Option<MemTableEntry<V>> {
+        self.inner.read().unwrap().get(key).cloned()
+    }
+
+    /// Collects copies of the records with keys between the given bounds
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<MemTableEntry<V>> {
+        self.inner
+            .read()
+            .unwrap()
+            .range(start, end)
+            .cloned()
+            .collect()
+    }
+
+    /// Takes the shared lock and returns the guard, which dereferences to the
+    /// MemTable
+    pub fn read(&self) -> RwLockReadGuard<'_, MemTable<C, V>> {
+        self.inner.read().unwrap()
+    }
+}
+
+/// Returns the smallest key greater than every key starting with the prefix
+///
+/// This increments the last byte of the prefix that is not 0xFF, dropping the
+/// bytes after it. A prefix of only 0xFF bytes, or an empty prefix, has no
+/// such key.
+pub(crate) fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let idx = prefix.iter().rposition(|&b| b != 0xFF)?;
+    let mut end = prefix[..=idx].to_vec();
+    end[idx] += 1;
+    Some(end)
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::ops::Bound;
+    use std::sync::Arc;
+
+    use crate::clock::MockClock;
+    use crate::comparator::{BytewiseComparator, Comparator};
+    use crate::error::Error;
+    use crate::mem_table::{
+        ImmutableMemTable, MemTable, MemTableEntry, MemTableStats, Operation, RangeTombstone,
+        SharedMemTable, DEFAULT_MAX_KEY_LEN, DEFAULT_MAX_SIZE,
+    };
+    use crate::merge::{self, MergeOperator};
+
+    #[test]
+    fn test_mem_table_put_start() {
+        let mut table = MemTable::new();
+        table.set_at(b"Lime", b"Lime Smoothie", 0); // 17 + 37
+        table.set_at(b"Orange", b"Orange Smoothie", 10); // 21 + 37
+
+        table.set_at(b"Apple", b"Apple Smoothie", 20); // 19 + 37
+
+        assert_eq!(table.entries[0].key, b"Apple");
+        assert_eq!(table.entries[0].value.as_ref().unwrap(), b"Apple Smoothie");
+        assert_eq!(table.entries[0].timestamp_ms, 20);
+        assert_eq!(table.entries[0].is_deleted, false);
+        assert_eq!(table.entries[1].key, b"Lime");
+        assert_eq!(table.entries[1].value.as_ref().unwrap(), b"Lime Smoothie");
+        assert_eq!(table.entries[1].timestamp_ms, 0);
+        assert_eq!(table.entries[1].is_deleted, false);
+        assert_eq!(table.entries[2].key, b"Orange");
+        assert_eq!(table.entries[2].value.as_ref().unwrap(), b"Orange Smoothie");
+        assert_eq!(table.entries[2].timestamp_ms, 10);
+        assert_eq!(table.entries[2].is_deleted, false);
+
+        assert_eq!(table.size, 168);
+    }
+
+    #[test]
+    fn test_mem_table_put_middle() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Orange", b"Orange Smoothie", 10);
+
+        table.set_at(b"Lime", b"Lime Smoothie", 20);
+
+        assert_eq!(table.entries[0].key, b"Apple");
+        assert_eq!(table.entries[0].value.as_ref().unwrap(), b"Apple Smoothie");
+        assert_eq!(table.entries[0].timestamp_ms, 0);
+        assert_eq!(table.entries[0].is_deleted, false);
+        assert_eq!(table.entries[1].key, b"Lime");
+        assert_eq!(table.entries[1].value.as_ref().unwrap(), b"Lime Smoothie");
+        assert_eq!(table.entries[1].timestamp_ms, 20);
+        assert_eq!(table.entries[1].is_deleted, false);
+        assert_eq!(table.entries[2].key, b"Orange");
+        assert_eq!(table.entries[2].value.as_ref().unwrap(), b"Orange Smoothie");
+        assert_eq!(table.entries[2].timestamp_ms, 10);
+        assert_eq!(table.entries[2].is_deleted, false);
+
+        assert_eq!(table.size, 168);
+    }
+
+    #[test]
+    fn test_mem_table_put_end() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+
+        table.set_at(b"Orange", b"Orange Smoothie", 20);
 
         assert_eq!(table.entries[0].key, b"Apple");
         assert_eq!(table.entries[0].value.as_ref().unwrap(), b"Apple Smoothie");
@@ -196,17 +1814,17 @@ mod tests {
         assert_eq!(table.entries[2].timestamp_ms, 20);
         assert_eq!(table.entries[2].is_deleted, false);
 
-        assert_eq!(table.size, 108);
+        assert_eq!(table.size, 168);
     }
 
     #[test]
     fn test_mem_table_put_overwrite() {
         let mut table = MemTable::new();
-        table.set(b"Apple", b"Apple Smoothie", 0);
-        table.set(b"Lime", b"Lime Smoothie", 10);
-        table.set(b"Orange", b"Orange Smoothie", 20);
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.set_at(b"Orange", b"Orange Smoothie", 20);
 
-        table.set(b"Lime", b"A sour fruit", 30);
+        table.set_at(b"Lime", b"A sour fruit", 30);
 
         assert_eq!(table.entries[0].key, b"Apple");
         assert_eq!(table.entries[0].value.as_ref().unwrap(), b"Apple Smoothie");
@@ -221,15 +1839,15 @@ mod tests {
         assert_eq!(table.entries[2].timestamp_ms, 20);
         assert_eq!(table.entries[2].is_deleted, false);
 
-        assert_eq!(table.size, 107);
+        assert_eq!(table.size, 167);
     }
 
     #[test]
     fn test_mem_table_get_exists() {
         let mut table = MemTable::new();
-        table.set(b"Apple", b"Apple Smoothie", 0);
-        table.set(b"Lime", b"Lime Smoothie", 10);
-        table.set(b"Orange", b"Orange Smoothie", 20);
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.set_at(b"Orange", b"Orange Smoothie", 20);
 
         let entry = table.get(b"Orange").unwrap();
 
@@ -241,23 +1859,35 @@ mod tests {
     #[test]
     fn test_mem_table_get_not_exists() {
         let mut table = MemTable::new();
-        table.set(b"Apple", b"Apple Smoothie", 0);
-        table.set(b"Lime", b"Lime Smoothie", 0);
-        table.set(b"Orange", b"Orange Smoothie", 0);
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 0);
+        table.set_at(b"Orange", b"Orange Smoothie", 0);
 
         let res = table.get(b"Potato");
         assert_eq!(res.is_some(), false);
     }
 
     #[test]
-    fn test_mem_table_delete_exists() {
+    fn test_mem_table_contains_key() {
         let mut table = MemTable::new();
-        table.set(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 0);
+        table.delete_at(b"Lime", 10);
 
-        table.delete(b"Apple", 10);
+        assert!(table.contains_key(b"Apple"));
+        assert!(!table.contains_key(b"Lime"));
+        assert!(!table.contains_key(b"Potato"));
+    }
 
-        let res = table.get(b"Apple").unwrap();
-        assert_eq!(res.key, b"Apple");
+    #[test]
+    fn test_mem_table_delete_exists() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+
+        table.delete_at(b"Apple", 10);
+
+        let res = table.get(b"Apple").unwrap();
+        assert_eq!(res.key, b"Apple");
         assert_eq!(res.value, None);
         assert_eq!(res.timestamp_ms, 10);
         assert_eq!(res.is_deleted, true);
@@ -267,14 +1897,14 @@ mod tests {
         assert_eq!(table.entries[0].timestamp_ms, 10);
         assert_eq!(table.entries[0].is_deleted, true);
 
-        assert_eq!(table.size, 22);
+        assert_eq!(table.size, 34);
     }
 
     #[test]
     fn test_mem_table_delete_empty() {
         let mut table = MemTable::new();
 
-        table.delete(b"Apple", 10);
+        table.delete_at(b"Apple", 10);
 
         let res = table.get(b"Apple").unwrap();
         assert_eq!(res.key, b"Apple");
@@ -287,6 +1917,1608 @@ mod tests {
         assert_eq!(table.entries[0].timestamp_ms, 10);
         assert_eq!(table.entries[0].is_deleted, true);
 
-        assert_eq!(table.size, 22);
+        assert_eq!(table.size, 34);
+    }
+
+    #[test]
+    fn test_mem_table_range_bounds() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.set_at(b"Orange", b"Orange Smoothie", 20);
+        table.set_at(b"Peach", b"Peach Smoothie", 30);
+
+        let keys: Vec<&[u8]> = table
+            .range(Bound::Included(b"Lime"), Bound::Included(b"Peach"))
+            .map(|e| e.key.as_slice())
+            .collect();
+        assert_eq!(keys, vec![&b"Lime"[..], b"Orange", b"Peach"]);
+
+        let keys: Vec<&[u8]> = table
+            .range(Bound::Excluded(b"Lime"), Bound::Excluded(b"Peach"))
+            .map(|e| e.key.as_slice())
+            .collect();
+        assert_eq!(keys, vec![&b"Orange"[..]]);
+
+        let keys: Vec<&[u8]> = table
+            .range(Bound::Included(b"Banana"), Bound::Excluded(b"Orange"))
+            .map(|e| e.key.as_slice())
+            .collect();
+        assert_eq!(keys, vec![&b"Lime"[..]]);
+    }
+
+    #[test]
+    fn test_mem_table_range_unbounded() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.delete_at(b"Orange", 20);
+
+        let entries: Vec<_> = table.range(Bound::Unbounded, Bound::Unbounded).collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].key, b"Apple");
+        assert_eq!(entries[1].key, b"Lime");
+        assert_eq!(entries[2].key, b"Orange");
+        assert!(entries[2].is_deleted);
+    }
+
+    #[test]
+    fn test_mem_table_range_empty() {
+        let mut table = MemTable::new();
+        assert_eq!(table.range(Bound::Unbounded, Bound::Unbounded).count(), 0);
+
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        assert_eq!(
+            table
+                .range(Bound::Included(b"Lime"), Bound::Excluded(b"Apple"))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_mem_table_iter_live() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.set_at(b"Orange", b"Orange Smoothie", 20);
+        table.delete_at(b"Lime", 30);
+        table.delete_at(b"Peach", 40);
+
+        let pairs: Vec<(&[u8], &[u8])> = table.iter_live().collect();
+        assert_eq!(
+            pairs,
+            vec![
+                (&b"Apple"[..], &b"Apple Smoothie"[..]),
+                (&b"Orange"[..], &b"Orange Smoothie"[..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mem_table_iter_live_only_tombstones() {
+        let mut table = MemTable::new();
+        table.delete_at(b"Apple", 0);
+        table.delete_at(b"Lime", 10);
+
+        assert_eq!(table.iter_live().count(), 0);
+    }
+
+    #[test]
+    fn test_mem_table_from_entries() {
+        let entries = vec![
+            MemTableEntry {
+                key: b"Apple".to_vec(),
+                value: Some(b"Apple Smoothie".to_vec()),
+                timestamp_ms: 0,
+                is_deleted: false,
+                is_merge: false,
+                expires_at_ms: None,
+            },
+            MemTableEntry {
+                key: b"Lime".to_vec(),
+                value: None,
+                timestamp_ms: 10,
+                is_deleted: true,
+                is_merge: false,
+                expires_at_ms: None,
+            },
+            MemTableEntry {
+                key: b"Orange".to_vec(),
+                value: Some(b"Orange Smoothie".to_vec()),
+                timestamp_ms: 20,
+                is_deleted: false,
+                is_merge: false,
+                expires_at_ms: None,
+            },
+        ];
+
+        let table = MemTable::from_entries(entries);
+
+        let mut expected = MemTable::new();
+        expected.set_at(b"Apple", b"Apple Smoothie", 0);
+        expected.delete_at(b"Lime", 10);
+        expected.set_at(b"Orange", b"Orange Smoothie", 20);
+
+        assert_eq!(table.len(), 3);
+        assert!(table.get(b"Lime").unwrap().is_deleted);
+        assert_eq!(table.size(), expected.size());
+        assert_eq!(table.size, 147);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "sorted by key")]
+    fn test_mem_table_from_entries_unsorted() {
+        let entry = |key: &[u8]| MemTableEntry {
+            key: key.to_vec(),
+            value: Some(b"Smoothie".to_vec()),
+            timestamp_ms: 0,
+            is_deleted: false,
+            is_merge: false,
+            expires_at_ms: None,
+        };
+
+        MemTable::from_entries(vec![entry(b"Orange"), entry(b"Apple")]);
+    }
+
+    #[test]
+    fn test_mem_table_is_full() {
+        let mut table = MemTable::with_capacity(111);
+        assert!(!table.is_full());
+
+        table.set_at(b"Apple", b"Apple Smoothie", 0); // 19 + 37
+        assert!(!table.is_full());
+
+        table.set_at(b"Lime", b"Lime Smoothie", 10); // 17 + 37
+        assert!(!table.is_full());
+
+        table.set_at(b"Lime", b"Lime Smoothies", 20);
+        assert_eq!(table.size, 111);
+        assert!(table.is_full());
+    }
+
+    #[test]
+    fn test_mem_table_default_capacity() {
+        let table = MemTable::new();
+        assert_eq!(table.max_size(), DEFAULT_MAX_SIZE);
+        assert!(!table.is_full());
+    }
+
+    #[test]
+    fn test_mem_table_put_over_tombstone() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.delete_at(b"Apple", 10);
+        assert_eq!(table.size, 34);
+
+        table.set_at(b"Apple", b"Apple Smoothie", 20);
+
+        let mut fresh = MemTable::new();
+        fresh.set_at(b"Apple", b"Apple Smoothie", 20);
+
+        assert_eq!(table.size, fresh.size);
+        assert_eq!(table.size, 56);
+    }
+
+    #[test]
+    fn test_mem_table_delete_repeated() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+
+        table.delete_at(b"Apple", 10);
+        assert_eq!(table.size, 34);
+
+        table.delete_at(b"Apple", 20);
+        assert_eq!(table.size, 34);
+
+        let res = table.get(b"Apple").unwrap();
+        assert_eq!(res.timestamp_ms, 20);
+        assert!(res.is_deleted);
+    }
+
+    #[test]
+    fn test_mem_table_delete_over_tombstone() {
+        let mut table = MemTable::new();
+        table.delete_at(b"Apple", 0);
+        table.delete_at(b"Apple", 10);
+        table.delete_at(b"Apple", 20);
+
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.size, 34);
+    }
+
+    #[test]
+    fn test_mem_table_delete_set_cycle() {
+        let mut table = MemTable::new();
+        table.set_at(b"Lime", b"Lime Smoothie", 0);
+
+        for ts in 1..10 {
+            table.delete_at(b"Lime", ts * 10);
+            table.delete_at(b"Lime", ts * 10 + 1);
+            assert_eq!(table.size, 33);
+
+            table.set_at(b"Lime", b"Lime Smoothie", ts * 10 + 2);
+            assert_eq!(table.size, 54);
+        }
+    }
+
+    #[test]
+    fn test_mem_table_set_returns_previous() {
+        let mut table = MemTable::new();
+
+        assert_eq!(table.set_at(b"Apple", b"Apple Smoothie", 0), None);
+        assert_eq!(
+            table.set_at(b"Apple", b"Apple Pie", 10),
+            Some(b"Apple Smoothie".to_vec())
+        );
+        assert_eq!(table.size, 51);
+
+        table.delete_at(b"Apple", 20);
+        assert_eq!(table.set_at(b"Apple", b"Apple Juice", 30), None);
+        assert_eq!(table.size, 53);
+    }
+
+    #[test]
+    fn test_mem_table_delete_returns_previous() {
+        let mut table = MemTable::new();
+
+        assert_eq!(table.delete_at(b"Lime", 0), None);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        assert_eq!(
+            table.delete_at(b"Lime", 20),
+            Some(b"Lime Smoothie".to_vec())
+        );
+        assert_eq!(table.delete_at(b"Lime", 30), None);
+        assert_eq!(table.size, 33);
+    }
+
+    #[test]
+    fn test_mem_table_clear() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.delete_at(b"Orange", 20);
+        let capacity = table.entries.capacity();
+
+        table.clear();
+
+        assert!(table.is_empty());
+        assert_eq!(table.len(), 0);
+        assert_eq!(table.size, 0);
+        assert_eq!(table.entries.capacity(), capacity);
+        assert!(table.get(b"Apple").is_none());
+
+        table.set_at(b"Apple", b"Apple Smoothie", 30);
+        assert_eq!(table.size, 56);
+    }
+
+    #[test]
+    fn test_mem_table_try_set() {
+        let mut table = MemTable::new().with_max_key_len(5).with_max_value_len(8);
+        assert_eq!(table.try_set(b"Apple", b"Smoothie").unwrap(), None);
+        assert!(matches!(
+            table.try_set(b"Banana", b"Pie"),
+            Err(Error::KeyTooLarge { len: 6, max: 5 })
+        ));
+        assert!(matches!(
+            table.try_set(b"Lime", b"Smoothies"),
+            Err(Error::ValueTooLarge { len: 9, max: 8 })
+        ));
+        assert_eq!(table.len(), 1);
+
+        let table = MemTable::new();
+        let key = vec![0; DEFAULT_MAX_KEY_LEN];
+        assert!(table.check_lengths(&key, None).is_ok());
+        assert!(table
+            .check_lengths(&[0; DEFAULT_MAX_KEY_LEN + 1], None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_mem_table_shrink_to_fit() {
+        let mut table = MemTable::new();
+        for n in 0..1000u32 {
+            table.set_at(&n.to_be_bytes(), b"Smoothie", 0);
+        }
+        for n in 10..1000u32 {
+            table.delete_at(&n.to_be_bytes(), 10);
+        }
+        table.compact_tombstones(|_| true);
+        assert_eq!(table.len(), 10);
+        let capacity = table.capacity();
+        assert!(capacity >= 1000);
+
+        table.shrink_to_fit();
+        assert!(table.capacity() < capacity);
+        assert!(table.capacity() >= 10);
+        assert_eq!(
+            table.get(&5u32.to_be_bytes()).unwrap().value.as_deref(),
+            Some(&b"Smoothie"[..])
+        );
+    }
+
+    #[test]
+    fn test_mem_table_take() {
+        let mut table = MemTable::with_capacity(100).with_versions(2);
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.delete_at(b"Lime", 10);
+
+        let taken = table.take();
+        assert!(table.is_empty());
+        assert_eq!(table.size, 0);
+        assert_eq!(taken.len(), 2);
+        assert_eq!(taken.size, 56 + 33);
+        assert_eq!(
+            taken.get(b"Apple").unwrap().value.as_deref(),
+            Some(&b"Apple Smoothie"[..])
+        );
+
+        // The settings carry over to both MemTables.
+        table.set_at(b"Apple", b"Apple Pie", 20);
+        table.set_at(b"Apple", b"Apple Smoothie", 30);
+        assert_eq!(table.entries.len(), 2);
+        assert_eq!(taken.max_size(), 100);
+        assert_eq!(taken.max_versions, 2);
+    }
+
+    /// Orders big-endian u32 keys from the largest to the smallest
+    struct ReverseU32Comparator;
+
+    impl Comparator for ReverseU32Comparator {
+        fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+            let a = u32::from_be_bytes(a.try_into().unwrap());
+            let b = u32::from_be_bytes(b.try_into().unwrap());
+            b.cmp(&a)
+        }
+    }
+
+    #[test]
+    fn test_mem_table_custom_comparator() {
+        let mut table = MemTable::with_comparator(ReverseU32Comparator);
+        for i in [5u32, 1, 300, 42, 7] {
+            table.set_at(&i.to_be_bytes(), b"Smoothie", i as u128);
+        }
+        table.set_at(&42u32.to_be_bytes(), b"Pie", 100);
+        table.delete_at(&1u32.to_be_bytes(), 200);
+
+        let keys: Vec<u32> = table
+            .entries()
+            .iter()
+            .map(|e| u32::from_be_bytes(e.key.as_slice().try_into().unwrap()))
+            .collect();
+        assert_eq!(keys, vec![300, 42, 7, 5, 1]);
+        #[cfg(debug_assertions)]
+        table.assert_sorted();
+
+        let entry = table.get(&42u32.to_be_bytes()).unwrap();
+        assert_eq!(entry.value.as_ref().unwrap(), b"Pie");
+        assert!(table.get(&1u32.to_be_bytes()).unwrap().is_deleted);
+        assert!(table.get(&6u32.to_be_bytes()).is_none());
+
+        let keys: Vec<u32> = table
+            .range(
+                Bound::Included(&42u32.to_be_bytes()),
+                Bound::Excluded(&5u32.to_be_bytes()),
+            )
+            .map(|e| u32::from_be_bytes(e.key.as_slice().try_into().unwrap()))
+            .collect();
+        assert_eq!(keys, vec![42, 7]);
+    }
+
+    #[test]
+    fn test_mem_table_lower_bound() {
+        let mut table = MemTable::new();
+        assert_eq!(table.lower_bound(b"Apple"), 0);
+
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.delete_at(b"Orange", 20);
+
+        assert_eq!(table.lower_bound(b"Aardvark"), 0);
+        assert_eq!(table.lower_bound(b"Apple"), 0);
+        assert_eq!(table.lower_bound(b"Banana"), 1);
+        assert_eq!(table.lower_bound(b"Lime"), 1);
+        assert_eq!(table.lower_bound(b"Orange"), 2);
+        assert_eq!(table.lower_bound(b"Zucchini"), 3);
+
+        // Seeking does not insert anything.
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.entries()[table.lower_bound(b"Banana")].key, b"Lime");
+    }
+
+    #[test]
+    fn test_mem_table_first_last_key() {
+        let mut table = MemTable::new();
+        assert_eq!(table.first_key(), None);
+        assert_eq!(table.last_key(), None);
+
+        table.set_at(b"Lime", b"Lime Smoothie", 0);
+        assert_eq!(table.first_key(), Some(&b"Lime"[..]));
+        assert_eq!(table.last_key(), Some(&b"Lime"[..]));
+
+        table.set_at(b"Orange", b"Orange Smoothie", 10);
+        table.delete_at(b"Apple", 20);
+        assert_eq!(table.first_key(), Some(&b"Apple"[..]));
+        assert_eq!(table.last_key(), Some(&b"Orange"[..]));
+    }
+
+    #[test]
+    fn test_mem_table_set_with_ttl() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut table = MemTable::new().with_clock(clock.clone());
+
+        table.set_with_ttl(b"Apple", b"Apple Smoothie", 0, 100);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+
+        let apple = table.get(b"Apple").unwrap();
+        assert_eq!(apple.expires_at_ms, Some(100));
+        assert_eq!(table.iter_live().count(), 2);
+
+        clock.set(99);
+        assert!(table.get(b"Apple").is_some());
+
+        clock.set(100);
+        assert!(table.get(b"Apple").is_none());
+        assert!(table.get(b"Lime").is_some());
+        let keys: Vec<&[u8]> = table.iter_live().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![b"Lime".as_slice()]);
+
+        // The expired record stays in the MemTable until it is flushed.
+        assert_eq!(table.len(), 2);
+
+        // Setting the key again without a TTL clears the expiry.
+        table.set_at(b"Apple", b"Apple Juice", 110);
+        assert_eq!(table.get(b"Apple").unwrap().expires_at_ms, None);
+    }
+
+    #[test]
+    fn test_mem_table_clock_stamps_writes() {
+        let clock = Arc::new(MockClock::new(100));
+        let mut table = MemTable::new().with_clock(clock.clone());
+
+        table.set(b"Apple", b"Apple Smoothie");
+        clock.advance(10);
+        table.set(b"Lime", b"Lime Smoothie");
+        clock.advance(10);
+        table.delete(b"Apple");
+
+        let apple = table.get(b"Apple").unwrap();
+        assert!(apple.is_deleted);
+        assert_eq!(apple.timestamp_ms, 120);
+        assert_eq!(table.get(b"Lime").unwrap().timestamp_ms, 110);
+    }
+
+    #[test]
+    fn test_mem_table_prefix() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Apricot", b"Apricot Smoothie", 10);
+        table.delete_at(b"Avocado", 20);
+        table.set_at(b"Banana", b"Banana Smoothie", 30);
+        table.set_at(b"Ap", b"Ap Smoothie", 40);
+
+        let keys: Vec<&[u8]> = table.prefix(b"Ap").map(|e| e.key.as_slice()).collect();
+        assert_eq!(keys, vec![b"Ap".as_slice(), b"Apple", b"Apricot"]);
+
+        // Tombstones are yielded as well.
+        let keys: Vec<&[u8]> = table.prefix(b"Av").map(|e| e.key.as_slice()).collect();
+        assert_eq!(keys, vec![b"Avocado".as_slice()]);
+
+        // An empty prefix matches every entry.
+        assert_eq!(table.prefix(b"").count(), 5);
+
+        // A prefix longer than any key matches nothing.
+        assert_eq!(table.prefix(b"Apple Smoothie Bowl").count(), 0);
+    }
+
+    #[test]
+    fn test_mem_table_prefix_all_ff() {
+        let mut table = MemTable::new();
+        table.set_at(&[0xFE], b"Below", 0);
+        table.set_at(&[0xFF], b"Prefix", 0);
+        table.set_at(&[0xFF, 0xFF], b"Longer", 0);
+        table.set_at(&[0xFF, 0xFF, 0x00], b"Longest", 0);
+
+        let keys: Vec<&[u8]> = table
+            .prefix(&[0xFF, 0xFF])
+            .map(|e| e.key.as_slice())
+            .collect();
+        assert_eq!(keys, vec![[0xFF, 0xFF].as_slice(), &[0xFF, 0xFF, 0x00]]);
+
+        // The scan ends before the keys starting with 0xFF.
+        let keys: Vec<&[u8]> = table.prefix(&[0xFE]).map(|e| e.key.as_slice()).collect();
+        assert_eq!(keys, vec![[0xFE].as_slice()]);
+    }
+
+    #[test]
+    fn test_mem_table_iter_rev() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.delete_at(b"Lime", 10);
+        table.set_at(b"Orange", b"Orange Smoothie", 20);
+
+        let keys: Vec<&[u8]> = table.iter_rev().map(|e| e.key.as_slice()).collect();
+        assert_eq!(keys, vec![b"Orange".as_slice(), b"Lime", b"Apple"]);
+        assert_eq!(table.iter_rev().next_back().unwrap().key, b"Apple");
+    }
+
+    #[test]
+    fn test_mem_table_range_rev() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Banana", b"Banana Smoothie", 10);
+        table.delete_at(b"Lime", 20);
+        table.set_at(b"Orange", b"Orange Smoothie", 30);
+        table.set_at(b"Peach", b"Peach Smoothie", 40);
+
+        let keys = |start: Bound<&[u8]>, end: Bound<&[u8]>| -> Vec<Vec<u8>> {
+            table
+                .range(start, end)
+                .rev()
+                .map(|e| e.key.clone())
+                .collect()
+        };
+
+        assert_eq!(
+            keys(Bound::Included(b"Banana"), Bound::Included(b"Orange")),
+            vec![b"Orange".to_vec(), b"Lime".to_vec(), b"Banana".to_vec()]
+        );
+        assert_eq!(
+            keys(Bound::Excluded(b"Banana"), Bound::Excluded(b"Orange")),
+            vec![b"Lime".to_vec()]
+        );
+        assert_eq!(
+            keys(Bound::Unbounded, Bound::Excluded(b"Banana")),
+            vec![b"Apple".to_vec()]
+        );
+        assert_eq!(
+            keys(Bound::Included(b"Peach"), Bound::Unbounded),
+            vec![b"Peach".to_vec()]
+        );
+        assert!(keys(Bound::Included(b"Orange"), Bound::Included(b"Banana")).is_empty());
+
+        // Reverse prefix scans yield the matching keys back to front.
+        table.set_at(b"Pear", b"Pear Smoothie", 50);
+        let keys: Vec<&[u8]> = table
+            .prefix(b"Pe")
+            .rev()
+            .map(|e| e.key.as_slice())
+            .collect();
+        assert_eq!(keys, vec![b"Pear".as_slice(), b"Peach"]);
+    }
+
+    #[test]
+    fn test_mem_table_extend_sorted() {
+        let pair = |key: &[u8], value: &[u8], ts| (key.to_vec(), value.to_vec(), ts);
+
+        let mut table = MemTable::new();
+        table.set_at(b"Lime", b"Lime Smoothie", 0);
+        table.extend_sorted(
+            vec![
+                pair(b"Orange", b"Orange Smoothie", 10),
+                pair(b"Peach", b"Peach Smoothie", 20),
+                // Out of order keys fall back to a regular set.
+                pair(b"Apple", b"Apple Smoothie", 30),
+                pair(b"Peach", b"Peach Pie", 40),
+                pair(b"Pear", b"Pear Smoothie", 50),
+            ]
+            .into_iter(),
+        );
+
+        let mut expected = MemTable::new();
+        expected.set_at(b"Lime", b"Lime Smoothie", 0);
+        expected.set_at(b"Orange", b"Orange Smoothie", 10);
+        expected.set_at(b"Peach", b"Peach Smoothie", 20);
+        expected.set_at(b"Apple", b"Apple Smoothie", 30);
+        expected.set_at(b"Peach", b"Peach Pie", 40);
+        expected.set_at(b"Pear", b"Pear Smoothie", 50);
+
+        assert_eq!(table.entries(), expected.entries());
+        assert_eq!(table.size(), expected.size());
+        assert_eq!(table.live_len(), expected.live_len());
+    }
+
+    #[test]
+    fn test_mem_table_set_owned() {
+        let mut table = MemTable::new();
+        let key = b"Apple".to_vec();
+        let value = b"Apple Smoothie".to_vec();
+        let (key_ptr, value_ptr) = (key.as_ptr(), value.as_ptr());
+        assert_eq!(table.set_owned(key, value, 0), None);
+        // The key and the Value were moved into the record, not copied.
+        assert_eq!(table.entries[0].key.as_ptr(), key_ptr);
+        assert_eq!(table.entries[0].value.as_ref().unwrap().as_ptr(), value_ptr);
+
+        let mut expected = MemTable::new();
+        expected.set_at(b"Apple", b"Apple Smoothie", 0);
+        expected.set_at(b"Apple", b"Apple Pie", 10);
+        assert_eq!(
+            table.set_owned(b"Apple".to_vec(), b"Apple Pie".to_vec(), 10),
+            Some(b"Apple Smoothie".to_vec())
+        );
+        assert_eq!(table.entries(), expected.entries());
+        assert_eq!(table.size(), expected.size());
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn bench_mem_table_extend_sorted() {
+        use std::time::Instant;
+
+        let pairs: Vec<(Vec<u8>, Vec<u8>, u128)> = (0..200_000u32)
+            .map(|i| (i.to_be_bytes().to_vec(), b"Smoothie".to_vec(), i as u128))
+            .collect();
+
+        let start = Instant::now();
+        let mut set_table = MemTable::new();
+        for (key, value, ts) in &pairs {
+            set_table.set_at(key, value.as_slice(), *ts);
+        }
+        let set_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        let mut bulk_table = MemTable::new();
+        bulk_table.extend_sorted(pairs.into_iter());
+        let bulk_elapsed = start.elapsed();
+
+        assert_eq!(set_table.entries(), bulk_table.entries());
+        println!("set: {:?}, extend_sorted: {:?}", set_elapsed, bulk_elapsed);
+    }
+
+    #[test]
+    fn test_mem_table_set_monotonic() {
+        let mut table = MemTable::new();
+        let mut expected = std::collections::BTreeMap::new();
+        // Runs of increasing keys interleaved with keys landing before them,
+        // overwrites of the last key and deletions.
+        for i in 0..300u32 {
+            let key = match i % 10 {
+                7 => (i / 2).to_be_bytes(),
+                8 => (i - 1).to_be_bytes(),
+                _ => (i * 4).to_be_bytes(),
+            };
+            if i % 25 == 0 {
+                table.delete_at(&key, i as u128);
+                expected.remove(&key);
+            } else {
+                table.set_at(&key, i.to_le_bytes().to_vec(), i as u128);
+                expected.insert(key, i.to_le_bytes().to_vec());
+            }
+        }
+
+        let keys: Vec<&[u8]> = table.entries().iter().map(|e| e.key.as_slice()).collect();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        let live: Vec<(&[u8], &[u8])> = table
+            .entries()
+            .iter()
+            .filter(|e| !e.is_deleted)
+            .map(|e| (e.key.as_slice(), e.value.as_deref().unwrap()))
+            .collect();
+        let expected: Vec<(&[u8], &[u8])> = expected
+            .iter()
+            .map(|(k, v)| (k.as_slice(), v.as_slice()))
+            .collect();
+        assert_eq!(live, expected);
+        assert_eq!(table.live_len(), expected.len());
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn bench_mem_table_set_monotonic() {
+        use std::time::Instant;
+
+        let keys: Vec<[u8; 8]> = (0..1_000_000u64).map(|i| i.to_be_bytes()).collect();
+
+        let start = Instant::now();
+        let mut table = MemTable::new();
+        for (i, key) in keys.iter().enumerate() {
+            table.set_at(key, &b"Smoothie"[..], i as u128);
+        }
+        let elapsed = start.elapsed();
+
+        assert_eq!(table.len(), keys.len());
+        println!("monotonic set: {:?}", elapsed);
+    }
+
+    #[test]
+    fn test_mem_table_heap_size() {
+        let mut table = MemTable::new();
+        assert_eq!(table.heap_size(), 0);
+
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.delete_at(b"Lime", 10);
+
+        let entries = table.entries.capacity() * std::mem::size_of::<MemTableEntry>();
+        assert!(table.heap_size() >= entries + b"AppleApple SmoothieLime".len());
+        assert!(table.heap_size() > table.size());
+
+        // Clearing keeps the entries allocation around.
+        table.clear();
+        assert_eq!(table.size(), 0);
+        assert_eq!(table.heap_size(), entries);
+    }
+
+    #[test]
+    fn test_mem_table_merge() {
+        let mut frozen = MemTable::new();
+        frozen.set_at(b"Apple", b"Apple Smoothie", 30);
+        frozen.set_at(b"Banana", b"Banana Smoothie", 0);
+        frozen.set_at(b"Lime", b"Lime Smoothie", 10);
+        frozen.set_at(b"Peach", b"Peach Smoothie", 20);
+
+        let mut active = MemTable::new();
+        active.set_at(b"Apple", b"Apple Pie", 20);
+        active.delete_at(b"Lime", 40);
+        active.set_at(b"Orange", b"Orange Smoothie", 40);
+        active.set_at(b"Peach", b"Peach Pie", 20);
+
+        let merged = frozen.merge(active);
+
+        let mut expected = MemTable::new();
+        // The frozen Apple is newer than the active one.
+        expected.set_at(b"Apple", b"Apple Smoothie", 30);
+        expected.set_at(b"Banana", b"Banana Smoothie", 0);
+        // The active tombstone is newer than the frozen Lime.
+        expected.delete_at(b"Lime", 40);
+        expected.set_at(b"Orange", b"Orange Smoothie", 40);
+        // Equal timestamps keep the entry of the other MemTable.
+        expected.set_at(b"Peach", b"Peach Pie", 20);
+
+        assert_eq!(merged.entries(), expected.entries());
+        assert_eq!(merged.size(), expected.size());
+        assert_eq!(merged.live_len(), expected.live_len());
+    }
+
+    #[test]
+    fn test_mem_table_merge_empty() {
+        let mut table = MemTable::with_capacity(100);
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        let size = table.size();
+
+        let merged = table.merge(MemTable::new());
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged.size(), size);
+        assert_eq!(merged.max_size(), 100);
+
+        let merged = MemTable::new().merge(merged);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged.size(), size);
+    }
+
+    #[test]
+    fn test_mem_table_get_value() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.delete_at(b"Lime", 20);
+
+        assert_eq!(
+            table.get_value(b"Apple"),
+            Some(b"Apple Smoothie".as_slice())
+        );
+        assert_eq!(table.get_value(b"Lime"), None);
+        assert_eq!(table.get_value(b"Orange"), None);
+    }
+
+    #[test]
+    fn test_mem_table_live_len() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.delete_at(b"Orange", 20);
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.live_len(), 2);
+
+        // Overwriting a live key or deleting a deleted key changes nothing.
+        table.set_at(b"Apple", b"Apple Pie", 30);
+        table.delete_at(b"Orange", 40);
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.live_len(), 2);
+
+        table.delete_at(b"Apple", 50);
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.live_len(), 1);
+
+        table.set_at(b"Orange", b"Orange Smoothie", 60);
+        assert_eq!(table.live_len(), 2);
+        assert_eq!(table.live_len(), table.iter_live().count());
+
+        table.clear();
+        assert_eq!(table.live_len(), 0);
+    }
+
+    /// Generates pseudo random numbers with SplitMix64, so the round trip
+    /// cases are reproducible
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        }
+
+        fn bytes(&mut self, max_len: u64) -> Vec<u8> {
+            let len = self.next() % (max_len + 1);
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    #[test]
+    fn test_mem_table_entry_encode_round_trip() {
+        let mut rng = Rng(42);
+        for i in 0..1000u32 {
+            let is_deleted = rng.next().is_multiple_of(3);
+            let entry = MemTableEntry {
+                key: rng.bytes(32),
+                value: (!is_deleted).then(|| rng.bytes(if i.is_multiple_of(10) { 0 } else { 256 })),
+                timestamp_ms: (rng.next() as u128) << 64 | rng.next() as u128,
+                is_deleted,
+                is_merge: !is_deleted && rng.next().is_multiple_of(4),
+                expires_at_ms: rng.next().is_multiple_of(2).then(|| rng.next() as u128),
+            };
+
+            // Records are decoded from the start of a buffer that may hold more.
+            let mut buf = b"prefix".to_vec();
+            entry.encode(&mut buf);
+            let len = buf.len() - b"prefix".len();
+            buf.extend_from_slice(b"suffix");
+
+            let (decoded, decoded_len) = MemTableEntry::decode(&buf[b"prefix".len()..]).unwrap();
+            assert_eq!(decoded, entry);
+            assert_eq!(decoded_len, len);
+        }
+    }
+
+    #[test]
+    fn test_mem_table_entry_encoded_len() {
+        let mut rng = Rng(7);
+        for _ in 0..1000 {
+            let is_deleted = rng.next().is_multiple_of(3);
+            let entry = MemTableEntry {
+                key: rng.bytes(64),
+                value: (!is_deleted).then(|| rng.bytes(512)),
+                timestamp_ms: rng.next() as u128,
+                is_deleted,
+                is_merge: !is_deleted && rng.next().is_multiple_of(4),
+                expires_at_ms: rng.next().is_multiple_of(2).then(|| rng.next() as u128),
+            };
+            let mut buf = Vec::new();
+            entry.encode(&mut buf);
+            assert_eq!(entry.encoded_len(), buf.len());
+        }
+
+        // The size of a MemTable is the encoded length of its records, however
+        // they were overwritten.
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_with_ttl(b"Apple", b"Apple Pie", 10, 100);
+        table.delete_at(b"Lime", 20);
+        table.set_at(b"Lime", b"Lime Smoothie", 30);
+        table.delete_at(b"Apple", 40);
+        let size: usize = table.entries().iter().map(MemTableEntry::encoded_len).sum();
+        assert_eq!(table.size(), size);
+    }
+
+    #[test]
+    fn test_mem_table_entry_decode_invalid() {
+        let entry = MemTableEntry {
+            key: b"Apple".to_vec(),
+            value: Some(b"Apple Smoothie".to_vec()),
+            timestamp_ms: 10,
+            is_deleted: false,
+            is_merge: false,
+            expires_at_ms: None,
+        };
+        let mut buf = Vec::new();
+        entry.encode(&mut buf);
+
+        for len in 0..buf.len() {
+            let err = MemTableEntry::decode(&buf[..len]).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        }
+
+        buf[10] ^= 0xFF;
+        let err = MemTableEntry::decode(&buf).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_mem_table_stats() {
+        let mut table = MemTable::new();
+        assert_eq!(table.stats(), MemTableStats::default());
+
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.delete_at(b"Orange", 20);
+        table.delete_at(b"Blackcurrant", 30);
+        table.set_at(b"Peach", b"Peach and Mango Smoothie", 40);
+
+        assert_eq!(
+            table.stats(),
+            MemTableStats {
+                live_count: 3,
+                tombstone_count: 2,
+                size: table.size(),
+                max_key_len: b"Blackcurrant".len(),
+                max_value_len: b"Peach and Mango Smoothie".len(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_shared_mem_table() {
+        let shared = SharedMemTable::new(MemTable::new());
+        shared.set_at(b"Apple", b"Apple Smoothie", 0);
+        shared.set_at(b"Lime", b"Lime Smoothie", 10);
+        assert_eq!(
+            shared.delete_at(b"Lime", 20),
+            Some(b"Lime Smoothie".to_vec())
+        );
+
+        let apple = shared.get(b"Apple").unwrap();
+        assert_eq!(apple.value.unwrap(), b"Apple Smoothie");
+        assert!(shared.get(b"Lime").unwrap().is_deleted);
+
+        let keys: Vec<Vec<u8>> = shared
+            .range(Bound::Unbounded, Bound::Unbounded)
+            .into_iter()
+            .map(|e| e.key)
+            .collect();
+        assert_eq!(keys, vec![b"Apple".to_vec(), b"Lime".to_vec()]);
+        assert_eq!(shared.read().live_len(), 1);
+    }
+
+    #[test]
+    fn test_shared_mem_table_concurrent() {
+        let shared = SharedMemTable::new(MemTable::new());
+        for i in 0..100u32 {
+            shared.set_at(&i.to_be_bytes(), i.to_le_bytes(), 0);
+        }
+
+        let writer = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                for i in 100..1000u32 {
+                    shared.set_at(&i.to_be_bytes(), i.to_le_bytes(), i as u128);
+                    if i.is_multiple_of(3) {
+                        shared.delete_at(&(i - 100).to_be_bytes(), i as u128);
+                    }
+                }
+            })
+        };
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        // Every scan sees a consistent, sorted MemTable.
+                        let entries = shared.range(Bound::Unbounded, Bound::Unbounded);
+                        assert!(entries.len() >= 100);
+                        assert!(entries.windows(2).all(|w| w[0].key < w[1].key));
+
+                        // A record is only ever replaced with a newer one.
+                        let entry = shared.get(&99u32.to_be_bytes()).unwrap();
+                        assert!(entry.is_deleted || entry.value.unwrap() == 99u32.to_le_bytes());
+                    }
+                })
+            })
+            .collect();
+
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        let table = shared.read();
+        assert_eq!(table.len(), 1000);
+        assert_eq!(table.live_len(), 1000 - 300);
+    }
+
+    #[test]
+    fn test_mem_table_freeze() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.delete_at(b"Lime", 10);
+        table.set_at(b"Orange", b"Orange Smoothie", 20);
+        let size = table.size();
+
+        let frozen: ImmutableMemTable = table.freeze();
+        assert_eq!(frozen.len(), 3);
+        assert_eq!(frozen.size(), size);
+        assert!(frozen.get(b"Lime").unwrap().is_deleted);
+        assert!(frozen.get(b"Peach").is_none());
+
+        let keys: Vec<&[u8]> = frozen.iter().map(|e| e.key.as_slice()).collect();
+        assert_eq!(keys, vec![b"Apple".as_slice(), b"Lime", b"Orange"]);
+
+        let keys: Vec<&[u8]> = frozen
+            .range(Bound::Excluded(b"Apple"), Bound::Unbounded)
+            .rev()
+            .map(|e| e.key.as_slice())
+            .collect();
+        assert_eq!(keys, vec![b"Orange".as_slice(), b"Lime"]);
+    }
+
+    #[test]
+    fn test_mem_table_multi_get() {
+        let mut table = MemTable::new();
+        for i in (0..100u32).step_by(2) {
+            table.set_at(&i.to_be_bytes(), i.to_le_bytes(), i as u128);
+        }
+        table.delete_at(&10u32.to_be_bytes(), 200);
+
+        let keys: Vec<[u8; 4]> = [0u32, 1, 10, 10, 11, 50, 98, 99, 150]
+            .iter()
+            .map(|i| i.to_be_bytes())
+            .collect();
+        let keys: Vec<&[u8]> = keys.iter().map(|k| k.as_slice()).collect();
+        let expected: Vec<_> = keys.iter().map(|key| table.get(key)).collect();
+
+        // Sorted keys take the single walk.
+        let found = table.multi_get(&keys);
+        assert_eq!(found, expected);
+        let found: Vec<bool> = found.iter().map(|e| e.is_some()).collect();
+        assert_eq!(
+            found,
+            vec![true, false, true, true, false, true, true, false, false]
+        );
+        assert!(table.multi_get(&keys)[2].unwrap().is_deleted);
+
+        // Unsorted keys are looked up one by one.
+        let reversed: Vec<&[u8]> = keys.iter().rev().copied().collect();
+        let expected: Vec<_> = expected.into_iter().rev().collect();
+        assert_eq!(table.multi_get(&reversed), expected);
+
+        assert!(table.multi_get(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_immutable_mem_table_into_iter() {
+        let mut table = MemTable::new();
+        table.set_at(b"Orange", b"Orange Smoothie", 0);
+        table.delete_at(b"Lime", 10);
+        table.set_at(b"Apple", b"Apple Smoothie", 20);
+        let expected = table.entries().to_vec();
+
+        let entries: Vec<MemTableEntry> = table.freeze().into_iter().collect();
+        assert_eq!(entries, expected);
+        assert!(entries.windows(2).all(|w| w[0].key < w[1].key));
+        assert!(entries[1].is_deleted);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "sorted by key and unique")]
+    fn test_mem_table_assert_sorted_duplicate() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Orange", b"Orange Smoothie", 0);
+        table.assert_sorted();
+
+        let duplicate = table.entries[1].clone();
+        table.entries.push(duplicate);
+        table.assert_sorted();
+    }
+
+    #[test]
+    fn test_mem_table_compact_tombstones() {
+        let mut table = MemTable::new();
+        assert_eq!(table.tombstone_ratio(), 0.0);
+
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.delete_at(b"Lime", 0);
+        table.delete_at(b"Orange", 0);
+        table.delete_at(b"Peach", 0);
+        assert_eq!(table.tombstone_ratio(), 0.75);
+
+        // Only Orange is still held by an older source.
+        table.compact_tombstones(|key| key != b"Orange");
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.live_len(), 1);
+        assert_eq!(table.tombstone_ratio(), 0.5);
+        assert!(table.get(b"Lime").is_none());
+        assert!(table.get(b"Orange").unwrap().is_deleted);
+        assert_eq!(
+            table.size(),
+            MemTable::from_entries(table.entries().to_vec()).size()
+        );
+
+        table.compact_tombstones(|_| false);
+        assert_eq!(table.len(), 2);
+
+        // Older versions of a key are not tombstones.
+        let mut table = MemTable::new().with_versions(3);
+        for timestamp_ms in [10, 20, 30] {
+            table.set_at(b"Apple", b"Apple Smoothie", timestamp_ms);
+        }
+        assert_eq!(table.tombstone_ratio(), 0.0);
+        table.delete_at(b"Apple", 40);
+        assert_eq!(table.entries().len(), 3);
+        assert_eq!(table.tombstone_ratio(), 1.0 / 3.0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "size accounting")]
+    fn test_mem_table_size_drift_debug() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.size = 0;
+        table.delete_at(b"Apple", 10);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_mem_table_size_drift_release() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.size = 0;
+        table.delete_at(b"Apple", 10);
+        assert_eq!(table.size(), 0);
+    }
+
+    #[test]
+    fn test_mem_table_apply() {
+        let mut table = MemTable::new();
+        table
+            .apply(Operation::Set {
+                key: b"Apple".to_vec(),
+                value: b"Apple Smoothie".to_vec(),
+                timestamp_ms: 0,
+                expires_at_ms: None,
+            })
+            .unwrap();
+        table
+            .apply(Operation::Set {
+                key: b"Lime".to_vec(),
+                value: b"Lime Smoothie".to_vec(),
+                timestamp_ms: 10,
+                expires_at_ms: Some(20),
+            })
+            .unwrap();
+        let previous = table
+            .apply(Operation::Delete {
+                key: b"Apple".to_vec(),
+                timestamp_ms: 30,
+            })
+            .unwrap();
+        assert_eq!(previous.unwrap(), b"Apple Smoothie");
+
+        let mut expected = MemTable::new();
+        expected.set_at(b"Apple", b"Apple Smoothie", 0);
+        expected.set_with_ttl(b"Lime", b"Lime Smoothie", 10, 10);
+        expected.delete_at(b"Apple", 30);
+        assert_eq!(table.entries(), expected.entries());
+        assert_eq!(table.size(), expected.size());
+
+        for entry in table.entries() {
+            let mut replayed = MemTable::new();
+            replayed.apply(Operation::from(entry.clone())).unwrap();
+            assert_eq!(replayed.entries(), std::slice::from_ref(entry));
+        }
+    }
+
+    struct AppendOperator;
+
+    impl MergeOperator for AppendOperator {
+        fn full_merge(&self, existing: Option<&[u8]>, operands: &[Vec<u8>]) -> Option<Vec<u8>> {
+            let mut value = existing.unwrap_or_default().to_vec();
+            operands.iter().for_each(|o| value.extend_from_slice(o));
+            Some(value)
+        }
+    }
+
+    #[test]
+    fn test_mem_table_merge_at() {
+        let mut table = MemTable::new().with_merge_operator(Arc::new(AppendOperator));
+
+        // Without a record to fold into, the operands are kept in order.
+        table.merge_at(b"Apple", b"Apple", 10).unwrap();
+        table.merge_at(b"Apple", b" Smoothie", 20).unwrap();
+        let apple = table.get(b"Apple").unwrap().clone();
+        assert!(apple.is_merge);
+        assert_eq!(apple.timestamp_ms, 20);
+        assert_eq!(
+            merge::decode_operands(apple.value.as_ref().unwrap()).unwrap(),
+            vec![b"Apple".to_vec(), b" Smoothie".to_vec()]
+        );
+
+        // A record held by the MemTable is folded into right away.
+        table.set_at(b"Lime", b"Lime", 30);
+        table.merge_at(b"Lime", b" Smoothie", 40).unwrap();
+        let lime = table.get(b"Lime").unwrap();
+        assert!(!lime.is_merge);
+        assert_eq!(lime.timestamp_ms, 40);
+        assert_eq!(table.get_value(b"Lime").unwrap(), b"Lime Smoothie");
+        table.delete_at(b"Lime", 50);
+        table.merge_at(b"Lime", b"Lime Pie", 60).unwrap();
+        assert_eq!(table.get_value(b"Lime").unwrap(), b"Lime Pie");
+
+        assert_eq!(table.live_len(), 2);
+        assert_eq!(
+            table.size(),
+            MemTable::from_entries(table.entries().to_vec()).size()
+        );
+
+        let mut replayed = MemTable::new();
+        replayed.apply(Operation::from(apple.clone())).unwrap();
+        assert_eq!(replayed.entries(), [apple]);
+    }
+
+    #[test]
+    fn test_mem_table_merge_at_without_operator() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple", 10);
+        let entries = table.entries().to_vec();
+
+        assert!(matches!(
+            table.merge_at(b"Apple", b" Smoothie", 20),
+            Err(Error::InvalidConfig(_))
+        ));
+        let merge = Operation::Merge {
+            key: b"Apple".to_vec(),
+            operands: vec![b" Smoothie".to_vec(), b" Bowl".to_vec()],
+            timestamp_ms: 20,
+        };
+        assert!(matches!(table.apply(merge), Err(Error::InvalidConfig(_))));
+        assert_eq!(table.entries(), entries);
+
+        // Operands with nothing to fold into are kept without it.
+        table.merge_at(b"Lime", b"Lime", 20).unwrap();
+        assert!(table.get(b"Lime").unwrap().is_merge);
+    }
+
+    #[test]
+    fn test_mem_table_merge_at_range_deleted() {
+        let mut table = MemTable::new().with_merge_operator(Arc::new(AppendOperator));
+        table.set_at(b"Apple", b"Apple", 10);
+        table.merge_at(b"Lime", b"Lime", 10).unwrap();
+        table.delete_range_at(b"A", b"Z", 20);
+
+        // The range deleted records are not folded into.
+        table.merge_at(b"Apple", b" Smoothie", 30).unwrap();
+        assert_eq!(table.get_value(b"Apple").unwrap(), b" Smoothie");
+        table.merge_at(b"Lime", b" Pie", 30).unwrap();
+        let lime = table.get(b"Lime").unwrap();
+        assert!(!lime.is_merge);
+        assert_eq!(table.get_value(b"Lime").unwrap(), b" Pie");
+
+        // Records written after the Range Tombstone still are.
+        table.merge_at(b"Apple", b" Bowl", 40).unwrap();
+        assert_eq!(table.get_value(b"Apple").unwrap(), b" Smoothie Bowl");
+    }
+
+    #[test]
+    fn test_mem_table_apply_random() {
+        let mut rng = Rng(7);
+        let mut table = MemTable::new();
+        for _ in 0..2000 {
+            let key = rng.bytes(2);
+            let timestamp_ms = rng.next() as u128;
+            let operation = if rng.next().is_multiple_of(3) {
+                Operation::Delete { key, timestamp_ms }
+            } else {
+                Operation::Set {
+                    key,
+                    value: rng.bytes(16),
+                    timestamp_ms,
+                    expires_at_ms: None,
+                }
+            };
+            table.apply(operation).unwrap();
+
+            #[cfg(debug_assertions)]
+            table.assert_sorted();
+        }
+
+        let rebuilt = MemTable::from_entries(table.entries().to_vec());
+        assert_eq!(table.size(), rebuilt.size());
+        assert_eq!(table.live_len(), rebuilt.live_len());
+    }
+
+    #[test]
+    fn test_mem_table_versions() {
+        let mut table = MemTable::new().with_versions(2);
+        assert_eq!(table.set_at(b"Apple", b"Apple Juice", 10), None);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        let previous = table.set_at(b"Apple", b"Apple Smoothie", 20);
+        assert_eq!(previous.unwrap(), b"Apple Juice");
+        table.set_at(b"Apple", b"Apple Pie", 30);
+
+        // Only the two newest versions of Apple are kept.
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.live_len(), 2);
+        assert_eq!(table.get_value(b"Apple").unwrap(), b"Apple Pie");
+        let entry = table.get_as_of(b"Apple", 25).unwrap();
+        assert_eq!(entry.value.as_ref().unwrap(), b"Apple Smoothie");
+        assert!(table.get_as_of(b"Apple", 15).is_none());
+        #[cfg(debug_assertions)]
+        table.assert_sorted();
+
+        // Versions written out of order are placed by their timestamp.
+        table.set_at(b"Lime", b"Lime Juice", 5);
+        assert_eq!(table.get_value(b"Lime").unwrap(), b"Lime Smoothie");
+        let entry = table.get_as_of(b"Lime", 7).unwrap();
+        assert_eq!(entry.value.as_ref().unwrap(), b"Lime Juice");
+
+        table.delete_at(b"Apple", 40);
+        assert!(table.get(b"Apple").unwrap().is_deleted);
+        let entry = table.get_as_of(b"Apple", 35).unwrap();
+        assert_eq!(entry.value.as_ref().unwrap(), b"Apple Pie");
+        assert_eq!(table.live_len(), 1);
+
+        let live: Vec<&[u8]> = table.iter_live().map(|(k, _)| k).collect();
+        assert_eq!(live, vec![b"Lime".as_slice()]);
+        let keys: Vec<&[u8]> = table
+            .range(Bound::Included(b"Apple"), Bound::Included(b"Apple"))
+            .map(|e| e.key.as_slice())
+            .collect();
+        assert_eq!(keys, vec![b"Apple".as_slice(), b"Apple".as_slice()]);
+
+        let size: usize = table.entries().iter().map(MemTableEntry::encoded_len).sum();
+        assert_eq!(table.size(), size);
+    }
+
+    #[test]
+    fn test_mem_table_versions_oldest_snapshot() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Juice", 10);
+        table.set_oldest_snapshot(Some(15));
+        table.set_at(b"Apple", b"Apple Smoothie", 20);
+        table.set_at(b"Apple", b"Apple Pie", 30);
+        table.delete_at(b"Apple", 40);
+
+        // Every version written after the Snapshot is kept, along with the
+        // one it reads.
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.live_len(), 0);
+        let entry = table.get_as_of(b"Apple", 15).unwrap();
+        assert_eq!(entry.value.as_ref().unwrap(), b"Apple Juice");
+
+        // Versions older than the Snapshot are dropped.
+        table.set_oldest_snapshot(Some(35));
+        table.set_at(b"Apple", b"Apple Tart", 50);
+        assert_eq!(table.len(), 3);
+        let entry = table.get_as_of(b"Apple", 35).unwrap();
+        assert_eq!(entry.value.as_ref().unwrap(), b"Apple Pie");
+
+        table.set_oldest_snapshot(None);
+        table.set_at(b"Apple", b"Apple Crumble", 60);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.live_len(), 1);
+        let size: usize = table.entries().iter().map(MemTableEntry::encoded_len).sum();
+        assert_eq!(table.size(), size);
+    }
+
+    #[test]
+    fn test_mem_table_get_as_of_single_version() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Juice", 10);
+        table.set_at(b"Apple", b"Apple Smoothie", 20);
+
+        assert_eq!(table.len(), 1);
+        assert!(table.get_as_of(b"Apple", 15).is_none());
+        let entry = table.get_as_of(b"Apple", 20).unwrap();
+        assert_eq!(entry.value.as_ref().unwrap(), b"Apple Smoothie");
+    }
+
+    #[test]
+    fn test_mem_table_iter_since() {
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 30);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.delete_at(b"Orange", 40);
+        table.set_at(b"Peach", b"Peach Smoothie", 20);
+        table.set_at(b"Plum", b"Plum Smoothie", 50);
+
+        let keys: Vec<&[u8]> = table.iter_since(30).map(|e| e.key.as_slice()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                b"Apple".as_slice(),
+                b"Orange".as_slice(),
+                b"Plum".as_slice()
+            ]
+        );
+        assert_eq!(table.iter_since(0).count(), 5);
+        assert_eq!(table.iter_since(51).count(), 0);
+    }
+
+    #[test]
+    fn test_mem_table_tombstones() {
+        let mut table = MemTable::new();
+        table.set_at(b"Plum", b"Plum Smoothie", 10);
+        table.delete_at(b"Orange", 20);
+        table.set_at(b"Apple", b"Apple Smoothie", 30);
+        table.delete_at(b"Lime", 40);
+        table.delete_at(b"Peach", 50);
+        table.set_at(b"Peach", b"Peach Smoothie", 60);
+        table.delete_at(b"Banana", 70);
+
+        let keys: Vec<&[u8]> = table.tombstones().map(|e| e.key.as_slice()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                b"Banana".as_slice(),
+                b"Lime".as_slice(),
+                b"Orange".as_slice()
+            ]
+        );
+        assert!(table
+            .tombstones()
+            .all(|e| e.is_deleted && e.value.is_none()));
+        assert_eq!(MemTable::new().tombstones().count(), 0);
+    }
+
+    #[test]
+    fn test_mem_table_range_keys() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut table = MemTable::new().with_clock(clock.clone());
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.delete_at(b"Lime", 0);
+        table.set_with_ttl(b"Orange", b"Orange Smoothie", 0, 10);
+        table.set_at(b"Peach", b"Peach Smoothie", 0);
+        table.set_at(b"Plum", b"Plum Smoothie", 0);
+
+        let keys: Vec<&[u8]> = table
+            .range_keys(Bound::Unbounded, Bound::Excluded(b"Plum"))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                b"Apple".as_slice(),
+                b"Orange".as_slice(),
+                b"Peach".as_slice()
+            ]
+        );
+
+        clock.set(10);
+        let keys: Vec<&[u8]> = table
+            .range_keys(Bound::Excluded(b"Apple"), Bound::Unbounded)
+            .collect();
+        assert_eq!(keys, vec![b"Peach".as_slice(), b"Plum".as_slice()]);
+    }
+
+    #[test]
+    fn test_mem_table_range_keys_versions() {
+        let mut table = MemTable::new().with_versions(2);
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.delete_at(b"Apple", 10);
+        table.set_at(b"Lime", b"Lime Juice", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+
+        let keys: Vec<&[u8]> = table
+            .range_keys(Bound::Unbounded, Bound::Unbounded)
+            .collect();
+        assert_eq!(keys, vec![b"Lime".as_slice()]);
+    }
+
+    #[test]
+    fn test_mem_table_owned_reads() {
+        let clock = Arc::new(MockClock::new(0));
+        let mut table = MemTable::new().with_clock(clock.clone()).with_versions(2);
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Apple", b"Apple Pie", 5);
+        table.delete_at(b"Lime", 0);
+        table.set_with_ttl(b"Orange", b"Orange Smoothie", 0, 10);
+        table.set_at(b"Peach", b"Peach Smoothie", 0);
+        table.set_at(b"Plum", b"Plum Smoothie", 0);
+        table.delete_at(b"Plum", 5);
+
+        let check = |table: &MemTable| {
+            for key in [
+                &b"Apple"[..],
+                b"Banana",
+                b"Lime",
+                b"Orange",
+                b"Peach",
+                b"Plum",
+            ] {
+                let borrowed = table.get(key).map(|e| {
+                    let value = e.value.clone().unwrap_or_default();
+                    (value, e.timestamp_ms, e.is_deleted)
+                });
+                assert_eq!(table.get_owned(key), borrowed);
+            }
+
+            let bounds = [
+                (Bound::Unbounded, Bound::Unbounded),
+                (
+                    Bound::Excluded(&b"Apple"[..]),
+                    Bound::Included(&b"Peach"[..]),
+                ),
+            ];
+            for (start, end) in bounds {
+                let borrowed: Vec<(Vec<u8>, Vec<u8>)> = table
+                    .range_keys(start, end)
+                    .map(|key| (key.to_vec(), table.get_value(key).unwrap().to_vec()))
+                    .collect();
+                assert_eq!(table.range_owned(start, end), borrowed);
+            }
+        };
+        check(&table);
+        assert_eq!(table.get_owned(b"Lime"), Some((Vec::new(), 0, true)));
+        assert_eq!(
+            table.range_owned(Bound::Unbounded, Bound::Unbounded),
+            vec![
+                (b"Apple".to_vec(), b"Apple Pie".to_vec()),
+                (b"Orange".to_vec(), b"Orange Smoothie".to_vec()),
+                (b"Peach".to_vec(), b"Peach Smoothie".to_vec()),
+            ]
+        );
+
+        // Expired records are skipped by both.
+        clock.set(10);
+        check(&table);
+        assert_eq!(table.get_owned(b"Orange"), None);
+
+        let frozen = table.freeze();
+        assert_eq!(
+            frozen.get_owned(b"Apple"),
+            Some((b"Apple Pie".to_vec(), 5, false))
+        );
+        assert_eq!(
+            frozen.range_owned(Bound::Unbounded, Bound::Unbounded),
+            vec![
+                (b"Apple".to_vec(), b"Apple Pie".to_vec()),
+                (b"Peach".to_vec(), b"Peach Smoothie".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mem_table_value_type() {
+        let mut table: MemTable<BytewiseComparator, [u8; 4]> = MemTable::default();
+        assert_eq!(table.set_at(b"Apple", 1u32.to_le_bytes(), 0), None);
+        assert_eq!(table.set_at(b"Lime", [0xFF; 4], 0), None);
+        assert_eq!(
+            table.set_at(b"Apple", 2u32.to_le_bytes(), 10),
+            Some(1u32.to_le_bytes())
+        );
+        assert_eq!(table.delete_at(b"Lime", 10), Some([0xFF; 4]));
+
+        // Sizes are counted from the bytes of the Values, as encoded in the WAL.
+        assert_eq!(table.size(), (5 + 4 + 37) + (4 + 29));
+        assert_eq!(
+            table.get_value(b"Apple"),
+            Some(2u32.to_le_bytes().as_slice())
+        );
+        assert_eq!(table.get_value(b"Lime"), None);
+        let pairs: Vec<(&[u8], &[u8])> = table.iter_live().collect();
+        assert_eq!(pairs, vec![(b"Apple".as_slice(), [2, 0, 0, 0].as_slice())]);
+    }
+
+    #[test]
+    fn test_mem_table_delete_range() {
+        let mut table = MemTable::new();
+        assert!(table.is_empty());
+
+        table.delete_range_at(b"Apple", b"Lime", 10);
+        table.delete_range_at(b"Lime", b"Lime", 20);
+        assert!(!table.is_empty());
+        assert_eq!(table.size(), 5 + 4 + 16);
+
+        let tombstone = RangeTombstone {
+            start: b"Apple".to_vec(),
+            end: b"Lime".to_vec(),
+            timestamp_ms: 10,
+        };
+        assert_eq!(table.range_tombstones(), std::slice::from_ref(&tombstone));
+
+        let comparator = BytewiseComparator;
+        assert!(tombstone.deletes(&comparator, b"Apple", 0));
+        assert!(tombstone.deletes(&comparator, b"Banana", 9));
+        assert!(!tombstone.deletes(&comparator, b"Banana", 10));
+        assert!(!tombstone.deletes(&comparator, b"Lime", 0));
+        assert!(!tombstone.deletes(&comparator, b"Aardvark", 0));
+
+        table.clear();
+        assert!(table.is_empty());
     }
 }
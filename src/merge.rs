@@ -0,0 +1,59 @@
+use std::io;
+
+use crate::error::{Error, Result};
+use crate::mem_table::take;
+
+/// MergeOperator folds the operands written with
+/// [`Database::merge`](crate::db::Database::merge) into a value
+///
+/// Merges record an update of a key, such as a counter increment, without
+/// reading its current value first. Their operands are kept until a read or
+/// a compaction finds the value they apply to, which is when they are folded
+/// into it by `full_merge`.
+pub trait MergeOperator: Send + Sync {
+    /// Applies the operands, from the oldest to the newest, over the existing
+    /// value of the key
+    ///
+    /// `existing` is None if the key is absent or deleted. Returning None
+    /// deletes the key.
+    fn full_merge(&self, existing: Option<&[u8]>, operands: &[Vec<u8>]) -> Option<Vec<u8>>;
+}
+
+/// Appends an operand to the Value of a merge record
+///
+/// The Value of a merge record holds its operands from the oldest to the
+/// newest, each as its length (8B) followed by its bytes.
+pub(crate) fn push_operand(operands: &mut Vec<u8>, operand: &[u8]) {
+    operands.extend_from_slice(&(operand.len() as u64).to_le_bytes());
+    operands.extend_from_slice(operand);
+}
+
+/// Decodes the operands of the Value of a merge record
+pub(crate) fn decode_operands(buf: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let mut operands = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let len = u64::from_le_bytes(take(buf, &mut pos, 8)?.try_into().unwrap());
+        operands.push(take(buf, &mut pos, len as usize)?.to_vec());
+    }
+    Ok(operands)
+}
+
+/// Folds the Values of the merge records of a key, from the newest to the
+/// oldest, over the existing value they apply to
+///
+/// Fails with [`Error::InvalidConfig`] without a MergeOperator.
+pub(crate) fn fold(
+    merge_operator: Option<&dyn MergeOperator>,
+    merges: &[Vec<u8>],
+    existing: Option<&[u8]>,
+) -> Result<Option<Vec<u8>>> {
+    let merge_operator = merge_operator.ok_or_else(|| {
+        Error::InvalidConfig("merge records can only be read with a MergeOperator".to_owned())
+    })?;
+    let mut operands = Vec::new();
+    for merge in merges.iter().rev() {
+        operands.extend(decode_operands(merge)?);
+    }
+    Ok(merge_operator.full_merge(existing, &operands))
+}
@@ -0,0 +1,191 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Metrics is a snapshot of the counters of a Database, taken by
+/// [`Database::metrics`](crate::db::Database::metrics)
+///
+/// Every counter starts at zero when the Database is opened.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metrics {
+    /// Number of point lookups answered by the MemTable or a frozen
+    /// MemTable
+    pub mem_table_hits: u64,
+    /// Number of point lookups that read a block of a Table, per level
+    pub table_reads: Vec<u64>,
+    /// Number of point lookups of a Table skipped because its BloomFilter
+    /// ruled the key out
+    pub bloom_negatives: u64,
+    /// Number of Tables skipped by prefix scans because their prefix
+    /// BloomFilter ruled the prefix out
+    pub prefix_bloom_negatives: u64,
+    /// Number of bytes appended to the WAL
+    pub wal_bytes_written: u64,
+    /// Number of MemTables flushed to a Table
+    pub flushes: u64,
+    /// Number of compactions
+    pub compactions: u64,
+    /// Number of writes that waited for flushes because the MemTables took
+    /// more memory than the write buffer limit
+    pub write_stalls: u64,
+}
+
+/// Profile describes the sizes of the records of a Database, taken by
+/// [`Database::profile`](crate::db::Database::profile)
+///
+/// Every record held by the MemTables and the Tables is counted, including
+/// the overwritten and deleted ones that compactions haven't dropped yet.
+/// Range Tombstones aren't counted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Profile {
+    /// Number of records that are not tombstones
+    pub live_count: u64,
+    /// Number of tombstones
+    pub tombstone_count: u64,
+    /// Lengths of the keys of every record
+    pub key_lens: LengthStats,
+    /// Lengths of the Values of the records that are not tombstones
+    pub value_lens: LengthStats,
+}
+
+impl Profile {
+    /// Counts a record, where a `value_len` of None is a tombstone
+    pub(crate) fn add(&mut self, key_len: usize, value_len: Option<usize>) {
+        self.key_lens.add(key_len);
+        match value_len {
+            Some(value_len) => {
+                self.live_count += 1;
+                self.value_lens.add(value_len);
+            }
+            None => self.tombstone_count += 1,
+        }
+    }
+
+    /// Adds the records counted by another Profile
+    pub(crate) fn merge(&mut self, other: &Profile) {
+        self.live_count += other.live_count;
+        self.tombstone_count += other.tombstone_count;
+        self.key_lens.merge(&other.key_lens);
+        self.value_lens.merge(&other.value_lens);
+    }
+}
+
+/// LengthStats summarizes a set of lengths in bytes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LengthStats {
+    pub count: u64,
+    /// Smallest length, or 0 without lengths
+    pub min: u64,
+    pub max: u64,
+    /// Sum of the lengths
+    pub total: u64,
+}
+
+impl LengthStats {
+    /// Returns the mean length, or 0 without lengths
+    pub fn mean(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            count => self.total as f64 / count as f64,
+        }
+    }
+
+    fn add(&mut self, len: usize) {
+        self.merge(&LengthStats {
+            count: 1,
+            min: len as u64,
+            max: len as u64,
+            total: len as u64,
+        });
+    }
+
+    fn merge(&mut self, other: &LengthStats) {
+        if other.count == 0 {
+            return;
+        }
+        self.min = match self.count {
+            0 => other.min,
+            _ => self.min.min(other.min),
+        };
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+        self.total += other.total;
+    }
+}
+
+/// Counters tracks the Metrics of a Database
+///
+/// Updates are relaxed atomic additions, so they are cheap enough for the
+/// read and write paths, and reads through a shared reference can update
+/// them too.
+pub(crate) struct Counters {
+    mem_table_hits: AtomicU64,
+    table_reads: Vec<AtomicU64>,
+    bloom_negatives: AtomicU64,
+    prefix_bloom_negatives: AtomicU64,
+    wal_bytes_written: AtomicU64,
+    flushes: AtomicU64,
+    compactions: AtomicU64,
+    write_stalls: AtomicU64,
+}
+
+impl Counters {
+    /// Creates Counters for a Database with the given number of levels
+    pub(crate) fn new(levels: usize) -> Counters {
+        Counters {
+            mem_table_hits: AtomicU64::new(0),
+            table_reads: (0..levels).map(|_| AtomicU64::new(0)).collect(),
+            bloom_negatives: AtomicU64::new(0),
+            prefix_bloom_negatives: AtomicU64::new(0),
+            wal_bytes_written: AtomicU64::new(0),
+            flushes: AtomicU64::new(0),
+            compactions: AtomicU64::new(0),
+            write_stalls: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn record_mem_table_hit(&self) {
+        self.mem_table_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_table_read(&self, level: usize) {
+        self.table_reads[level].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_bloom_negative(&self) {
+        self.bloom_negatives.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_prefix_bloom_negative(&self) {
+        self.prefix_bloom_negatives.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_wal_bytes(&self, bytes: u64) {
+        self.wal_bytes_written.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_flush(&self) {
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_compaction(&self) {
+        self.compactions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_write_stall(&self) {
+        self.write_stalls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reads every counter into a Metrics
+    pub(crate) fn snapshot(&self) -> Metrics {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Metrics {
+            mem_table_hits: load(&self.mem_table_hits),
+            table_reads: self.table_reads.iter().map(load).collect(),
+            bloom_negatives: load(&self.bloom_negatives),
+            prefix_bloom_negatives: load(&self.prefix_bloom_negatives),
+            wal_bytes_written: load(&self.wal_bytes_written),
+            flushes: load(&self.flushes),
+            compactions: load(&self.compactions),
+            write_stalls: load(&self.write_stalls),
+        }
+    }
+}
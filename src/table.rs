@@ -0,0 +1,3163 @@
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicU64};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::bloom::{self, BloomFilter, PrefixExtractor, DEFAULT_FP_RATE};
+use crate::cache::BlockCache;
+use crate::clock::{Clock, SystemClock};
+use crate::comparator::{BytewiseComparator, Comparator};
+use crate::compression::Compression;
+use crate::error::{Error, Result};
+use crate::mem_table::{
+    check_crc, take, ImmutableMemTable, MemTable, MemTableEntry, RangeTombstone, FLAG_BLOB,
+    FLAG_SHARED_VALUE,
+};
+use crate::merge::MergeOperator;
+use crate::metrics::{LengthStats, Profile};
+use crate::verify::ProblemKind;
+
+/// Default size at which a data block of a Table is closed
+pub const DEFAULT_BLOCK_SIZE: usize = 4 * 1024;
+
+/// Default size above which a Value is stored in the blob area of a Table
+pub const DEFAULT_LARGE_VALUE_THRESHOLD: usize = 64 * 1024;
+
+/// Default number of records between the restart points of a block
+pub const DEFAULT_RESTART_INTERVAL: usize = 16;
+
+/// TableSource is where a TableReader reads the bytes of a Table from
+///
+/// Any seekable reader is a TableSource, so besides a file a Table can be
+/// read from memory, such as from a `Cursor` over bytes embedded in the
+/// binary.
+pub trait TableSource: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> TableSource for T {}
+
+/// Id of the next Table opened by a TableReader, unique within the process
+static NEXT_TABLE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Size of the footer, holding the Index Offset(8 bytes), the Bloom Filter
+/// Offset(8 bytes), the Range Tombstones Offset(8 bytes), the Profile
+/// Offset(8 bytes), the Entry Count(8 bytes) and the CRC(4 bytes)
+const FOOTER_SIZE: u64 = 44;
+
+/// Size of the Profile of a Table, as ten 8 byte counters
+const PROFILE_SIZE: usize = 80;
+
+/// TableWriter writes the contents of a MemTable to the disk as a
+/// Table(SSTable)
+///
+/// A Table is laid out as:
+///
+/// ```text
+/// +-------+-----+-------+-------------+--------------+------------------+---------+------------------------------------------------------------------------------------+
+/// | Block | ... | Block | Block Index | Bloom Filter | Range Tombstones | Profile | Index Off(8B) | Bloom Off(8B) | Range Off(8B) | Profile Off(8B) | Count(8B) | CRC(4B) |
+/// +-------+-----+-------+-------------+--------------+------------------+---------+------------------------------------------------------------------------------------+
+/// ```
+///
+/// Records are grouped into data blocks of about `block_size` bytes, each
+/// laid out as `Codec (1B) | First Key Len (8B) | First Key | Records`. A
+/// block is closed once it reaches `block_size`, so it overshoots by at most
+/// one record. The Records of a block are compressed with the Codec, which
+/// is stored per block since blocks that barely compress are stored as is.
+///
+/// Keys are prefix compressed within a block: each record stores the length
+/// of the prefix it shares with the key before it, followed by the rest of
+/// its key. Every `restart_interval` records, starting with the first one, a
+/// record is a restart point instead, storing its key in full with a Shared
+/// of 0, so it can be decoded without any record before it. The
+/// Records of a block end with the offsets of its restart points within
+/// them, as `Offset (4B) | ... | Offset (4B) | Restart Count (4B)`. Records
+/// are laid out as:
+///
+/// ```text
+/// +-------------+-----------------+--------+------------+-------+-----+----------+
+/// | Shared (4B) | Suffix Len (4B) | Suffix | Flags (1B) | Value | ... | CRC (4B) |
+/// +-------------+-----------------+--------+------------+-------+-----+----------+
+/// ```
+///
+/// From the Flags on, records use the same layout as the WAL, and each record
+/// carries its own checksum.
+///
+/// Tables written with value runs store the Value of a run of records with
+/// the same Value only once, in the first record of the run. The other
+/// records of the run set the Shared Value Flag (bit 5 of the Flags) and
+/// leave out their Value Len and Value, taking the Value of the record
+/// before them. A run is cut at every restart point, whose record always
+/// holds its Value.
+///
+/// Values larger than the large value threshold would bloat the blocks and
+/// slow down scans, so they are kept out of them. Such a Value is written to
+/// the blob area, between the blocks, as `Value | CRC (4B)`, and its record
+/// sets the Blob Flag and holds `Offset (8B) | Len (8B)` as its Value,
+/// pointing at the blob.
+///
+/// The block index maps the first key of every block to its offset and
+/// length, laid out as `Key Len (8B) | Key | Offset (8B) | Len (8B)` per
+/// block. The BloomFilter holds every key of the Table, and is followed by a
+/// second BloomFilter holding the prefixes of the keys for Tables written
+/// with a [`PrefixExtractor`]. The Range Tombstones of the MemTable are laid
+/// out as `Start Len (8B) | Start | End Len (8B) | End | Timestamp (16B)`
+/// each.
+/// The [`Profile`] of the records is laid out as `Live Count (8B) |
+/// Tombstone Count (8B)` followed by `Count (8B) | Min (8B) | Max (8B) |
+/// Total (8B)` for the key lengths and then for the Value lengths. The CRC
+/// of the footer covers the block index, the BloomFilter, the Range
+/// Tombstones, the Profile and the footer itself.
+#[derive(Clone)]
+pub struct TableWriter {
+    block_size: usize,
+    bloom_fp_rate: f64,
+    compression: Compression,
+    large_value_threshold: usize,
+    restart_interval: usize,
+    split_points: Vec<Vec<u8>>,
+    merge_operator: Option<Arc<dyn MergeOperator>>,
+    tombstone_grace_ms: u128,
+    drop_expired: bool,
+    clock: Arc<dyn Clock>,
+    prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    value_runs: bool,
+}
+
+impl Default for TableWriter {
+    fn default() -> Self {
+        TableWriter::new()
+    }
+}
+
+impl TableWriter {
+    /// Creates a new TableWriter with the default block size
+    pub fn new() -> TableWriter {
+        TableWriter {
+            block_size: DEFAULT_BLOCK_SIZE,
+            bloom_fp_rate: DEFAULT_FP_RATE,
+            compression: Compression::None,
+            large_value_threshold: DEFAULT_LARGE_VALUE_THRESHOLD,
+            restart_interval: DEFAULT_RESTART_INTERVAL,
+            split_points: Vec::new(),
+            merge_operator: None,
+            tombstone_grace_ms: 0,
+            drop_expired: false,
+            clock: Arc::new(SystemClock),
+            prefix_extractor: None,
+            value_runs: false,
+        }
+    }
+
+    /// Sets the size in bytes at which a data block is closed
+    ///
+    /// Smaller blocks make point lookups read less, at the cost of a larger
+    /// block index.
+    pub fn with_block_size(mut self, block_size: usize) -> TableWriter {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Sets the codec the records of each block are compressed with
+    ///
+    /// Blocks that compression shrinks by less than an eighth are stored
+    /// uncompressed, as decompressing them would cost more than it saves.
+    pub fn with_compression(mut self, compression: Compression) -> TableWriter {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the target false positive rate of the Table's BloomFilter
+    pub fn with_bloom_fp_rate(mut self, bloom_fp_rate: f64) -> TableWriter {
+        self.bloom_fp_rate = bloom_fp_rate;
+        self
+    }
+
+    /// Sets the PrefixExtractor whose prefixes of the keys are held by a
+    /// second BloomFilter, for prefix scans to skip the Table
+    pub fn with_prefix_extractor(
+        mut self,
+        prefix_extractor: Arc<dyn PrefixExtractor>,
+    ) -> TableWriter {
+        self.prefix_extractor = Some(prefix_extractor);
+        self
+    }
+
+    pub fn prefix_extractor(&self) -> Option<&dyn PrefixExtractor> {
+        self.prefix_extractor.as_deref()
+    }
+
+    /// Sets whether records with the same Value as the record before them
+    /// leave it out, storing runs of equal Values only once
+    ///
+    /// This shrinks Tables where neighbouring keys often share their Value,
+    /// and is transparent to the readers.
+    pub fn with_value_runs(mut self, value_runs: bool) -> TableWriter {
+        self.value_runs = value_runs;
+        self
+    }
+
+    /// Sets the size in bytes above which a Value is stored in the blob area
+    /// instead of inline in its block
+    ///
+    /// Lookups only read a blob once they have found its record, and scans
+    /// that skip Values never read it.
+    pub fn with_large_value_threshold(mut self, large_value_threshold: usize) -> TableWriter {
+        self.large_value_threshold = large_value_threshold;
+        self
+    }
+
+    /// Sets the number of records between the restart points of a block
+    ///
+    /// Lookups binary search the restart points of a block and then decode
+    /// the records from the closest one, so smaller intervals make them
+    /// decode less, at the cost of storing more of the keys in full.
+    pub fn with_restart_interval(mut self, restart_interval: usize) -> TableWriter {
+        self.restart_interval = restart_interval.max(1);
+        self
+    }
+
+    /// Sets the keys at which `flush_split` starts a new Table
+    ///
+    /// The split points must be in increasing order of the Comparator of the
+    /// MemTables flushed.
+    pub fn with_split_points(mut self, split_points: Vec<Vec<u8>>) -> TableWriter {
+        self.split_points = split_points;
+        self
+    }
+
+    pub fn split_points(&self) -> &[Vec<u8>] {
+        &self.split_points
+    }
+
+    /// Sets the MergeOperator folding merge records when Tables are
+    /// compacted
+    pub fn with_merge_operator(mut self, merge_operator: Arc<dyn MergeOperator>) -> TableWriter {
+        self.merge_operator = Some(merge_operator);
+        self
+    }
+
+    pub fn merge_operator(&self) -> Option<&Arc<dyn MergeOperator>> {
+        self.merge_operator.as_ref()
+    }
+
+    /// Sets how long after its deletion a tombstone is kept when Tables are
+    /// compacted, even if nothing older holds its key
+    pub fn with_tombstone_grace_period(mut self, tombstone_grace_ms: u128) -> TableWriter {
+        self.tombstone_grace_ms = tombstone_grace_ms;
+        self
+    }
+
+    pub fn tombstone_grace_ms(&self) -> u128 {
+        self.tombstone_grace_ms
+    }
+
+    /// Sets whether compactions drop the records whose TTL has run out
+    ///
+    /// Expired records are otherwise carried forward with their Value, for
+    /// the reads as of a Snapshot taken before they expired.
+    pub fn with_drop_expired(mut self, drop_expired: bool) -> TableWriter {
+        self.drop_expired = drop_expired;
+        self
+    }
+
+    pub fn drop_expired(&self) -> bool {
+        self.drop_expired
+    }
+
+    /// Sets the Clock telling the age of tombstones and whether records have
+    /// expired when Tables are compacted
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> TableWriter {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Writes the MemTable to a new Table at the given path
+    ///
+    /// Records are written in the order of the MemTable's Comparator, so the
+    /// Table must be read with the same Comparator. Records that have expired
+    /// by the MemTable's Clock are written as tombstones, keeping their
+    /// timestamp so they still shadow older Tables.
+    ///
+    /// The Table is written to a temporary file next to `path`, which is only
+    /// renamed to `path` once the Table is complete and synced to the disk.
+    /// If writing fails, for example because the disk is full, the temporary
+    /// file is deleted and nothing appears at `path`.
+    pub fn flush<C: Comparator, V: AsRef<[u8]> + Clone>(
+        &self,
+        mem_table: &MemTable<C, V>,
+        path: &Path,
+    ) -> Result<()> {
+        let now_ms = mem_table.clock().now_ms();
+        let mut builder = TableBuilder::new(self, path, mem_table.len())?;
+        add_entries(mem_table.entries(), now_ms, &mut builder)?;
+        for tombstone in mem_table.range_tombstones() {
+            builder.add_range_tombstone(tombstone);
+        }
+        builder.finish()
+    }
+
+    /// Writes the MemTable to one new Table per range between the split
+    /// points, and returns the paths of the Tables
+    ///
+    /// The Table of the `i`th range is written at `path(i)`, holding the keys
+    /// from the split point before it included to the one after it excluded.
+    /// So the Tables don't overlap, and Range Tombstones are cut at the split
+    /// points. Ranges without records or Range Tombstones are skipped, and
+    /// without split points this writes a single Table like `flush`.
+    ///
+    /// If writing any of the Tables fails, the ones already written are
+    /// deleted.
+    pub fn flush_split<C: Comparator, V: AsRef<[u8]> + Clone>(
+        &self,
+        mem_table: &MemTable<C, V>,
+        mut path: impl FnMut(usize) -> PathBuf,
+    ) -> Result<Vec<PathBuf>> {
+        let comparator = mem_table.comparator();
+        let now_ms = mem_table.clock().now_ms();
+        let mut written = Vec::new();
+        let mut rest = mem_table.entries();
+
+        for i in 0..=self.split_points.len() {
+            let start = i.checked_sub(1).map(|j| self.split_points[j].as_slice());
+            let end = self.split_points.get(i).map(Vec::as_slice);
+            let len = end.map_or(rest.len(), |end| {
+                rest.partition_point(|e| comparator.compare(&e.key, end).is_lt())
+            });
+            let (entries, tail) = rest.split_at(len);
+            rest = tail;
+            let tombstones: Vec<RangeTombstone> = mem_table
+                .range_tombstones()
+                .iter()
+                .filter_map(|t| clip_range_tombstone(comparator, t, start, end))
+                .collect();
+            if entries.is_empty() && tombstones.is_empty() {
+                continue;
+            }
+
+            let table_path = path(i);
+            let result =
+                TableBuilder::new(self, &table_path, entries.len()).and_then(|mut builder| {
+                    add_entries(entries, now_ms, &mut builder)?;
+                    for tombstone in &tombstones {
+                        builder.add_range_tombstone(tombstone);
+                    }
+                    builder.finish()
+                });
+            if let Err(e) = result {
+                for path in written {
+                    let _ = fs::remove_file(path);
+                }
+                return Err(e);
+            }
+            written.push(table_path);
+        }
+
+        Ok(written)
+    }
+
+    /// Writes the MemTable as a Table to the writer, such as a `Vec<u8>` to
+    /// hold the Table in memory, and returns the writer
+    ///
+    /// The Table is the one `flush` would write, and can be read with
+    /// [`TableReader::from_source`].
+    pub fn write_to<C: Comparator, V: AsRef<[u8]> + Clone, W: Write>(
+        &self,
+        mem_table: &MemTable<C, V>,
+        out: W,
+    ) -> Result<W> {
+        let now_ms = mem_table.clock().now_ms();
+        let mut builder = TableBuilder::from_writer(self, out, mem_table.len());
+        add_entries(mem_table.entries(), now_ms, &mut builder)?;
+        for tombstone in mem_table.range_tombstones() {
+            builder.add_range_tombstone(tombstone);
+        }
+        builder.finish_into()
+    }
+
+    /// Writes a frozen MemTable to a new Table at the given path
+    ///
+    /// This behaves like `flush`.
+    pub fn flush_immutable<C: Comparator, V: AsRef<[u8]> + Clone>(
+        &self,
+        mem_table: &ImmutableMemTable<C, V>,
+        path: &Path,
+    ) -> Result<()> {
+        self.flush(mem_table.mem_table(), path)
+    }
+}
+
+/// Adds the records to the TableBuilder, turning the ones expired at
+/// `now_ms` into tombstones
+fn add_entries<V: AsRef<[u8]> + Clone, W: Write>(
+    entries: &[MemTableEntry<V>],
+    now_ms: u128,
+    builder: &mut TableBuilder<W>,
+) -> Result<()> {
+    for entry in entries {
+        if entry.is_expired(now_ms) {
+            builder.add(&MemTableEntry::<V> {
+                key: entry.key.clone(),
+                value: None,
+                timestamp_ms: entry.timestamp_ms,
+                is_deleted: true,
+                is_merge: false,
+                expires_at_ms: None,
+            })?;
+        } else {
+            builder.add(entry)?;
+        }
+    }
+    Ok(())
+}
+
+/// Cuts a Range Tombstone down to the keys from `start` included to `end`
+/// excluded, where None is unbounded, or returns None if nothing is left
+fn clip_range_tombstone<C: Comparator>(
+    comparator: &C,
+    tombstone: &RangeTombstone,
+    start: Option<&[u8]>,
+    end: Option<&[u8]>,
+) -> Option<RangeTombstone> {
+    let start = match start {
+        Some(start) if comparator.compare(start, &tombstone.start).is_gt() => start,
+        _ => &tombstone.start,
+    };
+    let end = match end {
+        Some(end) if comparator.compare(end, &tombstone.end).is_lt() => end,
+        _ => &tombstone.end,
+    };
+    comparator
+        .compare(start, end)
+        .is_lt()
+        .then(|| RangeTombstone {
+            start: start.to_vec(),
+            end: end.to_vec(),
+            timestamp_ms: tombstone.timestamp_ms,
+        })
+}
+
+/// TempFile deletes a file being written when dropped, unless it was renamed
+/// into place with `persist`
+struct TempFile {
+    path: PathBuf,
+    target: PathBuf,
+    persisted: bool,
+}
+
+impl TempFile {
+    fn persist(mut self) -> Result<()> {
+        fs::rename(&self.path, &self.target)?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// TableBuilder streams records to a new Table, which lets Tables larger than
+/// the memory be written
+///
+/// Only the block index and the BloomFilter are held in memory until the
+/// Table is finished. Records must be added in sorted key order.
+pub(crate) struct TableBuilder<W: Write = File> {
+    out: BufWriter<W>,
+    /// Temporary file the Table is written to, for Tables created at a path
+    tmp: Option<TempFile>,
+    block_size: usize,
+    compression: Compression,
+    large_value_threshold: usize,
+    restart_interval: usize,
+    block: Vec<u8>,
+    /// Number of records in the current block
+    block_len: usize,
+    /// Offsets of the restart points of the current block
+    restarts: Vec<u32>,
+    first_key: Vec<u8>,
+    prev_key: Vec<u8>,
+    value_runs: bool,
+    /// Value of the previous record of the current block, with value runs
+    /// and if the record holds it inline
+    prev_value: Option<Vec<u8>>,
+    offset: u64,
+    len: usize,
+    index: Vec<u8>,
+    bloom: BloomFilter,
+    /// BloomFilter of the prefixes of the keys, and the PrefixExtractor
+    /// picking them
+    prefix_bloom: Option<(BloomFilter, Arc<dyn PrefixExtractor>)>,
+    range_tombstones: Vec<u8>,
+    profile: Profile,
+}
+
+impl TableBuilder {
+    /// Creates a new Table at the given path, sizing its BloomFilter for
+    /// `expected_len` records
+    ///
+    /// The Table only appears at `path` once it is finished. Until then it is
+    /// written to a temporary file, which is deleted if the TableBuilder is
+    /// dropped or fails to finish.
+    pub(crate) fn new(
+        options: &TableWriter,
+        path: &Path,
+        expected_len: usize,
+    ) -> Result<TableBuilder> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp = TempFile {
+            path: PathBuf::from(tmp_path),
+            target: path.to_owned(),
+            persisted: false,
+        };
+
+        let mut builder =
+            TableBuilder::from_writer(options, File::create(&tmp.path)?, expected_len);
+        builder.tmp = Some(tmp);
+        Ok(builder)
+    }
+
+    /// Writes the block index, the BloomFilter, the Range Tombstones and the
+    /// footer, syncs the Table to the disk and renames it into place
+    pub(crate) fn finish(mut self) -> Result<()> {
+        let tmp = self.tmp.take();
+        let file = self.finish_into()?;
+        file.sync_all()?;
+        if let Some(tmp) = tmp {
+            tmp.persist()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> TableBuilder<W> {
+    /// Creates a new Table written to `out`, sizing its BloomFilter for
+    /// `expected_len` records
+    pub(crate) fn from_writer(
+        options: &TableWriter,
+        out: W,
+        expected_len: usize,
+    ) -> TableBuilder<W> {
+        TableBuilder {
+            out: BufWriter::new(out),
+            tmp: None,
+            block_size: options.block_size,
+            compression: options.compression,
+            large_value_threshold: options.large_value_threshold,
+            restart_interval: options.restart_interval,
+            block: Vec::new(),
+            block_len: 0,
+            restarts: Vec::new(),
+            first_key: Vec::new(),
+            prev_key: Vec::new(),
+            value_runs: options.value_runs,
+            prev_value: None,
+            offset: 0,
+            len: 0,
+            index: Vec::new(),
+            bloom: BloomFilter::new(expected_len, options.bloom_fp_rate),
+            prefix_bloom: options.prefix_extractor.as_ref().map(|extractor| {
+                let bloom = BloomFilter::new(expected_len, options.bloom_fp_rate);
+                (bloom, Arc::clone(extractor))
+            }),
+            range_tombstones: Vec::new(),
+            profile: Profile::default(),
+        }
+    }
+
+    /// Appends a record to the Table
+    ///
+    /// Several versions of a key must be added from the newest to the oldest.
+    /// They are kept in the same block, so a lookup finds them all in the
+    /// block that the index leads to.
+    pub(crate) fn add<V: AsRef<[u8]>>(&mut self, entry: &MemTableEntry<V>) -> Result<()> {
+        self.bloom.insert(&entry.key);
+        if let Some((bloom, extractor)) = &mut self.prefix_bloom {
+            if extractor.in_domain(&entry.key) {
+                bloom.insert(extractor.extract(&entry.key));
+            }
+        }
+        let value_len = entry.value.as_ref().map_or(0, |v| v.as_ref().len());
+        self.profile
+            .add(entry.key.len(), (!entry.is_deleted).then_some(value_len));
+
+        let header_len = 1 + 8 + self.first_key.len();
+        let trailer_len = 4 * (self.restarts.len() + 1);
+        if !self.block.is_empty()
+            && header_len + self.block.len() + trailer_len >= self.block_size
+            && entry.key != self.prev_key
+        {
+            self.write_block()?;
+        }
+        if self.block.is_empty() {
+            self.first_key.clone_from(&entry.key);
+        }
+        let is_restart = self.block_len.is_multiple_of(self.restart_interval);
+        if is_restart {
+            self.restarts.push(self.block.len() as u32);
+            self.prev_key.clear();
+        }
+
+        let value = entry.value.as_ref().map(AsRef::as_ref);
+        match value {
+            Some(value) if !entry.is_deleted && value.len() > self.large_value_threshold => {
+                self.prev_value = None;
+                let blob = self.write_blob(value)?;
+                let pointer = MemTableEntry {
+                    key: entry.key.clone(),
+                    value: Some(blob.encode().to_vec()),
+                    timestamp_ms: entry.timestamp_ms,
+                    is_deleted: false,
+                    is_merge: entry.is_merge,
+                    expires_at_ms: entry.expires_at_ms,
+                };
+                encode_record(&pointer, &self.prev_key, true, false, &mut self.block);
+            }
+            _ => {
+                let value = value.filter(|_| !entry.is_deleted);
+                let shares_value =
+                    !is_restart && value.is_some() && self.prev_value.as_deref() == value;
+                encode_record(entry, &self.prev_key, false, shares_value, &mut self.block);
+                if self.value_runs && !shares_value {
+                    self.prev_value = value.map(<[u8]>::to_vec);
+                }
+            }
+        }
+        self.prev_key.clone_from(&entry.key);
+        self.block_len += 1;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Adds a Range Tombstone to the Table
+    ///
+    /// Range Tombstones are kept in memory and written after the
+    /// BloomFilter when the Table is finished.
+    pub(crate) fn add_range_tombstone(&mut self, tombstone: &RangeTombstone) {
+        let out = &mut self.range_tombstones;
+        out.extend_from_slice(&(tombstone.start.len() as u64).to_le_bytes());
+        out.extend_from_slice(&tombstone.start);
+        out.extend_from_slice(&(tombstone.end.len() as u64).to_le_bytes());
+        out.extend_from_slice(&tombstone.end);
+        out.extend_from_slice(&tombstone.timestamp_ms.to_le_bytes());
+    }
+
+    /// Writes a Value to the blob area, right after whatever was written last
+    fn write_blob(&mut self, value: &[u8]) -> Result<BlobPointer> {
+        let blob = BlobPointer {
+            offset: self.offset,
+            len: value.len() as u64,
+        };
+        self.out.write_all(value)?;
+        self.out.write_all(&crc32c::crc32c(value).to_le_bytes())?;
+        self.offset += value.len() as u64 + 4;
+        Ok(blob)
+    }
+
+    /// Writes the current block, compressing its records and restart points
+    /// unless that saves less than an eighth of their size
+    fn write_block(&mut self) -> Result<()> {
+        for restart in &self.restarts {
+            self.block.extend_from_slice(&restart.to_le_bytes());
+        }
+        self.block
+            .extend_from_slice(&(self.restarts.len() as u32).to_le_bytes());
+
+        let compressed = self.compression.compress(&self.block);
+        let (compression, records) = if compressed.len() < self.block.len() - self.block.len() / 8 {
+            (self.compression, compressed.as_slice())
+        } else {
+            (Compression::None, self.block.as_slice())
+        };
+
+        let len = (1 + 8 + self.first_key.len() + records.len()) as u64;
+        self.index
+            .extend_from_slice(&(self.first_key.len() as u64).to_le_bytes());
+        self.index.extend_from_slice(&self.first_key);
+        self.index.extend_from_slice(&self.offset.to_le_bytes());
+        self.index.extend_from_slice(&len.to_le_bytes());
+
+        self.out.write_all(&[compression.id()])?;
+        self.out
+            .write_all(&(self.first_key.len() as u64).to_le_bytes())?;
+        self.out.write_all(&self.first_key)?;
+        self.out.write_all(records)?;
+
+        self.offset += len;
+        self.block.clear();
+        self.block_len = 0;
+        self.restarts.clear();
+        Ok(())
+    }
+
+    /// Writes the block index, the BloomFilter, the Range Tombstones, the
+    /// Profile and the footer, and returns the writer once everything has
+    /// been flushed to it
+    pub(crate) fn finish_into(mut self) -> Result<W> {
+        if !self.block.is_empty() {
+            self.write_block()?;
+        }
+
+        let index_offset = self.offset;
+        let bloom_offset = index_offset + self.index.len() as u64;
+
+        let mut meta = self.index;
+        self.bloom.encode(&mut meta);
+        if let Some((prefix_bloom, _)) = &self.prefix_bloom {
+            prefix_bloom.encode(&mut meta);
+        }
+        let range_offset = index_offset + meta.len() as u64;
+        meta.extend_from_slice(&self.range_tombstones);
+        let profile_offset = index_offset + meta.len() as u64;
+        encode_profile(&self.profile, &mut meta);
+        meta.extend_from_slice(&index_offset.to_le_bytes());
+        meta.extend_from_slice(&bloom_offset.to_le_bytes());
+        meta.extend_from_slice(&range_offset.to_le_bytes());
+        meta.extend_from_slice(&profile_offset.to_le_bytes());
+        meta.extend_from_slice(&(self.len as u64).to_le_bytes());
+        let crc = crc32c::crc32c(&meta);
+        meta.extend_from_slice(&crc.to_le_bytes());
+
+        self.out.write_all(&meta)?;
+        self.out.into_inner().map_err(|e| e.into_error().into())
+    }
+}
+
+/// A block read from a Table, with its records decompressed
+struct Block {
+    buf: Arc<[u8]>,
+    header_len: usize,
+    /// Records and restart points of a compressed block
+    decompressed: Option<Vec<u8>>,
+    /// Length of the records, up to the restart points
+    records_len: usize,
+    restarts: Vec<u32>,
+    /// Offset of the block in the Table
+    start: u64,
+    /// Offset of the records in the Table, for uncompressed blocks
+    records_offset: Option<u64>,
+}
+
+impl Block {
+    /// Returns the records followed by the restart points
+    fn data(&self) -> &[u8] {
+        match &self.decompressed {
+            Some(data) => data,
+            None => &self.buf[self.header_len..],
+        }
+    }
+
+    fn records(&self) -> &[u8] {
+        &self.data()[..self.records_len]
+    }
+
+    /// Decodes the record at `pos` within the records, restoring its key
+    /// from `prev_key` and a shared Value from `prev_value`, and moves `pos`
+    /// past it
+    ///
+    /// A record failing its checksum results in [`Error::Corruption`].
+    fn decode_at(
+        &self,
+        pos: &mut usize,
+        prev_key: &[u8],
+        prev_value: Option<&[u8]>,
+        with_value: bool,
+    ) -> Result<(MemTableEntry, Option<BlobPointer>)> {
+        let start = *pos;
+        decode_record(self.records(), pos, prev_key, prev_value, with_value).map_err(|_| {
+            Error::Corruption {
+                offset: self.records_offset.map_or(self.start, |o| o + start as u64),
+            }
+        })
+    }
+
+    /// Decodes the records from the `restart`th restart point to the end of
+    /// the block, stopping after the first error
+    fn decode_from<'a>(
+        &'a self,
+        restart: usize,
+        with_values: bool,
+    ) -> impl Iterator<Item = Result<(MemTableEntry, Option<BlobPointer>)>> + 'a {
+        let mut pos = self.restarts[restart] as usize;
+        let mut next_restart = restart;
+        let mut prev_key = Vec::new();
+        let mut prev_value = None;
+        std::iter::from_fn(move || {
+            if pos >= self.records_len {
+                return None;
+            }
+            let is_restart = self.restarts.get(next_restart) == Some(&(pos as u32));
+            if is_restart {
+                next_restart += 1;
+                prev_value = None;
+            }
+            let shared_with = if is_restart { &[] } else { prev_key.as_slice() };
+            let record = self.decode_at(&mut pos, shared_with, prev_value.as_deref(), with_values);
+            match &record {
+                Ok((entry, _)) => {
+                    prev_key.clone_from(&entry.key);
+                    prev_value.clone_from(&entry.value);
+                }
+                Err(_) => pos = self.records_len,
+            }
+            Some(record)
+        })
+    }
+}
+
+/// An entry of the block index, pointing at a block
+struct IndexEntry {
+    key: Vec<u8>,
+    offset: u64,
+    len: u64,
+}
+
+/// Location of a Value stored in the blob area of a Table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BlobPointer {
+    offset: u64,
+    len: u64,
+}
+
+impl BlobPointer {
+    fn encode(&self) -> [u8; 16] {
+        let mut buf = [0; 16];
+        buf[..8].copy_from_slice(&self.offset.to_le_bytes());
+        buf[8..].copy_from_slice(&self.len.to_le_bytes());
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> Option<BlobPointer> {
+        let (offset, rest) = split_u64(buf)?;
+        let (len, rest) = split_u64(rest)?;
+        rest.is_empty().then_some(BlobPointer { offset, len })
+    }
+}
+
+/// TableReader serves point lookups from a Table written by the TableWriter
+///
+/// The block index and BloomFilter are loaded into memory when the Table is
+/// opened, so a lookup only reads the one block that may contain the key, and
+/// lookups of keys rejected by the BloomFilter don't read at all. With a
+/// BlockCache, blocks are looked up in the cache before they are read from
+/// the disk.
+///
+/// The Comparator must be the one of the MemTable the Table was written from.
+pub struct TableReader<C = BytewiseComparator> {
+    /// Id of the Table in the BlockCache
+    id: u64,
+    /// Path of the Table's file, empty for a Table read from another source
+    path: PathBuf,
+    file: Mutex<Box<dyn TableSource>>,
+    cache: Option<Arc<BlockCache>>,
+    index: Vec<IndexEntry>,
+    bloom: BloomFilter,
+    /// BloomFilter of the prefixes of the keys, if the Table was written
+    /// with a PrefixExtractor
+    prefix_bloom: Option<BloomFilter>,
+    range_tombstones: Vec<RangeTombstone>,
+    profile: Profile,
+    index_offset: u64,
+    len: usize,
+    file_size: u64,
+    /// Largest key of the Table, known once the last block has been read
+    last_key: OnceLock<Vec<u8>>,
+    comparator: C,
+}
+
+impl TableReader {
+    /// Opens the Table at the given path and loads its block index and
+    /// BloomFilter
+    pub fn open(path: &Path) -> Result<TableReader> {
+        TableReader::open_with_comparator(path, BytewiseComparator)
+    }
+
+    /// Reads the Table from the source and loads its block index and
+    /// BloomFilter
+    ///
+    /// The Table has no path, so it can't be part of a Database.
+    pub fn from_source(source: impl TableSource + 'static) -> Result<TableReader> {
+        TableReader::from_source_with_comparator(source, BytewiseComparator)
+    }
+}
+
+impl<C: Comparator> TableReader<C> {
+    /// Opens the Table at the given path, whose keys are ordered by the given
+    /// Comparator
+    pub fn open_with_comparator(path: &Path, comparator: C) -> Result<TableReader<C>> {
+        let mut table = TableReader::from_source_with_comparator(File::open(path)?, comparator)?;
+        table.path = path.to_owned();
+        Ok(table)
+    }
+
+    /// Reads the Table from the source, whose keys are ordered by the given
+    /// Comparator
+    pub fn from_source_with_comparator(
+        source: impl TableSource + 'static,
+        comparator: C,
+    ) -> Result<TableReader<C>> {
+        let mut file: Box<dyn TableSource> = Box::new(source);
+
+        let file_len = file.seek(SeekFrom::End(0))?;
+        if file_len < FOOTER_SIZE {
+            return Err(Error::Corruption { offset: 0 });
+        }
+        let footer_offset = file_len - FOOTER_SIZE;
+
+        let mut footer = [0; FOOTER_SIZE as usize];
+        file.seek(SeekFrom::Start(footer_offset))?;
+        file.read_exact(&mut footer)?;
+        let index_offset = u64::from_le_bytes(footer[0..8].try_into().unwrap());
+        let bloom_offset = u64::from_le_bytes(footer[8..16].try_into().unwrap());
+        let range_offset = u64::from_le_bytes(footer[16..24].try_into().unwrap());
+        let profile_offset = u64::from_le_bytes(footer[24..32].try_into().unwrap());
+        let len = u64::from_le_bytes(footer[32..40].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(footer[40..44].try_into().unwrap());
+
+        if index_offset > bloom_offset
+            || bloom_offset > range_offset
+            || range_offset > profile_offset
+            || profile_offset > footer_offset
+        {
+            return Err(Error::Corruption {
+                offset: footer_offset,
+            });
+        }
+
+        let mut buf = vec![0; (footer_offset - index_offset) as usize];
+        file.seek(SeekFrom::Start(index_offset))?;
+        file.read_exact(&mut buf)?;
+
+        let expected = crc32c::crc32c_append(crc32c::crc32c(&buf), &footer[..40]);
+        if crc != expected {
+            return Err(Error::Corruption {
+                offset: index_offset,
+            });
+        }
+
+        let (index_buf, rest) = buf.split_at((bloom_offset - index_offset) as usize);
+        let (bloom_buf, rest) = rest.split_at((range_offset - bloom_offset) as usize);
+        let (range_buf, profile_buf) = rest.split_at((profile_offset - range_offset) as usize);
+        let bloom_corruption = || Error::Corruption {
+            offset: bloom_offset,
+        };
+        let bloom_len = bloom::encoded_len(bloom_buf)
+            .filter(|&len| len <= bloom_buf.len())
+            .ok_or_else(bloom_corruption)?;
+        let (bloom_buf, prefix_bloom_buf) = bloom_buf.split_at(bloom_len);
+        let bloom = BloomFilter::decode(bloom_buf).map_err(|_| bloom_corruption())?;
+        let prefix_bloom = match prefix_bloom_buf.is_empty() {
+            true => None,
+            false => Some(BloomFilter::decode(prefix_bloom_buf).map_err(|_| bloom_corruption())?),
+        };
+        let range_tombstones = decode_range_tombstones(range_buf).ok_or(Error::Corruption {
+            offset: range_offset,
+        })?;
+        let profile = decode_profile(profile_buf).ok_or(Error::Corruption {
+            offset: profile_offset,
+        })?;
+
+        let mut index = Vec::new();
+        let mut rest = index_buf;
+        while !rest.is_empty() {
+            let corruption = || Error::Corruption {
+                offset: index_offset + (index_buf.len() - rest.len()) as u64,
+            };
+
+            let (key_len, tail) = split_u64(rest).ok_or_else(corruption)?;
+            let key = tail.get(..key_len as usize).ok_or_else(corruption)?;
+            let (offset, tail) = split_u64(&tail[key.len()..]).ok_or_else(corruption)?;
+            let (len, tail) = split_u64(tail).ok_or_else(corruption)?;
+            if offset.checked_add(len).is_none_or(|end| end > index_offset) {
+                return Err(corruption());
+            }
+
+            index.push(IndexEntry {
+                key: key.to_vec(),
+                offset,
+                len,
+            });
+            rest = tail;
+        }
+
+        Ok(TableReader {
+            id: NEXT_TABLE_ID.fetch_add(1, atomic::Ordering::Relaxed),
+            path: PathBuf::new(),
+            file: Mutex::new(file),
+            cache: None,
+            index,
+            bloom,
+            prefix_bloom,
+            range_tombstones,
+            profile,
+            index_offset,
+            len,
+            file_size: file_len,
+            last_key: OnceLock::new(),
+            comparator,
+        })
+    }
+
+    /// Caches the blocks read from the Table in the given BlockCache, which
+    /// may be shared with other Tables
+    pub fn with_block_cache(mut self, cache: Arc<BlockCache>) -> TableReader<C> {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Gets a record from the Table
+    ///
+    /// Tombstones are returned as well, so that deletions can be resolved
+    /// against older Tables. If no record with the same key exists in the
+    /// Table, return None. A record failing its checksum results in
+    /// [`Error::Corruption`].
+    pub fn get(&self, key: &[u8]) -> Result<Option<MemTableEntry>> {
+        self.get_as_of(key, u128::MAX)
+    }
+
+    /// Gets the newest record of a key written at or before `timestamp_ms`
+    ///
+    /// Tables flushed from a MemTable created `with_versions` hold several
+    /// versions of a key, older versions are only found in those.
+    pub fn get_as_of(&self, key: &[u8], timestamp_ms: u128) -> Result<Option<MemTableEntry>> {
+        if !self.bloom.contains(key) {
+            return Ok(None);
+        }
+        self.get_as_of_with(key, timestamp_ms, true)
+    }
+
+    /// Gets the newest record of a key like `get_as_of`, leaving its Value
+    /// out unless `with_value` is set
+    ///
+    /// Without the Value, this is enough to tell a live record from a
+    /// tombstone without copying the Value out of its block. The BloomFilter
+    /// is not checked, that is up to the caller.
+    pub(crate) fn get_as_of_with(
+        &self,
+        key: &[u8],
+        timestamp_ms: u128,
+        with_value: bool,
+    ) -> Result<Option<MemTableEntry>> {
+        // Find the last index entry with a key less than or equal to the key.
+        let idx = match self.find_index(key) {
+            Ok(idx) => idx,
+            Err(0) => return Ok(None),
+            Err(idx) => idx - 1,
+        };
+        let block = self.read_block(idx)?;
+
+        // Find the last restart point with a key less than the key, the
+        // records of the key may start before a restart point holding it.
+        let (mut lo, mut hi) = (0, block.restarts.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let mut pos = block.restarts[mid] as usize;
+            let (entry, _) = block.decode_at(&mut pos, &[], None, false)?;
+            if self.comparator.compare(&entry.key, key).is_lt() {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        for record in block.decode_from(lo.saturating_sub(1), with_value) {
+            let (entry, blob) = record?;
+            match self.comparator.compare(&entry.key, key) {
+                Ordering::Less => continue,
+                Ordering::Equal if entry.timestamp_ms <= timestamp_ms => {
+                    return self.resolve_blob(entry, blob).map(Some)
+                }
+                Ordering::Equal => continue,
+                Ordering::Greater => break,
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Iterates over every record in the Table in sorted key order
+    ///
+    /// Records are read from the disk one block at a time, so the Table is
+    /// never loaded into memory as a whole.
+    pub fn iter(&self) -> TableIter<'_, C> {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Iterates over the records with keys between the given bounds in
+    /// sorted key order
+    ///
+    /// The block index is used to start reading at the block that may contain
+    /// the start bound.
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> TableIter<'_, C> {
+        self.range_with(start, end, true)
+    }
+
+    /// Iterates over the keys of the records between the given bounds that
+    /// are not tombstones, in sorted key order
+    ///
+    /// Values are skipped over while the blocks are decoded rather than
+    /// copied. They are still read from the disk as part of their block,
+    /// since the checksum of each record covers its Value.
+    pub fn range_keys(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> impl Iterator<Item = Result<Vec<u8>>> + '_ {
+        let mut prev_key: Option<Vec<u8>> = None;
+        self.range_with(start, end, false)
+            .filter_map(move |entry| match entry {
+                Ok(entry) => {
+                    // Only the newest version of a key decides whether it is
+                    // live.
+                    let is_newest = prev_key.as_ref() != Some(&entry.key);
+                    prev_key = Some(entry.key.clone());
+                    (is_newest && !entry.is_deleted).then_some(Ok(entry.key))
+                }
+                Err(e) => Some(Err(e)),
+            })
+    }
+
+    fn range_with(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        with_values: bool,
+    ) -> TableIter<'_, C> {
+        let next_block = match start {
+            Bound::Included(key) | Bound::Excluded(key) => match self.find_index(key) {
+                Ok(idx) => idx,
+                Err(idx) => idx.saturating_sub(1),
+            },
+            Bound::Unbounded => 0,
+        };
+        let back_block = match end {
+            Bound::Included(key) | Bound::Excluded(key) => match self.find_index(key) {
+                Ok(idx) => idx + 1,
+                Err(idx) => idx,
+            },
+            Bound::Unbounded => self.index.len(),
+        };
+
+        TableIter {
+            reader: self,
+            next_block,
+            back_block: back_block.max(next_block),
+            entries: Vec::new().into_iter(),
+            back_entries: Vec::new().into_iter(),
+            start: start.map(|k| k.to_vec()),
+            end: end.map(|k| k.to_vec()),
+            with_values,
+        }
+    }
+
+    /// Estimates the offset in the Table's file at which the records of the
+    /// key start
+    ///
+    /// This is based on the block index only, so nothing is read from the
+    /// disk. A key that falls inside a block is placed halfway through it, so
+    /// the estimate is off by at most half a block. Keys before the first
+    /// block map to 0.
+    pub fn approximate_offset(&self, key: &[u8]) -> u64 {
+        let idx = match self.find_index(key) {
+            Ok(idx) => return self.index[idx].offset,
+            Err(0) => return 0,
+            Err(idx) => idx - 1,
+        };
+
+        let start = self.index[idx].offset;
+        let end = self
+            .index
+            .get(idx + 1)
+            .map_or(self.index_offset, |e| e.offset);
+        start + (end - start) / 2
+    }
+
+    /// Performs Binary Search to find a key in the block index
+    fn find_index(&self, key: &[u8]) -> std::result::Result<usize, usize> {
+        self.index
+            .binary_search_by(|e| self.comparator.compare(&e.key, key))
+    }
+
+    /// Reads and decodes the block of the given index entry, copying the
+    /// Values of its records only if `with_values` is set
+    ///
+    /// Values stored in the blob area are not read, their records come with
+    /// the BlobPointer to pass to `resolve_blob` instead. A record failing
+    /// its checksum results in [`Error::Corruption`].
+    fn read_block_with(
+        &self,
+        idx: usize,
+        with_values: bool,
+    ) -> Result<Vec<(MemTableEntry, Option<BlobPointer>)>> {
+        let block = self.read_block(idx)?;
+        let entries = block
+            .decode_from(0, with_values)
+            .collect::<Result<Vec<_>>>()?;
+
+        if idx == self.index.len() - 1 {
+            if let Some((last, _)) = entries.last() {
+                self.last_key.get_or_init(|| last.key.clone());
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Reads the block of the given index entry, decompressing its records
+    /// and splitting off its restart points
+    ///
+    /// A block whose first key differs from its index entry results in
+    /// [`Error::Corruption`], as do malformed restart points.
+    fn read_block(&self, idx: usize) -> Result<Block> {
+        let start = self.index[idx].offset;
+        let buf = self.read_block_bytes(idx)?;
+
+        let corruption = || Error::Corruption { offset: start };
+        let (&codec, header) = buf.split_first().ok_or_else(corruption)?;
+        let (key_len, rest) = split_u64(header).ok_or_else(corruption)?;
+        let first_key = rest.get(..key_len as usize);
+        if first_key != Some(self.index[idx].key.as_slice()) {
+            return Err(corruption());
+        }
+        let header_len = 1 + 8 + key_len as usize;
+
+        // Offsets of corrupted records are only known for uncompressed blocks,
+        // otherwise the block itself is reported.
+        let compression = Compression::from_id(codec).ok_or_else(corruption)?;
+        let (decompressed, records_offset) = match compression {
+            Compression::None => (None, Some(start + header_len as u64)),
+            #[allow(unreachable_patterns)]
+            compression => {
+                let records = compression
+                    .decompress(&buf[header_len..])
+                    .ok_or_else(corruption)?;
+                (Some(records), None)
+            }
+        };
+        let mut block = Block {
+            buf,
+            header_len,
+            decompressed,
+            records_len: 0,
+            restarts: Vec::new(),
+            start,
+            records_offset,
+        };
+
+        let data = block.data();
+        let count = data
+            .len()
+            .checked_sub(4)
+            .map(|pos| u32::from_le_bytes(data[pos..].try_into().unwrap()) as usize)
+            .ok_or_else(corruption)?;
+        let records_len = (count + 1)
+            .checked_mul(4)
+            .and_then(|trailer_len| data.len().checked_sub(trailer_len))
+            .ok_or_else(corruption)?;
+        let restarts: Vec<u32> = data[records_len..data.len() - 4]
+            .chunks_exact(4)
+            .map(|offset| u32::from_le_bytes(offset.try_into().unwrap()))
+            .collect();
+        let well_formed = restarts.first() == Some(&0)
+            && restarts.windows(2).all(|w| w[0] < w[1])
+            && restarts
+                .last()
+                .is_some_and(|&last| (last as usize) < records_len);
+        if !well_formed {
+            return Err(corruption());
+        }
+
+        block.records_len = records_len;
+        block.restarts = restarts;
+        Ok(block)
+    }
+
+    /// Reads the bytes of the block of the given index entry, from the
+    /// BlockCache if it holds them and from the disk otherwise
+    ///
+    /// Blocks read from the disk are added to the BlockCache.
+    fn read_block_bytes(&self, idx: usize) -> Result<Arc<[u8]>> {
+        let start = self.index[idx].offset;
+        if let Some(block) = self.cache.as_ref().and_then(|c| c.get(self.id, start)) {
+            return Ok(block);
+        }
+
+        let mut buf = vec![0; self.index[idx].len as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut buf)?;
+        }
+
+        let block: Arc<[u8]> = buf.into();
+        if let Some(cache) = &self.cache {
+            cache.insert(self.id, start, Arc::clone(&block));
+        }
+        Ok(block)
+    }
+
+    /// Reads the Value of a record from the blob area, if it is stored there
+    ///
+    /// A blob failing its checksum results in [`Error::Corruption`].
+    fn resolve_blob(
+        &self,
+        mut entry: MemTableEntry,
+        blob: Option<BlobPointer>,
+    ) -> Result<MemTableEntry> {
+        let Some(blob) = blob else {
+            return Ok(entry);
+        };
+
+        let corruption = || Error::Corruption {
+            offset: blob.offset,
+        };
+        let end = blob
+            .offset
+            .checked_add(blob.len)
+            .and_then(|end| end.checked_add(4))
+            .ok_or_else(corruption)?;
+        if end > self.index_offset {
+            return Err(corruption());
+        }
+
+        let mut buf = vec![0; (blob.len + 4) as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(blob.offset))?;
+            file.read_exact(&mut buf)?;
+        }
+        let crc = buf.split_off(blob.len as usize);
+        if crc32c::crc32c(&buf).to_le_bytes() != crc.as_slice() {
+            return Err(corruption());
+        }
+
+        entry.value = Some(buf);
+        Ok(entry)
+    }
+
+    /// Gets the smallest and the largest key of the Table
+    ///
+    /// Returns None for an empty Table. The largest key is read from the last
+    /// block the first time it is needed.
+    pub fn key_range(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let Some(first) = self.index.first() else {
+            return Ok(None);
+        };
+        Ok(Some((first.key.clone(), self.last_key()?.to_vec())))
+    }
+
+    /// Whether the key is between the smallest and the largest key of the
+    /// Table
+    ///
+    /// A key outside of them can't be in the Table, so there is no need to
+    /// read any of its blocks.
+    pub fn covers(&self, key: &[u8]) -> Result<bool> {
+        self.overlaps(Bound::Included(key), Bound::Included(key))
+    }
+
+    /// Whether any key between the given bounds is between the smallest and
+    /// the largest key of the Table
+    pub fn overlaps(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<bool> {
+        if !self.index.is_empty() {
+            self.last_key()?;
+        }
+        Ok(self.may_overlap(start, end))
+    }
+
+    /// Whether the Table may hold keys between the given bounds, without
+    /// reading anything
+    ///
+    /// Until the last block has been read, only the smallest key is compared
+    /// to the bounds.
+    pub(crate) fn may_overlap(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+        let Some(first) = self.index.first() else {
+            return false;
+        };
+        let before_end = match end {
+            Bound::Included(end) => self.comparator.compare(&first.key, end).is_le(),
+            Bound::Excluded(end) => self.comparator.compare(&first.key, end).is_lt(),
+            Bound::Unbounded => true,
+        };
+        let Some(last) = self.last_key.get() else {
+            return before_end;
+        };
+        before_end
+            && match start {
+                Bound::Included(start) => self.comparator.compare(start, last).is_le(),
+                Bound::Excluded(start) => self.comparator.compare(start, last).is_lt(),
+                Bound::Unbounded => true,
+            }
+    }
+
+    /// Reads the largest key from the last block, unless it was read before
+    ///
+    /// The Table must not be empty.
+    fn last_key(&self) -> Result<&[u8]> {
+        if let Some(key) = self.last_key.get() {
+            return Ok(key);
+        }
+        let idx = self.index.len() - 1;
+        self.read_block_with(idx, false)?;
+        self.last_key
+            .get()
+            .map(Vec::as_slice)
+            .ok_or(Error::Corruption {
+                offset: self.index[idx].offset,
+            })
+    }
+
+    /// Reads every block and blob of the Table and returns the problems
+    /// found
+    ///
+    /// Besides the checksums, this checks that the blocks don't overlap, that
+    /// the keys are in order, with the versions of a key from the newest to
+    /// the oldest, and that the footer's record count matches the records.
+    pub fn verify(&self) -> Vec<ProblemKind> {
+        let mut problems = Vec::new();
+        // Key and timestamp of the previous record
+        let mut prev: Option<(Vec<u8>, u128)> = None;
+        let mut found = 0;
+        let mut complete = true;
+        let mut block_end = 0;
+
+        for (idx, index_entry) in self.index.iter().enumerate() {
+            let offset = index_entry.offset;
+            if offset < block_end {
+                problems.push(ProblemKind::Corruption { offset });
+            }
+            block_end = offset + index_entry.len;
+
+            let records = match self.read_block_with(idx, true) {
+                Ok(records) => records,
+                Err(e) => {
+                    problems.push(ProblemKind::from(e));
+                    complete = false;
+                    continue;
+                }
+            };
+            found += records.len();
+
+            for (entry, blob) in records {
+                let in_order = prev.as_ref().is_none_or(|(key, timestamp_ms)| {
+                    match self.comparator.compare(key, &entry.key) {
+                        Ordering::Less => true,
+                        Ordering::Equal => *timestamp_ms > entry.timestamp_ms,
+                        Ordering::Greater => false,
+                    }
+                });
+                if !in_order {
+                    problems.push(ProblemKind::Misordered {
+                        offset,
+                        key: entry.key.clone(),
+                    });
+                }
+                prev = Some((entry.key.clone(), entry.timestamp_ms));
+                if let Err(e) = self.resolve_blob(entry, blob) {
+                    problems.push(ProblemKind::from(e));
+                }
+            }
+        }
+
+        if complete && found != self.len {
+            problems.push(ProblemKind::CountMismatch {
+                expected: self.len,
+                found,
+            });
+        }
+        problems
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the Profile of the records of the Table, written along with
+    /// them so it is known without reading them
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    /// Gets the size of the Table in bytes
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Iterates over the first key of every block, in sorted key order
+    #[cfg(feature = "parallel-compaction")]
+    pub(crate) fn block_keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.index.iter().map(|e| e.key.as_slice())
+    }
+
+    pub fn range_tombstones(&self) -> &[RangeTombstone] {
+        &self.range_tombstones
+    }
+
+    pub fn bloom(&self) -> &BloomFilter {
+        &self.bloom
+    }
+
+    /// Returns false if no key of the Table has the prefix, as extracted by
+    /// the PrefixExtractor the Table was written with
+    ///
+    /// Tables written without a PrefixExtractor may hold any prefix.
+    pub fn may_contain_prefix(&self, prefix: &[u8]) -> bool {
+        self.prefix_bloom
+            .as_ref()
+            .is_none_or(|bloom| bloom.contains(prefix))
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.comparator
+    }
+}
+
+/// Iterator over the records of a Table, created by [`TableReader::iter`]
+/// and [`TableReader::range`]
+///
+/// Iterating backwards yields the versions of a key from the oldest to the
+/// newest.
+pub struct TableIter<'a, C = BytewiseComparator> {
+    reader: &'a TableReader<C>,
+    /// Index of the next block to read from the front
+    next_block: usize,
+    /// Index of the block after the next one to read from the back
+    back_block: usize,
+    entries: std::vec::IntoIter<(MemTableEntry, Option<BlobPointer>)>,
+    back_entries: std::vec::IntoIter<(MemTableEntry, Option<BlobPointer>)>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    with_values: bool,
+}
+
+impl<C: Comparator> TableIter<'_, C> {
+    /// Compares a key to the bounds of the iterator, returning Equal for a
+    /// key between them
+    fn locate(&self, key: &[u8]) -> Ordering {
+        let comparator = &self.reader.comparator;
+        let before_start = match &self.start {
+            Bound::Included(start) => comparator.compare(key, start).is_lt(),
+            Bound::Excluded(start) => comparator.compare(key, start).is_le(),
+            Bound::Unbounded => false,
+        };
+        let after_end = match &self.end {
+            Bound::Included(end) => comparator.compare(key, end).is_gt(),
+            Bound::Excluded(end) => comparator.compare(key, end).is_ge(),
+            Bound::Unbounded => false,
+        };
+
+        match (before_start, after_end) {
+            (true, _) => Ordering::Less,
+            (_, true) => Ordering::Greater,
+            _ => Ordering::Equal,
+        }
+    }
+
+    /// Ends the iteration in both directions
+    fn finish(&mut self) {
+        self.next_block = self.back_block;
+        self.entries = Vec::new().into_iter();
+        self.back_entries = Vec::new().into_iter();
+    }
+}
+
+impl<C: Comparator> Iterator for TableIter<'_, C> {
+    type Item = Result<MemTableEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // Once every block has been read, the front takes over what is
+            // left of the block read from the back.
+            let next = match self.entries.next() {
+                Some(next) => Some(next),
+                None if self.next_block >= self.back_block => self.back_entries.next(),
+                None => None,
+            };
+            if let Some((entry, blob)) = next {
+                match self.locate(&entry.key) {
+                    Ordering::Less => continue,
+                    Ordering::Greater => {
+                        self.finish();
+                        return None;
+                    }
+                    Ordering::Equal => return Some(self.reader.resolve_blob(entry, blob)),
+                }
+            }
+            if self.next_block >= self.back_block {
+                return None;
+            }
+
+            match self
+                .reader
+                .read_block_with(self.next_block, self.with_values)
+            {
+                Ok(entries) => self.entries = entries.into_iter(),
+                Err(e) => {
+                    // Stop iterating after an error rather than yielding it
+                    // again for every remaining block.
+                    self.finish();
+                    return Some(Err(e));
+                }
+            }
+            self.next_block += 1;
+        }
+    }
+}
+
+impl<C: Comparator> DoubleEndedIterator for TableIter<'_, C> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            let next = match self.back_entries.next_back() {
+                Some(next) => Some(next),
+                None if self.next_block >= self.back_block => self.entries.next_back(),
+                None => None,
+            };
+            if let Some((entry, blob)) = next {
+                match self.locate(&entry.key) {
+                    Ordering::Greater => continue,
+                    Ordering::Less => {
+                        self.finish();
+                        return None;
+                    }
+                    Ordering::Equal => return Some(self.reader.resolve_blob(entry, blob)),
+                }
+            }
+            if self.next_block >= self.back_block {
+                return None;
+            }
+
+            self.back_block -= 1;
+            match self
+                .reader
+                .read_block_with(self.back_block, self.with_values)
+            {
+                Ok(entries) => self.back_entries = entries.into_iter(),
+                Err(e) => {
+                    self.finish();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Encodes a record of a block, sharing the prefix of its key with the
+/// previous key
+///
+/// With `is_blob`, the Value of the entry must be an encoded BlobPointer.
+/// With `shares_value`, the Value is left out for the one of the previous
+/// record.
+fn encode_record<V: AsRef<[u8]>>(
+    entry: &MemTableEntry<V>,
+    prev_key: &[u8],
+    is_blob: bool,
+    shares_value: bool,
+    out: &mut Vec<u8>,
+) {
+    let start = out.len();
+    let shared = prev_key
+        .iter()
+        .zip(&entry.key)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = &entry.key[shared..];
+
+    out.extend_from_slice(&(shared as u32).to_le_bytes());
+    out.extend_from_slice(&(suffix.len() as u32).to_le_bytes());
+    out.extend_from_slice(suffix);
+    let flags_pos = out.len();
+    entry.encode_body_with(shares_value, out);
+    if is_blob {
+        out[flags_pos] |= FLAG_BLOB;
+    }
+
+    let crc = crc32c::crc32c(&out[start..]);
+    out.extend_from_slice(&crc.to_le_bytes());
+}
+
+/// Decodes the record of a block at `pos`, restoring its key from the
+/// previous key
+///
+/// With `with_value`, a record whose Value is in the blob area is returned
+/// without a Value, along with the BlobPointer to it, and a record sharing
+/// the Value of the previous record is returned with `prev_value`.
+fn decode_record(
+    buf: &[u8],
+    pos: &mut usize,
+    prev_key: &[u8],
+    prev_value: Option<&[u8]>,
+    with_value: bool,
+) -> io::Result<(MemTableEntry, Option<BlobPointer>)> {
+    let start = *pos;
+    let shared = u32::from_le_bytes(take(buf, pos, 4)?.try_into().unwrap()) as usize;
+    let suffix_len = u32::from_le_bytes(take(buf, pos, 4)?.try_into().unwrap()) as usize;
+    let suffix = take(buf, pos, suffix_len)?;
+    let prefix = prev_key.get(..shared).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "shared prefix longer than key")
+    })?;
+    let key = [prefix, suffix].concat();
+
+    let flags = buf.get(*pos).copied().unwrap_or(0);
+    let is_blob = flags & FLAG_BLOB != 0;
+    let mut entry = MemTableEntry::decode_body(key, buf, pos, with_value)?;
+    let mut crc_pos = *pos - start;
+    check_crc(&buf[start..], &mut crc_pos)?;
+    *pos = start + crc_pos;
+
+    if flags & FLAG_SHARED_VALUE != 0 && with_value {
+        let value = prev_value.filter(|_| !entry.is_deleted && !is_blob);
+        entry.value = Some(value.map(<[u8]>::to_vec).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "shared value without a value before it",
+            )
+        })?);
+    }
+    let blob =
+        match entry.value.take() {
+            Some(pointer) if is_blob => Some(BlobPointer::decode(&pointer).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "malformed blob pointer")
+            })?),
+            value => {
+                entry.value = value;
+                None
+            }
+        };
+    Ok((entry, blob))
+}
+
+/// Decodes the Range Tombstones section of a Table
+fn decode_range_tombstones(mut buf: &[u8]) -> Option<Vec<RangeTombstone>> {
+    let mut tombstones = Vec::new();
+    while !buf.is_empty() {
+        let (start_len, rest) = split_u64(buf)?;
+        let start = rest.get(..start_len as usize)?;
+        let (end_len, rest) = split_u64(&rest[start.len()..])?;
+        let end = rest.get(..end_len as usize)?;
+        let timestamp = rest.get(end.len()..end.len() + 16)?;
+        tombstones.push(RangeTombstone {
+            start: start.to_vec(),
+            end: end.to_vec(),
+            timestamp_ms: u128::from_le_bytes(timestamp.try_into().unwrap()),
+        });
+        buf = &rest[end.len() + 16..];
+    }
+    Some(tombstones)
+}
+
+fn encode_profile(profile: &Profile, out: &mut Vec<u8>) {
+    out.extend_from_slice(&profile.live_count.to_le_bytes());
+    out.extend_from_slice(&profile.tombstone_count.to_le_bytes());
+    for lens in [&profile.key_lens, &profile.value_lens] {
+        for n in [lens.count, lens.min, lens.max, lens.total] {
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+}
+
+fn decode_profile(buf: &[u8]) -> Option<Profile> {
+    if buf.len() != PROFILE_SIZE {
+        return None;
+    }
+    let mut counters = buf
+        .chunks_exact(8)
+        .map(|n| u64::from_le_bytes(n.try_into().unwrap()));
+    let mut next = || counters.next().unwrap();
+    let (live_count, tombstone_count) = (next(), next());
+    let mut lens = || LengthStats {
+        count: next(),
+        min: next(),
+        max: next(),
+        total: next(),
+    };
+    let (key_lens, value_lens) = (lens(), lens());
+    Some(Profile {
+        live_count,
+        tombstone_count,
+        key_lens,
+        value_lens,
+    })
+}
+
+fn split_u64(buf: &[u8]) -> Option<(u64, &[u8])> {
+    if buf.len() < 8 {
+        return None;
+    }
+    let (int, rest) = buf.split_at(8);
+    Some((u64::from_le_bytes(int.try_into().unwrap()), rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::io::{self, Write};
+    use std::ops::Bound;
+    use std::sync::Arc;
+
+    use tempfile::tempdir;
+
+    use crate::bloom::FixedPrefix;
+    use crate::cache::BlockCache;
+    use crate::clock::MockClock;
+    use crate::comparator::{BytewiseComparator, Comparator};
+    use crate::compression::Compression;
+    use crate::error::Error;
+    use crate::faulty::FaultyFile;
+    use crate::mem_table::{MemTable, MemTableEntry, RangeTombstone};
+    use crate::metrics::{LengthStats, Profile};
+    use crate::table::{TableBuilder, TableReader, TableWriter, DEFAULT_BLOCK_SIZE, FOOTER_SIZE};
+    use crate::wal::WalFile;
+
+    /// Accepts a number of bytes and then fails every write, like a full disk
+    struct FailingWriter {
+        remaining: usize,
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.remaining == 0 {
+                return Err(io::Error::other("disk full"));
+            }
+            let n = buf.len().min(self.remaining);
+            self.remaining -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_table_get() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.set_at(b"Orange", b"Orange Smoothie", 20);
+        table.delete_at(b"Peach", 30);
+        TableWriter::new().flush(&table, &path).unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 4);
+
+        let entry = reader.get(b"Lime").unwrap().unwrap();
+        assert_eq!(entry.value.as_ref().unwrap(), b"Lime Smoothie");
+        assert_eq!(entry.timestamp_ms, 10);
+        assert!(!entry.is_deleted);
+
+        let entry = reader.get(b"Peach").unwrap().unwrap();
+        assert_eq!(entry.value, None);
+        assert_eq!(entry.timestamp_ms, 30);
+        assert!(entry.is_deleted);
+
+        assert!(reader.get(b"Banana").unwrap().is_none());
+        assert!(reader.get(b"Aardvark").unwrap().is_none());
+        assert!(reader.get(b"Zucchini").unwrap().is_none());
+
+        let entry = reader
+            .get_as_of_with(b"Lime", u128::MAX, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.value, None);
+        assert!(!entry.is_deleted);
+        let entry = reader
+            .get_as_of_with(b"Peach", u128::MAX, false)
+            .unwrap()
+            .unwrap();
+        assert!(entry.is_deleted);
+    }
+
+    #[test]
+    fn test_table_from_source() {
+        let mut table = MemTable::new();
+        for i in 0..100u32 {
+            let key = format!("key-{:03}", i);
+            table.set_at(key.as_bytes(), i.to_le_bytes(), i as u128);
+        }
+        table.delete_at(b"key-050", 100);
+        let bytes = TableWriter::new()
+            .with_block_size(256)
+            .write_to(&table, Vec::new())
+            .unwrap();
+
+        // The bytes could as well be embedded with `include_bytes!`.
+        let bytes: &'static [u8] = bytes.leak();
+        let reader = TableReader::from_source(io::Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.len(), 100);
+        assert_eq!(reader.file_size(), bytes.len() as u64);
+        assert_eq!(reader.path(), std::path::Path::new(""));
+
+        let entry = reader.get(b"key-007").unwrap().unwrap();
+        assert_eq!(entry.value.unwrap(), 7u32.to_le_bytes());
+        assert!(reader.get(b"key-050").unwrap().unwrap().is_deleted);
+        assert!(reader.get(b"key-100").unwrap().is_none());
+        assert_eq!(reader.iter().count(), 100);
+        assert!(reader.verify().is_empty());
+
+        // A truncated source is rejected.
+        let truncated = io::Cursor::new(&bytes[..bytes.len() - 1]);
+        assert!(TableReader::from_source(truncated).is_err());
+    }
+
+    #[test]
+    fn test_table_crash_before_sync() {
+        let mut table = MemTable::new();
+        for i in 0..40u32 {
+            let key = format!("key-{:03}", i);
+            table.set_at(key.as_bytes(), vec![i as u8; 1024], i as u128);
+        }
+        let mut file = TableWriter::new()
+            .write_to(&table, FaultyFile::new())
+            .unwrap();
+        let writes = file.pending_writes();
+        assert!((2..=12).contains(&writes));
+
+        // Until the Table is synced, a crash may leave any of its writes out,
+        // and whatever is missing is caught on open or by `verify`.
+        for mask in 0..(1u32 << writes) - 1 {
+            let contents = file.recovered(|i| mask & (1 << i) != 0);
+            if let Ok(reader) = TableReader::from_source(io::Cursor::new(contents)) {
+                assert!(!reader.verify().is_empty(), "persisted {mask:b}");
+            }
+        }
+
+        file.sync_data().unwrap();
+        let reader = TableReader::from_source(io::Cursor::new(file.recovered(|_| false))).unwrap();
+        assert!(reader.verify().is_empty());
+        assert_eq!(reader.len(), 40);
+        let entry = reader.get(b"key-007").unwrap().unwrap();
+        assert_eq!(entry.value.unwrap(), vec![7; 1024]);
+    }
+
+    #[test]
+    fn test_table_prefix_bloom() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        for i in 0..100u32 {
+            let key = format!("user-{:02}/{}", i % 50, i);
+            table.set_at(key.as_bytes(), i.to_le_bytes(), i as u128);
+        }
+        // Keys out of the domain have no prefix.
+        table.set_at(b"user", b"short", 0);
+        let writer = TableWriter::new().with_prefix_extractor(Arc::new(FixedPrefix(7)));
+        writer.flush(&table, &path).unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        for i in 0..50u32 {
+            assert!(reader.may_contain_prefix(format!("user-{:02}", i).as_bytes()));
+        }
+        let false_positives = (50..1050u32)
+            .filter(|i| reader.may_contain_prefix(format!("user-{:02}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 50, "{false_positives} false positives");
+        assert_eq!(
+            reader.get(b"user").unwrap().unwrap().value.unwrap(),
+            b"short"
+        );
+
+        // Without a PrefixExtractor any prefix may be in the Table.
+        TableWriter::new().flush(&table, &path).unwrap();
+        let reader = TableReader::open(&path).unwrap();
+        assert!(reader.may_contain_prefix(b"user-99"));
+    }
+
+    #[test]
+    fn test_table_profile() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        table.set_at(b"a", [1; 10], 0);
+        // Values in the blob area count with their full length.
+        table.set_at(b"bbb", vec![2; 100_000], 10);
+        table.delete_at(b"cc", 20);
+        TableWriter::new().flush(&table, &path).unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert_eq!(
+            reader.profile(),
+            Profile {
+                live_count: 2,
+                tombstone_count: 1,
+                key_lens: LengthStats {
+                    count: 3,
+                    min: 1,
+                    max: 3,
+                    total: 6,
+                },
+                value_lens: LengthStats {
+                    count: 2,
+                    min: 10,
+                    max: 100_000,
+                    total: 100_010,
+                },
+            }
+        );
+        assert_eq!(reader.profile().value_lens.mean(), 50_005.0);
+
+        TableWriter::new().flush(&MemTable::new(), &path).unwrap();
+        let reader = TableReader::open(&path).unwrap();
+        assert_eq!(reader.profile(), Profile::default());
+        assert_eq!(reader.profile().key_lens.mean(), 0.0);
+    }
+
+    #[test]
+    fn test_table_get_many_blocks() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        for i in 0..100u32 {
+            let key = format!("key-{:03}", i * 2);
+            table.set_at(key.as_bytes(), i.to_le_bytes(), i as u128);
+        }
+        TableWriter::new()
+            .with_block_size(256)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        for i in 0..100u32 {
+            let key = format!("key-{:03}", i * 2);
+            let entry = reader.get(key.as_bytes()).unwrap().unwrap();
+            assert_eq!(entry.value.unwrap(), i.to_le_bytes());
+
+            let missing = format!("key-{:03}", i * 2 + 1);
+            assert!(reader.get(missing.as_bytes()).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_table_block_size() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        for i in 0..1000u32 {
+            let key = format!("key-{:04}", i);
+            table.set_at(key.as_bytes(), [b'x'; 40], i as u128);
+        }
+        TableWriter::new()
+            .with_block_size(512)
+            .flush(&table, &path)
+            .unwrap();
+
+        // Every block but the last is closed by the record that takes it past
+        // the block size, and a record takes less than 100 bytes.
+        let reader = TableReader::open(&path).unwrap();
+        let offsets: Vec<u64> = reader.index.iter().map(|e| e.offset).collect();
+        assert!(offsets.len() > 1);
+        assert!(offsets
+            .windows(2)
+            .all(|w| (512..512 + 100).contains(&(w[1] - w[0]))));
+
+        for (idx, entry) in reader.index.iter().enumerate() {
+            assert_eq!(
+                reader.read_block_with(idx, false).unwrap()[0].0.key,
+                entry.key
+            );
+        }
+        for i in (0..1000u32).step_by(37) {
+            let key = format!("key-{:04}", i);
+            let entry = reader.get(key.as_bytes()).unwrap().unwrap();
+            assert_eq!(entry.timestamp_ms, i as u128);
+        }
+    }
+
+    #[test]
+    fn test_table_prefix_compression() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        for i in 0..500u32 {
+            let key = format!("users/00042/sessions/{:05}/last-seen", i);
+            table.set_at(key.as_bytes(), i.to_le_bytes(), i as u128);
+        }
+        table.delete_at(b"users/00042/sessions/00100/last-seen", 1000);
+        TableWriter::new()
+            .with_block_size(1024)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        let entries = reader.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+        assert_eq!(entries.as_slice(), table.entries());
+        for entry in table.entries().iter().step_by(13) {
+            assert_eq!(reader.get(&entry.key).unwrap().as_ref(), Some(entry));
+        }
+
+        // Records encoded with full keys take up a lot more than the blocks,
+        // even though the blocks store the key of every restart point in full.
+        let uncompressed: usize = table
+            .entries()
+            .iter()
+            .map(|e| {
+                let mut buf = Vec::new();
+                e.encode(&mut buf);
+                buf.len()
+            })
+            .sum();
+        let blocks = reader.index_offset as usize;
+        assert!(blocks * 5 < uncompressed * 4);
+    }
+
+    #[test]
+    fn test_table_value_runs() {
+        let dir = tempdir().unwrap();
+
+        // Long runs of equal Values, broken up by tombstones, by Values in
+        // the blob area and by older versions of a key.
+        let mut table = MemTable::new();
+        for i in 0..1000u32 {
+            let key = format!("key-{:04}", i);
+            let value = format!("run {} of a long and repetitive value", i / 100);
+            table.set_at(key.as_bytes(), value.as_bytes(), 10);
+        }
+        table.set_at(b"key-0150", b"run 1 of a long and repetitive value", 5);
+        table.delete_at(b"key-0250", 20);
+        table.set_at(b"key-0350", vec![3; 256], 20);
+        let flush = |value_runs: bool, name: &str| {
+            let path = dir.path().join(name);
+            TableWriter::new()
+                .with_block_size(1024)
+                .with_large_value_threshold(128)
+                .with_value_runs(value_runs)
+                .flush(&table, &path)
+                .unwrap();
+            TableReader::open(&path).unwrap()
+        };
+        let plain = flush(false, "0.sst");
+        let runs = flush(true, "1.sst");
+        assert!(runs.file_size() * 2 < plain.file_size());
+
+        let entries = runs.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+        assert_eq!(entries.as_slice(), table.entries());
+        for entry in table.entries().iter().step_by(7) {
+            assert_eq!(runs.get(&entry.key).unwrap().as_ref(), Some(entry));
+        }
+        let older = runs.get_as_of(b"key-0150", 5).unwrap().unwrap();
+        assert_eq!(
+            older.value.unwrap(),
+            b"run 1 of a long and repetitive value"
+        );
+        assert!(runs.get(b"key-0250").unwrap().unwrap().is_deleted);
+        assert_eq!(
+            runs.get(b"key-0350").unwrap().unwrap().value.unwrap(),
+            vec![3; 256]
+        );
+        let rev = runs
+            .iter()
+            .rev()
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+        assert!(rev.iter().rev().eq(table.entries()));
+        assert!(runs.verify().is_empty());
+    }
+
+    fn json_table() -> MemTable {
+        let mut table = MemTable::new();
+        for i in 0..300u32 {
+            let key = format!("order-{:05}", i);
+            let value = format!(
+                "{{\"id\":{},\"items\":[\"Apple Smoothie\",\"Lime Smoothie\"],\"paid\":true}}",
+                i
+            );
+            table.set_at(key.as_bytes(), value.as_bytes(), i as u128);
+        }
+        table.delete_at(b"order-00007", 500);
+        table
+    }
+
+    fn block_codecs<C>(reader: &TableReader<C>) -> Vec<u8> {
+        let bytes = std::fs::read(&reader.path).unwrap();
+        reader
+            .index
+            .iter()
+            .map(|e| bytes[e.offset as usize])
+            .collect()
+    }
+
+    #[test]
+    fn test_table_compression_none() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let table = json_table();
+        TableWriter::new()
+            .with_compression(Compression::None)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        let entries = reader.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+        assert_eq!(entries.as_slice(), table.entries());
+        assert!(block_codecs(&reader).iter().all(|&c| c == 0));
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn test_table_compression_lz4() {
+        let dir = tempdir().unwrap();
+        let plain_path = dir.path().join("0.sst");
+        let path = dir.path().join("1.sst");
+
+        let table = json_table();
+        TableWriter::new().flush(&table, &plain_path).unwrap();
+        TableWriter::new()
+            .with_compression(Compression::Lz4)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        let entries = reader.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+        assert_eq!(entries.as_slice(), table.entries());
+        for entry in table.entries().iter().step_by(7) {
+            assert_eq!(reader.get(&entry.key).unwrap().as_ref(), Some(entry));
+        }
+
+        assert!(block_codecs(&reader)
+            .iter()
+            .all(|&c| c == Compression::Lz4.id()));
+        let plain = TableReader::open(&plain_path).unwrap();
+        assert!(reader.file_size() * 2 < plain.file_size());
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_table_compression_zstd() {
+        let dir = tempdir().unwrap();
+        let plain_path = dir.path().join("0.sst");
+        let path = dir.path().join("1.sst");
+
+        let table = json_table();
+        TableWriter::new().flush(&table, &plain_path).unwrap();
+        TableWriter::new()
+            .with_compression(Compression::Zstd)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        let entries = reader.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+        assert_eq!(entries.as_slice(), table.entries());
+        for entry in table.entries().iter().step_by(7) {
+            assert_eq!(reader.get(&entry.key).unwrap().as_ref(), Some(entry));
+        }
+
+        assert!(block_codecs(&reader)
+            .iter()
+            .all(|&c| c == Compression::Zstd.id()));
+        let plain = TableReader::open(&plain_path).unwrap();
+        assert!(reader.file_size() * 2 < plain.file_size());
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn test_table_compression_fallback() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        // Records of pseudo random bytes barely compress.
+        let mut state = 1u64;
+        let mut random = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect()
+        };
+        let mut table = MemTable::new();
+        for i in 0..100u32 {
+            let timestamp_ms = u128::from_le_bytes(random(16).try_into().unwrap());
+            table.set_at(&i.to_be_bytes(), random(512), timestamp_ms);
+        }
+        TableWriter::new()
+            .with_block_size(2048)
+            .with_compression(Compression::Lz4)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert!(block_codecs(&reader).iter().all(|&c| c == 0));
+        let entries = reader.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+        assert_eq!(entries.as_slice(), table.entries());
+    }
+
+    #[test]
+    fn test_table_empty() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        TableWriter::new().flush(&MemTable::new(), &path).unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert!(reader.is_empty());
+        assert!(reader.get(b"Apple").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_table_flush_value_type() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table: MemTable<BytewiseComparator, [u8; 8]> = MemTable::default();
+        table.set_at(b"Apple", 7u64.to_le_bytes(), 0);
+        table.delete_at(b"Lime", 0);
+        TableWriter::new().flush(&table, &path).unwrap();
+
+        // Values are read back as bytes.
+        let reader = TableReader::open(&path).unwrap();
+        let entry = reader.get(b"Apple").unwrap().unwrap();
+        assert_eq!(entry.value, Some(7u64.to_le_bytes().to_vec()));
+        assert!(reader.get(b"Lime").unwrap().unwrap().is_deleted);
+    }
+
+    #[test]
+    fn test_table_block_cache() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        for i in 0..100u32 {
+            table.set_at(&i.to_be_bytes(), [7; 64], 0);
+        }
+        TableWriter::new()
+            .with_block_size(512)
+            .flush(&table, &path)
+            .unwrap();
+
+        let cache = Arc::new(BlockCache::new(1024 * 1024));
+        let reader = TableReader::open(&path)
+            .unwrap()
+            .with_block_cache(Arc::clone(&cache));
+
+        let first = reader.get(&42u32.to_be_bytes()).unwrap();
+        assert_eq!((cache.misses(), cache.hits()), (1, 0));
+        let second = reader.get(&42u32.to_be_bytes()).unwrap();
+        assert_eq!((cache.misses(), cache.hits()), (1, 1));
+        assert_eq!(first, second);
+
+        // Another reader of the same file has blocks of its own.
+        let other = TableReader::open(&path)
+            .unwrap()
+            .with_block_cache(Arc::clone(&cache));
+        assert_eq!(other.get(&42u32.to_be_bytes()).unwrap(), first);
+        assert_eq!((cache.misses(), cache.hits()), (2, 1));
+
+        // Scans go through the cache too.
+        assert_eq!(reader.iter().count(), 100);
+        assert!(cache.hits() > 1);
+    }
+
+    #[test]
+    fn test_table_range_tombstones() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        table.delete_range_at(b"Apple", b"Lime", 10);
+        table.delete_range_at(b"", b"\xFF", 20);
+        TableWriter::new().flush(&table, &path).unwrap();
+
+        // A Table may hold nothing but Range Tombstones.
+        let reader = TableReader::open(&path).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(reader.range_tombstones(), table.range_tombstones());
+
+        table.set_at(b"Apple", b"Apple Smoothie", 30);
+        TableWriter::new().flush(&table, &path).unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 1);
+        assert_eq!(
+            reader.range_tombstones(),
+            &[
+                RangeTombstone {
+                    start: b"Apple".to_vec(),
+                    end: b"Lime".to_vec(),
+                    timestamp_ms: 10,
+                },
+                RangeTombstone {
+                    start: Vec::new(),
+                    end: b"\xFF".to_vec(),
+                    timestamp_ms: 20,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_write_error() {
+        let options = TableWriter::new().with_block_size(64);
+        let mut builder = TableBuilder::from_writer(&options, FailingWriter { remaining: 100 }, 0);
+        let mut table = MemTable::new();
+        for i in 0..100u32 {
+            table.set_at(&i.to_be_bytes(), b"Smoothie", 0);
+        }
+        let res = table
+            .entries()
+            .iter()
+            .try_for_each(|entry| builder.add(entry))
+            .and_then(|()| builder.finish_into().map(drop));
+        assert!(matches!(res, Err(Error::Io(e)) if e.kind() == io::ErrorKind::Other));
+    }
+
+    #[test]
+    fn test_table_flush_is_atomic() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+
+        // A builder that is dropped unfinished leaves nothing behind.
+        let mut builder = TableBuilder::new(&TableWriter::new(), &path, 1).unwrap();
+        builder.add(&table.entries()[0]).unwrap();
+        assert!(dir.path().join("0.sst.tmp").exists());
+        drop(builder);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        TableWriter::new().flush(&table, &path).unwrap();
+        assert!(path.exists());
+        assert!(!dir.path().join("0.sst.tmp").exists());
+    }
+
+    #[test]
+    fn test_table_iter() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        for i in 0..50u32 {
+            let key = format!("key-{:03}", i);
+            table.set_at(key.as_bytes(), i.to_le_bytes(), i as u128);
+        }
+        table.delete_at(b"key-010", 100);
+        TableWriter::new()
+            .with_block_size(256)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        let entries = reader.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+        assert_eq!(entries.as_slice(), table.entries());
+    }
+
+    #[test]
+    fn test_table_bloom_filter() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        for i in 0..100u32 {
+            let key = format!("key-{:03}", i);
+            table.set_at(key.as_bytes(), i.to_le_bytes(), i as u128);
+        }
+        TableWriter::new()
+            .with_bloom_fp_rate(0.001)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        for entry in table.entries() {
+            assert!(reader.bloom().contains(&entry.key));
+        }
+
+        let rejected = (100..1100u32)
+            .filter(|i| !reader.bloom().contains(format!("key-{:03}", i).as_bytes()))
+            .count();
+        assert!(rejected > 990);
+    }
+
+    #[test]
+    fn test_table_corrupted_record() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", b"Lime Smoothie", 10);
+        table.set_at(b"Orange", b"Orange Smoothie", 20);
+        TableWriter::new().flush(&table, &path).unwrap();
+
+        // Flip a byte inside the value of the Lime record.
+        let mut bytes = std::fs::read(&path).unwrap();
+        let pos = bytes
+            .windows(13)
+            .position(|w| w == b"Lime Smoothie")
+            .unwrap();
+        bytes[pos] ^= 0xFF;
+        std::fs::write(&path, bytes).unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        // The Lime record starts after the block header (1 + 8 + 5 bytes) and
+        // the Apple record (19 + 37 bytes), a restart point storing its key in
+        // full.
+        assert!(matches!(
+            reader.get(b"Lime"),
+            Err(Error::Corruption { offset: 70 })
+        ));
+        assert!(reader.iter().any(|e| e.is_err()));
+    }
+
+    #[test]
+    fn test_table_corrupted_index() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        TableWriter::new().flush(&table, &path).unwrap();
+
+        // Flip the last byte of the block index, the offset of its only entry.
+        let mut bytes = std::fs::read(&path).unwrap();
+        let index_offset = u64::from_le_bytes(
+            bytes[bytes.len() - FOOTER_SIZE as usize..][..8]
+                .try_into()
+                .unwrap(),
+        );
+        bytes[index_offset as usize + 8 + 5] ^= 0xFF;
+        std::fs::write(&path, bytes).unwrap();
+
+        assert!(matches!(
+            TableReader::open(&path),
+            Err(Error::Corruption { offset }) if offset == index_offset
+        ));
+    }
+
+    #[test]
+    fn test_table_range() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        for i in 0..50u32 {
+            let key = format!("key-{:03}", i * 2);
+            table.set_at(key.as_bytes(), i.to_le_bytes(), i as u128);
+        }
+        TableWriter::new()
+            .with_block_size(128)
+            .flush(&table, &path)
+            .unwrap();
+        let reader = TableReader::open(&path).unwrap();
+
+        let keys = |start: Bound<&[u8]>, end: Bound<&[u8]>| -> Vec<Vec<u8>> {
+            reader.range(start, end).map(|e| e.unwrap().key).collect()
+        };
+
+        assert_eq!(
+            keys(Bound::Included(b"key-010"), Bound::Excluded(b"key-016")),
+            vec![
+                b"key-010".to_vec(),
+                b"key-012".to_vec(),
+                b"key-014".to_vec()
+            ]
+        );
+        assert_eq!(
+            keys(Bound::Excluded(b"key-010"), Bound::Included(b"key-016")),
+            vec![
+                b"key-012".to_vec(),
+                b"key-014".to_vec(),
+                b"key-016".to_vec()
+            ]
+        );
+        assert_eq!(
+            keys(Bound::Included(b"key-011"), Bound::Included(b"key-013")),
+            vec![b"key-012".to_vec()]
+        );
+        assert_eq!(keys(Bound::Included(b"key-095"), Bound::Unbounded).len(), 2);
+        assert_eq!(keys(Bound::Unbounded, Bound::Excluded(b"key-000")).len(), 0);
+        assert_eq!(keys(Bound::Unbounded, Bound::Unbounded).len(), 50);
+    }
+
+    #[test]
+    fn test_table_range_rev() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        for i in 0..50u32 {
+            let key = format!("key-{:03}", i * 2);
+            table.set_at(key.as_bytes(), i.to_le_bytes(), i as u128);
+        }
+        TableWriter::new()
+            .with_block_size(128)
+            .flush(&table, &path)
+            .unwrap();
+        let reader = TableReader::open(&path).unwrap();
+
+        let keys = |iter: &mut dyn Iterator<Item = crate::Result<MemTableEntry>>| -> Vec<String> {
+            iter.map(|e| String::from_utf8(e.unwrap().key).unwrap())
+                .collect()
+        };
+        assert_eq!(
+            keys(
+                &mut reader
+                    .range(Bound::Excluded(b"key-010"), Bound::Included(b"key-016"))
+                    .rev()
+            ),
+            vec!["key-016", "key-014", "key-012"]
+        );
+        assert_eq!(
+            keys(
+                &mut reader
+                    .range(Bound::Included(b"key-011"), Bound::Excluded(b"key-013"))
+                    .rev()
+            ),
+            vec!["key-012"]
+        );
+        let all: Vec<String> = keys(&mut reader.iter()).into_iter().rev().collect();
+        assert_eq!(keys(&mut reader.iter().rev()), all);
+
+        // Both ends meet without skipping or repeating a record.
+        let mut iter = reader.iter();
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        loop {
+            match (iter.next(), iter.next_back()) {
+                (Some(a), Some(b)) => {
+                    front.push(a.unwrap().key);
+                    back.push(b.unwrap().key);
+                }
+                (Some(a), None) => front.push(a.unwrap().key),
+                (None, _) => break,
+            }
+        }
+        front.extend(back.into_iter().rev());
+        assert_eq!(
+            front,
+            table
+                .entries()
+                .iter()
+                .map(|e| e.key.clone())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Orders keys ignoring ASCII case
+    #[derive(Clone, Copy)]
+    struct CaseInsensitiveComparator;
+
+    impl Comparator for CaseInsensitiveComparator {
+        fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+            a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase())
+        }
+    }
+
+    #[test]
+    fn test_table_custom_comparator() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::with_comparator(CaseInsensitiveComparator);
+        table.set_at(b"banana", b"Banana Smoothie", 0);
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"cherry", b"Cherry Smoothie", 0);
+        table.set_at(b"Date", b"Date Smoothie", 0);
+        TableWriter::new()
+            .with_block_size(64)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open_with_comparator(&path, CaseInsensitiveComparator).unwrap();
+        let keys: Vec<Vec<u8>> = reader.iter().map(|e| e.unwrap().key).collect();
+        assert_eq!(
+            keys,
+            vec![
+                b"Apple".to_vec(),
+                b"banana".to_vec(),
+                b"cherry".to_vec(),
+                b"Date".to_vec()
+            ]
+        );
+
+        let entry = reader.get(b"cherry").unwrap().unwrap();
+        assert_eq!(entry.value.unwrap(), b"Cherry Smoothie");
+
+        let keys: Vec<Vec<u8>> = reader
+            .range(Bound::Excluded(b"Apple"), Bound::Included(b"cherry"))
+            .map(|e| e.unwrap().key)
+            .collect();
+        assert_eq!(keys, vec![b"banana".to_vec(), b"cherry".to_vec()]);
+    }
+
+    #[test]
+    fn test_table_flush_expired() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new().with_clock(Arc::new(MockClock::new(50)));
+        table.set_with_ttl(b"Apple", b"Apple Smoothie", 0, 20);
+        table.set_with_ttl(b"Lime", b"Lime Smoothie", 10, 100);
+        table.set_at(b"Orange", b"Orange Smoothie", 20);
+        TableWriter::new().flush(&table, &path).unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 3);
+
+        let apple = reader.get(b"Apple").unwrap().unwrap();
+        assert!(apple.is_deleted);
+        assert_eq!(apple.value, None);
+        assert_eq!(apple.timestamp_ms, 0);
+        assert_eq!(apple.expires_at_ms, None);
+
+        let lime = reader.get(b"Lime").unwrap().unwrap();
+        assert_eq!(lime.value.unwrap(), b"Lime Smoothie");
+        assert_eq!(lime.expires_at_ms, Some(110));
+
+        let orange = reader.get(b"Orange").unwrap().unwrap();
+        assert_eq!(orange.expires_at_ms, None);
+    }
+
+    #[test]
+    fn test_table_flush_immutable() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.delete_at(b"Lime", 10);
+        let frozen = table.freeze();
+        TableWriter::new().flush_immutable(&frozen, &path).unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert_eq!(reader.len(), frozen.len());
+        let entries: Vec<_> = reader.iter().map(|e| e.unwrap()).collect();
+        assert_eq!(entries, frozen.iter().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_table_approximate_offset() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        table.extend_sorted((0..1000u32).map(|i| (i.to_be_bytes().to_vec(), vec![0; 100], 0)));
+        TableWriter::new().flush(&table, &path).unwrap();
+        let reader = TableReader::open(&path).unwrap();
+
+        assert_eq!(reader.approximate_offset(b""), 0);
+        assert_eq!(reader.approximate_offset(&0u32.to_be_bytes()), 0);
+
+        let first_half = reader.approximate_offset(&500u32.to_be_bytes());
+        let all = reader.approximate_offset(&u32::MAX.to_be_bytes());
+        let half_block = (DEFAULT_BLOCK_SIZE / 2) as u64 + 200;
+        assert!(all <= reader.index_offset);
+        assert!(all + half_block >= reader.index_offset);
+        assert!(first_half.abs_diff(reader.index_offset / 2) <= half_block);
+    }
+
+    #[test]
+    fn test_table_versions() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new().with_versions(100);
+        for ts in 0..50 {
+            table.set_at(b"Apple", format!("Apple {}", ts).as_bytes(), ts);
+        }
+        table.set_at(b"Lime", b"Lime Smoothie", 0);
+        TableWriter::new()
+            .with_block_size(64)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 51);
+        // The versions of Apple were not split across blocks.
+        assert_eq!(reader.index.len(), 2);
+
+        let entry = reader.get(b"Apple").unwrap().unwrap();
+        assert_eq!(entry.value.unwrap(), b"Apple 49");
+        let entry = reader.get_as_of(b"Apple", 10).unwrap().unwrap();
+        assert_eq!(entry.value.unwrap(), b"Apple 10");
+        assert!(reader.get_as_of(b"Lime", 0).unwrap().is_some());
+
+        let entries: Vec<_> = reader.iter().map(|e| e.unwrap()).collect();
+        assert_eq!(entries, table.entries());
+    }
+
+    /// A MemTable of 2000 keys, with several versions and a tombstone for
+    /// some of them
+    fn restart_table() -> MemTable {
+        let mut table = MemTable::new().with_versions(4);
+        for i in 0..2000u32 {
+            let key = format!("fruit/{:05}", i * 2);
+            table.set_at(key.as_bytes(), format!("Smoothie {}", i), 0);
+            if i % 7 == 0 {
+                table.set_at(key.as_bytes(), format!("Pie {}", i), 10);
+            }
+            if i % 11 == 0 {
+                table.delete_at(key.as_bytes(), 20);
+            }
+        }
+        table
+    }
+
+    #[test]
+    fn test_table_restart_interval() {
+        let dir = tempdir().unwrap();
+        let table = restart_table();
+
+        let mut sizes = Vec::new();
+        for restart_interval in [1, 4, 16, 1000] {
+            let path = dir.path().join(format!("{}.sst", restart_interval));
+            TableWriter::new()
+                .with_restart_interval(restart_interval)
+                .flush(&table, &path)
+                .unwrap();
+            let reader = TableReader::open(&path).unwrap();
+
+            let entries: Vec<_> = reader.iter().map(|e| e.unwrap()).collect();
+            assert_eq!(entries, table.entries());
+            let rev: Vec<_> = reader.iter().rev().map(|e| e.unwrap()).collect();
+            assert_eq!(rev.len(), entries.len());
+            // Restart points store their keys in full, sharing no prefix.
+            for idx in 0..reader.index.len() {
+                let block = reader.read_block(idx).unwrap();
+                for &restart in &block.restarts {
+                    let shared = &block.records()[restart as usize..restart as usize + 4];
+                    assert_eq!(shared, [0; 4]);
+                }
+            }
+            for i in (0..4001u32).step_by(7) {
+                let key = format!("fruit/{:05}", i);
+                for timestamp_ms in [0, 10, u128::MAX] {
+                    let expected = table
+                        .entries()
+                        .iter()
+                        .find(|e| e.key == key.as_bytes() && e.timestamp_ms <= timestamp_ms);
+                    let entry = reader.get_as_of(key.as_bytes(), timestamp_ms).unwrap();
+                    assert_eq!(entry.as_ref(), expected);
+                }
+            }
+            sizes.push(reader.file_size());
+        }
+
+        // Fewer restart points store fewer keys in full.
+        assert!(sizes.windows(2).all(|w| w[0] > w[1]));
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn bench_table_restart_interval() {
+        use std::time::Instant;
+
+        let dir = tempdir().unwrap();
+        let table = restart_table();
+        let cache = Arc::new(BlockCache::new(16 * 1024 * 1024));
+        for restart_interval in [1, 4, 16, 64, 256] {
+            let path = dir.path().join(format!("{}.sst", restart_interval));
+            TableWriter::new()
+                .with_block_size(64 * 1024)
+                .with_restart_interval(restart_interval)
+                .flush(&table, &path)
+                .unwrap();
+            let reader = TableReader::open(&path)
+                .unwrap()
+                .with_block_cache(Arc::clone(&cache));
+
+            let start = Instant::now();
+            for _ in 0..10 {
+                for i in 0..2000u32 {
+                    let key = format!("fruit/{:05}", i * 2);
+                    assert!(reader.get(key.as_bytes()).unwrap().is_some());
+                }
+            }
+            println!(
+                "restart interval {}: {} bytes, 20000 gets in {:?}",
+                restart_interval,
+                reader.file_size(),
+                start.elapsed()
+            );
+        }
+    }
+
+    #[test]
+    fn test_table_range_keys() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        for i in 0..500u32 {
+            if i % 5 == 0 {
+                table.delete_at(&i.to_be_bytes(), 0);
+            } else {
+                table.set_at(&i.to_be_bytes(), [7; 64], 0);
+            }
+        }
+        TableWriter::new().flush(&table, &path).unwrap();
+        let reader = TableReader::open(&path).unwrap();
+
+        let keys: Vec<Vec<u8>> = reader
+            .range_keys(
+                Bound::Included(&100u32.to_be_bytes()),
+                Bound::Excluded(&400u32.to_be_bytes()),
+            )
+            .map(|k| k.unwrap())
+            .collect();
+        let expected: Vec<Vec<u8>> = (100..400u32)
+            .filter(|i| i % 5 != 0)
+            .map(|i| i.to_be_bytes().to_vec())
+            .collect();
+        assert_eq!(keys, expected);
+
+        let entries: Vec<_> = reader
+            .range_with(Bound::Unbounded, Bound::Unbounded, false)
+            .map(|e| e.unwrap())
+            .collect();
+        assert!(entries.iter().all(|e| e.value.is_none()));
+        assert_eq!(entries.len(), 500);
+    }
+
+    #[test]
+    fn test_table_key_range() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        TableWriter::new().flush(&MemTable::new(), &path).unwrap();
+        assert_eq!(TableReader::open(&path).unwrap().key_range().unwrap(), None);
+
+        let mut table = MemTable::new();
+        for i in 0..100u32 {
+            table.set_at(format!("key-{:03}", i).as_bytes(), b"Smoothie", 0);
+        }
+        TableWriter::new()
+            .with_block_size(256)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        let (first, last) = reader.key_range().unwrap().unwrap();
+        assert_eq!(first, b"key-000");
+        assert_eq!(last, b"key-099");
+        assert_eq!(reader.file_size(), std::fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn test_table_covers() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        TableWriter::new().flush(&MemTable::new(), &path).unwrap();
+        assert!(!TableReader::open(&path).unwrap().covers(b"Apple").unwrap());
+
+        let mut table = MemTable::new();
+        table.set_at(b"Banana", b"Smoothie", 0);
+        table.set_at(b"Lime", b"Smoothie", 0);
+        TableWriter::new().flush(&table, &path).unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert!(!reader.covers(b"Apple").unwrap());
+        assert!(reader.covers(b"Banana").unwrap());
+        assert!(reader.covers(b"Cherry").unwrap());
+        assert!(reader.covers(b"Lime").unwrap());
+        assert!(!reader.covers(b"Orange").unwrap());
+
+        let overlaps = |start, end| reader.overlaps(start, end).unwrap();
+        assert!(overlaps(Bound::Unbounded, Bound::Unbounded));
+        assert!(!overlaps(Bound::Unbounded, Bound::Excluded(&b"Banana"[..])));
+        assert!(overlaps(Bound::Unbounded, Bound::Included(&b"Banana"[..])));
+        assert!(!overlaps(Bound::Excluded(&b"Lime"[..]), Bound::Unbounded));
+        assert!(overlaps(
+            Bound::Included(&b"Apple"[..]),
+            Bound::Included(&b"Orange"[..])
+        ));
+    }
+
+    #[test]
+    fn test_table_flush_split() {
+        let dir = tempdir().unwrap();
+        let mut table = MemTable::new();
+        for key in [&b"Apple"[..], b"Banana", b"Lime", b"Orange", b"Potato"] {
+            table.set_at(key, b"Smoothie", 1);
+        }
+        table.delete_range_at(b"Cherry", b"Pear", 2);
+
+        let writer = TableWriter::new().with_split_points(vec![b"C".to_vec(), b"M".to_vec()]);
+        let paths = writer
+            .flush_split(&table, |i| dir.path().join(format!("{}.sst", i)))
+            .unwrap();
+        assert_eq!(paths.len(), 3);
+
+        let readers: Vec<TableReader> = paths
+            .iter()
+            .map(|p| TableReader::open(p).unwrap())
+            .collect();
+        let key_ranges: Vec<(Vec<u8>, Vec<u8>)> = readers
+            .iter()
+            .map(|r| r.key_range().unwrap().unwrap())
+            .collect();
+        assert_eq!(
+            key_ranges,
+            vec![
+                (b"Apple".to_vec(), b"Banana".to_vec()),
+                (b"Lime".to_vec(), b"Lime".to_vec()),
+                (b"Orange".to_vec(), b"Potato".to_vec()),
+            ]
+        );
+        assert_eq!(readers.iter().map(|r| r.len()).sum::<usize>(), 5);
+
+        // The Range Tombstone is cut at the split points.
+        let tombstones: Vec<(Vec<u8>, Vec<u8>)> = readers
+            .iter()
+            .flat_map(|r| r.range_tombstones())
+            .map(|t| (t.start.clone(), t.end.clone()))
+            .collect();
+        assert_eq!(
+            tombstones,
+            vec![
+                (b"Cherry".to_vec(), b"M".to_vec()),
+                (b"M".to_vec(), b"Pear".to_vec()),
+            ]
+        );
+
+        // Ranges without records are skipped.
+        let writer = TableWriter::new().with_split_points(vec![b"B".to_vec(), b"Z".to_vec()]);
+        let mut table = MemTable::new();
+        table.set_at(b"Cherry", b"Smoothie", 1);
+        let paths = writer
+            .flush_split(&table, |i| dir.path().join(format!("skip-{}.sst", i)))
+            .unwrap();
+        assert_eq!(paths, vec![dir.path().join("skip-1.sst")]);
+    }
+
+    #[test]
+    fn test_table_flush_large() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        table.extend_sorted((0..50_000u32).map(|i| {
+            let key = format!("key-{:08}", i).into_bytes();
+            (key, i.to_le_bytes().to_vec(), i as u128)
+        }));
+        TableWriter::new().flush(&table, &path).unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert_eq!(reader.len(), table.len());
+
+        let keys: Vec<Vec<u8>> = reader.iter().map(|e| e.unwrap().key).collect();
+        assert_eq!(keys.len(), table.len());
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_table_large_values() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+        let large = |i: u32| vec![i as u8; 1000 + i as usize];
+
+        let mut table = MemTable::new();
+        for i in 0..20u32 {
+            let key = format!("key-{:02}", i).into_bytes();
+            if i % 3 == 0 {
+                table.set_at(&key, large(i), i as u128);
+            } else {
+                table.set_at(&key, b"Smoothie", i as u128);
+            }
+        }
+        table.delete_at(b"key-99", 99);
+        TableWriter::new()
+            .with_block_size(128)
+            .with_compression(Compression::default())
+            .with_large_value_threshold(100)
+            .flush(&table, &path)
+            .unwrap();
+
+        // The blocks only hold pointers to the large Values.
+        let reader = TableReader::open(&path).unwrap();
+        let block_bytes: u64 = reader.index.iter().map(|e| e.len).sum();
+        assert!(block_bytes < 2000);
+
+        let entry = reader.get(b"key-03").unwrap().unwrap();
+        assert_eq!(entry.value.unwrap(), large(3));
+        assert_eq!(entry.timestamp_ms, 3);
+        let entry = reader.get(b"key-04").unwrap().unwrap();
+        assert_eq!(entry.value.as_deref(), Some(&b"Smoothie"[..]));
+        assert!(reader.get(b"key-99").unwrap().unwrap().is_deleted);
+
+        let entries = reader.iter().collect::<crate::Result<Vec<_>>>().unwrap();
+        assert_eq!(entries.as_slice(), table.entries());
+        let keys = reader
+            .range_keys(Bound::Unbounded, Bound::Unbounded)
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(keys.len(), 20);
+    }
+
+    #[test]
+    fn test_table_corrupted_blob() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        table.set_at(b"Apple", b"Apple Smoothie", 0);
+        table.set_at(b"Lime", [7; 500], 0);
+        TableWriter::new()
+            .with_large_value_threshold(100)
+            .flush(&table, &path)
+            .unwrap();
+
+        // The only blob sits at the start of the file, before the block.
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[10] ^= 0xFF;
+        std::fs::write(&path, bytes).unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert!(reader.get(b"Apple").unwrap().is_some());
+        assert!(matches!(
+            reader.get(b"Lime"),
+            Err(Error::Corruption { offset: 0 })
+        ));
+        assert!(reader.get_as_of_with(b"Lime", u128::MAX, false).is_ok());
+    }
+
+    #[test]
+    fn test_table_empty_key() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("0.sst");
+
+        let mut table = MemTable::new();
+        table.set_at(b"", b"Empty", 0);
+        table.set_at(b"Apple", b"Apple Smoothie", 10);
+        table.set_at(b"Lime", b"Lime Smoothie", 20);
+        TableWriter::new()
+            .with_block_size(1)
+            .flush(&table, &path)
+            .unwrap();
+
+        let reader = TableReader::open(&path).unwrap();
+        assert_eq!(reader.get(b"").unwrap().unwrap().value.unwrap(), b"Empty");
+        assert_eq!(reader.iter().next().unwrap().unwrap().key, b"");
+
+        let keys: Vec<Vec<u8>> = reader
+            .range(Bound::Excluded(b""), Bound::Unbounded)
+            .map(|e| e.unwrap().key)
+            .collect();
+        assert_eq!(keys, vec![b"Apple".to_vec(), b"Lime".to_vec()]);
+    }
+}
@@ -0,0 +1,160 @@
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::db::Database;
+use crate::error::{Error, Result};
+use crate::key::{AsBytes, FromBytes};
+
+/// TypedDb reads and writes keys of type `K` and Values of type `V` in a
+/// Database
+///
+/// Keys are encoded with [`AsBytes`], which preserves their order, so `range`
+/// yields them in the order of `K`. Values are serialized as JSON.
+pub struct TypedDb<K, V> {
+    db: Database,
+    _types: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K: AsBytes + FromBytes, V: Serialize + DeserializeOwned> TypedDb<K, V> {
+    /// Wraps a Database, whose keys and Values must all be of these types
+    pub fn new(db: Database) -> TypedDb<K, V> {
+        TypedDb {
+            db,
+            _types: PhantomData,
+        }
+    }
+
+    /// Gets the Value of a key
+    ///
+    /// A stored Value that doesn't deserialize as `V` results in
+    /// [`Error::Serialization`].
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        match self.db.get(&key.as_bytes())? {
+            Some(value) => decode_value(&value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets a key to a Value
+    pub fn set(&mut self, key: &K, value: &V) -> Result<()> {
+        let value = serde_json::to_vec(value).map_err(|e| Error::Serialization(e.to_string()))?;
+        self.db.set(&key.as_bytes(), &value)
+    }
+
+    /// Deletes a key
+    pub fn delete(&mut self, key: &K) -> Result<()> {
+        self.db.delete(&key.as_bytes())
+    }
+
+    /// Scans the pairs with keys in the range, in the order of `K`
+    ///
+    /// A stored key or Value that doesn't decode as `K` or `V` results in
+    /// [`Error::Serialization`].
+    pub fn range(&self, range: impl RangeBounds<K>) -> impl Iterator<Item = Result<(K, V)>> + '_ {
+        let encode = |bound: Bound<&K>| bound.map(|k| k.as_bytes().into_owned());
+        let (start, end) = (encode(range.start_bound()), encode(range.end_bound()));
+        self.db
+            .range(
+                start.as_ref().map(Vec::as_slice),
+                end.as_ref().map(Vec::as_slice),
+            )
+            .map(|pair| {
+                let (key, value) = pair?;
+                let key = K::from_bytes(&key).ok_or_else(|| {
+                    Error::Serialization(format!("the key {:?} is not a typed key", key))
+                })?;
+                Ok((key, decode_value(&value)?))
+            })
+    }
+
+    pub fn db(&self) -> &Database {
+        &self.db
+    }
+
+    pub fn db_mut(&mut self) -> &mut Database {
+        &mut self.db
+    }
+
+    pub fn into_inner(self) -> Database {
+        self.db
+    }
+}
+
+fn decode_value<V: DeserializeOwned>(value: &[u8]) -> Result<V> {
+    serde_json::from_slice(value).map_err(|e| Error::Serialization(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use tempfile::tempdir;
+
+    use crate::db::Database;
+    use crate::error::Error;
+    use crate::typed::TypedDb;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        fruit: String,
+        quantity: u32,
+        paid: bool,
+    }
+
+    fn order(fruit: &str, quantity: u32) -> Order {
+        Order {
+            fruit: fruit.to_owned(),
+            quantity,
+            paid: quantity.is_multiple_of(2),
+        }
+    }
+
+    #[test]
+    fn test_typed_get_set_delete() {
+        let dir = tempdir().unwrap();
+        let mut db: TypedDb<u64, Order> = TypedDb::new(Database::open(dir.path()).unwrap());
+
+        db.set(&7, &order("Apple", 3)).unwrap();
+        db.set(&42, &order("Lime", 4)).unwrap();
+        db.delete(&7).unwrap();
+
+        assert_eq!(db.get(&42).unwrap(), Some(order("Lime", 4)));
+        assert_eq!(db.get(&7).unwrap(), None);
+
+        db.db_mut().set(&9u64.to_be_bytes(), b"not json").unwrap();
+        assert!(matches!(db.get(&9), Err(Error::Serialization(_))));
+    }
+
+    #[test]
+    fn test_typed_range_order() {
+        let dir = tempdir().unwrap();
+        let mut db: TypedDb<i64, Order> = TypedDb::new(Database::open(dir.path()).unwrap());
+        let keys = [300, -5, 0, 256, -300, 1, i64::MIN, i64::MAX, 255];
+        for (i, key) in keys.iter().enumerate() {
+            db.set(key, &order("Apple", i as u32)).unwrap();
+            if i == 4 {
+                db.db_mut().flush().unwrap();
+            }
+        }
+
+        let scanned: Vec<i64> = db.range(..).map(|pair| pair.unwrap().0).collect();
+        let mut sorted = keys.to_vec();
+        sorted.sort();
+        assert_eq!(scanned, sorted);
+
+        let scanned: Vec<(i64, Order)> = db.range(-5..256).map(|pair| pair.unwrap()).collect();
+        assert_eq!(
+            scanned,
+            vec![
+                (-5, order("Apple", 1)),
+                (0, order("Apple", 2)),
+                (1, order("Apple", 5)),
+                (255, order("Apple", 8)),
+            ]
+        );
+        let scanned: Vec<i64> = db.range(256..=300).map(|pair| pair.unwrap().0).collect();
+        assert_eq!(scanned, vec![256, 300]);
+    }
+}
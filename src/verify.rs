@@ -0,0 +1,65 @@
+use crate::error::Error;
+
+/// VerifyReport lists the problems found by
+/// [`Database::verify`](crate::db::Database::verify)
+///
+/// An empty report means every Table listed in the Manifest was read in full
+/// and found consistent.
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Number of Table files that were checked
+    pub tables_checked: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl VerifyReport {
+    /// Whether no problem was found
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Iterates over the problems found in the given file
+    pub fn problems_of<'a>(&'a self, file: &'a str) -> impl Iterator<Item = &'a ProblemKind> {
+        self.problems
+            .iter()
+            .filter(move |p| p.file == file)
+            .map(|p| &p.kind)
+    }
+}
+
+/// A problem found in a file of the Database
+#[derive(Debug)]
+pub struct Problem {
+    /// Name of the file in the Database's directory
+    pub file: String,
+    pub kind: ProblemKind,
+}
+
+/// The kinds of problems found by `Database::verify`
+#[derive(Debug)]
+pub enum ProblemKind {
+    /// The Table is listed in the Manifest but its file is missing
+    Missing,
+    /// The Table file is not listed in the Manifest
+    Orphan,
+    /// The footer, the block index, the BloomFilter or the Range Tombstones
+    /// of the Table could not be read, so none of its blocks were checked
+    Unreadable(Error),
+    /// The block, record or blob at the given offset failed its checksum or
+    /// could not be decoded
+    Corruption { offset: u64 },
+    /// A key of the block at the given offset doesn't come after the key
+    /// before it
+    Misordered { offset: u64, key: Vec<u8> },
+    /// The Table holds a different number of records than its footer says
+    CountMismatch { expected: usize, found: usize },
+}
+
+impl From<Error> for ProblemKind {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Corruption { offset } => ProblemKind::Corruption { offset },
+            e => ProblemKind::Unreadable(e),
+        }
+    }
+}
@@ -0,0 +1,913 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::mem_table::{MemTable, MemTableEntry, Operation, RangeTombstone};
+
+/// WriteAheadLog(WAL) is an append-only log of every write made to the
+/// MemTable
+///
+/// In case of a restart the MemTable is rebuilt by replaying the WAL.
+///
+/// Writes are appended as records, each holding a batch of writes that is
+/// recovered either as a whole or not at all. A record is laid out as:
+///
+/// ```text
+/// +----------+---------+----------+
+/// | Len (4B) | Payload | CRC (4B) |
+/// +----------+---------+----------+
+/// ```
+///
+/// The CRC is the CRC32C of the Len bytes of the Payload. The Payload holds a
+/// Count (4B) followed by Count writes, each encoded by
+/// `MemTableEntry::encode` or, for Range Tombstones, by
+/// `RangeTombstone::encode`. All integers are little-endian.
+pub struct WriteAheadLog {
+    path: PathBuf,
+    file: BufWriter<Box<dyn WalFile>>,
+    sync_policy: SyncPolicy,
+    last_sync: Instant,
+    /// Number of bytes appended since the WAL was opened
+    bytes_written: u64,
+}
+
+/// SyncPolicy controls how often the WAL is synced to the disk
+///
+/// Only records that have been synced are guaranteed to survive a crash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    /// Sync after every appended record. This is the most durable, but the
+    /// slowest policy.
+    #[default]
+    EveryWrite,
+    /// Never sync explicitly, the records are only synced when the WAL is
+    /// dropped. Every record written since the last sync is lost on a crash.
+    Never,
+    /// Sync on the first append after the given duration has passed since the
+    /// last sync. Up to the given duration of records is lost on a crash.
+    Interval(Duration),
+}
+
+/// WalFile is the file a WAL appends its records to
+pub(crate) trait WalFile: Write + Send {
+    /// Syncs the written data to the disk
+    fn sync_data(&mut self) -> io::Result<()>;
+
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+}
+
+impl WalFile for File {
+    fn sync_data(&mut self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+}
+
+impl WriteAheadLog {
+    /// Opens the WAL at the given path, creating it if it doesn't exist
+    ///
+    /// New records are appended to the end of the existing log.
+    pub fn open(path: &Path) -> io::Result<WriteAheadLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(WriteAheadLog::from_file(path, file))
+    }
+
+    /// Creates a WAL appending to the given file, whose contents are read
+    /// back from `path`
+    pub(crate) fn from_file(path: &Path, file: impl WalFile + 'static) -> WriteAheadLog {
+        WriteAheadLog {
+            path: path.to_owned(),
+            file: BufWriter::new(Box::new(file)),
+            sync_policy: SyncPolicy::default(),
+            last_sync: Instant::now(),
+            bytes_written: 0,
+        }
+    }
+
+    /// Sets how often the WAL is synced to the disk
+    pub fn with_sync_policy(mut self, sync_policy: SyncPolicy) -> WriteAheadLog {
+        self.sync_policy = sync_policy;
+        self
+    }
+
+    /// Appends a MemTable Entry to the end of the WAL
+    ///
+    /// Depending on the [`SyncPolicy`] the record may only be buffered in
+    /// memory once this returns.
+    pub fn append(&mut self, entry: &MemTableEntry) -> io::Result<()> {
+        self.append_batch(std::slice::from_ref(entry))
+    }
+
+    /// Appends several MemTable Entries to the end of the WAL as one batch
+    ///
+    /// If a crash happens while the batch is being written, none of its
+    /// entries are recovered.
+    pub fn append_batch(&mut self, entries: &[MemTableEntry]) -> io::Result<()> {
+        self.append_batch_with(entries, &[])
+    }
+
+    /// Appends a Range Tombstone to the end of the WAL as a batch of its own
+    pub fn append_range_tombstone(&mut self, tombstone: &RangeTombstone) -> io::Result<()> {
+        self.append_batch_with(&[], std::slice::from_ref(tombstone))
+    }
+
+    /// Appends several MemTable Entries and Range Tombstones to the end of
+    /// the WAL as one batch
+    ///
+    /// The entries are replayed before the Range Tombstones.
+    pub fn append_batch_with(
+        &mut self,
+        entries: &[MemTableEntry],
+        range_tombstones: &[RangeTombstone],
+    ) -> io::Result<()> {
+        let len = entries
+            .iter()
+            .map(MemTableEntry::encoded_len)
+            .sum::<usize>()
+            + range_tombstones
+                .iter()
+                .map(|t| t.start.len() + t.end.len() + 37)
+                .sum::<usize>();
+        let mut record = Vec::with_capacity(16 + len);
+        record.extend_from_slice(&0u32.to_le_bytes());
+        let count = (entries.len() + range_tombstones.len()) as u32;
+        record.extend_from_slice(&count.to_le_bytes());
+        for entry in entries {
+            entry.encode(&mut record);
+        }
+        for tombstone in range_tombstones {
+            tombstone.encode(&mut record);
+        }
+        self.write(record)
+    }
+
+    /// Writes a record and syncs it according to the SyncPolicy
+    ///
+    /// The record holds its Payload after 4 bytes left for the Len, which
+    /// are filled in here along with the CRC.
+    fn write(&mut self, mut record: Vec<u8>) -> io::Result<()> {
+        let len = (record.len() - 4) as u32;
+        record[..4].copy_from_slice(&len.to_le_bytes());
+        let crc = crc32c::crc32c(&record[4..]);
+        record.extend_from_slice(&crc.to_le_bytes());
+
+        self.file.write_all(&record)?;
+        self.bytes_written += record.len() as u64;
+
+        match self.sync_policy {
+            SyncPolicy::EveryWrite => self.sync(),
+            SyncPolicy::Interval(interval) if self.last_sync.elapsed() >= interval => self.sync(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flushes the buffered records and syncs the WAL to the disk
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_mut().sync_data()?;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Replays the WAL at the given path into a new MemTable
+    ///
+    /// A missing WAL is treated as an empty one. If the last record was only
+    /// partially written (e.g. a crash mid-write) or a record fails its
+    /// checksum, recovery stops at the last intact record, and the rest of
+    /// the WAL is ignored. Fails with
+    /// [`Error::InvalidConfig`](crate::Error::InvalidConfig) if a merge has
+    /// to be folded, as the new MemTable has no MergeOperator.
+    pub fn recover(path: &Path) -> Result<MemTable> {
+        let mut table = MemTable::new();
+        WriteAheadLog::recover_into(path, &mut table)?;
+        Ok(table)
+    }
+
+    /// Replays the WAL at the given path into an existing MemTable and
+    /// returns the number of records replayed
+    ///
+    /// This follows the same rules as `recover`, and lets several WAL
+    /// segments be replayed one after the other into the same MemTable.
+    pub fn recover_into(path: &Path, table: &mut MemTable) -> Result<usize> {
+        WriteAheadLog::recover_segment(path, table).map(|(count, _)| count)
+    }
+
+    /// Replays the WAL like `recover_into`, also returning the offset of the
+    /// end of the last intact record, where recovery stopped
+    pub(crate) fn recover_segment(path: &Path, table: &mut MemTable) -> Result<(usize, u64)> {
+        let mut buf = Vec::new();
+        match File::open(path) {
+            Ok(mut file) => file.read_to_end(&mut buf)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((0, 0)),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut reader = RecordReader::new(&buf);
+        let mut count = 0;
+        while let Some(batch) = reader.next_batch() {
+            for operation in batch {
+                table.apply(operation)?;
+            }
+            count += 1;
+        }
+
+        Ok((count, reader.pos as u64))
+    }
+
+    /// Cuts the WAL at the given path down to `intact_len` bytes and syncs it
+    ///
+    /// Recovery ignores what follows the last intact record, but records
+    /// appended after a torn one would be lost along with it on the next
+    /// recovery, so the torn tail must go before the WAL is appended to.
+    pub(crate) fn truncate_torn_tail(path: &Path, intact_len: u64) -> io::Result<()> {
+        let file = match OpenOptions::new().write(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if file.metadata()?.len() > intact_len {
+            file.set_len(intact_len)?;
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Iterates over the batches of the WAL in the order they were appended,
+    /// without applying them
+    ///
+    /// Records still buffered in memory are included. Where recovery would
+    /// stop at an incomplete or corrupted record, an error is yielded
+    /// instead, after which the iteration ends.
+    pub fn iter_batches(&self) -> Batches {
+        match fs::read(&self.path) {
+            Ok(mut buf) => {
+                buf.extend_from_slice(self.file.buffer());
+                Batches {
+                    buf,
+                    pos: 0,
+                    error: None,
+                }
+            }
+            Err(e) => Batches {
+                buf: Vec::new(),
+                pos: 0,
+                error: Some(e),
+            },
+        }
+    }
+
+    /// Iterates over the Operations of every batch of the WAL like
+    /// `iter_batches`, without the batch boundaries
+    pub fn iter_records(&self) -> impl Iterator<Item = io::Result<Operation>> {
+        self.iter_batches()
+            .flat_map(|batch| -> Vec<io::Result<Operation>> {
+                match batch {
+                    Ok(batch) => batch.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                }
+            })
+    }
+
+    /// Discards every record in the WAL
+    ///
+    /// This is called once the MemTable it backs has been flushed to a Table.
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_mut().set_len(0)?;
+        self.file.get_mut().sync_data()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn sync_policy(&self) -> SyncPolicy {
+        self.sync_policy
+    }
+
+    /// Returns the number of bytes appended since the WAL was opened
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+}
+
+impl Drop for WriteAheadLog {
+    fn drop(&mut self) {
+        // Errors can't be reported from a drop, call `sync` beforehand to
+        // handle them.
+        let _ = self.sync();
+    }
+}
+
+/// Iterator over the batches of a WAL, created by
+/// [`WriteAheadLog::iter_batches`]
+pub struct Batches {
+    buf: Vec<u8>,
+    pos: usize,
+    /// Error to yield before ending the iteration
+    error: Option<io::Error>,
+}
+
+impl Iterator for Batches {
+    type Item = io::Result<Vec<Operation>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        if self.pos >= self.buf.len() {
+            return None;
+        }
+
+        let mut reader = RecordReader {
+            buf: &self.buf,
+            pos: self.pos,
+        };
+        let batch = reader.next_batch().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("incomplete or corrupted record at offset {}", self.pos),
+            )
+        });
+        self.pos = match batch {
+            Ok(_) => reader.pos,
+            Err(_) => self.buf.len(),
+        };
+        Some(batch)
+    }
+}
+
+/// Decodes records from an in-memory buffer
+struct RecordReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> RecordReader<'a> {
+    fn new(buf: &'a [u8]) -> RecordReader<'a> {
+        RecordReader { buf, pos: 0 }
+    }
+
+    /// Decodes the batch of the next record, returning None once the buffer
+    /// is exhausted or the record is incomplete or corrupted
+    fn next_batch(&mut self) -> Option<Vec<Operation>> {
+        let start = self.pos;
+        let batch = self.decode_record();
+        if batch.is_none() {
+            self.pos = start;
+        }
+        batch
+    }
+
+    fn decode_record(&mut self) -> Option<Vec<Operation>> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into().ok()?);
+        let payload = self.take(len as usize)?;
+        let crc = u32::from_le_bytes(self.take(4)?.try_into().ok()?);
+        if crc != crc32c::crc32c(payload) {
+            return None;
+        }
+
+        let count = u32::from_le_bytes(payload.get(..4)?.try_into().ok()?);
+        let mut pos = 4;
+        let batch = (0..count)
+            .map(|_| {
+                let (operation, len) = Operation::decode(&payload[pos..]).ok()?;
+                pos += len;
+                Some(operation)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        // The Payload holds exactly Count writes.
+        (pos == payload.len()).then_some(batch)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let bytes = self.buf.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+
+    use tempfile::tempdir;
+
+    use std::time::Duration;
+
+    use crate::faulty::FaultyFile;
+    use crate::mem_table::{MemTable, MemTableEntry, Operation, RangeTombstone};
+    use crate::wal::{SyncPolicy, WriteAheadLog};
+
+    fn entry(key: &[u8], value: Option<&[u8]>, timestamp_ms: u128) -> MemTableEntry {
+        MemTableEntry {
+            key: key.to_vec(),
+            value: value.map(|v| v.to_vec()),
+            timestamp_ms,
+            is_deleted: value.is_none(),
+            is_merge: false,
+            expires_at_ms: None,
+        }
+    }
+
+    #[test]
+    fn test_wal_recover() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(&entry(b"Apple", Some(b"Apple Smoothie"), 0))
+            .unwrap();
+        wal.append(&entry(b"Lime", Some(b"Lime Smoothie"), 10))
+            .unwrap();
+        wal.append(&entry(b"Orange", Some(b"Orange Smoothie"), 20))
+            .unwrap();
+        wal.append(&entry(b"Lime", None, 30)).unwrap();
+        drop(wal);
+
+        let table = WriteAheadLog::recover(&path).unwrap();
+        assert_eq!(table.len(), 3);
+
+        let lime = table.get(b"Lime").unwrap();
+        assert!(lime.is_deleted);
+        assert_eq!(lime.timestamp_ms, 30);
+
+        let orange = table.get(b"Orange").unwrap();
+        assert_eq!(orange.value.as_ref().unwrap(), b"Orange Smoothie");
+        assert_eq!(orange.timestamp_ms, 20);
+
+        // Apple (56) + Lime tombstone (33) + Orange (58)
+        assert_eq!(table.size(), 147);
+    }
+
+    #[test]
+    fn test_wal_iter_records() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        // Records still buffered are iterated as well.
+        let mut wal = WriteAheadLog::open(&path)
+            .unwrap()
+            .with_sync_policy(SyncPolicy::Never);
+        wal.append_batch(&[
+            entry(b"Apple", Some(b"Apple Smoothie"), 10),
+            entry(b"Lime", None, 10),
+        ])
+        .unwrap();
+        wal.append_range_tombstone(&RangeTombstone {
+            start: b"Banana".to_vec(),
+            end: b"Cherry".to_vec(),
+            timestamp_ms: 20,
+        })
+        .unwrap();
+        wal.append(&entry(b"Orange", Some(b"Orange Smoothie"), 30))
+            .unwrap();
+
+        let set = |key: &[u8], value: &[u8], timestamp_ms| Operation::Set {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            timestamp_ms,
+            expires_at_ms: None,
+        };
+        let records: Vec<Operation> = wal.iter_records().map(Result::unwrap).collect();
+        assert_eq!(
+            records,
+            vec![
+                set(b"Apple", b"Apple Smoothie", 10),
+                Operation::Delete {
+                    key: b"Lime".to_vec(),
+                    timestamp_ms: 10,
+                },
+                Operation::DeleteRange {
+                    start: b"Banana".to_vec(),
+                    end: b"Cherry".to_vec(),
+                    timestamp_ms: 20,
+                },
+                set(b"Orange", b"Orange Smoothie", 30),
+            ]
+        );
+        let batch_lens: Vec<usize> = wal.iter_batches().map(|b| b.unwrap().len()).collect();
+        assert_eq!(batch_lens, vec![2, 1, 1]);
+        drop(wal);
+
+        // A torn record ends the iteration with an error.
+        let file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.set_len(std::fs::metadata(&path).unwrap().len() - 3)
+            .unwrap();
+        let wal = WriteAheadLog::open(&path).unwrap();
+        let records: Vec<_> = wal.iter_records().collect();
+        assert_eq!(records.len(), 4);
+        assert!(records[..3].iter().all(Result::is_ok));
+        assert_eq!(
+            records[3].as_ref().unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_wal_recover_expiry() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(&MemTableEntry {
+            expires_at_ms: Some(u128::MAX),
+            ..entry(b"Apple", Some(b"Apple Smoothie"), 10)
+        })
+        .unwrap();
+        wal.append(&entry(b"Lime", Some(b"Lime Smoothie"), 20))
+            .unwrap();
+        drop(wal);
+
+        let table = WriteAheadLog::recover(&path).unwrap();
+        let apple = table.get(b"Apple").unwrap();
+        assert_eq!(apple.value.as_ref().unwrap(), b"Apple Smoothie");
+        assert_eq!(apple.timestamp_ms, 10);
+        assert_eq!(apple.expires_at_ms, Some(u128::MAX));
+        assert_eq!(table.get(b"Lime").unwrap().expires_at_ms, None);
+    }
+
+    #[test]
+    fn test_wal_recover_corrupted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(&entry(b"Apple", Some(b"Apple Smoothie"), 0))
+            .unwrap();
+        wal.append(&entry(b"Lime", Some(b"Lime Smoothie"), 10))
+            .unwrap();
+        wal.append(&entry(b"Orange", Some(b"Orange Smoothie"), 20))
+            .unwrap();
+        drop(wal);
+
+        // Flip a byte inside the value of the second record.
+        let mut bytes = std::fs::read(&path).unwrap();
+        let pos = bytes.windows(4).position(|w| w == b"Lime").unwrap();
+        bytes[pos + 4 + 1 + 8] ^= 0xFF;
+        std::fs::write(&path, bytes).unwrap();
+
+        let table = WriteAheadLog::recover(&path).unwrap();
+        assert_eq!(table.len(), 1);
+        assert!(table.get(b"Apple").is_some());
+        assert!(table.get(b"Lime").is_none());
+        assert!(table.get(b"Orange").is_none());
+    }
+
+    #[test]
+    fn test_wal_recover_reopen_appends() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(&entry(b"Apple", Some(b"Apple Smoothie"), 0))
+            .unwrap();
+        drop(wal);
+
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(&entry(b"Lime", Some(b"Lime Smoothie"), 10))
+            .unwrap();
+        drop(wal);
+
+        let table = WriteAheadLog::recover(&path).unwrap();
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn test_wal_recover_missing() {
+        let dir = tempdir().unwrap();
+
+        let table = WriteAheadLog::recover(&dir.path().join("wal.log")).unwrap();
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn test_wal_recover_truncated() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(&entry(b"Apple", Some(b"Apple Smoothie"), 0))
+            .unwrap();
+        wal.append(&entry(b"Lime", Some(b"Lime Smoothie"), 10))
+            .unwrap();
+        drop(wal);
+
+        // Chop off the tail of the last record as if a crash happened mid-write.
+        let len = std::fs::metadata(&path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 5).unwrap();
+
+        let table = WriteAheadLog::recover(&path).unwrap();
+        assert_eq!(table.len(), 1);
+        assert!(table.get(b"Apple").is_some());
+        assert!(table.get(b"Lime").is_none());
+        assert_eq!(table.size(), 56);
+    }
+
+    #[test]
+    fn test_wal_sync_policy_never_buffers() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let mut wal = WriteAheadLog::open(&path)
+            .unwrap()
+            .with_sync_policy(SyncPolicy::Never);
+        wal.append(&entry(b"Apple", Some(b"Apple Smoothie"), 0))
+            .unwrap();
+
+        // The record is still buffered in memory.
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+
+        wal.sync().unwrap();
+        assert_eq!(WriteAheadLog::recover(&path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_wal_sync_on_drop() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let mut wal = WriteAheadLog::open(&path)
+            .unwrap()
+            .with_sync_policy(SyncPolicy::Interval(Duration::from_secs(3600)));
+        wal.append(&entry(b"Apple", Some(b"Apple Smoothie"), 0))
+            .unwrap();
+        wal.append(&entry(b"Lime", Some(b"Lime Smoothie"), 10))
+            .unwrap();
+        drop(wal);
+
+        assert_eq!(WriteAheadLog::recover(&path).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_wal_sync_every_write() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        assert_eq!(wal.sync_policy(), SyncPolicy::EveryWrite);
+        wal.append(&entry(b"Apple", Some(b"Apple Smoothie"), 0))
+            .unwrap();
+
+        assert_eq!(WriteAheadLog::recover(&path).unwrap().len(), 1);
+        drop(wal);
+    }
+
+    #[test]
+    fn test_wal_recover_batch() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(&entry(b"Apple", Some(b"Apple Smoothie"), 0))
+            .unwrap();
+        wal.append_batch(&[
+            entry(b"Lime", Some(b"Lime Smoothie"), 10),
+            entry(b"Orange", Some(b"Orange Smoothie"), 10),
+            entry(b"Apple", None, 10),
+        ])
+        .unwrap();
+        drop(wal);
+
+        let table = WriteAheadLog::recover(&path).unwrap();
+        assert_eq!(table.len(), 3);
+        assert!(table.get(b"Apple").unwrap().is_deleted);
+        assert!(table.get(b"Lime").is_some());
+        assert!(table.get(b"Orange").is_some());
+    }
+
+    #[test]
+    fn test_wal_recover_range_tombstone() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let tombstone = RangeTombstone {
+            start: b"Lime".to_vec(),
+            end: b"Peach".to_vec(),
+            timestamp_ms: 10,
+        };
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(&entry(b"Lime", Some(b"Lime Smoothie"), 0))
+            .unwrap();
+        wal.append_range_tombstone(&tombstone).unwrap();
+        wal.append(&entry(b"Orange", Some(b"Orange Smoothie"), 20))
+            .unwrap();
+        drop(wal);
+
+        let table = WriteAheadLog::recover(&path).unwrap();
+        assert_eq!(table.range_tombstones(), &[tombstone]);
+        assert_eq!(table.len(), 2);
+        assert!(table.get(b"Peach").is_none());
+    }
+
+    #[test]
+    fn test_wal_recover_torn_batch() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(&entry(b"Apple", Some(b"Apple Smoothie"), 0))
+            .unwrap();
+        wal.append_batch(&[
+            entry(b"Lime", Some(b"Lime Smoothie"), 10),
+            entry(b"Orange", Some(b"Orange Smoothie"), 10),
+        ])
+        .unwrap();
+        drop(wal);
+
+        // Cut the batch in the middle of its last record, after the Lime
+        // record has been fully written.
+        let len = std::fs::metadata(&path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 10).unwrap();
+
+        let table = WriteAheadLog::recover(&path).unwrap();
+        assert_eq!(table.len(), 1);
+        assert!(table.get(b"Apple").is_some());
+        assert!(table.get(b"Lime").is_none());
+    }
+
+    #[test]
+    fn test_wal_recover_truncated_anywhere() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let entries = [
+            entry(b"Apple", Some(b"Apple Smoothie"), 0),
+            entry(b"Lime", Some(b"Lime Smoothie"), 10),
+            entry(b"Apple", None, 20),
+        ];
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        let mut ends = Vec::new();
+        for entry in &entries {
+            wal.append(entry).unwrap();
+            ends.push(wal.bytes_written());
+        }
+        drop(wal);
+
+        let bytes = std::fs::read(&path).unwrap();
+        // Cutting anywhere, including inside a Len, keeps every record that
+        // ends before the cut.
+        for cut in 0..=bytes.len() {
+            std::fs::write(&path, &bytes[..cut]).unwrap();
+            let complete = ends.iter().filter(|&&end| end <= cut as u64).count();
+
+            let mut table = MemTable::new();
+            let count = WriteAheadLog::recover_into(&path, &mut table).unwrap();
+            assert_eq!(count, complete, "cut at {cut}");
+
+            let mut expected = MemTable::new();
+            for entry in &entries[..complete] {
+                expected.apply(Operation::from(entry.clone())).unwrap();
+            }
+            assert_eq!(table.entries(), expected.entries(), "cut at {cut}");
+        }
+    }
+
+    #[test]
+    fn test_wal_recover_corrupted_len() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.append(&entry(b"Apple", Some(b"Apple Smoothie"), 0))
+            .unwrap();
+        let end = wal.bytes_written() as usize;
+        wal.append(&entry(b"Lime", Some(b"Lime Smoothie"), 10))
+            .unwrap();
+        drop(wal);
+
+        // A Len running past the end of the WAL ends recovery as well.
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[end..end + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut table = MemTable::new();
+        assert_eq!(WriteAheadLog::recover_into(&path, &mut table).unwrap(), 1);
+        assert!(table.get(b"Apple").is_some());
+        assert!(table.get(b"Lime").is_none());
+    }
+
+    /// Recovers the contents of a crashed FaultyFile from a real WAL at `path`
+    fn recover_crashed(path: &std::path::Path, contents: &[u8]) -> MemTable {
+        std::fs::write(path, contents).unwrap();
+        WriteAheadLog::recover(path).unwrap()
+    }
+
+    fn recovered_from(entries: &[MemTableEntry]) -> MemTable {
+        let mut table = MemTable::new();
+        for entry in entries {
+            table.apply(Operation::from(entry.clone())).unwrap();
+        }
+        table
+    }
+
+    #[test]
+    fn test_wal_crash_before_and_after_sync() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let entries = [
+            entry(b"Apple", Some(b"Apple Smoothie"), 0),
+            entry(b"Lime", Some(b"Lime Smoothie"), 10),
+            entry(b"Apple", None, 20),
+        ];
+        // With EveryWrite each append is a write followed by a sync, so the
+        // `n`th operation writes or syncs the record `n / 2`.
+        for crash_at in 0..2 * entries.len() {
+            let file = FaultyFile::new().with_crash_at(crash_at);
+            let mut wal = WriteAheadLog::from_file(&path, file.clone());
+            let appended = entries
+                .iter()
+                .take_while(|entry| wal.append(entry).is_ok())
+                .count();
+            assert_eq!(appended, crash_at / 2, "crash at {crash_at}");
+
+            // Every acknowledged record survives, and a record written but
+            // not synced may survive as well.
+            let table = recover_crashed(&path, &file.recovered(|_| false));
+            assert_eq!(
+                table.entries(),
+                recovered_from(&entries[..appended]).entries(),
+                "crash at {crash_at}"
+            );
+            let table = recover_crashed(&path, &file.recovered(|_| true));
+            assert_eq!(
+                table.entries(),
+                recovered_from(&entries[..crash_at.div_ceil(2)]).entries(),
+                "crash at {crash_at}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_wal_crash_reordered_writes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        // Values larger than the write buffer go to the file one record per
+        // write.
+        let value = vec![7; 16 * 1024];
+        let entries: Vec<MemTableEntry> = (0..4u8)
+            .map(|i| entry(&[b'a' + i], Some(&value), i as u128))
+            .collect();
+        let file = FaultyFile::new();
+        let mut wal =
+            WriteAheadLog::from_file(&path, file.clone()).with_sync_policy(SyncPolicy::Never);
+        wal.append(&entries[0]).unwrap();
+        wal.sync().unwrap();
+        for entry in &entries[1..] {
+            wal.append(entry).unwrap();
+        }
+        assert_eq!(file.pending_writes(), 3);
+
+        // Whichever writes reach the disk, the synced record survives and
+        // recovery stops at the first record missing, even if later ones
+        // made it.
+        for mask in 0..8u32 {
+            let persisted = |i: usize| mask & (1 << i) != 0;
+            let table = recover_crashed(&path, &file.recovered(persisted));
+            let survived = 1 + (0..3).take_while(|&i| persisted(i)).count();
+            assert_eq!(
+                table.entries(),
+                recovered_from(&entries[..survived]).entries(),
+                "persisted {mask:03b}"
+            );
+        }
+
+        // Once synced every record survives.
+        wal.sync().unwrap();
+        let table = recover_crashed(&path, &file.recovered(|_| false));
+        assert_eq!(table.entries(), recovered_from(&entries).entries());
+    }
+
+    #[test]
+    fn test_wal_crash_sync_policy_never() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wal.log");
+
+        let file = FaultyFile::new();
+        let mut wal =
+            WriteAheadLog::from_file(&path, file.clone()).with_sync_policy(SyncPolicy::Never);
+        wal.append(&entry(b"Apple", Some(b"Apple Smoothie"), 0))
+            .unwrap();
+        wal.sync().unwrap();
+        wal.append(&entry(b"Lime", Some(b"Lime Smoothie"), 10))
+            .unwrap();
+
+        // The record appended after the sync is lost, however the buffered
+        // writes reach the disk.
+        for contents in [file.recovered(|_| false), file.recovered(|_| true)] {
+            let table = recover_crashed(&path, &contents);
+            assert_eq!(table.len(), 1);
+            assert!(table.get(b"Apple").is_some());
+        }
+    }
+}
@@ -0,0 +1,60 @@
+// Benchmarks counting allocations, kept in their own test binary as they
+// replace the global allocator
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::Instant;
+
+use rust_lsm_db::mem_table::MemTable;
+
+thread_local! {
+    /// Number of allocations made by the thread
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts the allocations of every thread
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+#[ignore = "benchmark, run with --ignored --nocapture"]
+fn bench_mem_table_set_owned() {
+    let pairs: Vec<(Vec<u8>, Vec<u8>)> = (0..200_000u32)
+        .map(|i| (i.to_be_bytes().to_vec(), b"Smoothie".to_vec()))
+        .collect();
+    let allocations = || ALLOCATIONS.with(Cell::get);
+
+    let mut set_table = MemTable::new();
+    let (start, before) = (Instant::now(), allocations());
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        set_table.set_at(key, value.as_slice(), i as u128);
+    }
+    let (set_elapsed, set_allocations) = (start.elapsed(), allocations() - before);
+
+    let mut owned_table = MemTable::new();
+    let (start, before) = (Instant::now(), allocations());
+    for (i, (key, value)) in pairs.into_iter().enumerate() {
+        owned_table.set_owned(key, value, i as u128);
+    }
+    let (owned_elapsed, owned_allocations) = (start.elapsed(), allocations() - before);
+
+    assert_eq!(set_table.entries(), owned_table.entries());
+    assert!(owned_allocations < set_allocations);
+    println!(
+        "set_at: {:?} ({} allocations), set_owned: {:?} ({} allocations)",
+        set_elapsed, set_allocations, owned_elapsed, owned_allocations
+    );
+}